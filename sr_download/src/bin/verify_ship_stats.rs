@@ -2,18 +2,20 @@ use std::{collections::BTreeMap, io::Write, path::PathBuf, time::Instant};
 
 use clap::Parser;
 use colored::Colorize;
-use sqlx::{PgPool, Row};
 use tracing::Level;
 
 use sr_download::{
     config::ConfigFile,
-    db_part::utils::{ShipVerifyState, connect_server, verify_ship},
+    db_part::{
+        DbPool, search,
+        utils::{SaveVerifyState, ShipVerifyState, connect_server, verify_save, verify_ship},
+    },
 };
 
 #[derive(Parser, Debug)]
 #[command(
     name = "verify_ship_stats",
-    about = "Scan database XML/ship verification states and print a summary table"
+    about = "Scan database XML/ship/save verification states and print a summary table"
 )]
 struct Cli {
     #[arg(short = 'c', long = "config", default_value = "./config.toml")]
@@ -46,6 +48,13 @@ impl VerifyStats {
         }
         *self.states.entry(state.as_str()).or_default() += 1;
     }
+
+    fn record_save_state(&mut self, state: SaveVerifyState) {
+        self.total_rows += 1;
+        self.rows_with_data += 1;
+        self.valid_xml += 1;
+        *self.states.entry(state.as_str()).or_default() += 1;
+    }
 }
 
 #[tokio::main]
@@ -64,27 +73,15 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn scan_db(db: &PgPool, batch_size: i64) -> anyhow::Result<VerifyStats> {
+async fn scan_db(db: &DbPool, batch_size: i64) -> anyhow::Result<VerifyStats> {
     let mut stats = VerifyStats::default();
-    let total_rows = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM full_data")
-        .fetch_one(db)
-        .await? as u64;
-    let mut last_save_id = 0i32;
+    let total_rows = search::count_data(db).await? as u64;
+    let mut last_save_id = 0u32;
     let mut batch_index = 0u64;
     let started_at = Instant::now();
 
     loop {
-        let rows = sqlx::query(
-            "SELECT save_id, data
-             FROM full_data
-             WHERE save_id > $1
-             ORDER BY save_id
-             LIMIT $2",
-        )
-        .bind(last_save_id)
-        .bind(batch_size)
-        .fetch_all(db)
-        .await?;
+        let rows = search::data_batch(db, last_save_id, batch_size).await?;
 
         if rows.is_empty() {
             break;
@@ -92,15 +89,21 @@ async fn scan_db(db: &PgPool, batch_size: i64) -> anyhow::Result<VerifyStats> {
 
         let batch_start_id = rows
             .first()
-            .and_then(|row| row.try_get::<i32, _>("save_id").ok())
+            .map(|(save_id, _)| *save_id)
             .unwrap_or(last_save_id);
-        for row in rows {
-            last_save_id = row.try_get::<i32, _>("save_id")?;
-            let data = row.try_get::<Option<String>, _>("data")?;
+        for (save_id, data) in rows {
+            last_save_id = save_id;
             match data {
                 Some(text) if !text.is_empty() => {
                     let state = verify_ship(&text);
-                    stats.record_state(state);
+                    // 不是船的再看看是不是存档
+                    let save_state = (state == ShipVerifyState::NotShip)
+                        .then(|| verify_save(&text))
+                        .filter(|state| *state != SaveVerifyState::NotSave);
+                    match save_state {
+                        Some(save_state) => stats.record_save_state(save_state),
+                        None => stats.record_state(state),
+                    }
                 }
                 _ => {
                     stats.record_missing();
@@ -124,13 +127,13 @@ async fn scan_db(db: &PgPool, batch_size: i64) -> anyhow::Result<VerifyStats> {
 
 fn render_progress(
     batch_index: u64,
-    batch_start_id: i32,
-    last_save_id: i32,
+    batch_start_id: u32,
+    last_save_id: u32,
     total_rows: u64,
     total_stats: &VerifyStats,
     elapsed: std::time::Duration,
 ) {
-    const PROGRESS_LINES: usize = 14;
+    const PROGRESS_LINES: usize = 17;
 
     if batch_index > 1 {
         print!("\r\x1B[{}A\x1B[J", PROGRESS_LINES);
@@ -206,6 +209,21 @@ fn render_progress(
         count_for(total_stats, ShipVerifyState::VerifiedShip),
         total_stats.total_rows,
     );
+    print_row(
+        "伪存档",
+        count_for_save(total_stats, SaveVerifyState::FakeSave),
+        total_stats.total_rows,
+    );
+    print_row(
+        "损坏存档",
+        count_for_save(total_stats, SaveVerifyState::BrokenSave),
+        total_stats.total_rows,
+    );
+    print_row(
+        "有效存档",
+        count_for_save(total_stats, SaveVerifyState::VerifiedSave),
+        total_stats.total_rows,
+    );
     let _ = std::io::stdout().flush();
 }
 
@@ -243,12 +261,31 @@ fn print_summary(stats: &VerifyStats, elapsed: std::time::Duration) {
         count_for(stats, ShipVerifyState::VerifiedShip),
         stats.total_rows,
     );
+    print_row(
+        "伪存档",
+        count_for_save(stats, SaveVerifyState::FakeSave),
+        stats.total_rows,
+    );
+    print_row(
+        "损坏存档",
+        count_for_save(stats, SaveVerifyState::BrokenSave),
+        stats.total_rows,
+    );
+    print_row(
+        "有效存档",
+        count_for_save(stats, SaveVerifyState::VerifiedSave),
+        stats.total_rows,
+    );
 }
 
 fn count_for(stats: &VerifyStats, state: ShipVerifyState) -> u64 {
     stats.states.get(state.as_str()).copied().unwrap_or(0)
 }
 
+fn count_for_save(stats: &VerifyStats, state: SaveVerifyState) -> u64 {
+    stats.states.get(state.as_str()).copied().unwrap_or(0)
+}
+
 fn print_row(label: &str, count: u64, total: u64) {
     let percent = if total == 0 {
         0.0
@@ -17,6 +17,9 @@ pub mod db_config {
         pub schema: String,
         pub max_connections: u32,
         pub sqlx_logging: bool,
+        /// 额外把数据写一份到这个目录 (文件存档格式)
+        #[serde(default)]
+        pub archive_mirror: Option<String>,
     }
 
     impl Default for DbConfig {
@@ -26,12 +29,41 @@ pub mod db_config {
                 schema: "public".to_string(),
                 max_connections: 10,
                 sqlx_logging: false,
+                archive_mirror: None,
             }
         }
     }
+
+    /// 存储后端, 由 `url` 的 scheme 决定
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum DbBackend {
+        /// `postgres://` 或者 `postgresql://`
+        Postgres,
+        /// `sqlite:`, 比如 `sqlite://srdown.db` 或者 `sqlite::memory:`
+        Sqlite,
+        /// `file://`, 直接存成文件, 比如 `file://./archive`
+        Archive,
+    }
+
+    impl DbConfig {
+        pub fn backend(&self) -> anyhow::Result<DbBackend> {
+            let scheme = self.url.split(':').next().unwrap_or_default();
+            match scheme {
+                "postgres" | "postgresql" => Ok(DbBackend::Postgres),
+                "sqlite" => Ok(DbBackend::Sqlite),
+                "file" => Ok(DbBackend::Archive),
+                _ => Err(anyhow::anyhow!("不支持的数据库 url: {}", self.url)),
+            }
+        }
+
+        /// `file://` 后面的目录
+        pub fn archive_root(&self) -> Option<&str> {
+            self.url.strip_prefix("file://")
+        }
+    }
 }
 
-pub use db_config::DbConfig;
+pub use db_config::{DbBackend, DbConfig};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename = "fast-sync")]
@@ -70,6 +102,14 @@ impl Default for SyncConfig {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename = "catalog")]
+pub struct CatalogConfig {
+    /// 自定义的 PartList.xml 路径, 不填就用内置的
+    #[serde(default)]
+    pub part_list: Option<String>,
+}
+
 pub mod serve_config {
     use serde::{Deserialize, Serialize};
 
@@ -95,6 +135,10 @@ pub mod serve_config {
         10_000
     }
 
+    fn default_thumbnail_dir() -> String {
+        "./thumbnails".to_string()
+    }
+
     #[derive(Serialize, Deserialize, Debug, Clone)]
     #[serde(rename = "serve")]
     pub struct ServeConfig {
@@ -108,6 +152,9 @@ pub mod serve_config {
         pub resync_token: String,
         #[serde(default = "十秒")]
         pub refresh_interval: u32,
+        /// 缩略图缓存目录
+        #[serde(default = "default_thumbnail_dir")]
+        pub thumbnail_dir: String,
     }
 
     impl Default for ServeConfig {
@@ -118,6 +165,7 @@ pub mod serve_config {
                 enable: just_false(),
                 resync_token: loong_token(),
                 refresh_interval: 十秒(),
+                thumbnail_dir: default_thumbnail_dir(),
             }
         }
     }
@@ -133,6 +181,8 @@ pub struct ConfigFile {
     pub sync: SyncConfig,
     #[serde(default)]
     pub serve: ServeConfig,
+    #[serde(default)]
+    pub catalog: CatalogConfig,
 }
 
 pub static GLOBAL_CFG: OnceLock<ConfigFile> = OnceLock::new();
@@ -1,19 +1,97 @@
 use blake3::Hasher;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{FromRow, PgPool, SqlitePool};
+use tracing::{Level, event};
 
 use crate::config::ConfigFile;
-use crate::xml_part::{XmlResult, model::SaveDocument, model::ShipDocument, model::XmlDocument};
+use crate::xml_part::{
+    XmlResult, model::SaveDocument, model::ShipDocument, model::XmlDocument, recover::Recovered,
+};
 pub use defines::{SaveId, TEXT_DATA_MAX_LEN};
 
+/// 对每种 sqlx 后端都跑一遍同样的代码, 文件存档单独处理
+///
+/// `$pool` 在每个分支里分别是 `&PgPool` / `&SqlitePool`, `$archive` 是 `&FsArchive`
+macro_rules! with_pool {
+    ($db:expr, $pool:ident => $body:expr, $archive:ident => $archive_body:expr) => {
+        match &$db.store {
+            $crate::db_part::DbStore::Postgres($pool) => $body,
+            $crate::db_part::DbStore::Sqlite($pool) => $body,
+            $crate::db_part::DbStore::Archive($archive) => $archive_body,
+        }
+    };
+}
+
+pub mod archive;
+pub mod canonical;
 pub mod defines;
+pub mod import_dir;
+pub mod plausibility;
+pub mod salvage;
 pub mod search;
+pub mod similarity;
+pub mod sqlite;
+#[cfg(test)]
+pub mod test_util;
+pub mod transfer;
 pub mod updates;
 pub mod utils;
 
+pub use archive::FsArchive;
 pub use utils::{connect, connect_server};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+/// 具体的存储后端
+///
+/// 用哪个由 `DbConfig::url` 的 scheme 决定
+#[derive(Debug, Clone)]
+pub enum DbStore {
+    Postgres(PgPool),
+    /// 单机的个人存档用这个就够了
+    Sqlite(SqlitePool),
+    /// 直接存成一堆 xml 文件
+    Archive(FsArchive),
+}
+
+/// 数据库连接池
+///
+/// 如果配置了 `archive_mirror`, 写进主存储的数据会顺手再往文件存档里写一份
+#[derive(Debug, Clone)]
+pub struct DbPool {
+    pub store: DbStore,
+    pub mirror: Option<FsArchive>,
+}
+
+impl DbPool {
+    pub fn new(store: DbStore) -> Self {
+        Self {
+            store,
+            mirror: None,
+        }
+    }
+
+    pub fn with_mirror(mut self, mirror: FsArchive) -> Self {
+        self.mirror = Some(mirror);
+        self
+    }
+
+    pub fn backend_name(&self) -> &'static str {
+        match self.store {
+            DbStore::Postgres(_) => "postgres",
+            DbStore::Sqlite(_) => "sqlite",
+            DbStore::Archive(_) => "archive",
+        }
+    }
+
+    pub async fn close(&self) {
+        with_pool!(self, pool => pool.close().await, _archive => ())
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
 #[sqlx(type_name = "save_type", rename_all = "lowercase")]
 pub enum SaveType {
     None,
@@ -39,7 +117,16 @@ impl std::fmt::Display for SaveType {
     }
 }
 
-pub async fn full_update(db: &PgPool, conf: &ConfigFile) {
+impl From<&XmlDocument> for SaveType {
+    fn from(doc: &XmlDocument) -> Self {
+        match doc {
+            XmlDocument::Ship(_) => SaveType::Ship,
+            XmlDocument::Save(_) => SaveType::Save,
+        }
+    }
+}
+
+pub async fn full_update(db: &DbPool, conf: &ConfigFile) {
     updates::update_db(db, conf).await;
     utils::check_null_data(db).await;
     utils::update_xml_tested(db).await;
@@ -150,6 +237,13 @@ impl DbData {
         utils::verify_ship(text)
     }
 
+    pub fn verify_save(&self) -> utils::SaveVerifyState {
+        let Some(text) = self.text.as_ref() else {
+            return utils::SaveVerifyState::NotSave;
+        };
+        utils::verify_save(text)
+    }
+
     pub fn parse_xml(&self) -> XmlResult<XmlDocument> {
         let Some(text) = self.text.as_ref() else {
             return Err(crate::xml_part::XmlError::UnsupportedRoot(
@@ -159,6 +253,16 @@ impl DbData {
         crate::xml_part::parse::parse_any_xml(text)
     }
 
+    /// 不是合法 xml 的话先修一修再解析, 见 [`crate::xml_part::recover`]
+    pub fn recover_xml(&self) -> XmlResult<Recovered> {
+        let Some(text) = self.text.as_ref() else {
+            return Err(crate::xml_part::XmlError::UnsupportedRoot(
+                "<missing data>".to_string(),
+            ));
+        };
+        crate::xml_part::recover::recover_xml(text)
+    }
+
     pub fn parse_ship_xml(&self) -> XmlResult<ShipDocument> {
         match self.parse_xml()? {
             XmlDocument::Ship(doc) => Ok(doc),
@@ -180,40 +284,47 @@ impl DbData {
     }
 
     pub fn xml_status(&self) -> String {
-        self.verify_ship().to_string()
+        match self.save_type {
+            SaveType::Save => self.verify_save().to_string(),
+            _ => self.verify_ship().to_string(),
+        }
     }
 
     /// 直接从 full_data 里选即可
-    pub async fn from_db(save_id: SaveId, db: &PgPool) -> Option<Self> {
-        sqlx::query_as::<_, FullDataRow>(
-            "SELECT data, save_id, save_type, len, blake_hash, xml_tested
-             FROM full_data
-             WHERE save_id = $1",
-        )
-        .bind(save_id as i32)
-        .fetch_optional(db)
-        .await
-        .ok()?
-        .map(Into::into)
+    pub async fn from_db(save_id: SaveId, db: &DbPool) -> Option<Self> {
+        with_pool!(db, pool => {
+            sqlx::query_as::<_, FullDataRow>(
+                "SELECT data, save_id, save_type, len, blake_hash, xml_tested
+                 FROM full_data
+                 WHERE save_id = $1",
+            )
+            .bind(save_id as i32)
+            .fetch_optional(pool)
+            .await
+            .ok()?
+            .map(Into::into)
+        }, archive => archive.load(save_id).ok().flatten())
     }
 }
 
-pub async fn check_data_len(db: &PgPool, save_id: SaveId) -> Option<i64> {
-    sqlx::query_scalar::<_, i64>(
-        "SELECT len
-         FROM main_data
-         WHERE save_id = $1
-         LIMIT 1",
-    )
-    .bind(save_id as i32)
-    .fetch_optional(db)
-    .await
-    .ok()
-    .flatten()
+pub async fn check_data_len(db: &DbPool, save_id: SaveId) -> Option<i64> {
+    with_pool!(db, pool => {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT len
+             FROM main_data
+             WHERE save_id = $1
+             LIMIT 1",
+        )
+        .bind(save_id as i32)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+    }, archive => archive.read_meta(save_id).ok().flatten().map(|meta| meta.len))
 }
 
 #[allow(unused)]
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
 pub enum CoverStrategy {
     #[default]
     /// 如果数据已经存在, 则覆盖, 并返回 Ok(true)
@@ -235,7 +346,25 @@ pub async fn save_data_to_db<T, D>(
     save_type: T,
     data: D,
     cover_strategy: Option<CoverStrategy>,
-    db: &PgPool,
+    db: &DbPool,
+) -> anyhow::Result<bool>
+where
+    D: Into<String>,
+    T: Into<SaveType>,
+{
+    save_data_to_db_at(save_id, save_type, data, cover_strategy, Utc::now(), db).await
+}
+
+/// 和 `save_data_to_db` 一样, 只是记录的时间由调用方指定
+///
+/// 导入的时候用来保留原来的时间
+pub async fn save_data_to_db_at<T, D>(
+    save_id: SaveId,
+    save_type: T,
+    data: D,
+    cover_strategy: Option<CoverStrategy>,
+    time: DateTime<Utc>,
+    db: &DbPool,
 ) -> anyhow::Result<bool>
 where
     D: Into<String>,
@@ -244,103 +373,131 @@ where
     // 干活之前, 先检查一下数据是否已经存在
     // 如果已经存在, 那就根据策略来处理
     let cover_strategy = cover_strategy.unwrap_or_default();
-    let time = chrono::Utc::now();
     let save_type: SaveType = save_type.into();
-    let exitst_data = sqlx::query_as::<_, ExistingMainDataRow>(
-        "SELECT save_id, save_type, blake_hash, len, short_data, xml_tested
-         FROM main_data
-         WHERE save_id = $1
-         LIMIT 1",
-    )
-    .bind(save_id as i32)
-    .fetch_optional(db)
-    .await?;
-    if exitst_data.is_some() {
-        match cover_strategy {
-            CoverStrategy::Error => return Err(anyhow::anyhow!("Data already exists")),
-            CoverStrategy::Skip => return Ok(false),
-            _ => (),
-        }
-    }
-
     let data: String = data.into();
-    // 检查长度
-    let data_len = data.len();
-    // 计算 blake3 hash
-    let mut hasher = Hasher::new();
-    hasher.update(data.as_bytes());
-    let hash = hasher.finalize().to_hex().to_string();
-
-    sqlx::query("SELECT 1").execute(db).await?;
-    let mut tx = db.begin().await?;
-
-    if exitst_data.is_some()
-        && matches!(
-            cover_strategy,
-            CoverStrategy::Cover | CoverStrategy::CoverIfDifferent
+    let saved = with_pool!(db, db => {
+        let exitst_data = sqlx::query_as::<_, ExistingMainDataRow>(
+            "SELECT save_id, save_type, blake_hash, len, short_data, xml_tested
+             FROM main_data
+             WHERE save_id = $1
+             LIMIT 1",
         )
-    {
-        // 如果数据已经存在, 那就检查一下是否需要覆盖
-        let exitst_data = exitst_data.unwrap();
-        if exitst_data.blake_hash == hash
-            && matches!(cover_strategy, CoverStrategy::CoverIfDifferent)
-        {
-            // 数据一样, 不需要覆盖
-            tx.commit().await?;
-            return Ok(false);
+        .bind(save_id as i32)
+        .fetch_optional(db)
+        .await?;
+        if exitst_data.is_some() {
+            match cover_strategy {
+                CoverStrategy::Error => return Err(anyhow::anyhow!("Data already exists")),
+                CoverStrategy::Skip => return Ok(false),
+                _ => (),
+            }
         }
-        if exitst_data.len > TEXT_DATA_MAX_LEN as i64 {
-            sqlx::query("DELETE FROM long_data WHERE save_id = $1")
+
+        // 检查长度
+        let data_len = data.len();
+        // 计算 blake3 hash
+        let mut hasher = Hasher::new();
+        hasher.update(data.as_bytes());
+        let hash = hasher.finalize().to_hex().to_string();
+
+        sqlx::query("SELECT 1").execute(db).await?;
+        let mut tx = db.begin().await?;
+
+        if exitst_data.is_some()
+            && matches!(
+                cover_strategy,
+                CoverStrategy::Cover | CoverStrategy::CoverIfDifferent
+            )
+        {
+            // 如果数据已经存在, 那就检查一下是否需要覆盖
+            let exitst_data = exitst_data.unwrap();
+            if exitst_data.blake_hash == hash
+                && matches!(cover_strategy, CoverStrategy::CoverIfDifferent)
+            {
+                // 数据一样, 不需要覆盖
+                tx.commit().await?;
+                return Ok(false);
+            }
+            if exitst_data.len > TEXT_DATA_MAX_LEN as i64 {
+                sqlx::query("DELETE FROM long_data WHERE save_id = $1")
+                    .bind(save_id as i32)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            // 数据换了, 之前算的可疑分数 / 规范 hash / 特征也不作数了
+            sqlx::query("DELETE FROM plausibility WHERE save_id = $1")
+                .bind(save_id as i32)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM canonical_hash WHERE save_id = $1")
+                .bind(save_id as i32)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM ship_features WHERE save_id = $1")
+                .bind(save_id as i32)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM main_data WHERE save_id = $1")
                 .bind(save_id as i32)
                 .execute(&mut *tx)
                 .await?;
         }
-        sqlx::query("DELETE FROM main_data WHERE save_id = $1")
+
+        let xml_tested = Some(utils::verify_xml(&data).is_ok());
+
+        if data_len > TEXT_DATA_MAX_LEN {
+            sqlx::query(
+                "INSERT INTO main_data
+                 (save_id, save_type, blake_hash, len, short_data, xml_tested, time)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
             .bind(save_id as i32)
+            .bind(save_type)
+            .bind(&hash)
+            .bind(data_len as i64)
+            .bind(Option::<String>::None)
+            .bind(xml_tested)
+            .bind(time)
             .execute(&mut *tx)
             .await?;
-    }
-
-    let xml_tested = Some(utils::verify_xml(&data).is_ok());
-
-    if data_len > TEXT_DATA_MAX_LEN {
-        sqlx::query(
-            "INSERT INTO main_data
-             (save_id, save_type, blake_hash, len, short_data, xml_tested, time)
-             VALUES ($1, $2, $3, $4, $5, $6, $7)",
-        )
-        .bind(save_id as i32)
-        .bind(save_type)
-        .bind(&hash)
-        .bind(data_len as i64)
-        .bind(Option::<String>::None)
-        .bind(xml_tested)
-        .bind(time)
-        .execute(&mut *tx)
-        .await?;
-        sqlx::query("INSERT INTO long_data (save_id, len, text) VALUES ($1, $2, $3)")
+            sqlx::query("INSERT INTO long_data (save_id, len, text) VALUES ($1, $2, $3)")
+                .bind(save_id as i32)
+                .bind(data_len as i64)
+                .bind(&data)
+                .execute(&mut *tx)
+                .await?;
+        } else {
+            sqlx::query(
+                "INSERT INTO main_data
+                 (save_id, save_type, blake_hash, len, short_data, xml_tested, time)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
             .bind(save_id as i32)
+            .bind(save_type)
+            .bind(hash)
             .bind(data_len as i64)
-            .bind(data)
+            .bind(Some(data.as_str()))
+            .bind(xml_tested)
+            .bind(time)
             .execute(&mut *tx)
             .await?;
-    } else {
-        sqlx::query(
-            "INSERT INTO main_data
-             (save_id, save_type, blake_hash, len, short_data, xml_tested, time)
-             VALUES ($1, $2, $3, $4, $5, $6, $7)",
-        )
-        .bind(save_id as i32)
-        .bind(save_type)
-        .bind(hash)
-        .bind(data_len as i64)
-        .bind(Some(data))
-        .bind(xml_tested)
-        .bind(time)
-        .execute(&mut *tx)
-        .await?;
-    }
-    tx.commit().await?;
+        }
+        tx.commit().await?;
+
+        Ok(true)
+    }, archive => archive.save(save_id, save_type, &data, cover_strategy, time))?;
 
-    Ok(true)
+    // 镜像跟着主存储走, 主存储没写就不动它
+    if saved
+        && let Some(mirror) = &db.mirror
+        && let Err(e) = mirror.save(save_id, save_type, &data, CoverStrategy::Cover, time)
+    {
+        event!(
+            Level::WARN,
+            "写入文件镜像 {} 的时候出现错误: {:?}",
+            save_id,
+            e
+        );
+    }
+    Ok(saved)
 }
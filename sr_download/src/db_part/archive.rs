@@ -0,0 +1,576 @@
+//! 文件系统存档
+//!
+//! 每条记录存成 `<root>/ab/cd/<save_id>.<save_type>.xml`, 旁边再放一个 `<save_id>.meta.json`
+//! `ab` 是 `save_id >> 16`, `cd` 是 `(save_id >> 8) & 0xff`, 所以每个目录最多 256 条记录,
+//! 而且目录按十六进制排序之后就是按 save_id 排序的
+//!
+//! 查相似船用的特征另外按零件数分桶存在 `<root>/index/features/<part_count>.json`, 见 [`FsArchive::features_bucket`]
+//!
+//! 整个目录可以直接 rsync 走, 或者丢给静态文件服务器
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use blake3::Hasher;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db_part::{
+        CoverStrategy, DbData, SaveId, SaveType, canonical::StoredCanonical,
+        plausibility::StoredScore, similarity::StoredFeatures, utils,
+    },
+    xml_part::similarity::ShipFeatures,
+};
+
+pub const META_SUFFIX: &str = ".meta.json";
+/// 特征索引的目录, 名字不是十六进制, 扫分片目录的时候不会碰到
+pub const FEATURES_INDEX_DIR: &str = "index/features";
+
+/// 每条记录旁边的元数据
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveMeta {
+    pub save_id: SaveId,
+    pub save_type: SaveType,
+    pub blake_hash: String,
+    pub len: i64,
+    pub xml_tested: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// 可疑分数, 没打过分 / 数据被覆盖过的是 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plausibility: Option<StoredScore>,
+    /// 规范 hash, 没算过 / 数据被覆盖过的是 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical: Option<StoredCanonical>,
+    /// 查相似船用的特征, 没算过 / 数据被覆盖过的是 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<StoredFeatures>,
+}
+
+impl From<ArchiveMeta> for DbData {
+    fn from(meta: ArchiveMeta) -> Self {
+        Self {
+            text: None,
+            save_id: meta.save_id,
+            save_type: meta.save_type,
+            len: meta.len,
+            blake_hash: meta.blake_hash,
+            xml_tested: meta.xml_tested,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FsArchive {
+    root: PathBuf,
+}
+
+impl FsArchive {
+    pub fn open(root: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn shard_dir(&self, save_id: SaveId) -> PathBuf {
+        self.root
+            .join(format!("{:02x}", save_id >> 16))
+            .join(format!("{:02x}", (save_id >> 8) & 0xff))
+    }
+
+    pub fn meta_path(&self, save_id: SaveId) -> PathBuf {
+        self.shard_dir(save_id)
+            .join(format!("{save_id}{META_SUFFIX}"))
+    }
+
+    pub fn data_path(&self, save_id: SaveId, save_type: SaveType) -> PathBuf {
+        self.shard_dir(save_id)
+            .join(format!("{save_id}.{save_type}.xml"))
+    }
+
+    pub fn read_meta(&self, save_id: SaveId) -> anyhow::Result<Option<ArchiveMeta>> {
+        match fs::read_to_string(self.meta_path(save_id)) {
+            Ok(text) => Ok(Some(serde_json::from_str(&text)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 读出完整的一条记录
+    ///
+    /// 元数据在但是 xml 文件丢了的时候 `text` 是 None
+    pub fn load(&self, save_id: SaveId) -> anyhow::Result<Option<DbData>> {
+        let Some(meta) = self.read_meta(save_id)? else {
+            return Ok(None);
+        };
+        let text = if meta.len == 0 {
+            Some(String::new())
+        } else {
+            match fs::read_to_string(self.data_path(save_id, meta.save_type)) {
+                Ok(text) => Some(text),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        let mut data: DbData = meta.into();
+        data.text = text;
+        Ok(Some(data))
+    }
+
+    /// 和 `save_data_to_db` 一样的语义
+    pub fn save(
+        &self,
+        save_id: SaveId,
+        save_type: SaveType,
+        data: &str,
+        cover_strategy: CoverStrategy,
+        time: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        let mut hasher = Hasher::new();
+        hasher.update(data.as_bytes());
+        let hash = hasher.finalize().to_hex().to_string();
+
+        let exist_meta = self.read_meta(save_id)?;
+        if let Some(exist_meta) = &exist_meta {
+            match cover_strategy {
+                CoverStrategy::Error => return Err(anyhow::anyhow!("Data already exists")),
+                CoverStrategy::Skip => return Ok(false),
+                CoverStrategy::CoverIfDifferent if exist_meta.blake_hash == hash => {
+                    return Ok(false);
+                }
+                _ => (),
+            }
+        }
+
+        let old_features = exist_meta
+            .as_ref()
+            .and_then(|meta| meta.features.as_ref()?.features.clone());
+        fs::create_dir_all(self.shard_dir(save_id))?;
+        if let Some(exist_meta) = &exist_meta
+            && exist_meta.save_type != save_type
+        {
+            remove_if_exists(&self.data_path(save_id, exist_meta.save_type))?;
+        }
+        let data_path = self.data_path(save_id, save_type);
+        if data.is_empty() {
+            remove_if_exists(&data_path)?;
+        } else {
+            write_atomic(&data_path, data.as_bytes())?;
+        }
+        // 元数据最后写, 有它才算这条记录存好了
+        let meta = ArchiveMeta {
+            save_id,
+            save_type,
+            blake_hash: hash,
+            len: data.len() as i64,
+            xml_tested: utils::verify_xml(data).is_ok(),
+            created_at: exist_meta.map(|meta| meta.created_at).unwrap_or(time),
+            updated_at: time,
+            plausibility: None,
+            canonical: None,
+            features: None,
+        };
+        write_atomic(
+            &self.meta_path(save_id),
+            serde_json::to_string_pretty(&meta)?.as_bytes(),
+        )?;
+        // 数据换了特征就不算数了
+        self.index_features(save_id, old_features.as_ref(), None)?;
+        Ok(true)
+    }
+
+    /// 改一下元数据再写回去, 记录不存在的时候报错
+    fn update_meta(
+        &self,
+        save_id: SaveId,
+        update: impl FnOnce(&mut ArchiveMeta),
+    ) -> anyhow::Result<()> {
+        let Some(mut meta) = self.read_meta(save_id)? else {
+            return Err(anyhow::anyhow!("{save_id} not found"));
+        };
+        update(&mut meta);
+        write_atomic(
+            &self.meta_path(save_id),
+            serde_json::to_string_pretty(&meta)?.as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// 把可疑分数写进元数据
+    pub fn set_plausibility(&self, save_id: SaveId, score: StoredScore) -> anyhow::Result<()> {
+        self.update_meta(save_id, |meta| meta.plausibility = Some(score))
+    }
+
+    /// 把规范 hash 写进元数据
+    pub fn set_canonical(&self, save_id: SaveId, canonical: StoredCanonical) -> anyhow::Result<()> {
+        self.update_meta(save_id, |meta| meta.canonical = Some(canonical))
+    }
+
+    /// 把特征写进元数据, 顺便更新特征索引
+    pub fn set_features(&self, save_id: SaveId, features: StoredFeatures) -> anyhow::Result<()> {
+        let old = self
+            .read_meta(save_id)?
+            .and_then(|meta| meta.features?.features);
+        let new = features.features.clone();
+        self.update_meta(save_id, |meta| meta.features = Some(features))?;
+        self.index_features(save_id, old.as_ref(), new.as_ref())
+    }
+
+    pub fn features_bucket_path(&self, part_count: u32) -> PathBuf {
+        self.root
+            .join(FEATURES_INDEX_DIR)
+            .join(format!("{part_count}.json"))
+    }
+
+    /// 零件数是 `part_count` 的记录的特征, save_id -> 特征
+    ///
+    /// 查相似的船只用读零件数范围里的这几个桶, 不用把所有记录的元数据翻一遍
+    pub fn features_bucket(
+        &self,
+        part_count: u32,
+    ) -> anyhow::Result<BTreeMap<SaveId, ShipFeatures>> {
+        match fs::read_to_string(self.features_bucket_path(part_count)) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn update_bucket(
+        &self,
+        part_count: u32,
+        update: impl FnOnce(&mut BTreeMap<SaveId, ShipFeatures>),
+    ) -> anyhow::Result<()> {
+        let mut bucket = self.features_bucket(part_count)?;
+        update(&mut bucket);
+        let path = self.features_bucket_path(part_count);
+        if bucket.is_empty() {
+            remove_if_exists(&path)?;
+        } else {
+            fs::create_dir_all(self.root.join(FEATURES_INDEX_DIR))?;
+            write_atomic(&path, serde_json::to_string(&bucket)?.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// 特征从 `old` 变成了 `new`, 把 `save_id` 挪到对应的桶里
+    fn index_features(
+        &self,
+        save_id: SaveId,
+        old: Option<&ShipFeatures>,
+        new: Option<&ShipFeatures>,
+    ) -> anyhow::Result<()> {
+        if let Some(old) = old
+            && new.map(|new| new.part_count) != Some(old.part_count)
+        {
+            self.update_bucket(old.part_count, |bucket| {
+                bucket.remove(&save_id);
+            })?;
+        }
+        if let Some(new) = new {
+            self.update_bucket(new.part_count, |bucket| {
+                bucket.insert(save_id, new.clone());
+            })?;
+        }
+        Ok(())
+    }
+
+    /// 从 `after_id` 的下一条开始, 按顺序最多拿 `limit` 个 id
+    pub fn ids_after(&self, after_id: SaveId, limit: usize) -> anyhow::Result<Vec<SaveId>> {
+        let mut ids = Vec::new();
+        for (top, top_dir) in hex_dirs(&self.root)? {
+            if shard_start(top, 0xff) + 0xff <= after_id {
+                continue;
+            }
+            for (mid, mid_dir) in hex_dirs(&top_dir)? {
+                if shard_start(top, mid) + 0xff <= after_id {
+                    continue;
+                }
+                for id in meta_ids(&mid_dir)? {
+                    if id <= after_id {
+                        continue;
+                    }
+                    ids.push(id);
+                    if ids.len() >= limit {
+                        return Ok(ids);
+                    }
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// 从最小的 id 往后找, 返回第一条满足条件的元数据
+    pub fn find_first(
+        &self,
+        filter: impl Fn(&ArchiveMeta) -> bool,
+    ) -> anyhow::Result<Option<ArchiveMeta>> {
+        for (_, top_dir) in hex_dirs(&self.root)? {
+            for (_, mid_dir) in hex_dirs(&top_dir)? {
+                for id in meta_ids(&mid_dir)? {
+                    if let Some(meta) = self.read_meta(id)?
+                        && filter(&meta)
+                    {
+                        return Ok(Some(meta));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// 从最大的 id 往回找, 返回第一条满足条件的元数据
+    pub fn find_last(
+        &self,
+        filter: impl Fn(&ArchiveMeta) -> bool,
+    ) -> anyhow::Result<Option<ArchiveMeta>> {
+        for (_, top_dir) in hex_dirs(&self.root)?.into_iter().rev() {
+            for (_, mid_dir) in hex_dirs(&top_dir)?.into_iter().rev() {
+                for id in meta_ids(&mid_dir)?.into_iter().rev() {
+                    if let Some(meta) = self.read_meta(id)?
+                        && filter(&meta)
+                    {
+                        return Ok(Some(meta));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn count(&self) -> anyhow::Result<usize> {
+        let mut count = 0;
+        for (_, top_dir) in hex_dirs(&self.root)? {
+            for (_, mid_dir) in hex_dirs(&top_dir)? {
+                count += meta_ids(&mid_dir)?.len();
+            }
+        }
+        Ok(count)
+    }
+
+    /// 有元数据但是 xml 文件不见了的记录
+    pub fn missing_data_ids(&self) -> anyhow::Result<Vec<SaveId>> {
+        let mut missing = Vec::new();
+        let mut after_id = 0;
+        loop {
+            let ids = self.ids_after(after_id, 1024)?;
+            let Some(last) = ids.last() else {
+                break;
+            };
+            after_id = *last;
+            for id in ids {
+                if let Some(meta) = self.read_meta(id)?
+                    && meta.len > 0
+                    && !self.data_path(id, meta.save_type).exists()
+                {
+                    missing.push(id);
+                }
+            }
+        }
+        Ok(missing)
+    }
+}
+
+fn shard_start(top: u32, mid: u32) -> SaveId {
+    (top << 16) | (mid << 8)
+}
+
+/// 名字是十六进制数字的子目录, 按数值排好序
+fn hex_dirs(dir: &Path) -> anyhow::Result<Vec<(u32, PathBuf)>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Some(value) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| u32::from_str_radix(name, 16).ok())
+        {
+            dirs.push((value, entry.path()));
+        }
+    }
+    dirs.sort_by_key(|(value, _)| *value);
+    Ok(dirs)
+}
+
+/// 一个分片目录里所有有元数据的 id, 排好序
+fn meta_ids(dir: &Path) -> anyhow::Result<Vec<SaveId>> {
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        if let Some(id) = name
+            .to_str()
+            .and_then(|name| name.strip_suffix(META_SUFFIX))
+            .and_then(|id| id.parse::<SaveId>().ok())
+        {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// 先写临时文件再 rename, 免得写一半被别人读到
+fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::Utc;
+
+    use crate::{
+        db_part::{CoverStrategy, SaveType, similarity::StoredFeatures, test_util::temp_archive},
+        xml_part::similarity::ShipFeatures,
+    };
+
+    #[test]
+    fn sharded_layout() {
+        let archive = temp_archive("archive_layout");
+        let path = archive.data_path(1294489, SaveType::Save);
+        assert!(path.ends_with("13/c0/1294489.save.xml"));
+        let path = archive.meta_path(144444);
+        assert!(path.ends_with("02/34/144444.meta.json"));
+    }
+
+    #[test]
+    fn save_load_and_cover() {
+        let archive = temp_archive("archive_save");
+        let ship = crate::net::EMPTY_SHIP;
+        assert!(
+            archive
+                .save(1, SaveType::Ship, ship, CoverStrategy::Cover, Utc::now())
+                .unwrap()
+        );
+        assert!(
+            !archive
+                .save(1, SaveType::Ship, ship, CoverStrategy::CoverIfDifferent, Utc::now())
+                .unwrap()
+        );
+        assert!(
+            !archive
+                .save(1, SaveType::Ship, "x", CoverStrategy::Skip, Utc::now())
+                .unwrap()
+        );
+        assert!(
+            archive
+                .save(1, SaveType::Ship, "x", CoverStrategy::Error, Utc::now())
+                .is_err()
+        );
+        let data = archive.load(1).unwrap().unwrap();
+        assert_eq!(data.text.as_deref(), Some(ship));
+        assert!(data.xml_tested);
+        assert!(data.verify_hash());
+
+        // 类型变了之后旧文件要删掉
+        assert!(
+            archive
+                .save(1, SaveType::Save, "<Runtime/>", CoverStrategy::Cover, Utc::now())
+                .unwrap()
+        );
+        assert!(!archive.data_path(1, SaveType::Ship).exists());
+        let meta = archive.read_meta(1).unwrap().unwrap();
+        assert_eq!(meta.save_type, SaveType::Save);
+
+        assert!(
+            archive
+                .save(2, SaveType::None, "", CoverStrategy::Cover, Utc::now())
+                .unwrap()
+        );
+        assert_eq!(archive.load(2).unwrap().unwrap().text.as_deref(), Some(""));
+        assert!(archive.missing_data_ids().unwrap().is_empty());
+    }
+
+    #[test]
+    fn ordered_scans() {
+        let archive = temp_archive("archive_scan");
+        for id in [3, 300, 70000, 1294489] {
+            archive
+                .save(id, SaveType::Ship, "<Ship/>", CoverStrategy::Cover, Utc::now())
+                .unwrap();
+        }
+        archive
+            .save(1300000, SaveType::None, "", CoverStrategy::Cover, Utc::now())
+            .unwrap();
+        assert_eq!(archive.count().unwrap(), 5);
+        assert_eq!(archive.ids_after(0, 3).unwrap(), vec![3, 300, 70000]);
+        assert_eq!(
+            archive.ids_after(300, 10).unwrap(),
+            vec![70000, 1294489, 1300000]
+        );
+        let last = archive
+            .find_last(|meta| meta.save_type != SaveType::None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(last.save_id, 1294489);
+        let first = archive
+            .find_first(|meta| meta.save_type != SaveType::None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.save_id, 3);
+    }
+
+    #[test]
+    fn features_index_follows_meta() {
+        let archive = temp_archive("archive_features");
+        let features = |part_count| ShipFeatures {
+            part_count,
+            mass_class: 3,
+            stage_count: 1,
+            histogram: BTreeMap::from([("pod-1".to_string(), part_count)]),
+        };
+        let stored = |features| StoredFeatures {
+            features,
+            computed_at: Utc::now(),
+        };
+        for id in [1, 2] {
+            archive
+                .save(id, SaveType::Ship, "<Ship/>", CoverStrategy::Cover, Utc::now())
+                .unwrap();
+            archive.set_features(id, stored(Some(features(4)))).unwrap();
+        }
+        let ids = |part_count| {
+            archive
+                .features_bucket(part_count)
+                .unwrap()
+                .into_keys()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(4), vec![1, 2]);
+
+        // 换桶, 解析不了, 数据被覆盖, 都要从原来的桶里拿掉
+        archive.set_features(1, stored(Some(features(7)))).unwrap();
+        assert_eq!((ids(4), ids(7)), (vec![2], vec![1]));
+        archive.set_features(1, stored(None)).unwrap();
+        assert!(ids(7).is_empty());
+        assert!(!archive.features_bucket_path(7).exists());
+        archive
+            .save(2, SaveType::Ship, "<Ship />", CoverStrategy::Cover, Utc::now())
+            .unwrap();
+        assert!(ids(4).is_empty());
+        // 桶的目录不能被当成分片
+        assert_eq!(archive.ids_after(0, 10).unwrap(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,241 @@
+//! 规范 hash 的存取
+//!
+//! 规范形式由 [`crate::xml_part::canonical`] 算, 这里只管存进 `canonical_hash` 表 (文件存档是写进 `.meta.json`)
+//! 以及按 hash 找出同一个设计的所有记录
+//!
+//! 跟可疑分数一样, 数据被覆盖的时候旧 hash 会删掉, 再跑一遍 `canonical` 就补上了
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::{Level, event};
+
+use crate::{
+    db_part::{DbData, DbPool, SaveId, SaveType, search},
+    xml_part::canonical::canonical_document_hash,
+};
+
+const BATCH_SIZE: i64 = 500;
+
+/// 存下来的规范 hash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredCanonical {
+    /// 解析不了 / 存档里没有船的是 None
+    pub hash: Option<String>,
+    pub computed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CanonicalStats {
+    pub hashed: usize,
+    /// 解析失败的
+    pub unparsed: usize,
+    /// 已经有 hash 跳过的
+    pub skipped: usize,
+}
+
+#[derive(Debug, FromRow)]
+struct CanonicalRow {
+    hash: Option<String>,
+    computed_at: DateTime<Utc>,
+}
+
+impl From<CanonicalRow> for StoredCanonical {
+    fn from(row: CanonicalRow) -> Self {
+        Self {
+            hash: row.hash,
+            computed_at: row.computed_at,
+        }
+    }
+}
+
+/// 算一条记录的规范 hash, 不是船也不是存档的返回 None
+pub fn canonical_data(data: &DbData) -> Option<StoredCanonical> {
+    if !matches!(data.save_type, SaveType::Ship | SaveType::Save) {
+        return None;
+    }
+    let hash = data
+        .parse_xml()
+        .ok()
+        .and_then(|doc| canonical_document_hash(&doc))
+        .and_then(Result::ok);
+    Some(StoredCanonical {
+        hash,
+        computed_at: Utc::now(),
+    })
+}
+
+pub async fn store_canonical(
+    db: &DbPool,
+    save_id: SaveId,
+    canonical: StoredCanonical,
+) -> anyhow::Result<()> {
+    with_pool!(db, pool => {
+        sqlx::query(
+            "INSERT INTO canonical_hash (save_id, hash, computed_at)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (save_id) DO UPDATE
+             SET hash = excluded.hash, computed_at = excluded.computed_at",
+        )
+        .bind(save_id as i32)
+        .bind(canonical.hash.as_deref())
+        .bind(canonical.computed_at)
+        .execute(pool)
+        .await?;
+    }, archive => archive.set_canonical(save_id, canonical.clone())?);
+    Ok(())
+}
+
+pub async fn load_canonical(db: &DbPool, save_id: SaveId) -> Option<StoredCanonical> {
+    with_pool!(db, pool => {
+        sqlx::query_as::<_, CanonicalRow>(
+            "SELECT hash, computed_at
+             FROM canonical_hash
+             WHERE save_id = $1",
+        )
+        .bind(save_id as i32)
+        .fetch_optional(pool)
+        .await
+        .ok()?
+        .map(Into::into)
+    }, archive => archive.read_meta(save_id).ok()??.canonical)
+}
+
+/// 规范 hash 是 `hash` 的记录, 从 `after_id` 往后按 save_id 排
+///
+/// 文件存档没有索引, 只能一条条翻过去
+pub async fn records_with_hash(
+    db: &DbPool,
+    hash: &str,
+    after_id: SaveId,
+    limit: i64,
+) -> anyhow::Result<Vec<SaveId>> {
+    Ok(with_pool!(db, pool => {
+        sqlx::query_scalar::<_, i32>(
+            "SELECT save_id
+             FROM canonical_hash
+             WHERE hash = $1 AND save_id > $2
+             ORDER BY save_id
+             LIMIT $3",
+        )
+        .bind(hash)
+        .bind(after_id.min(i32::MAX as SaveId) as i32)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|id| id as SaveId)
+        .collect()
+    }, archive => {
+        let mut found = Vec::new();
+        let mut cursor = after_id;
+        while found.len() < limit.max(0) as usize {
+            let ids = archive.ids_after(cursor, BATCH_SIZE as usize)?;
+            let Some(last) = ids.last() else {
+                break;
+            };
+            cursor = *last;
+            for id in ids {
+                let same = archive
+                    .read_meta(id)?
+                    .and_then(|meta| meta.canonical)
+                    .is_some_and(|canonical| canonical.hash.as_deref() == Some(hash));
+                if same {
+                    found.push(id);
+                    if found.len() >= limit.max(0) as usize {
+                        break;
+                    }
+                }
+            }
+        }
+        found
+    }))
+}
+
+/// 给库里的船和存档算规范 hash, `rehash` 为 false 的时候跳过已经算过的
+pub async fn hash_records(db: &DbPool, rehash: bool) -> anyhow::Result<CanonicalStats> {
+    let mut stats = CanonicalStats::default();
+    let mut after_id = 0;
+    loop {
+        let records = search::record_batch(db, after_id, SaveId::MAX, None, BATCH_SIZE).await?;
+        let Some(last) = records.last() else {
+            break;
+        };
+        after_id = last.data.save_id;
+
+        for record in records {
+            let save_id = record.data.save_id;
+            if !rehash && load_canonical(db, save_id).await.is_some() {
+                stats.skipped += 1;
+                continue;
+            }
+            let Some(canonical) = canonical_data(&record.data) else {
+                continue;
+            };
+            match canonical.hash {
+                Some(_) => stats.hashed += 1,
+                None => stats.unparsed += 1,
+            }
+            store_canonical(db, save_id, canonical).await?;
+        }
+        event!(Level::INFO, "规范 hash 已经算到 {}", after_id);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_records, load_canonical, records_with_hash};
+    use crate::db_part::{
+        CoverStrategy, DbPool, SaveType, save_data_to_db, test_util::for_each_backend,
+    };
+
+    async fn check_backend(db: DbPool) {
+        // 同一条船换了 id 和位置
+        let moved = crate::net::EMPTY_SHIP
+            .replace(r#"id="1""#, r#"id="5""#)
+            .replace(r#"x="0.000000""#, r#"x="12.5""#);
+        assert_ne!(moved, crate::net::EMPTY_SHIP);
+        for (id, save_type, data) in [
+            (1, SaveType::Ship, crate::net::EMPTY_SHIP.to_string()),
+            (2, SaveType::Ship, moved),
+            (3, SaveType::Ship, "<Ship".to_string()),
+            (4, SaveType::None, String::new()),
+        ] {
+            save_data_to_db(id, save_type, data, None, &db)
+                .await
+                .unwrap();
+        }
+
+        let stats = hash_records(&db, false).await.unwrap();
+        assert_eq!((stats.hashed, stats.unparsed), (2, 1));
+        let hash = load_canonical(&db, 1).await.unwrap().hash.unwrap();
+        assert_eq!(
+            load_canonical(&db, 2).await.unwrap().hash,
+            Some(hash.clone())
+        );
+        assert_eq!(load_canonical(&db, 3).await.unwrap().hash, None);
+        assert!(load_canonical(&db, 4).await.is_none());
+
+        assert_eq!(
+            records_with_hash(&db, &hash, 0, 10).await.unwrap(),
+            vec![1, 2]
+        );
+        assert_eq!(records_with_hash(&db, &hash, 1, 10).await.unwrap(), vec![2]);
+        assert_eq!(records_with_hash(&db, &hash, 0, 1).await.unwrap(), vec![1]);
+
+        let again = hash_records(&db, false).await.unwrap();
+        assert_eq!(again.skipped, 3);
+
+        // 覆盖之后 hash 就没了
+        save_data_to_db(2, SaveType::Ship, "<Ship", Some(CoverStrategy::Cover), &db)
+            .await
+            .unwrap();
+        assert!(load_canonical(&db, 2).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn hashes_and_groups() {
+        for_each_backend(check_backend).await;
+    }
+}
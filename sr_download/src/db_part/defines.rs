@@ -9,6 +9,12 @@ pub mod db_names {
     pub const FULL_DATA_TABLE: &str = "full_data";
     /// 用于存储 db 版本号的表
     pub const DB_VERSION_TABLE: &str = "db_version";
+    /// 可疑分数表
+    pub const PLAUSIBILITY_TABLE: &str = "plausibility";
+    /// 规范 hash 表
+    pub const CANONICAL_HASH_TABLE: &str = "canonical_hash";
+    /// 相似船查询用的特征表
+    pub const SHIP_FEATURES_TABLE: &str = "ship_features";
     /// 老的 sea_orm 的标记表
     pub const SEA_ORM_TABLE: &str = "seaql_migrations";
 
@@ -28,7 +34,10 @@ pub mod db_names {
 ///    - `long_data` 表
 ///    - `full_data` 视图
 ///    - `ships` 表
-pub const CURRENT_DB_VERSION: i32 = 2;
+/// 3. 加了 `plausibility` 表, 存每条记录的可疑分数
+/// 4. 加了 `canonical_hash` 表, 存每条记录的规范 hash
+/// 5. 加了 `ship_features` 表, 存查相似船用的特征
+pub const CURRENT_DB_VERSION: i32 = 5;
 
 pub const TEXT_DATA_MAX_LEN: usize = 1024;
 pub type SaveId = u32;
@@ -109,6 +118,62 @@ CREATE INDEX IF NOT EXISTS idx_main_data_hash_covering
 ON main_data (blake_hash) INCLUDE (save_id, save_type, len)
 "#;
 
+/// `score` 是 NULL 的就是解析不了, 没法打分
+pub const CREATE_PLAUSIBILITY_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS plausibility (
+    save_id integer PRIMARY KEY,
+    score integer,
+    flags integer NOT NULL,
+    checked_at timestamp with time zone NOT NULL,
+    CONSTRAINT save_id FOREIGN KEY (save_id)
+        REFERENCES main_data(save_id)
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+"#;
+pub const CREATE_PLAUSIBILITY_SCORE_INDEX_SQL: &str = r#"
+CREATE INDEX IF NOT EXISTS plausibility_score_idx
+ON plausibility (score, save_id)
+"#;
+
+/// `hash` 是 NULL 的就是解析不了 / 没有船
+pub const CREATE_CANONICAL_HASH_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS canonical_hash (
+    save_id integer PRIMARY KEY,
+    hash character(64),
+    computed_at timestamp with time zone NOT NULL,
+    CONSTRAINT save_id FOREIGN KEY (save_id)
+        REFERENCES main_data(save_id)
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+"#;
+pub const CREATE_CANONICAL_HASH_INDEX_SQL: &str = r#"
+CREATE INDEX IF NOT EXISTS canonical_hash_hash_idx
+ON canonical_hash (hash, save_id)
+"#;
+
+/// 特征都是 NULL 的就是解析不了 / 没有船, `histogram` 是 `零件类型 -> 数量` 的 json
+pub const CREATE_SHIP_FEATURES_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS ship_features (
+    save_id integer PRIMARY KEY,
+    part_count integer,
+    mass_class integer,
+    stage_count integer,
+    histogram text,
+    computed_at timestamp with time zone NOT NULL,
+    CONSTRAINT save_id FOREIGN KEY (save_id)
+        REFERENCES main_data(save_id)
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+"#;
+/// 查相似船的时候按零件数范围和质量档位筛候选
+pub const CREATE_SHIP_FEATURES_INDEX_SQL: &str = r#"
+CREATE INDEX IF NOT EXISTS ship_features_count_mass_idx
+ON ship_features (part_count, mass_class, save_id)
+"#;
+
 pub fn quote_ident(input: &str) -> String {
     format!("\"{}\"", input.replace('"', "\"\""))
 }
@@ -0,0 +1,364 @@
+//! 把本地的一堆 xml 文件导进库里
+//!
+//! 游戏导出的和以前的工具留下来的文件都是散的, 只能靠文件名或者一个 csv 对上 save_id
+//! - 文件名: 取文件名里的第一串数字, 比如 `12345.xml` / `ship_12345.xml`
+//! - csv: 每行 `文件名,save_id`, 文件名可以是相对目录的路径也可以只写文件名, 表头会被跳过
+//!
+//! 类型靠 `parse_any_xml` 判断, 解析不了的文件不会写进去
+//!
+//! 跟库里已有的记录查重靠 hash: 数据库有索引, 每个文件查一次;
+//! 文件存档没有, 开始之前把所有元数据扫一遍建一个 `hash -> save_id` 的表, 导入的时候跟着改
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use blake3::Hasher;
+use colored::Colorize;
+use tracing::{Level, event};
+
+use crate::db_part::{
+    CoverStrategy, DbPool, DbStore, FsArchive, SaveId, SaveType, save_data_to_db, search,
+};
+use crate::xml_part::parse::parse_any_xml;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirImportStats {
+    /// 写进去了的
+    pub saved: usize,
+    /// 按 `CoverStrategy` 跳过的
+    pub skipped: usize,
+    /// 对不上 save_id 的
+    pub no_id: usize,
+    /// 读不出来或者解析失败的
+    pub parse_failed: usize,
+    /// 别的 save_id 已经有一样 hash 的
+    pub duplicates: usize,
+}
+
+/// 库里已有记录的 hash
+enum KnownHashes {
+    /// 数据库, 直接查
+    Query,
+    /// 文件存档, hash -> 有这个 hash 的 save_id
+    Archive {
+        archive: FsArchive,
+        hashes: HashMap<String, BTreeSet<SaveId>>,
+    },
+}
+
+impl KnownHashes {
+    /// 文件存档的话把所有元数据扫一遍
+    fn load(db: &DbPool) -> anyhow::Result<Self> {
+        let DbStore::Archive(archive) = &db.store else {
+            return Ok(Self::Query);
+        };
+        let mut hashes: HashMap<String, BTreeSet<SaveId>> = HashMap::new();
+        let mut after_id = 0;
+        loop {
+            let ids = archive.ids_after(after_id, 1024)?;
+            let Some(last) = ids.last() else {
+                break;
+            };
+            after_id = *last;
+            for id in ids {
+                if let Some(meta) = archive.read_meta(id)? {
+                    hashes.entry(meta.blake_hash).or_default().insert(id);
+                }
+            }
+        }
+        Ok(Self::Archive {
+            archive: archive.clone(),
+            hashes,
+        })
+    }
+
+    /// 有这个 hash 的记录里 id 最小的, 跟 [`search::find_by_hash`] 一样
+    async fn lowest(&self, db: &DbPool, hash: &str) -> anyhow::Result<Option<SaveId>> {
+        match self {
+            Self::Query => search::find_by_hash(db, hash).await,
+            Self::Archive { hashes, .. } => {
+                Ok(hashes.get(hash).and_then(|ids| ids.first().copied()))
+            }
+        }
+    }
+
+    /// `save_id` 现在的 hash, 写之前记下来, 写完了好从表里挪走
+    fn current(&self, save_id: SaveId) -> anyhow::Result<Option<String>> {
+        match self {
+            Self::Query => Ok(None),
+            Self::Archive { archive, .. } => {
+                Ok(archive.read_meta(save_id)?.map(|meta| meta.blake_hash))
+            }
+        }
+    }
+
+    /// `save_id` 的内容从 `old` 换成了 `hash`
+    fn replaced(&mut self, save_id: SaveId, old: Option<String>, hash: &str) {
+        let Self::Archive { hashes, .. } = self else {
+            return;
+        };
+        if let Some(old) = old
+            && let Some(ids) = hashes.get_mut(&old)
+        {
+            ids.remove(&save_id);
+            if ids.is_empty() {
+                hashes.remove(&old);
+            }
+        }
+        hashes.entry(hash.to_string()).or_default().insert(save_id);
+    }
+}
+
+/// 读 `文件名,save_id` 格式的 csv
+pub fn read_id_map(path: &Path) -> anyhow::Result<HashMap<String, SaveId>> {
+    let mut map = HashMap::new();
+    for (line_no, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((name, save_id)) = line.rsplit_once(',') else {
+            return Err(anyhow::anyhow!(
+                "{}:{} 格式不对: {}",
+                path.display(),
+                line_no + 1,
+                line
+            ));
+        };
+        match save_id.trim().parse::<SaveId>() {
+            Ok(save_id) => {
+                map.insert(name.trim().trim_matches('"').to_string(), save_id);
+            }
+            // 表头
+            Err(_) if line_no == 0 => continue,
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "{}:{} save_id 不对: {}",
+                    path.display(),
+                    line_no + 1,
+                    e
+                ));
+            }
+        }
+    }
+    Ok(map)
+}
+
+/// 取文件名里的第一串数字
+fn id_from_file_name(path: &Path) -> Option<SaveId> {
+    let stem = path.file_name()?.to_str()?;
+    let start = stem.find(|c: char| c.is_ascii_digit())?;
+    let digits = &stem[start..];
+    let end = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    digits[..end].parse().ok()
+}
+
+/// 目录下所有的 xml 文件, 排好序
+fn xml_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("xml"))
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// 导入 `dir` 下面所有的 xml 文件
+///
+/// `id_map` 里有的文件优先用 `id_map`, 没有的再看文件名
+pub async fn import_dir(
+    db: &DbPool,
+    dir: &Path,
+    id_map: Option<&HashMap<String, SaveId>>,
+    cover_strategy: CoverStrategy,
+) -> anyhow::Result<DirImportStats> {
+    let mut stats = DirImportStats::default();
+    // 这一批里自己的重复
+    let mut seen: HashMap<String, SaveId> = HashMap::new();
+    let mut known = KnownHashes::load(db)?;
+
+    for path in xml_files(dir)? {
+        let relative = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let save_id = id_map
+            .and_then(|map| map.get(&relative).or_else(|| map.get(&file_name)))
+            .copied()
+            .or_else(|| id_from_file_name(&path));
+        let Some(save_id) = save_id else {
+            event!(
+                Level::WARN,
+                "{}",
+                format!("{relative} 对不上 save_id, 跳过").yellow()
+            );
+            stats.no_id += 1;
+            continue;
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                event!(
+                    Level::WARN,
+                    "{}",
+                    format!("{relative} ({save_id}) 读不出来: {e}").red()
+                );
+                stats.parse_failed += 1;
+                continue;
+            }
+        };
+        let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+        let save_type: SaveType = match parse_any_xml(text) {
+            Ok(doc) => (&doc).into(),
+            Err(e) => {
+                event!(
+                    Level::WARN,
+                    "{}",
+                    format!("{relative} ({save_id}) 解析失败: {e}").red()
+                );
+                stats.parse_failed += 1;
+                continue;
+            }
+        };
+
+        let mut hasher = Hasher::new();
+        hasher.update(text.as_bytes());
+        let hash = hasher.finalize().to_hex().to_string();
+        let exist_id = match seen.get(&hash) {
+            Some(exist_id) => Some(*exist_id),
+            None => known.lowest(db, &hash).await?,
+        };
+        if let Some(exist_id) = exist_id
+            && exist_id != save_id
+        {
+            event!(
+                Level::WARN,
+                "{}",
+                format!("{relative} ({save_id}) 和 {exist_id} 内容一样, 跳过").yellow()
+            );
+            stats.duplicates += 1;
+            continue;
+        }
+        let old = known.current(save_id)?;
+        if save_data_to_db(save_id, save_type, text, Some(cover_strategy), db).await? {
+            known.replaced(save_id, old, &hash);
+            event!(
+                Level::INFO,
+                "导入了 {} ({}, {})",
+                relative,
+                save_id,
+                save_type
+            );
+            stats.saved += 1;
+        } else {
+            stats.skipped += 1;
+        }
+        seen.insert(hash, save_id);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{DirImportStats, id_from_file_name, import_dir};
+    use crate::db_part::{
+        CoverStrategy, DbData, DbPool, SaveType, save_data_to_db, test_util::for_each_backend,
+    };
+
+    #[test]
+    fn file_name_ids() {
+        assert_eq!(id_from_file_name("12345.xml".as_ref()), Some(12345));
+        assert_eq!(id_from_file_name("a/ship_42.ship.xml".as_ref()), Some(42));
+        assert_eq!(id_from_file_name("ship.xml".as_ref()), None);
+    }
+
+    async fn check_backend(db: DbPool) {
+        let root = std::env::temp_dir().join(format!(
+            "sr_download_import_dir_{}_{}",
+            db.backend_name(),
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        let ship = crate::net::EMPTY_SHIP;
+        let variant = |x: &str| ship.replace(r#"x="0.000000""#, &format!(r#"x="{x}""#));
+        // 库里本来就有的
+        save_data_to_db(20, SaveType::Ship, variant("1.5"), None, &db)
+            .await
+            .unwrap();
+        save_data_to_db(30, SaveType::Ship, variant("2.5"), None, &db)
+            .await
+            .unwrap();
+
+        let dir = root.join("files");
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("1.xml"), ship).unwrap();
+        std::fs::write(
+            dir.join("nested/save_2.xml"),
+            include_str!("../save_1294489.xml"),
+        )
+        .unwrap();
+        std::fs::write(dir.join("3.xml"), ship).unwrap();
+        std::fs::write(dir.join("4.xml"), "<Ship").unwrap();
+        std::fs::write(dir.join("mystery.xml"), ship).unwrap();
+        // 不是 utf-8 的也只算解析失败, 不能把整个导入停掉
+        std::fs::write(dir.join("5.xml"), b"<Ship name=\"\xff\"/>").unwrap();
+        // 跟库里的 20 一样
+        std::fs::write(dir.join("21.xml"), variant("1.5")).unwrap();
+        // 30 被改掉之后, 原来的内容就不算重复了
+        std::fs::write(dir.join("30.xml"), variant("3.5")).unwrap();
+        std::fs::write(dir.join("31.xml"), variant("2.5")).unwrap();
+
+        let id_map = HashMap::from([("mystery.xml".to_string(), 10)]);
+        let stats = import_dir(&db, &dir, Some(&id_map), CoverStrategy::CoverIfDifferent)
+            .await
+            .unwrap();
+        assert_eq!(
+            stats,
+            DirImportStats {
+                saved: 4,
+                skipped: 0,
+                no_id: 0,
+                parse_failed: 2,
+                duplicates: 3,
+            }
+        );
+        assert_eq!(
+            DbData::from_db(2, &db).await.unwrap().save_type,
+            SaveType::Save
+        );
+        assert!(DbData::from_db(3, &db).await.is_none());
+        assert!(DbData::from_db(21, &db).await.is_none());
+        assert!(DbData::from_db(31, &db).await.is_some());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn imports_directory() {
+        for_each_backend(check_backend).await;
+    }
+}
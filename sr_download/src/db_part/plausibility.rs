@@ -0,0 +1,264 @@
+//! 可疑分数的存取
+//!
+//! 分数本身由 [`crate::xml_part::plausibility`] 算, 这里只管存进 `plausibility` 表 (文件存档是写进 `.meta.json`)
+//! 以及按分数把记录筛出来
+//!
+//! 数据被覆盖的时候旧分数会跟着删掉, 再跑一遍 `score` 就补上了
+
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::{Level, event};
+
+use crate::{
+    db_part::{DbData, DbPool, SaveId, SaveType, search},
+    xml_part::{catalog::PartCatalog, plausibility::check_document},
+};
+
+const BATCH_SIZE: i64 = 500;
+
+/// 存下来的分数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredScore {
+    /// 解析不了的是 None
+    pub score: Option<u32>,
+    /// 一共几个问题
+    pub flags: u32,
+    pub checked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScoreStats {
+    pub scored: usize,
+    pub suspicious: usize,
+    /// 解析失败的
+    pub unparsed: usize,
+    /// 已经有分数跳过的
+    pub skipped: usize,
+}
+
+#[derive(Debug, FromRow)]
+struct ScoreRow {
+    save_id: i32,
+    score: Option<i32>,
+    flags: i32,
+    checked_at: DateTime<Utc>,
+}
+
+impl From<ScoreRow> for StoredScore {
+    fn from(row: ScoreRow) -> Self {
+        Self {
+            score: row.score.map(|score| score.max(0) as u32),
+            flags: row.flags.max(0) as u32,
+            checked_at: row.checked_at,
+        }
+    }
+}
+
+/// 给一条记录打分, 不是船也不是存档的返回 None
+pub fn score_data(data: &DbData, catalog: &PartCatalog) -> Option<StoredScore> {
+    if !matches!(data.save_type, SaveType::Ship | SaveType::Save) {
+        return None;
+    }
+    let checked_at = Utc::now();
+    Some(match data.parse_xml() {
+        Ok(doc) => {
+            let report = check_document(&doc, catalog);
+            StoredScore {
+                score: Some(report.score),
+                flags: report.flags.len() as u32,
+                checked_at,
+            }
+        }
+        Err(_) => StoredScore {
+            score: None,
+            flags: 0,
+            checked_at,
+        },
+    })
+}
+
+pub async fn store_score(db: &DbPool, save_id: SaveId, score: StoredScore) -> anyhow::Result<()> {
+    with_pool!(db, pool => {
+        sqlx::query(
+            "INSERT INTO plausibility (save_id, score, flags, checked_at)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (save_id) DO UPDATE
+             SET score = excluded.score, flags = excluded.flags, checked_at = excluded.checked_at",
+        )
+        .bind(save_id as i32)
+        .bind(score.score.map(|score| score as i32))
+        .bind(score.flags as i32)
+        .bind(score.checked_at)
+        .execute(pool)
+        .await?;
+    }, archive => archive.set_plausibility(save_id, score)?);
+    Ok(())
+}
+
+pub async fn load_score(db: &DbPool, save_id: SaveId) -> Option<StoredScore> {
+    with_pool!(db, pool => {
+        sqlx::query_as::<_, ScoreRow>(
+            "SELECT save_id, score, flags, checked_at
+             FROM plausibility
+             WHERE save_id = $1",
+        )
+        .bind(save_id as i32)
+        .fetch_optional(pool)
+        .await
+        .ok()?
+        .map(Into::into)
+    }, archive => archive.read_meta(save_id).ok()??.plausibility)
+}
+
+/// 分数至少是 `min_score` 的记录, 从 `after_id` 往后按 save_id 排
+pub async fn suspicious_records(
+    db: &DbPool,
+    min_score: u32,
+    after_id: SaveId,
+    limit: i64,
+) -> anyhow::Result<Vec<(SaveId, StoredScore)>> {
+    Ok(with_pool!(db, pool => {
+        sqlx::query_as::<_, ScoreRow>(
+            "SELECT save_id, score, flags, checked_at
+             FROM plausibility
+             WHERE score >= $1 AND save_id > $2
+             ORDER BY save_id
+             LIMIT $3",
+        )
+        .bind(min_score as i32)
+        .bind(after_id.min(i32::MAX as SaveId) as i32)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.save_id as SaveId, row.into()))
+        .collect()
+    }, archive => {
+        let mut found = Vec::new();
+        let mut cursor = after_id;
+        while found.len() < limit.max(0) as usize {
+            let ids = archive.ids_after(cursor, BATCH_SIZE as usize)?;
+            let Some(last) = ids.last() else {
+                break;
+            };
+            cursor = *last;
+            for id in ids {
+                let Some(score) = archive.read_meta(id)?.and_then(|meta| meta.plausibility) else {
+                    continue;
+                };
+                if score.score.is_some_and(|score| score >= min_score) {
+                    found.push((id, score));
+                    if found.len() >= limit.max(0) as usize {
+                        break;
+                    }
+                }
+            }
+        }
+        found
+    }))
+}
+
+/// 给库里的船和存档打分, `rescore` 为 false 的时候跳过已经有分数的
+pub async fn score_records(
+    db: &DbPool,
+    catalog: &PartCatalog,
+    rescore: bool,
+) -> anyhow::Result<ScoreStats> {
+    let mut stats = ScoreStats::default();
+    let mut after_id = 0;
+    loop {
+        let records = search::record_batch(db, after_id, SaveId::MAX, None, BATCH_SIZE).await?;
+        let Some(last) = records.last() else {
+            break;
+        };
+        after_id = last.data.save_id;
+
+        for record in records {
+            let save_id = record.data.save_id;
+            if !rescore && load_score(db, save_id).await.is_some() {
+                stats.skipped += 1;
+                continue;
+            }
+            let Some(score) = score_data(&record.data, catalog) else {
+                continue;
+            };
+            match score.score {
+                Some(value) => {
+                    stats.scored += 1;
+                    if value >= crate::xml_part::plausibility::SUSPICIOUS_SCORE {
+                        stats.suspicious += 1;
+                        event!(
+                            Level::INFO,
+                            "{}",
+                            format!("{save_id} 可疑分数 {value}").yellow()
+                        );
+                    }
+                }
+                None => stats.unparsed += 1,
+            }
+            store_score(db, save_id, score).await?;
+        }
+        event!(Level::INFO, "已经打分到 {}", after_id);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_score, score_records, suspicious_records};
+    use crate::{
+        db_part::{CoverStrategy, DbPool, SaveType, save_data_to_db, test_util::for_each_backend},
+        xml_part::catalog::PartCatalog,
+    };
+
+    async fn check_backend(db: DbPool) {
+        let cheated = crate::net::EMPTY_SHIP.replace(r#"angleV="0.000000""#, r#"angleV="1e9""#);
+        for (id, save_type, data) in [
+            (1, SaveType::Ship, crate::net::EMPTY_SHIP.to_string()),
+            (2, SaveType::Ship, cheated),
+            (3, SaveType::Ship, "<Ship".to_string()),
+            (4, SaveType::None, String::new()),
+        ] {
+            save_data_to_db(id, save_type, data, None, &db)
+                .await
+                .unwrap();
+        }
+
+        let stats = score_records(&db, PartCatalog::embedded(), false)
+            .await
+            .unwrap();
+        assert_eq!((stats.scored, stats.suspicious, stats.unparsed), (2, 1, 1));
+        assert_eq!(load_score(&db, 1).await.unwrap().score, Some(0));
+        assert_eq!(load_score(&db, 3).await.unwrap().score, None);
+        assert!(load_score(&db, 4).await.is_none());
+
+        let found = suspicious_records(&db, 30, 0, 10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, 2);
+        assert!(suspicious_records(&db, 30, 2, 10).await.unwrap().is_empty());
+
+        let again = score_records(&db, PartCatalog::embedded(), false)
+            .await
+            .unwrap();
+        assert_eq!(again.skipped, 3);
+
+        // 覆盖之后分数就没了
+        save_data_to_db(
+            2,
+            SaveType::Ship,
+            crate::net::EMPTY_SHIP,
+            Some(CoverStrategy::Cover),
+            &db,
+        )
+        .await
+        .unwrap();
+        assert!(load_score(&db, 2).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn scores_and_filters() {
+        for_each_backend(check_backend).await;
+    }
+}
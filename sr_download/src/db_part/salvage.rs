@@ -0,0 +1,96 @@
+//! 看看 `not xml` 的记录有多少能救回来
+//!
+//! 用 [`crate::xml_part::recover`] 修一遍, 修完能解析的就算能救; 只是统计, 库里的数据不会改
+
+use tracing::{Level, event};
+
+use crate::db_part::{DbPool, SaveId, SaveType, search};
+
+const BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SalvageStats {
+    /// 不是合法 xml 的船和存档
+    pub not_xml: usize,
+    /// 修完能解析的
+    pub salvaged: usize,
+    /// 修完还是解析不了的
+    pub failed: usize,
+    /// 能救的那些一共修了几处
+    pub repairs: usize,
+}
+
+/// 把库里不是合法 xml 的船和存档都修一遍
+pub async fn salvage_records(db: &DbPool) -> anyhow::Result<SalvageStats> {
+    let mut stats = SalvageStats::default();
+    let mut after_id = 0;
+    loop {
+        let records = search::record_batch(db, after_id, SaveId::MAX, None, BATCH_SIZE).await?;
+        let Some(last) = records.last() else {
+            break;
+        };
+        after_id = last.data.save_id;
+
+        for record in records {
+            let data = record.data;
+            if !matches!(data.save_type, SaveType::Ship | SaveType::Save)
+                || data.text.is_none()
+                || data.verify_xml()
+            {
+                continue;
+            }
+            stats.not_xml += 1;
+            match data.recover_xml() {
+                Ok(recovered) => {
+                    stats.salvaged += 1;
+                    stats.repairs += recovered.repairs.len();
+                    event!(
+                        Level::DEBUG,
+                        "id: {} 修了 {} 处之后能解析了",
+                        data.save_id,
+                        recovered.repairs.len()
+                    );
+                }
+                Err(e) => {
+                    stats.failed += 1;
+                    event!(Level::DEBUG, "id: {} 救不回来: {}", data.save_id, e);
+                }
+            }
+        }
+        event!(Level::INFO, "已经检查到 {}", after_id);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::salvage_records;
+    use crate::db_part::{DbPool, SaveType, save_data_to_db, test_util::for_each_backend};
+
+    async fn check_backend(db: DbPool) {
+        let ship = crate::net::EMPTY_SHIP;
+        // 断在 `<Staging` 中间
+        let truncated = &ship[..ship.find("<Staging").unwrap() + 5];
+        for (id, save_type, data) in [
+            (1, SaveType::Ship, ship),
+            (2, SaveType::Ship, truncated),
+            (3, SaveType::Ship, "<Ship"),
+            (4, SaveType::Save, "not xml at all <"),
+            (5, SaveType::None, ""),
+        ] {
+            save_data_to_db(id, save_type, data, None, &db)
+                .await
+                .unwrap();
+        }
+
+        let stats = salvage_records(&db).await.unwrap();
+        assert_eq!((stats.not_xml, stats.salvaged, stats.failed), (3, 1, 2));
+        // 扔掉半个标签, 关上 Pod / Part / Parts / Ship, 补上 Staging 和 Connections
+        assert_eq!(stats.repairs, 7);
+    }
+
+    #[tokio::test]
+    async fn counts_salvageable() {
+        for_each_backend(check_backend).await;
+    }
+}
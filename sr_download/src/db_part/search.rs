@@ -1,96 +1,319 @@
-use sqlx::{PgPool, Row};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, Row};
 use tracing::{Level, event};
 
 use crate::db_part::SaveType;
 use crate::db_part::defines::SaveId;
 
-use super::DbData;
+use super::{DbData, DbPool};
 
 /// 找到最大的数据的 id
-pub async fn max_id(db: &PgPool) -> SaveId {
-    let data = sqlx::query(
-        "SELECT save_id
-         FROM main_data
-         WHERE len > 0 AND save_type != $1
-         ORDER BY save_id DESC
-         LIMIT 1",
-    )
-    .bind(SaveType::None)
-    .fetch_optional(db)
-    .await;
-    match data {
-        Ok(Some(row)) => row
-            .try_get::<i32, _>("save_id")
-            .map(|v| v as SaveId)
-            .unwrap_or(0),
-        Ok(None) => 0,
-        Err(e) => {
-            event!(Level::WARN, "Error when find_max_id: {:?}", e);
-            0
+pub async fn max_id(db: &DbPool) -> SaveId {
+    with_pool!(db, pool => {
+        let data = sqlx::query(
+            "SELECT save_id
+             FROM main_data
+             WHERE len > 0 AND save_type != $1
+             ORDER BY save_id DESC
+             LIMIT 1",
+        )
+        .bind(SaveType::None)
+        .fetch_optional(pool)
+        .await;
+        match data {
+            Ok(Some(row)) => row
+                .try_get::<i32, _>("save_id")
+                .map(|v| v as SaveId)
+                .unwrap_or(0),
+            Ok(None) => 0,
+            Err(e) => {
+                event!(Level::WARN, "Error when find_max_id: {:?}", e);
+                0
+            }
         }
-    }
+    }, archive => {
+        match archive.find_last(|meta| meta.len > 0 && meta.save_type != SaveType::None) {
+            Ok(meta) => meta.map(|meta| meta.save_id).unwrap_or(0),
+            Err(e) => {
+                event!(Level::WARN, "Error when find_max_id: {:?}", e);
+                0
+            }
+        }
+    })
 }
 
 /// 找到最大的存档
-pub async fn max_save(db: &PgPool) -> Option<DbData> {
-    let data = sqlx::query(
-        "SELECT save_id, save_type, blake_hash, len, short_data, xml_tested
-         FROM main_data
-         WHERE save_type = $1
-         ORDER BY save_id DESC
-         LIMIT 1",
-    )
-    .bind(SaveType::Save)
-    .fetch_optional(db)
-    .await;
-    match data {
-        Ok(Some(row)) => Some(DbData {
-            text: row.try_get("short_data").ok()?,
-            save_id: row.try_get::<i32, _>("save_id").ok()? as SaveId,
-            save_type: row.try_get("save_type").ok()?,
-            len: row.try_get("len").ok()?,
-            blake_hash: row.try_get("blake_hash").ok()?,
-            xml_tested: row
-                .try_get::<Option<bool>, _>("xml_tested")
-                .ok()?
-                .unwrap_or(false),
-        }),
-        Ok(None) => None,
-        Err(e) => {
-            event!(Level::WARN, "Error when find_max_save: {:?}", e);
-            None
+pub async fn max_save(db: &DbPool) -> Option<DbData> {
+    with_pool!(db, pool => {
+        let data = sqlx::query(
+            "SELECT save_id, save_type, blake_hash, len, short_data, xml_tested
+             FROM main_data
+             WHERE save_type = $1
+             ORDER BY save_id DESC
+             LIMIT 1",
+        )
+        .bind(SaveType::Save)
+        .fetch_optional(pool)
+        .await;
+        match data {
+            Ok(Some(row)) => Some(DbData {
+                text: row.try_get("short_data").ok()?,
+                save_id: row.try_get::<i32, _>("save_id").ok()? as SaveId,
+                save_type: row.try_get("save_type").ok()?,
+                len: row.try_get("len").ok()?,
+                blake_hash: row.try_get("blake_hash").ok()?,
+                xml_tested: row
+                    .try_get::<Option<bool>, _>("xml_tested")
+                    .ok()?
+                    .unwrap_or(false),
+            }),
+            Ok(None) => None,
+            Err(e) => {
+                event!(Level::WARN, "Error when find_max_save: {:?}", e);
+                None
+            }
         }
-    }
+    }, archive => {
+        match archive.find_last(|meta| meta.save_type == SaveType::Save) {
+            Ok(meta) => archive.load(meta?.save_id).ok().flatten(),
+            Err(e) => {
+                event!(Level::WARN, "Error when find_max_save: {:?}", e);
+                None
+            }
+        }
+    })
 }
 
 /// 找到最大的飞船
-pub async fn max_ship(db: &PgPool) -> Option<DbData> {
-    let data = sqlx::query(
-        "SELECT save_id, save_type, blake_hash, len, short_data, xml_tested
-         FROM main_data
-         WHERE save_type = $1
-         ORDER BY save_id DESC
-         LIMIT 1",
-    )
-    .bind(SaveType::Ship)
-    .fetch_optional(db)
-    .await;
-    match data {
-        Ok(Some(row)) => Some(DbData {
-            text: row.try_get("short_data").ok()?,
-            save_id: row.try_get::<i32, _>("save_id").ok()? as SaveId,
-            save_type: row.try_get("save_type").ok()?,
-            len: row.try_get("len").ok()?,
-            blake_hash: row.try_get("blake_hash").ok()?,
-            xml_tested: row
-                .try_get::<Option<bool>, _>("xml_tested")
-                .ok()?
-                .unwrap_or(false),
-        }),
-        Ok(None) => None,
-        Err(e) => {
-            event!(Level::WARN, "Error when find_max_ship: {:?}", e);
-            None
+pub async fn max_ship(db: &DbPool) -> Option<DbData> {
+    with_pool!(db, pool => {
+        let data = sqlx::query(
+            "SELECT save_id, save_type, blake_hash, len, short_data, xml_tested
+             FROM main_data
+             WHERE save_type = $1
+             ORDER BY save_id DESC
+             LIMIT 1",
+        )
+        .bind(SaveType::Ship)
+        .fetch_optional(pool)
+        .await;
+        match data {
+            Ok(Some(row)) => Some(DbData {
+                text: row.try_get("short_data").ok()?,
+                save_id: row.try_get::<i32, _>("save_id").ok()? as SaveId,
+                save_type: row.try_get("save_type").ok()?,
+                len: row.try_get("len").ok()?,
+                blake_hash: row.try_get("blake_hash").ok()?,
+                xml_tested: row
+                    .try_get::<Option<bool>, _>("xml_tested")
+                    .ok()?
+                    .unwrap_or(false),
+            }),
+            Ok(None) => None,
+            Err(e) => {
+                event!(Level::WARN, "Error when find_max_ship: {:?}", e);
+                None
+            }
+        }
+    }, archive => {
+        match archive.find_last(|meta| meta.save_type == SaveType::Ship) {
+            Ok(meta) => archive.load(meta?.save_id).ok().flatten(),
+            Err(e) => {
+                event!(Level::WARN, "Error when find_max_ship: {:?}", e);
+                None
+            }
+        }
+    })
+}
+
+/// 数据总条数
+pub async fn count_data(db: &DbPool) -> anyhow::Result<i64> {
+    Ok(with_pool!(db, pool => {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM full_data")
+            .fetch_one(pool)
+            .await?
+    }, archive => archive.count()? as i64))
+}
+
+/// 按 save_id 顺序分批读取完整数据
+///
+/// 从 `after_id` 的下一条开始, 最多 `limit` 条
+pub async fn data_batch(
+    db: &DbPool,
+    after_id: SaveId,
+    limit: i64,
+) -> anyhow::Result<Vec<(SaveId, Option<String>)>> {
+    let rows = with_pool!(db, pool => {
+        sqlx::query_as::<_, (i32, Option<String>)>(
+            "SELECT save_id, data
+             FROM full_data
+             WHERE save_id > $1
+             ORDER BY save_id
+             LIMIT $2",
+        )
+        .bind(after_id as i32)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?
+    }, archive => {
+        let mut rows = Vec::new();
+        for id in archive.ids_after(after_id, limit.max(0) as usize)? {
+            let text = archive.load(id)?.and_then(|data| data.text);
+            rows.push((id as i32, text));
+        }
+        rows
+    });
+    Ok(rows
+        .into_iter()
+        .map(|(save_id, data)| (save_id as SaveId, data))
+        .collect())
+}
+
+/// 找一条 hash 相同的记录
+///
+/// 有好几条的话返回 id 最小的那条, 几种后端一样
+///
+/// 文件存档没有 hash 索引, 只能从前往后一条条翻
+pub async fn find_by_hash(db: &DbPool, blake_hash: &str) -> anyhow::Result<Option<SaveId>> {
+    Ok(with_pool!(db, pool => {
+        sqlx::query_scalar::<_, i32>(
+            "SELECT save_id
+             FROM main_data
+             WHERE blake_hash = $1
+             ORDER BY save_id
+             LIMIT 1",
+        )
+        .bind(blake_hash)
+        .fetch_optional(pool)
+        .await?
+        .map(|save_id| save_id as SaveId)
+    }, archive => {
+        archive
+            .find_first(|meta| meta.blake_hash == blake_hash)?
+            .map(|meta| meta.save_id)
+    }))
+}
+
+/// 带时间的完整记录, 导出的时候用
+#[derive(Debug, Clone)]
+pub struct DbRecord {
+    pub data: DbData,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+struct RecordRow {
+    save_id: i32,
+    save_type: SaveType,
+    blake_hash: String,
+    len: i64,
+    xml_tested: Option<bool>,
+    time: DateTime<Utc>,
+    data: Option<String>,
+}
+
+impl From<RecordRow> for DbRecord {
+    fn from(row: RecordRow) -> Self {
+        Self {
+            data: DbData {
+                text: row.data,
+                save_id: row.save_id as SaveId,
+                save_type: row.save_type,
+                len: row.len,
+                blake_hash: row.blake_hash,
+                xml_tested: row.xml_tested.unwrap_or(false),
+            },
+            time: row.time,
+        }
+    }
+}
+
+/// 按 save_id 顺序分批读取 `(after_id, end_id]` 之间的记录
+///
+/// `save_type` 为 None 的时候不过滤类型
+pub async fn record_batch(
+    db: &DbPool,
+    after_id: SaveId,
+    end_id: SaveId,
+    save_type: Option<SaveType>,
+    limit: i64,
+) -> anyhow::Result<Vec<DbRecord>> {
+    Ok(with_pool!(db, pool => {
+        sqlx::query_as::<_, RecordRow>(
+            "SELECT md.save_id, md.save_type, md.blake_hash, md.len, md.xml_tested, md.time, fd.data
+             FROM main_data md
+             JOIN full_data fd ON md.save_id = fd.save_id
+             WHERE md.save_id > $1 AND md.save_id <= $2
+               AND ($3 IS NULL OR md.save_type = $3)
+             ORDER BY md.save_id
+             LIMIT $4",
+        )
+        .bind(after_id.min(i32::MAX as SaveId) as i32)
+        .bind(end_id.min(i32::MAX as SaveId) as i32)
+        .bind(save_type)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect()
+    }, archive => {
+        let mut records = Vec::new();
+        let mut cursor = after_id;
+        while records.len() < limit.max(0) as usize {
+            let ids = archive.ids_after(cursor, limit.max(0) as usize)?;
+            let Some(last) = ids.last() else {
+                break;
+            };
+            cursor = *last;
+            for id in ids {
+                if id > end_id || records.len() >= limit.max(0) as usize {
+                    return Ok(records);
+                }
+                let Some(meta) = archive.read_meta(id)? else {
+                    continue;
+                };
+                if save_type.is_some_and(|save_type| save_type != meta.save_type) {
+                    continue;
+                }
+                let time = meta.updated_at;
+                let Some(data) = archive.load(id)? else {
+                    continue;
+                };
+                records.push(DbRecord { data, time });
+            }
+        }
+        records
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::find_by_hash;
+    use crate::db_part::{CoverStrategy, DbData, DbPool, DbStore, FsArchive, SaveType};
+
+    #[tokio::test]
+    async fn hash_lookup_returns_lowest_id() {
+        let root =
+            std::env::temp_dir().join(format!("sr_download_search_hash_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let archive = FsArchive::open(&root).unwrap();
+        for id in [70000, 5, 300] {
+            archive
+                .save(
+                    id,
+                    SaveType::Ship,
+                    crate::net::EMPTY_SHIP,
+                    CoverStrategy::Cover,
+                    Utc::now(),
+                )
+                .unwrap();
         }
+        let hash = DbData::new(1, crate::net::EMPTY_SHIP.to_string(), SaveType::Ship).blake_hash;
+        let db = DbPool::new(DbStore::Archive(archive));
+        assert_eq!(find_by_hash(&db, &hash).await.unwrap(), Some(5));
+        assert_eq!(find_by_hash(&db, "nope").await.unwrap(), None);
     }
 }
@@ -0,0 +1,413 @@
+//! 船的特征的存取和相似船查询
+//!
+//! 特征由 [`crate::xml_part::similarity`] 算, 存进 `ship_features` 表
+//! (文件存档是写进 `.meta.json`, 再按零件数分桶记一份索引, 见 [`FsArchive::features_bucket`])
+//!
+//! 查相似的船分两步:
+//! 1. 顺着 `(part_count, mass_class, save_id)` 上的索引, 把零件数在范围内、质量差不多的记录一页一页捞出来
+//! 2. 每一页在 Rust 里一条条算相似度, 只留到目前为止最像的几条
+//!
+//! 零件数范围是按 [`MIN_SIMILARITY`] 算的, 超出范围的不可能够相似, 所以几百万条记录也只用扫一小块,
+//! 范围里的记录一条都不会漏, 内存里也只有一页加上留下来的那几条
+//! 文件存档是一个零件数一个桶, 一个桶就是一页, 只读范围里的那几个桶
+//!
+//! [`FsArchive::features_bucket`]: crate::db_part::FsArchive::features_bucket
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::{Level, event};
+
+use crate::{
+    db_part::{DbData, DbPool, SaveId, SaveType, search},
+    xml_part::{
+        catalog::PartCatalog,
+        similarity::{ShipFeatures, document_features, part_count_range},
+    },
+};
+
+const BATCH_SIZE: i64 = 500;
+/// 相似度低于这个的不要
+pub const MIN_SIMILARITY: f64 = 0.5;
+/// 质量档位最多差几档
+pub const MASS_CLASS_TOLERANCE: i32 = 1;
+
+/// 存下来的特征
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredFeatures {
+    /// 解析不了 / 存档里没有船的是 None
+    pub features: Option<ShipFeatures>,
+    pub computed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeatureStats {
+    pub computed: usize,
+    /// 解析失败的
+    pub unparsed: usize,
+    /// 已经有特征跳过的
+    pub skipped: usize,
+}
+
+/// 一条相似的记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimilarRecord {
+    pub save_id: SaveId,
+    pub similarity: f64,
+    pub features: ShipFeatures,
+}
+
+#[derive(Debug, FromRow)]
+struct FeatureRow {
+    save_id: i32,
+    part_count: Option<i32>,
+    mass_class: Option<i32>,
+    stage_count: Option<i32>,
+    histogram: Option<String>,
+    computed_at: DateTime<Utc>,
+}
+
+impl FeatureRow {
+    /// 直方图是坏的 json 的话当成没有特征
+    fn features(&self) -> Option<ShipFeatures> {
+        let histogram: BTreeMap<String, u32> =
+            serde_json::from_str(self.histogram.as_deref()?).ok()?;
+        Some(ShipFeatures {
+            part_count: self.part_count?.max(0) as u32,
+            mass_class: self.mass_class?,
+            stage_count: self.stage_count?.max(0) as u32,
+            histogram,
+        })
+    }
+}
+
+impl From<FeatureRow> for StoredFeatures {
+    fn from(row: FeatureRow) -> Self {
+        Self {
+            features: row.features(),
+            computed_at: row.computed_at,
+        }
+    }
+}
+
+/// 算一条记录的特征, 不是船也不是存档的返回 None
+pub fn feature_data(data: &DbData, catalog: &PartCatalog) -> Option<StoredFeatures> {
+    if !matches!(data.save_type, SaveType::Ship | SaveType::Save) {
+        return None;
+    }
+    let features = data
+        .parse_xml()
+        .ok()
+        .and_then(|doc| document_features(&doc, catalog));
+    Some(StoredFeatures {
+        features,
+        computed_at: Utc::now(),
+    })
+}
+
+pub async fn store_features(
+    db: &DbPool,
+    save_id: SaveId,
+    stored: StoredFeatures,
+) -> anyhow::Result<()> {
+    let features = stored.features.as_ref();
+    let histogram = features
+        .map(|features| serde_json::to_string(&features.histogram))
+        .transpose()?;
+    with_pool!(db, pool => {
+        sqlx::query(
+            "INSERT INTO ship_features
+             (save_id, part_count, mass_class, stage_count, histogram, computed_at)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (save_id) DO UPDATE
+             SET part_count = excluded.part_count, mass_class = excluded.mass_class,
+                 stage_count = excluded.stage_count, histogram = excluded.histogram,
+                 computed_at = excluded.computed_at",
+        )
+        .bind(save_id as i32)
+        .bind(features.map(|features| features.part_count.min(i32::MAX as u32) as i32))
+        .bind(features.map(|features| features.mass_class))
+        .bind(features.map(|features| features.stage_count.min(i32::MAX as u32) as i32))
+        .bind(histogram.as_deref())
+        .bind(stored.computed_at)
+        .execute(pool)
+        .await?;
+    }, archive => archive.set_features(save_id, stored.clone())?);
+    Ok(())
+}
+
+pub async fn load_features(db: &DbPool, save_id: SaveId) -> Option<StoredFeatures> {
+    with_pool!(db, pool => {
+        sqlx::query_as::<_, FeatureRow>(
+            "SELECT save_id, part_count, mass_class, stage_count, histogram, computed_at
+             FROM ship_features
+             WHERE save_id = $1",
+        )
+        .bind(save_id as i32)
+        .fetch_optional(pool)
+        .await
+        .ok()?
+        .map(Into::into)
+    }, archive => archive.read_meta(save_id).ok()??.features)
+}
+
+/// 跟 `features` 最像的 `limit` 条记录, 不包括 `exclude`
+///
+/// 相似度从高到低, 一样的话质量档位和分级数差得少的在前, 再一样就按 save_id
+pub async fn similar_records(
+    db: &DbPool,
+    features: &ShipFeatures,
+    exclude: SaveId,
+    limit: usize,
+) -> anyhow::Result<Vec<SimilarRecord>> {
+    let (part_low, part_high) = part_count_range(features.part_count, MIN_SIMILARITY);
+    let (low, high) = (
+        part_low.min(i32::MAX as u32) as i32,
+        part_high.min(i32::MAX as u32) as i32,
+    );
+    let mass_low = features.mass_class.saturating_sub(MASS_CLASS_TOLERANCE);
+    let mass_high = features.mass_class.saturating_add(MASS_CLASS_TOLERANCE);
+    let mut best = Vec::new();
+    with_pool!(db, pool => {
+        // 上一页最后一条的 (part_count, mass_class, save_id)
+        let mut cursor = (low, i32::MIN, i32::MIN);
+        loop {
+            let rows = sqlx::query_as::<_, FeatureRow>(
+                "SELECT save_id, part_count, mass_class, stage_count, histogram, computed_at
+                 FROM ship_features
+                 WHERE (part_count, mass_class, save_id) > ($1, $2, $3)
+                   AND part_count <= $4
+                   AND mass_class BETWEEN $5 AND $6
+                 ORDER BY part_count, mass_class, save_id
+                 LIMIT $7",
+            )
+            .bind(cursor.0)
+            .bind(cursor.1)
+            .bind(cursor.2)
+            .bind(high)
+            .bind(mass_low)
+            .bind(mass_high)
+            .bind(BATCH_SIZE)
+            .fetch_all(pool)
+            .await?;
+            let Some(last) = rows.last() else {
+                break;
+            };
+            cursor = (
+                last.part_count.unwrap_or(high),
+                last.mass_class.unwrap_or(mass_high),
+                last.save_id,
+            );
+            let page = rows
+                .into_iter()
+                .filter(|row| row.save_id as SaveId != exclude)
+                .filter_map(|row| Some((row.save_id as SaveId, row.features()?)));
+            keep_best(&mut best, features, page, limit);
+        }
+    }, archive => {
+        for part_count in part_low..=part_high {
+            let page = archive
+                .features_bucket(part_count)?
+                .into_iter()
+                .filter(|(id, other)| {
+                    *id != exclude && (mass_low..=mass_high).contains(&other.mass_class)
+                });
+            keep_best(&mut best, features, page, limit);
+        }
+    });
+    Ok(best)
+}
+
+/// 把这一页的候选并进 `best`, 只留最像的 `limit` 条
+fn keep_best(
+    best: &mut Vec<SimilarRecord>,
+    features: &ShipFeatures,
+    page: impl IntoIterator<Item = (SaveId, ShipFeatures)>,
+    limit: usize,
+) {
+    best.extend(
+        page.into_iter()
+            .map(|(save_id, other)| SimilarRecord {
+                save_id,
+                similarity: features.similarity(&other),
+                features: other,
+            })
+            .filter(|record| record.similarity >= MIN_SIMILARITY),
+    );
+    best.sort_by(|a, b| {
+        b.similarity
+            .total_cmp(&a.similarity)
+            .then_with(|| {
+                features
+                    .distance(&a.features)
+                    .cmp(&features.distance(&b.features))
+            })
+            .then_with(|| a.save_id.cmp(&b.save_id))
+    });
+    best.truncate(limit);
+}
+
+/// 给库里的船和存档算特征, `recompute` 为 false 的时候跳过已经算过的
+pub async fn compute_records(
+    db: &DbPool,
+    catalog: &PartCatalog,
+    recompute: bool,
+) -> anyhow::Result<FeatureStats> {
+    let mut stats = FeatureStats::default();
+    let mut after_id = 0;
+    loop {
+        let records = search::record_batch(db, after_id, SaveId::MAX, None, BATCH_SIZE).await?;
+        let Some(last) = records.last() else {
+            break;
+        };
+        after_id = last.data.save_id;
+
+        for record in records {
+            let save_id = record.data.save_id;
+            if !recompute && load_features(db, save_id).await.is_some() {
+                stats.skipped += 1;
+                continue;
+            }
+            let Some(stored) = feature_data(&record.data, catalog) else {
+                continue;
+            };
+            match stored.features {
+                Some(_) => stats.computed += 1,
+                None => stats.unparsed += 1,
+            }
+            store_features(db, save_id, stored).await?;
+        }
+        event!(Level::INFO, "特征已经算到 {}", after_id);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::Utc;
+
+    use super::{
+        BATCH_SIZE, StoredFeatures, compute_records, load_features, similar_records, store_features,
+    };
+    use crate::{
+        db_part::{
+            CoverStrategy, DbPool, SaveId, SaveType, save_data_to_db,
+            test_util::{for_each_backend, memory_db},
+        },
+        xml_part::{catalog::PartCatalog, similarity::ShipFeatures},
+    };
+
+    const SAMPLE_SAVE: &str = include_str!("../save_1294489.xml");
+
+    async fn check_backend(db: DbPool) {
+        for (id, save_type, data) in [
+            (1, SaveType::Save, SAMPLE_SAVE.to_string()),
+            (2, SaveType::Save, SAMPLE_SAVE.to_string()),
+            (3, SaveType::Ship, crate::net::EMPTY_SHIP.to_string()),
+            (4, SaveType::Ship, "<Ship".to_string()),
+            (5, SaveType::None, String::new()),
+        ] {
+            save_data_to_db(id, save_type, data, None, &db)
+                .await
+                .unwrap();
+        }
+
+        let catalog = PartCatalog::embedded();
+        let stats = compute_records(&db, catalog, false).await.unwrap();
+        assert_eq!((stats.computed, stats.unparsed), (3, 1));
+        assert!(load_features(&db, 4).await.unwrap().features.is_none());
+        assert!(load_features(&db, 5).await.is_none());
+
+        let features = load_features(&db, 1).await.unwrap().features.unwrap();
+        let similar = similar_records(&db, &features, 1, 10).await.unwrap();
+        // 空船只有一个零件, 零件数差太多, 不会被捞出来
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].save_id, 2);
+        assert_eq!(similar[0].similarity, 1.0);
+
+        let empty = load_features(&db, 3).await.unwrap().features.unwrap();
+        assert!(
+            similar_records(&db, &empty, 3, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let again = compute_records(&db, catalog, false).await.unwrap();
+        assert_eq!(again.skipped, 4);
+
+        // 覆盖之后特征就没了
+        save_data_to_db(
+            2,
+            SaveType::Ship,
+            crate::net::EMPTY_SHIP,
+            Some(CoverStrategy::Cover),
+            &db,
+        )
+        .await
+        .unwrap();
+        assert!(load_features(&db, 2).await.is_none());
+        assert!(
+            similar_records(&db, &features, 1, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn finds_similar() {
+        for_each_backend(check_backend).await;
+    }
+
+    fn features(fuselages: u32) -> ShipFeatures {
+        let histogram = BTreeMap::from([
+            ("pod-1".to_string(), 1),
+            ("fuselage-1".to_string(), fuselages),
+            ("engine-1".to_string(), 10 - fuselages),
+        ]);
+        ShipFeatures {
+            part_count: 11,
+            mass_class: 12,
+            stage_count: 1,
+            histogram,
+        }
+    }
+
+    /// 零件数一样的记录超过一页, 最像的那条 id 最大也要找得到
+    #[tokio::test]
+    async fn scans_past_first_page() {
+        let db = memory_db().await;
+
+        let target = features(6);
+        let ids = (1..=BATCH_SIZE as SaveId * 2).chain([5000]);
+        for id in ids {
+            save_data_to_db(id, SaveType::Ship, crate::net::EMPTY_SHIP, None, &db)
+                .await
+                .unwrap();
+        }
+        for id in 1..=(BATCH_SIZE as SaveId * 2) {
+            let stored = StoredFeatures {
+                features: Some(features(5)),
+                computed_at: Utc::now(),
+            };
+            store_features(&db, id, stored).await.unwrap();
+        }
+        let stored = StoredFeatures {
+            features: Some(target.clone()),
+            computed_at: Utc::now(),
+        };
+        store_features(&db, 5000, stored).await.unwrap();
+
+        let similar = similar_records(&db, &target, 0, 3).await.unwrap();
+        assert_eq!(similar.len(), 3);
+        assert_eq!(similar[0].save_id, 5000);
+        assert_eq!(similar[0].similarity, 1.0);
+        assert_eq!(similar[1].save_id, 1);
+        assert_eq!(similar[2].save_id, 2);
+    }
+}
@@ -0,0 +1,288 @@
+//! SQLite 后端的表结构
+//!
+//! 表和 postgres 那边一一对应 (`main_data` / `long_data` / `full_data` / `db_version` / `plausibility` /
+//! `canonical_hash` / `ship_features`)
+//! 只是 `save_type` 用 TEXT 存, `update_xml_tested` 也没法写成数据库函数, 得在 Rust 里做
+
+use sqlx::{Executor, SqlitePool};
+use tracing::{Level, event};
+
+use crate::db_part::defines::CURRENT_DB_VERSION;
+
+pub const CREATE_MAIN_DATA_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS main_data (
+    save_id INTEGER PRIMARY KEY,
+    save_type TEXT NOT NULL CHECK (save_type IN ('ship', 'save', 'unknown', 'none')),
+    blake_hash CHARACTER(64) NOT NULL,
+    len BIGINT NOT NULL,
+    short_data VARCHAR(1024),
+    xml_tested BOOLEAN,
+    time DATETIME NOT NULL
+)
+"#;
+pub const CREATE_LONG_DATA_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS long_data (
+    save_id INTEGER PRIMARY KEY,
+    len BIGINT NOT NULL,
+    text TEXT NOT NULL,
+    CONSTRAINT save_id FOREIGN KEY (save_id)
+        REFERENCES main_data(save_id)
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+"#;
+pub const CREATE_FULL_DATA_VIEW_SQL: &str = r#"
+CREATE VIEW IF NOT EXISTS full_data AS
+SELECT
+    md.save_id,
+    md.save_type,
+    md.blake_hash,
+    md.xml_tested,
+    md.len,
+    CASE
+        WHEN md.len > 1024 THEN ld.text
+        ELSE md.short_data
+    END AS data
+FROM main_data md
+LEFT JOIN long_data ld ON md.save_id = ld.save_id
+"#;
+pub const CREATE_DB_VERSION_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS db_version (
+    version INTEGER PRIMARY KEY,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+)
+"#;
+pub const UPSERT_DB_VERSION_SQL: &str = r#"
+INSERT INTO db_version (version, updated_at)
+VALUES ($1, CURRENT_TIMESTAMP)
+ON CONFLICT (version) DO UPDATE SET updated_at = excluded.updated_at
+"#;
+pub const CREATE_MAIN_SAVE_TYPE_SAVE_ID_INDEX_SQL: &str = r#"
+CREATE INDEX IF NOT EXISTS maindata_savetype_saveid_idx
+ON main_data (save_type, save_id, len, xml_tested)
+"#;
+pub const CREATE_MAIN_HASH_INDEX_SQL: &str = r#"
+CREATE INDEX IF NOT EXISTS idx_main_data_hash
+ON main_data (blake_hash, save_id, save_type, len)
+"#;
+
+pub const CREATE_PLAUSIBILITY_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS plausibility (
+    save_id INTEGER PRIMARY KEY,
+    score INTEGER,
+    flags INTEGER NOT NULL,
+    checked_at DATETIME NOT NULL,
+    CONSTRAINT save_id FOREIGN KEY (save_id)
+        REFERENCES main_data(save_id)
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+"#;
+pub const CREATE_PLAUSIBILITY_SCORE_INDEX_SQL: &str = r#"
+CREATE INDEX IF NOT EXISTS plausibility_score_idx
+ON plausibility (score, save_id)
+"#;
+
+pub const CREATE_CANONICAL_HASH_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS canonical_hash (
+    save_id INTEGER PRIMARY KEY,
+    hash CHARACTER(64),
+    computed_at DATETIME NOT NULL,
+    CONSTRAINT save_id FOREIGN KEY (save_id)
+        REFERENCES main_data(save_id)
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+"#;
+pub const CREATE_CANONICAL_HASH_INDEX_SQL: &str = r#"
+CREATE INDEX IF NOT EXISTS canonical_hash_hash_idx
+ON canonical_hash (hash, save_id)
+"#;
+
+pub const CREATE_SHIP_FEATURES_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS ship_features (
+    save_id INTEGER PRIMARY KEY,
+    part_count INTEGER,
+    mass_class INTEGER,
+    stage_count INTEGER,
+    histogram TEXT,
+    computed_at DATETIME NOT NULL,
+    CONSTRAINT save_id FOREIGN KEY (save_id)
+        REFERENCES main_data(save_id)
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+"#;
+pub const CREATE_SHIP_FEATURES_INDEX_SQL: &str = r#"
+CREATE INDEX IF NOT EXISTS ship_features_count_mass_idx
+ON ship_features (part_count, mass_class, save_id)
+"#;
+
+/// SQLite 全都是 `IF NOT EXISTS`, 不用像 postgres 那样先查一遍
+pub async fn ensure_schema(db: &SqlitePool) -> anyhow::Result<()> {
+    db.execute(CREATE_MAIN_DATA_SQL).await?;
+    db.execute(CREATE_LONG_DATA_SQL).await?;
+    db.execute(CREATE_FULL_DATA_VIEW_SQL).await?;
+    db.execute(CREATE_DB_VERSION_SQL).await?;
+    db.execute(CREATE_MAIN_SAVE_TYPE_SAVE_ID_INDEX_SQL).await?;
+    db.execute(CREATE_MAIN_HASH_INDEX_SQL).await?;
+    db.execute(CREATE_PLAUSIBILITY_SQL).await?;
+    db.execute(CREATE_PLAUSIBILITY_SCORE_INDEX_SQL).await?;
+    db.execute(CREATE_CANONICAL_HASH_SQL).await?;
+    db.execute(CREATE_CANONICAL_HASH_INDEX_SQL).await?;
+    db.execute(CREATE_SHIP_FEATURES_SQL).await?;
+    db.execute(CREATE_SHIP_FEATURES_INDEX_SQL).await?;
+
+    sqlx::query(UPSERT_DB_VERSION_SQL)
+        .bind(CURRENT_DB_VERSION)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// postgres 那边的 `update_xml_tested()` 的替代品
+pub async fn update_xml_tested(db: &SqlitePool) -> anyhow::Result<u64> {
+    let rows = sqlx::query_as::<_, (i32, String)>(
+        "SELECT save_id, data
+         FROM full_data
+         WHERE xml_tested IS NULL
+           AND data IS NOT NULL",
+    )
+    .fetch_all(db)
+    .await?;
+    let mut updated = 0;
+    for (save_id, data) in rows {
+        let xml_tested = crate::db_part::utils::verify_xml(&data).is_ok();
+        sqlx::query("UPDATE main_data SET xml_tested = $1 WHERE save_id = $2")
+            .bind(xml_tested)
+            .bind(save_id)
+            .execute(db)
+            .await?;
+        updated += 1;
+    }
+    event!(Level::DEBUG, "sqlite 更新了 {} 条 xml_tested", updated);
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db_part::{
+        CoverStrategy, DbData, SaveType, TEXT_DATA_MAX_LEN, check_data_len, save_data_to_db,
+        search,
+        test_util::{memory_db, temp_archive},
+    };
+
+    #[tokio::test]
+    async fn save_and_load_short_and_long_data() {
+        let db = memory_db().await;
+        let short = crate::net::EMPTY_SHIP.to_string();
+        let long = format!("<Runtime>{}</Runtime>", "a".repeat(TEXT_DATA_MAX_LEN));
+
+        assert!(
+            save_data_to_db(1, SaveType::Ship, short.clone(), None, &db)
+                .await
+                .unwrap()
+        );
+        assert!(
+            save_data_to_db(2, SaveType::Save, long.clone(), None, &db)
+                .await
+                .unwrap()
+        );
+
+        let ship = DbData::from_db(1, &db).await.unwrap();
+        assert_eq!(ship.text.as_deref(), Some(short.as_str()));
+        assert_eq!(ship.save_type, SaveType::Ship);
+        assert!(ship.xml_tested);
+        assert!(ship.verify_hash());
+
+        let save = DbData::from_db(2, &db).await.unwrap();
+        assert_eq!(save.text.as_deref(), Some(long.as_str()));
+        assert_eq!(check_data_len(&db, 2).await, Some(long.len() as i64));
+
+        assert_eq!(search::max_id(&db).await, 2);
+        assert_eq!(search::max_ship(&db).await.unwrap().save_id, 1);
+        assert_eq!(search::max_save(&db).await.unwrap().save_id, 2);
+    }
+
+    #[tokio::test]
+    async fn cover_strategies() {
+        let db = memory_db().await;
+        let long = "b".repeat(TEXT_DATA_MAX_LEN + 1);
+
+        assert!(
+            save_data_to_db(7, SaveType::Ship, "a", None, &db)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !save_data_to_db(7, SaveType::Ship, "b", Some(CoverStrategy::Skip), &db)
+                .await
+                .unwrap()
+        );
+        assert!(
+            save_data_to_db(7, SaveType::Ship, "b", Some(CoverStrategy::Error), &db)
+                .await
+                .is_err()
+        );
+        assert!(
+            !save_data_to_db(
+                7,
+                SaveType::Ship,
+                "a",
+                Some(CoverStrategy::CoverIfDifferent),
+                &db
+            )
+            .await
+            .unwrap()
+        );
+        // 短数据覆盖成长数据, 再覆盖回来
+        assert!(
+            save_data_to_db(
+                7,
+                SaveType::Ship,
+                long.clone(),
+                Some(CoverStrategy::CoverIfDifferent),
+                &db
+            )
+            .await
+            .unwrap()
+        );
+        assert_eq!(DbData::from_db(7, &db).await.unwrap().text, Some(long));
+        assert!(
+            save_data_to_db(7, SaveType::Save, "c", Some(CoverStrategy::Cover), &db)
+                .await
+                .unwrap()
+        );
+        let data = DbData::from_db(7, &db).await.unwrap();
+        assert_eq!(data.text.as_deref(), Some("c"));
+        assert_eq!(data.save_type, SaveType::Save);
+    }
+
+    #[tokio::test]
+    async fn archive_mirror_follows_primary() {
+        let mirror = temp_archive("mirror");
+        let db = memory_db().await.with_mirror(mirror.clone());
+
+        save_data_to_db(9, SaveType::Ship, "<Ship/>", None, &db)
+            .await
+            .unwrap();
+        assert_eq!(
+            mirror.load(9).unwrap().unwrap().text.as_deref(),
+            Some("<Ship/>")
+        );
+        // 主存储跳过了, 镜像也不该动
+        save_data_to_db(
+            9,
+            SaveType::Ship,
+            "<Ship />",
+            Some(CoverStrategy::Skip),
+            &db,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            mirror.load(9).unwrap().unwrap().text.as_deref(),
+            Some("<Ship/>")
+        );
+    }
+}
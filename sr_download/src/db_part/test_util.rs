@@ -0,0 +1,42 @@
+//! 测试用的空存储
+//!
+//! postgres 要连服务器, 这里只有本地能建的两种: 内存 sqlite 和临时目录里的文件存档
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sqlx::sqlite::SqlitePoolOptions;
+
+use crate::db_part::{DbPool, DbStore, FsArchive, sqlite};
+
+/// 空的内存 sqlite, 表都建好了
+pub async fn memory_db() -> DbPool {
+    // 内存数据库每个连接都是独立的, 所以只能开一个连接
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlite::ensure_schema(&pool).await.unwrap();
+    DbPool::new(DbStore::Sqlite(pool))
+}
+
+/// 临时目录里的空文件存档, 测试是并行跑的, `name` 不能跟别的测试重复
+pub fn temp_archive(name: &str) -> FsArchive {
+    let dir = std::env::temp_dir().join(format!("sr_download_{name}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    FsArchive::open(dir).unwrap()
+}
+
+/// 文件存档和内存 sqlite 各建一个空的跑一遍 `check`
+pub async fn for_each_backend<F, Fut>(check: F)
+where
+    F: Fn(DbPool) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let archive = temp_archive(&format!("backend_{}", NEXT.fetch_add(1, Ordering::Relaxed)));
+    check(DbPool::new(DbStore::Archive(archive.clone()))).await;
+    let _ = std::fs::remove_dir_all(archive.root());
+
+    check(memory_db().await).await;
+}
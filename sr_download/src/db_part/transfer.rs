@@ -0,0 +1,330 @@
+//! 导出 / 导入一段记录
+//!
+//! 导出的文件是一个 tar 包, 里面是 `data/<save_id>.<save_type>.xml` 加上一个 `manifest.jsonl`
+//! manifest 每行一条记录, 写在 tar 包的最后 (导出的时候边读边写, 写完才知道有哪些)
+//!
+//! 导入的时候先把 manifest 读出来, 再按顺序把 xml 读进来, 校验过 hash 之后再按 `CoverStrategy` 写进库里
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use blake3::Hasher;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use tracing::{Level, event};
+
+use crate::db_part::{CoverStrategy, DbPool, SaveId, SaveType, save_data_to_db_at, search};
+
+pub const MANIFEST_NAME: &str = "manifest.jsonl";
+pub const DATA_DIR: &str = "data";
+const BATCH_SIZE: i64 = 500;
+
+/// manifest 里的一行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub save_id: SaveId,
+    pub save_type: SaveType,
+    pub blake_hash: String,
+    pub len: i64,
+    pub time: DateTime<Utc>,
+    /// 导出的时间
+    pub exported_at: DateTime<Utc>,
+}
+
+impl ManifestEntry {
+    pub fn data_name(&self) -> String {
+        format!("{DATA_DIR}/{}.{}.xml", self.save_id, self.save_type)
+    }
+}
+
+/// 要导出哪些记录
+#[derive(Debug, Clone, Copy)]
+pub struct ExportFilter {
+    /// 包含
+    pub start_id: SaveId,
+    /// 包含
+    pub end_id: SaveId,
+    /// None 就是全都要
+    pub save_type: Option<SaveType>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportStats {
+    /// 写进去了的
+    pub saved: usize,
+    /// 按 `CoverStrategy` 跳过的
+    pub skipped: usize,
+    /// hash / 长度对不上, 或者 manifest 里没有的
+    pub invalid: usize,
+}
+
+/// 导出到 `path`, 返回导出的记录数
+pub async fn export(db: &DbPool, path: &Path, filter: ExportFilter) -> anyhow::Result<usize> {
+    let mut builder = tar::Builder::new(BufWriter::new(File::create(path)?));
+    let mut manifest = Vec::new();
+    let exported_at = Utc::now();
+    let mut after_id = filter.start_id.saturating_sub(1);
+
+    loop {
+        let records =
+            search::record_batch(db, after_id, filter.end_id, filter.save_type, BATCH_SIZE).await?;
+        let Some(last) = records.last() else {
+            break;
+        };
+        after_id = last.data.save_id;
+
+        for record in records {
+            let data = record.data;
+            let Some(text) = data.text else {
+                event!(
+                    Level::WARN,
+                    "{}",
+                    format!("导出 {} 的时候没找到数据, 跳过", data.save_id).yellow()
+                );
+                continue;
+            };
+            let entry = ManifestEntry {
+                save_id: data.save_id,
+                save_type: data.save_type,
+                blake_hash: data.blake_hash,
+                len: data.len,
+                time: record.time,
+                exported_at,
+            };
+            append_file(
+                &mut builder,
+                &entry.data_name(),
+                text.as_bytes(),
+                record.time,
+            )?;
+            serde_json::to_writer(&mut manifest, &entry)?;
+            manifest.push(b'\n');
+        }
+        event!(Level::INFO, "已经导出到 {}", after_id);
+        if after_id >= filter.end_id {
+            break;
+        }
+    }
+
+    let count = manifest.iter().filter(|b| **b == b'\n').count();
+    append_file(&mut builder, MANIFEST_NAME, &manifest, exported_at)?;
+    builder.into_inner()?.flush()?;
+    Ok(count)
+}
+
+fn append_file<W: Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    data: &[u8],
+    time: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(time.timestamp().max(0) as u64);
+    builder.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+/// 只把 manifest 读出来
+pub fn read_manifest(path: &Path) -> anyhow::Result<BTreeMap<SaveId, ManifestEntry>> {
+    let mut archive = tar::Archive::new(BufReader::new(File::open(path)?));
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.path()?.as_os_str() != MANIFEST_NAME {
+            continue;
+        }
+        let mut manifest = BTreeMap::new();
+        for line in BufReader::new(entry).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: ManifestEntry = serde_json::from_str(&line)?;
+            manifest.insert(entry.save_id, entry);
+        }
+        return Ok(manifest);
+    }
+    Err(anyhow::anyhow!(
+        "{} 里没有 {}",
+        path.display(),
+        MANIFEST_NAME
+    ))
+}
+
+/// 从 `path` 导入, 已经有的记录按 `cover_strategy` 处理
+pub async fn import(
+    db: &DbPool,
+    path: &Path,
+    cover_strategy: CoverStrategy,
+) -> anyhow::Result<ImportStats> {
+    let mut manifest = read_manifest(path)?;
+    event!(Level::INFO, "manifest 里有 {} 条记录", manifest.len());
+
+    let mut stats = ImportStats::default();
+    let mut archive = tar::Archive::new(BufReader::new(File::open(path)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if name == MANIFEST_NAME {
+            continue;
+        }
+        let Some(meta) = data_save_id(&name).and_then(|save_id| manifest.remove(&save_id)) else {
+            event!(
+                Level::WARN,
+                "{}",
+                format!("{name} 不在 manifest 里, 跳过").yellow()
+            );
+            stats.invalid += 1;
+            continue;
+        };
+        if name != meta.data_name() {
+            event!(
+                Level::WARN,
+                "{}",
+                format!(
+                    "{name} 和 manifest 里的类型 {} 对不上, 跳过",
+                    meta.save_type
+                )
+                .yellow()
+            );
+            stats.invalid += 1;
+            continue;
+        }
+
+        let mut text = String::new();
+        entry.read_to_string(&mut text)?;
+        let mut hasher = Hasher::new();
+        hasher.update(text.as_bytes());
+        let hash = hasher.finalize().to_hex().to_string();
+        if hash != meta.blake_hash || text.len() as i64 != meta.len {
+            event!(
+                Level::WARN,
+                "{}",
+                format!("{} 的 hash 或长度对不上, 跳过", meta.save_id).red()
+            );
+            stats.invalid += 1;
+            continue;
+        }
+
+        if save_data_to_db_at(
+            meta.save_id,
+            meta.save_type,
+            text,
+            Some(cover_strategy),
+            meta.time,
+            db,
+        )
+        .await?
+        {
+            stats.saved += 1;
+        } else {
+            stats.skipped += 1;
+        }
+    }
+
+    for save_id in manifest.keys() {
+        event!(
+            Level::WARN,
+            "{}",
+            format!("manifest 里有 {save_id} 但是没找到数据").yellow()
+        );
+    }
+    stats.invalid += manifest.len();
+    Ok(stats)
+}
+
+/// `data/<save_id>.<save_type>.xml` -> save_id
+fn data_save_id(name: &str) -> Option<SaveId> {
+    name.strip_prefix(DATA_DIR)?
+        .strip_prefix('/')?
+        .split('.')
+        .next()?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{ExportFilter, ImportStats, export, import, read_manifest};
+    use crate::db_part::{
+        CoverStrategy, DbData, DbPool, DbStore, FsArchive, SaveType, save_data_to_db,
+    };
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "sr_download_transfer_{name}_{}",
+            std::process::id()
+        ))
+    }
+
+    fn temp_db(name: &str) -> DbPool {
+        let dir = temp_path(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        DbPool::new(DbStore::Archive(FsArchive::open(dir).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn export_then_import() {
+        let source = temp_db("source");
+        for (id, save_type, data) in [
+            (1, SaveType::Ship, "<Ship/>"),
+            (2, SaveType::Save, "<Runtime/>"),
+            (3, SaveType::None, ""),
+            (4, SaveType::Ship, "<Ship a=\"1\"/>"),
+        ] {
+            save_data_to_db(id, save_type, data, None, &source)
+                .await
+                .unwrap();
+        }
+
+        let tar_path = temp_path("range.tar");
+        let filter = ExportFilter {
+            start_id: 2,
+            end_id: 4,
+            save_type: None,
+        };
+        assert_eq!(export(&source, &tar_path, filter).await.unwrap(), 3);
+        let manifest = read_manifest(&tar_path).unwrap();
+        assert_eq!(manifest.keys().copied().collect::<Vec<_>>(), vec![2, 3, 4]);
+
+        let target = temp_db("target");
+        save_data_to_db(4, SaveType::Ship, "<Ship/>", None, &target)
+            .await
+            .unwrap();
+        let stats = import(&target, &tar_path, CoverStrategy::Skip)
+            .await
+            .unwrap();
+        assert_eq!(
+            stats,
+            ImportStats {
+                saved: 2,
+                skipped: 1,
+                invalid: 0
+            }
+        );
+        assert_eq!(
+            DbData::from_db(2, &target).await.unwrap().text.as_deref(),
+            Some("<Runtime/>")
+        );
+        assert_eq!(
+            DbData::from_db(4, &target).await.unwrap().text.as_deref(),
+            Some("<Ship/>")
+        );
+
+        let ships = temp_path("ships.tar");
+        let filter = ExportFilter {
+            start_id: 0,
+            end_id: 100,
+            save_type: Some(SaveType::Ship),
+        };
+        assert_eq!(export(&source, &ships, filter).await.unwrap(), 2);
+    }
+}
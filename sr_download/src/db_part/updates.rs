@@ -3,11 +3,15 @@ use tracing::{Level, event};
 
 use crate::config::ConfigFile;
 use crate::db_part::defines::{
-    self, CREATE_DB_VERSION_SQL, CREATE_FULL_DATA_VIEW_SQL, CREATE_LONG_DATA_SQL,
-    CREATE_LONG_SAVE_ID_INDEX_SQL, CREATE_MAIN_DATA_SQL, CREATE_MAIN_HASH_COVERING_INDEX_SQL,
-    CREATE_MAIN_SAVE_TYPE_SAVE_ID_INDEX_SQL, CREATE_SAVE_TYPE_SQL, CREATE_UPDATE_XML_TESTED_SQL,
-    CURRENT_DB_VERSION, UPSERT_DB_VERSION_SQL,
+    self, CREATE_CANONICAL_HASH_INDEX_SQL, CREATE_CANONICAL_HASH_SQL, CREATE_DB_VERSION_SQL,
+    CREATE_FULL_DATA_VIEW_SQL, CREATE_LONG_DATA_SQL, CREATE_LONG_SAVE_ID_INDEX_SQL,
+    CREATE_MAIN_DATA_SQL, CREATE_MAIN_HASH_COVERING_INDEX_SQL,
+    CREATE_MAIN_SAVE_TYPE_SAVE_ID_INDEX_SQL, CREATE_PLAUSIBILITY_SCORE_INDEX_SQL,
+    CREATE_PLAUSIBILITY_SQL, CREATE_SAVE_TYPE_SQL, CREATE_SHIP_FEATURES_INDEX_SQL,
+    CREATE_SHIP_FEATURES_SQL, CREATE_UPDATE_XML_TESTED_SQL, CURRENT_DB_VERSION,
+    UPSERT_DB_VERSION_SQL,
 };
+use crate::db_part::{DbPool, DbStore};
 
 pub mod pre_local {
     use super::*;
@@ -36,6 +40,21 @@ async fn ensure_schema(db: &PgPool, conf: &ConfigFile) -> anyhow::Result<()> {
     if !defines::check_table_exists(db, defines::db_names::LONG_DATA_TABLE, &conf.db.schema).await {
         db.execute(CREATE_LONG_DATA_SQL).await?;
     }
+    if !defines::check_table_exists(db, defines::db_names::PLAUSIBILITY_TABLE, &conf.db.schema)
+        .await
+    {
+        db.execute(CREATE_PLAUSIBILITY_SQL).await?;
+    }
+    if !defines::check_table_exists(db, defines::db_names::CANONICAL_HASH_TABLE, &conf.db.schema)
+        .await
+    {
+        db.execute(CREATE_CANONICAL_HASH_SQL).await?;
+    }
+    if !defines::check_table_exists(db, defines::db_names::SHIP_FEATURES_TABLE, &conf.db.schema)
+        .await
+    {
+        db.execute(CREATE_SHIP_FEATURES_SQL).await?;
+    }
 
     db.execute(CREATE_FULL_DATA_VIEW_SQL).await?;
     db.execute(CREATE_UPDATE_XML_TESTED_SQL).await?;
@@ -50,6 +69,15 @@ async fn ensure_schema(db: &PgPool, conf: &ConfigFile) -> anyhow::Result<()> {
     if !defines::check_index_exists(db, "idx_main_data_hash_covering", &conf.db.schema).await {
         db.execute(CREATE_MAIN_HASH_COVERING_INDEX_SQL).await?;
     }
+    if !defines::check_index_exists(db, "plausibility_score_idx", &conf.db.schema).await {
+        db.execute(CREATE_PLAUSIBILITY_SCORE_INDEX_SQL).await?;
+    }
+    if !defines::check_index_exists(db, "canonical_hash_hash_idx", &conf.db.schema).await {
+        db.execute(CREATE_CANONICAL_HASH_INDEX_SQL).await?;
+    }
+    if !defines::check_index_exists(db, "ship_features_count_mass_idx", &conf.db.schema).await {
+        db.execute(CREATE_SHIP_FEATURES_INDEX_SQL).await?;
+    }
 
     sqlx::query(UPSERT_DB_VERSION_SQL)
         .bind(CURRENT_DB_VERSION)
@@ -58,10 +86,18 @@ async fn ensure_schema(db: &PgPool, conf: &ConfigFile) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub async fn update_db(db: &PgPool, conf: &ConfigFile) {
+pub async fn update_db(db: &DbPool, conf: &ConfigFile) {
     event!(Level::INFO, "开始更新数据库");
-    pre_local::try_merge(db, conf).await;
-    if let Err(e) = ensure_schema(db, conf).await {
+    let result = match &db.store {
+        DbStore::Postgres(pool) => {
+            pre_local::try_merge(pool, conf).await;
+            ensure_schema(pool, conf).await
+        }
+        DbStore::Sqlite(pool) => crate::db_part::sqlite::ensure_schema(pool).await,
+        // 文件存档没有表结构, 目录在连接的时候就建好了
+        DbStore::Archive(_) => Ok(()),
+    };
+    if let Err(e) = result {
         event!(Level::ERROR, "无法更新数据库结构: {:?}", e);
         return;
     }
@@ -1,101 +1,140 @@
 use colored::Colorize;
-use quick_xml::{Reader, de::from_str, events::Event, name::QName};
-use serde::Deserialize;
+use quick_xml::{Reader, events::Event};
 use sqlx::{
-    Executor, PgPool, Row,
-    postgres::{PgPoolOptions, PgRow},
+    Executor,
+    postgres::PgPoolOptions,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
 };
-use std::collections::HashSet;
+use std::str::FromStr;
 use tracing::{Level, event};
 
 use crate::{
-    config::ConfigFile,
+    config::{ConfigFile, DbBackend},
     db_part::SaveType,
     db_part::{
-        CoverStrategy,
+        CoverStrategy, DbPool, DbStore, FsArchive,
         defines::{SaveId, db_names},
         save_data_to_db,
     },
+    xml_part::{
+        catalog::PartCatalog,
+        stream::{StreamSummary, scan},
+    },
 };
 
-pub async fn connect(conf: &ConfigFile) -> anyhow::Result<PgPool> {
-    let search_path_sql = format!(
-        "SET search_path TO {}",
-        crate::db_part::defines::quote_ident(&conf.db.schema)
-    );
-    let after_connect_sql = search_path_sql.clone();
+pub async fn connect(conf: &ConfigFile) -> anyhow::Result<DbPool> {
     event!(Level::INFO, "正在连接数据库");
-    let db = PgPoolOptions::new()
-        .max_connections(conf.db.max_connections)
-        .after_connect(move |conn, _meta| {
-            let sql = after_connect_sql.clone();
-            Box::pin(async move {
-                conn.execute(sql.as_str()).await?;
-                Ok(())
-            })
-        })
-        .connect(&conf.db.url)
-        .await?;
-    db.execute(search_path_sql.as_str()).await?;
-    sqlx::query("SELECT 1").execute(&db).await?;
+    let db = connect_pool(conf, conf.db.max_connections).await?;
     event!(Level::INFO, "{}", "已经连接数据库".blue());
     Ok(db)
 }
 
-pub async fn connect_server(conf: &ConfigFile) -> anyhow::Result<PgPool> {
-    let search_path_sql = format!(
-        "SET search_path TO {}",
-        crate::db_part::defines::quote_ident(&conf.db.schema)
-    );
-    let after_connect_sql = search_path_sql.clone();
+pub async fn connect_server(conf: &ConfigFile) -> anyhow::Result<DbPool> {
     event!(Level::INFO, "服务器正在连接数据库");
-    let db = PgPoolOptions::new()
-        .max_connections(conf.serve.db_max_connect)
-        .after_connect(move |conn, _meta| {
-            let sql = after_connect_sql.clone();
-            Box::pin(async move {
-                conn.execute(sql.as_str()).await?;
-                Ok(())
-            })
-        })
-        .connect(&conf.db.url)
-        .await?;
-    db.execute(search_path_sql.as_str()).await?;
-    sqlx::query("SELECT 1").execute(&db).await?;
+    let db = connect_pool(conf, conf.serve.db_max_connect).await?;
     event!(Level::INFO, "{}", "服务器已经连接数据库".blue());
     Ok(db)
 }
 
+async fn connect_pool(conf: &ConfigFile, max_connections: u32) -> anyhow::Result<DbPool> {
+    let store = match conf.db.backend()? {
+        DbBackend::Postgres => {
+            let search_path_sql = format!(
+                "SET search_path TO {}",
+                crate::db_part::defines::quote_ident(&conf.db.schema)
+            );
+            let after_connect_sql = search_path_sql.clone();
+            let db = PgPoolOptions::new()
+                .max_connections(max_connections)
+                .after_connect(move |conn, _meta| {
+                    let sql = after_connect_sql.clone();
+                    Box::pin(async move {
+                        conn.execute(sql.as_str()).await?;
+                        Ok(())
+                    })
+                })
+                .connect(&conf.db.url)
+                .await?;
+            db.execute(search_path_sql.as_str()).await?;
+            sqlx::query("SELECT 1").execute(&db).await?;
+            DbStore::Postgres(db)
+        }
+        DbBackend::Sqlite => {
+            // 多个 worker 同时写, 开 WAL 会好受很多
+            let options = SqliteConnectOptions::from_str(&conf.db.url)?
+                .create_if_missing(true)
+                .journal_mode(SqliteJournalMode::Wal);
+            let db = SqlitePoolOptions::new()
+                .max_connections(max_connections)
+                .connect_with(options)
+                .await?;
+            sqlx::query("SELECT 1").execute(&db).await?;
+            DbStore::Sqlite(db)
+        }
+        DbBackend::Archive => {
+            let root = conf.db.archive_root().unwrap_or_default();
+            DbStore::Archive(FsArchive::open(root)?)
+        }
+    };
+    let mut db = DbPool::new(store);
+    if let Some(mirror) = &conf.db.archive_mirror {
+        event!(Level::INFO, "数据会同时写入文件镜像: {}", mirror);
+        db = db.with_mirror(FsArchive::open(mirror)?);
+    }
+    Ok(db)
+}
+
 /// 更新数据库内所有 xml_tested = null 的数据
-pub async fn update_xml_tested(db: &PgPool) -> Option<()> {
-    let sql = r#"SELECT count(1)
+pub async fn update_xml_tested(db: &DbPool) -> Option<()> {
+    let sql = r#"SELECT count(1) AS count
 	from full_data
 	where xml_tested is NULL
 	and len != 0
 	and "save_type" != 'none'"#;
-    let data: PgRow = sqlx::query(sql).fetch_one(db).await.ok()?;
-    let count: i64 = data.try_get("count").ok()?;
+    let count: i64 = with_pool!(db, pool => {
+        sqlx::query_scalar(sql).fetch_one(pool).await.ok()?
+    }, _archive => 0);
     if count == 0 {
         event!(Level::INFO, "所有的 xml_tested 都已经更新过了");
         return Some(());
     }
     event!(Level::INFO, "正在检查 {} 条数据的xml状态", count);
-    let sql = format!("SELECT {}()", db_names::UPDATE_XML_TESTED);
     event!(Level::INFO, "正在更新数据库内所有 xml_tested = null 的数据");
-    let _ = db.execute(sql.as_str()).await;
+    match &db.store {
+        DbStore::Postgres(pool) => {
+            let sql = format!("SELECT {}()", db_names::UPDATE_XML_TESTED);
+            let _ = pool.execute(sql.as_str()).await;
+        }
+        DbStore::Sqlite(pool) => {
+            if let Err(e) = crate::db_part::sqlite::update_xml_tested(pool).await {
+                event!(Level::WARN, "更新 xml_tested 的时候出现错误: {:?}", e);
+            }
+        }
+        // 文件存档在写入的时候就已经算好了
+        DbStore::Archive(_) => (),
+    }
     event!(Level::INFO, "已经更新数据库内所有 xml_tested = null 的数据");
     Some(())
 }
 
 /// 检查所有 data = null 的数据
 /// 然后补全
-pub async fn check_null_data(db: &PgPool) -> Option<()> {
+pub async fn check_null_data(db: &DbPool) -> Option<()> {
     let sql = format!(
-        "SELECT count(1) from {} where data is NULL",
+        "SELECT save_id from {} where data is NULL",
         db_names::FULL_DATA_TABLE
     );
-    let data: PgRow = sqlx::query(&sql).fetch_one(db).await.ok()?;
-    let count: i64 = data.try_get("count").ok()?;
+    let quert_results: Vec<db_names::DbSaveId> = with_pool!(db, pool => {
+        sqlx::query_scalar(&sql).fetch_all(pool).await.ok()?
+    }, archive => {
+        archive
+            .missing_data_ids()
+            .ok()?
+            .into_iter()
+            .map(|id| id as db_names::DbSaveId)
+            .collect()
+    });
+    let count = quert_results.len();
     if count == 0 {
         event!(Level::INFO, "数据库内数据都是完整的, 放心");
         return Some(());
@@ -105,14 +144,8 @@ pub async fn check_null_data(db: &PgPool) -> Option<()> {
         "数据库内有 {} 条数据的 data 是空的, 正在更新",
         count
     );
-    let sql = format!(
-        "SELECT save_id from {} where data is NULL",
-        db_names::FULL_DATA_TABLE
-    );
-    let quert_results = sqlx::query(&sql).fetch_all(db).await.ok()?;
     let downloader = crate::Downloader::new(None);
-    for result in quert_results {
-        let id: db_names::DbSaveId = result.try_get("save_id").ok()?;
+    for id in quert_results {
         let id = id as SaveId;
         event!(Level::INFO, "正在补全id: {} 的数据", id);
         match downloader.try_download_as_any(id).await {
@@ -158,7 +191,7 @@ pub async fn check_null_data(db: &PgPool) -> Option<()> {
 }
 
 pub trait FromDb {
-    fn from_db(db: &PgPool) -> impl std::future::Future<Output = Option<Self>> + Send
+    fn from_db(db: &DbPool) -> impl std::future::Future<Output = Option<Self>> + Send
     where
         Self: Sized;
 }
@@ -173,6 +206,21 @@ pub enum ShipVerifyState {
 }
 
 impl ShipVerifyState {
+    pub fn from_summary(summary: &StreamSummary) -> Self {
+        if !summary.is_xml() {
+            Self::NotXml
+        } else if !summary.is_ship_shape() {
+            Self::NotShip
+        } else if !summary.shape.disconnected || !summary.is_parsable() {
+            // 游戏自己存的船一定带 `DisconnectedParts`, 没有的就是别人拼出来的
+            Self::FakeShip
+        } else if summary.is_valid() {
+            Self::VerifiedShip
+        } else {
+            Self::BrokenShip
+        }
+    }
+
     pub fn as_str(self) -> &'static str {
         match self {
             Self::NotXml => "not xml",
@@ -190,453 +238,75 @@ impl std::fmt::Display for ShipVerifyState {
     }
 }
 
-/// 校验一下是不是合法 xml
-pub fn verify_xml(data: &str) -> quick_xml::Result<()> {
-    let mut reader = Reader::from_str(data);
-    reader.config_mut().trim_text(true);
-    loop {
-        match reader.read_event() {
-            Ok(Event::Eof) => break,
-            Ok(_) => (),
-            Err(e) => return Err(e),
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveVerifyState {
+    NotXml,
+    NotSave,
+    FakeSave,
+    BrokenSave,
+    VerifiedSave,
+}
+
+impl SaveVerifyState {
+    pub fn from_summary(summary: &StreamSummary) -> Self {
+        if !summary.is_xml() {
+            Self::NotXml
+        } else if !summary.is_save_shape() {
+            Self::NotSave
+        } else if !summary.is_parsable() {
+            Self::FakeSave
+        } else if summary.is_valid() {
+            Self::VerifiedSave
+        } else {
+            Self::BrokenSave
         }
     }
-    Ok(())
-}
 
-pub fn verify_ship(data: &str) -> ShipVerifyState {
-    if verify_xml(data).is_err() {
-        return ShipVerifyState::NotXml;
-    }
-    if !matches_basic_ship_shape(data) {
-        return ShipVerifyState::NotShip;
-    }
-    match from_str::<RawShipForVerify>(data) {
-        Ok(ship) => {
-            if ship.is_semantically_valid() {
-                ShipVerifyState::VerifiedShip
-            } else {
-                ShipVerifyState::BrokenShip
-            }
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::NotXml => "not xml",
+            Self::NotSave => "not save",
+            Self::FakeSave => "fake save",
+            Self::BrokenSave => "broken save",
+            Self::VerifiedSave => "verified save",
         }
-        Err(_) => ShipVerifyState::FakeShip,
     }
 }
 
-fn matches_basic_ship_shape(data: &str) -> bool {
+impl std::fmt::Display for SaveVerifyState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 校验一下是不是合法 xml
+pub fn verify_xml(data: &str) -> quick_xml::Result<()> {
     let mut reader = Reader::from_str(data);
     reader.config_mut().trim_text(true);
-
-    let mut depth = 0usize;
-    let mut root_checked = false;
-    let mut has_parts = false;
-    let mut has_connections = false;
-
     loop {
         match reader.read_event() {
-            Ok(Event::Start(ref e)) => {
-                if !root_checked {
-                    root_checked = true;
-                    if e.name() != QName(b"Ship") {
-                        return false;
-                    }
-                } else if depth == 1 {
-                    match e.name() {
-                        QName(b"Parts") => has_parts = true,
-                        QName(b"Connections") => has_connections = true,
-                        _ => {}
-                    }
-                }
-                depth += 1;
-            }
-            Ok(Event::Empty(ref e)) => {
-                if !root_checked {
-                    return false;
-                }
-                if depth == 1 {
-                    match e.name() {
-                        QName(b"Parts") => has_parts = true,
-                        QName(b"Connections") => has_connections = true,
-                        _ => {}
-                    }
-                }
-            }
-            Ok(Event::End(_)) => {
-                depth = depth.saturating_sub(1);
-            }
             Ok(Event::Eof) => break,
-            Ok(_) => {}
-            Err(_) => return false,
-        }
-    }
-
-    root_checked && has_parts && has_connections
-}
-
-fn default_ship_version() -> i32 {
-    1
-}
-fn default_lifted_off() -> i8 {
-    0
-}
-fn default_touching_ground() -> i8 {
-    1
-}
-fn default_editor_angle() -> i32 {
-    0
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(rename = "Ship")]
-struct RawShipForVerify {
-    #[serde(rename = "Parts")]
-    #[allow(dead_code)]
-    parts: VerifyParts,
-    #[serde(rename = "Connections")]
-    #[allow(dead_code)]
-    connects: VerifyConnections,
-    #[serde(rename = "@version", default = "default_ship_version")]
-    #[allow(dead_code)]
-    version: i32,
-    #[serde(rename = "@liftedOff", default = "default_lifted_off")]
-    #[allow(dead_code)]
-    lift_off: i8,
-    #[serde(rename = "@touchingGround", default = "default_touching_ground")]
-    #[allow(dead_code)]
-    touch_ground: i8,
-    #[serde(rename = "DisconnectedParts")]
-    #[allow(dead_code)]
-    disconnected: VerifyDisconnectedParts,
-}
-
-impl RawShipForVerify {
-    fn is_semantically_valid(&self) -> bool {
-        let mut part_ids = HashSet::new();
-        if !collect_part_ids(&self.parts.parts, &mut part_ids) {
-            return false;
-        }
-        for disconnected in &self.disconnected.parts {
-            if !collect_part_ids(&disconnected.parts.parts, &mut part_ids) {
-                return false;
-            }
-        }
-
-        connections_valid(&self.connects.connections, &part_ids)
-            && normal_connections_acyclic(&self.connects.connections)
-            && self.disconnected.parts.iter().all(|part| {
-                connections_valid(&part.connects.connections, &part_ids)
-                    && normal_connections_acyclic(&part.connects.connections)
-            })
-            && part_activations_valid(&self.parts.parts, &part_ids)
-            && self
-                .disconnected
-                .parts
-                .iter()
-                .all(|part| part_activations_valid(&part.parts.parts, &part_ids))
-    }
-}
-
-fn collect_part_ids(parts: &[VerifyPart], all_ids: &mut HashSet<i64>) -> bool {
-    for part in parts {
-        if !all_ids.insert(part.id) {
-            return false;
-        }
-    }
-    true
-}
-
-fn connections_valid(connections: &[VerifyConnection], part_ids: &HashSet<i64>) -> bool {
-    let mut seen_normal = HashSet::new();
-    let mut seen_dock = HashSet::new();
-
-    connections.iter().all(|connection| match connection {
-        VerifyConnection::Normal {
-            parent_attach_point,
-            child_attach_point,
-            parent_part,
-            child_part,
-        } => {
-            if !part_ids.contains(parent_part) || !part_ids.contains(child_part) {
-                return false;
-            }
-            if parent_part == child_part {
-                return false;
-            }
-            seen_normal.insert((
-                *parent_attach_point,
-                *child_attach_point,
-                *parent_part,
-                *child_part,
-            ))
-        }
-        VerifyConnection::Dock {
-            dock_part,
-            parent_part,
-            child_part,
-        } => {
-            if !part_ids.contains(dock_part)
-                || !part_ids.contains(parent_part)
-                || !part_ids.contains(child_part)
-            {
-                return false;
-            }
-            if parent_part == child_part || *dock_part == *parent_part || *dock_part == *child_part
-            {
-                return false;
-            }
-            seen_dock.insert((*dock_part, *parent_part, *child_part))
-        }
-    })
-}
-
-fn part_activations_valid(parts: &[VerifyPart], part_ids: &HashSet<i64>) -> bool {
-    parts.iter().all(|part| {
-        let Some(pod) = &part.pod else {
-            return true;
-        };
-        pod.stages
-            .steps
-            .iter()
-            .flat_map(|step| step.activates.iter())
-            .all(|activate| part_ids.contains(&activate.id))
-    })
-}
-
-fn normal_connections_acyclic(connections: &[VerifyConnection]) -> bool {
-    let mut nodes = HashSet::new();
-    let mut edges = Vec::new();
-
-    for connection in connections {
-        if let VerifyConnection::Normal {
-            parent_part,
-            child_part,
-            ..
-        } = connection
-        {
-            nodes.insert(*parent_part);
-            nodes.insert(*child_part);
-            edges.push((*parent_part, *child_part));
-        }
-    }
-
-    let mut visiting = HashSet::new();
-    let mut visited = HashSet::new();
-
-    nodes
-        .into_iter()
-        .all(|node| !has_cycle(node, &edges, &mut visiting, &mut visited))
-}
-
-fn has_cycle(
-    node: i64,
-    edges: &[(i64, i64)],
-    visiting: &mut HashSet<i64>,
-    visited: &mut HashSet<i64>,
-) -> bool {
-    if visited.contains(&node) {
-        return false;
-    }
-    if !visiting.insert(node) {
-        return true;
-    }
-
-    for (_, child) in edges.iter().filter(|(parent, _)| *parent == node) {
-        if has_cycle(*child, edges, visiting, visited) {
-            return true;
+            Ok(_) => (),
+            Err(e) => return Err(e),
         }
     }
-
-    visiting.remove(&node);
-    visited.insert(node);
-    false
-}
-
-#[derive(Debug, Deserialize)]
-struct VerifyParts {
-    #[serde(rename = "Part", default)]
-    #[allow(dead_code)]
-    parts: Vec<VerifyPart>,
-}
-
-#[derive(Debug, Deserialize)]
-struct VerifyConnections {
-    #[serde(rename = "$value", default)]
-    #[allow(dead_code)]
-    connections: Vec<VerifyConnection>,
-}
-
-#[derive(Debug, Deserialize)]
-struct VerifyDisconnectedParts {
-    #[serde(rename = "DisconnectedPart", default)]
-    #[allow(dead_code)]
-    parts: Vec<VerifyDisconnectedPart>,
-}
-
-#[derive(Debug, Deserialize)]
-struct VerifyDisconnectedPart {
-    #[serde(rename = "Parts")]
-    #[allow(dead_code)]
-    parts: VerifyParts,
-    #[serde(rename = "Connections")]
-    #[allow(dead_code)]
-    connects: VerifyConnections,
-}
-
-#[derive(Debug, Deserialize)]
-struct VerifyPart {
-    #[serde(rename = "Tank")]
-    #[allow(dead_code)]
-    tank: Option<VerifyFuel>,
-    #[serde(rename = "Engine")]
-    #[allow(dead_code)]
-    engine: Option<VerifyFuel>,
-    #[serde(rename = "Pod")]
-    #[allow(dead_code)]
-    pod: Option<VerifyPod>,
-    #[serde(rename = "@partType")]
-    #[allow(dead_code)]
-    part_type_id: String,
-    #[serde(rename = "@id")]
-    #[allow(dead_code)]
-    id: i64,
-    #[serde(rename = "@x")]
-    #[allow(dead_code)]
-    x: f64,
-    #[serde(rename = "@y")]
-    #[allow(dead_code)]
-    y: f64,
-    #[serde(rename = "@editorAngle", default = "default_editor_angle")]
-    #[allow(dead_code)]
-    editor_angle: i32,
-    #[serde(rename = "@angle")]
-    #[allow(dead_code)]
-    angle: f64,
-    #[serde(rename = "@angleV")]
-    #[allow(dead_code)]
-    angle_v: f64,
-    #[serde(rename = "@flippedX")]
-    #[allow(dead_code)]
-    flip_x: Option<i8>,
-    #[serde(rename = "@flippedY")]
-    #[allow(dead_code)]
-    flip_y: Option<i8>,
-    #[serde(rename = "@chuteX")]
-    #[allow(dead_code)]
-    chute_x: Option<f64>,
-    #[serde(rename = "@chuteY")]
-    #[allow(dead_code)]
-    chute_y: Option<f64>,
-    #[serde(rename = "@chuteAngle")]
-    #[allow(dead_code)]
-    chute_angle: Option<f64>,
-    #[serde(rename = "@chuteHeight")]
-    #[allow(dead_code)]
-    chute_height: Option<f64>,
-    #[serde(rename = "@extension")]
-    #[allow(dead_code)]
-    extension: Option<f64>,
-    #[serde(rename = "@inflate")]
-    #[allow(dead_code)]
-    inflate: Option<i8>,
-    #[serde(rename = "@inflation")]
-    #[allow(dead_code)]
-    inflation: Option<f64>,
-    #[serde(rename = "@exploded")]
-    #[allow(dead_code)]
-    exploded: Option<i8>,
-    #[serde(rename = "@rope")]
-    #[allow(dead_code)]
-    rope: Option<i8>,
-    #[serde(rename = "@activated")]
-    #[allow(dead_code)]
-    activated: Option<i8>,
-    #[serde(rename = "@deployed")]
-    #[allow(dead_code)]
-    deployed: Option<i8>,
-}
-
-#[derive(Debug, Deserialize)]
-struct VerifyFuel {
-    #[serde(rename = "@fuel")]
-    #[allow(dead_code)]
-    fuel: f64,
-}
-
-#[derive(Debug, Deserialize)]
-struct VerifyPod {
-    #[serde(rename = "Staging")]
-    #[allow(dead_code)]
-    stages: VerifyStaging,
-    #[serde(rename = "@name")]
-    #[allow(dead_code)]
-    name: String,
-    #[serde(rename = "@throttle")]
-    #[allow(dead_code)]
-    throttle: f64,
-}
-
-#[derive(Debug, Deserialize)]
-struct VerifyStaging {
-    #[serde(rename = "@currentStage")]
-    #[allow(dead_code)]
-    current_stage: i32,
-    #[serde(rename = "Step", default)]
-    #[allow(dead_code)]
-    steps: Vec<VerifyStep>,
-}
-
-#[derive(Debug, Deserialize)]
-struct VerifyStep {
-    #[serde(rename = "Activate", default)]
-    #[allow(dead_code)]
-    activates: Vec<VerifyActivate>,
+    Ok(())
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename = "Activate")]
-struct VerifyActivate {
-    #[serde(rename = "@Id")]
-    #[allow(dead_code)]
-    id: i64,
-    #[serde(rename = "@moved")]
-    #[allow(dead_code)]
-    moved: i8,
+/// 一遍扫完, 见 [`crate::xml_part::stream`]
+pub fn verify_ship(data: &str) -> ShipVerifyState {
+    ShipVerifyState::from_summary(&scan(data, PartCatalog::global()))
 }
 
-#[derive(Debug, Deserialize)]
-enum VerifyConnection {
-    #[serde(rename = "Connection")]
-    Normal {
-        #[serde(rename = "@parentAttachPoint")]
-        #[allow(dead_code)]
-        parent_attach_point: i32,
-        #[serde(rename = "@childAttachPoint")]
-        #[allow(dead_code)]
-        child_attach_point: i32,
-        #[serde(rename = "@parentPart")]
-        #[allow(dead_code)]
-        parent_part: i64,
-        #[serde(rename = "@childPart")]
-        #[allow(dead_code)]
-        child_part: i64,
-    },
-    #[serde(rename = "DockConnection")]
-    Dock {
-        #[serde(rename = "@dockPart")]
-        #[allow(dead_code)]
-        dock_part: i64,
-        #[serde(rename = "@parentPart")]
-        #[allow(dead_code)]
-        parent_part: i64,
-        #[serde(rename = "@childPart")]
-        #[allow(dead_code)]
-        child_part: i64,
-    },
+pub fn verify_save(data: &str) -> SaveVerifyState {
+    SaveVerifyState::from_summary(&scan(data, PartCatalog::global()))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{ShipVerifyState, verify_ship};
+    use super::{SaveVerifyState, ShipVerifyState, verify_save, verify_ship};
+
+    const SAMPLE_SAVE: &str = include_str!("../save_1294489.xml");
 
     #[test]
     fn verify_ship_reports_not_xml() {
@@ -720,7 +390,7 @@ mod tests {
     <Part partType="fueltank-1" id="2" x="1" y="0" editorAngle="0" angle="0" angleV="0" />
   </Parts>
   <Connections>
-    <Connection parentAttachPoint="0" childAttachPoint="1" parentPart="1" childPart="2" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
     <Connection parentAttachPoint="0" childAttachPoint="1" parentPart="1" childPart="2" />
   </Connections>
   <DisconnectedParts />
@@ -770,11 +440,28 @@ mod tests {
     <Part partType="fueltank-1" id="2" x="1" y="0" editorAngle="0" angle="0" angleV="0" />
   </Parts>
   <Connections>
-    <Connection parentAttachPoint="0" childAttachPoint="1" parentPart="1" childPart="2" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
   </Connections>
   <DisconnectedParts />
 </Ship>
 "#;
         assert_eq!(verify_ship(xml), ShipVerifyState::VerifiedShip);
     }
+
+    #[test]
+    fn verify_save_reports_states() {
+        assert_eq!(verify_save("<Runtime"), SaveVerifyState::NotXml);
+        assert_eq!(
+            verify_save(crate::net::EMPTY_SHIP),
+            SaveVerifyState::NotSave
+        );
+        assert_eq!(
+            verify_save("<Runtime><Nodes /></Runtime>"),
+            SaveVerifyState::FakeSave
+        );
+        assert_eq!(verify_save(SAMPLE_SAVE), SaveVerifyState::VerifiedSave);
+        let broken = SAMPLE_SAVE.replacen(r#"shipId="1""#, r#"shipId="404""#, 1);
+        assert_eq!(verify_save(&broken), SaveVerifyState::BrokenSave);
+        assert_eq!(verify_ship(SAMPLE_SAVE), ShipVerifyState::NotShip);
+    }
 }
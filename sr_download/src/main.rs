@@ -1,11 +1,26 @@
 use std::{path::PathBuf, time::SystemTime};
 
-use clap::{ArgGroup, Parser};
+use clap::{ArgGroup, Parser, Subcommand};
 use colored::Colorize;
-use sr_download::{START_TIME, config, fast_mode, serve_mode};
+use sr_download::db_part::{
+    self, CoverStrategy, SaveType, canonical, import_dir, plausibility, salvage, similarity,
+    transfer,
+};
+use sr_download::{
+    START_TIME, SaveId, config, fast_mode, serve_mode,
+    web_part::thumbnail::{self, ThumbnailCache},
+    xml_part::catalog::PartCatalog,
+};
 use tracing::{Level, event};
 
 enum RunMode {
+    /// 一直跑的下载任务
+    Job(JobMode),
+    /// 导出导入、补算分数 / hash / 特征这些跑完就退出的子命令
+    Command(Command),
+}
+
+enum JobMode {
     /// 服务模式
     Serve,
     /// 快速模式
@@ -17,6 +32,7 @@ enum RunMode {
     about = "simple rocket下载器",
     version,
     author,
+    subcommand_negates_reqs = true,
     group(
         ArgGroup::new("mode")
             .required(true)
@@ -43,6 +59,69 @@ struct Cli {
     /// 快速同步模式(用于从零开始)
     #[arg(short = 'f', long = "fast", group = "mode")]
     fast: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 把一段记录导出成 tar 包 (xml 文件 + manifest.jsonl)
+    Export {
+        /// 输出文件
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+        /// 起始 id (包含)
+        #[arg(long = "start", default_value_t = 0)]
+        start: SaveId,
+        /// 结束 id (包含)
+        #[arg(long = "end", default_value_t = SaveId::MAX)]
+        end: SaveId,
+        /// 只导出这个类型
+        #[arg(long = "type", value_enum)]
+        save_type: Option<SaveType>,
+    },
+    /// 从 tar 包导入, 会校验 hash
+    Import {
+        /// 输入文件
+        input: PathBuf,
+        /// 已经有数据的时候怎么办
+        #[arg(long = "cover", value_enum, default_value = "cover-if-different")]
+        cover: CoverStrategy,
+    },
+    /// 把一个目录下的 xml 文件导进库里, save_id 从文件名或者 csv 里取
+    ImportDir {
+        /// xml 所在目录
+        dir: PathBuf,
+        /// `文件名,save_id` 格式的 csv
+        #[arg(long = "map")]
+        map: Option<PathBuf>,
+        /// 已经有数据的时候怎么办
+        #[arg(long = "cover", value_enum, default_value = "cover-if-different")]
+        cover: CoverStrategy,
+    },
+    /// 给库里的船和存档算可疑分数并存下来
+    Score {
+        /// 已经有分数的也重新算
+        #[arg(long = "rescore")]
+        rescore: bool,
+    },
+    /// 给库里的船和存档算规范 hash, 用来找换了 id / 挪了位置的重复上传
+    Canonical {
+        /// 已经算过的也重新算
+        #[arg(long = "rehash")]
+        rehash: bool,
+    },
+    /// 给库里的船和存档算查相似船用的特征
+    Features {
+        /// 已经算过的也重新算
+        #[arg(long = "recompute")]
+        recompute: bool,
+    },
+    /// 看看不是合法 xml 的记录有多少能修好 (只统计, 不改数据)
+    Salvage,
+    /// 给库里的船和存档补画缩略图, `--fast` 和导入进来的记录不会在后台画
+    Thumbnails,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -58,10 +137,12 @@ fn main() -> anyhow::Result<()> {
         tracing_subscriber::fmt().with_max_level(Level::INFO).init();
     }
 
-    let mode = if cli.serve {
-        RunMode::Serve
+    let mode = if let Some(command) = cli.command {
+        RunMode::Command(command)
+    } else if cli.serve {
+        RunMode::Job(JobMode::Serve)
     } else if cli.fast {
-        RunMode::Fast
+        RunMode::Job(JobMode::Fast)
     } else {
         event!(
             Level::ERROR,
@@ -87,6 +168,14 @@ fn main() -> anyhow::Result<()> {
 }
 
 async fn async_main(run_mode: RunMode) -> anyhow::Result<()> {
+    match run_mode {
+        RunMode::Job(job_mode) => job_main(job_mode).await,
+        // 子命令跑完就退出, 不用等 Ctrl-C
+        RunMode::Command(command) => command_main(command).await,
+    }
+}
+
+async fn job_main(job_mode: JobMode) -> anyhow::Result<()> {
     let (stop_sender, stop_receiver) = tokio::sync::oneshot::channel::<()>();
 
     let stop_waiter = tokio::spawn(async move {
@@ -97,11 +186,128 @@ async fn async_main(run_mode: RunMode) -> anyhow::Result<()> {
         stop_sender.send(()).unwrap();
     });
 
-    let job_waiter = match run_mode {
-        RunMode::Serve => tokio::spawn(serve_mode::main(stop_receiver)),
-        RunMode::Fast => tokio::spawn(fast_mode::main(stop_receiver)),
+    let job_waiter = match job_mode {
+        JobMode::Serve => tokio::spawn(serve_mode::main(stop_receiver)),
+        JobMode::Fast => tokio::spawn(fast_mode::main(stop_receiver)),
     };
     job_waiter.await??;
     let _ = stop_waiter.await;
     Ok(())
 }
+
+async fn command_main(command: Command) -> anyhow::Result<()> {
+    let conf = config::ConfigFile::get_global();
+    let db = db_part::connect(conf).await?;
+    // 只建表/升级表结构, 补下载空数据那些是下载任务的事
+    db_part::updates::update_db(&db, conf).await;
+
+    match command {
+        Command::Export {
+            output,
+            start,
+            end,
+            save_type,
+        } => {
+            let filter = transfer::ExportFilter {
+                start_id: start,
+                end_id: end,
+                save_type,
+            };
+            let count = transfer::export(&db, &output, filter).await?;
+            event!(
+                Level::INFO,
+                "{}",
+                format!("导出了 {} 条记录到 {}", count, output.display()).green()
+            );
+        }
+        Command::Import { input, cover } => {
+            let stats = transfer::import(&db, &input, cover).await?;
+            event!(
+                Level::INFO,
+                "{}",
+                format!(
+                    "导入完成: 写入 {} 条, 跳过 {} 条, 校验失败 {} 条",
+                    stats.saved, stats.skipped, stats.invalid
+                )
+                .green()
+            );
+        }
+        Command::ImportDir { dir, map, cover } => {
+            let id_map = map.as_deref().map(import_dir::read_id_map).transpose()?;
+            let stats = import_dir::import_dir(&db, &dir, id_map.as_ref(), cover).await?;
+            event!(
+                Level::INFO,
+                "{}",
+                format!(
+                    "导入完成: 写入 {} 条, 跳过 {} 条, 没有 id {} 条, 解析失败 {} 条, 重复 {} 条",
+                    stats.saved, stats.skipped, stats.no_id, stats.parse_failed, stats.duplicates
+                )
+                .green()
+            );
+        }
+        Command::Score { rescore } => {
+            let stats = plausibility::score_records(&db, PartCatalog::global(), rescore).await?;
+            event!(
+                Level::INFO,
+                "{}",
+                format!(
+                    "打分完成: 打分 {} 条, 可疑 {} 条, 解析失败 {} 条, 跳过 {} 条",
+                    stats.scored, stats.suspicious, stats.unparsed, stats.skipped
+                )
+                .green()
+            );
+        }
+        Command::Canonical { rehash } => {
+            let stats = canonical::hash_records(&db, rehash).await?;
+            event!(
+                Level::INFO,
+                "{}",
+                format!(
+                    "规范 hash 算完了: 算了 {} 条, 解析失败 {} 条, 跳过 {} 条",
+                    stats.hashed, stats.unparsed, stats.skipped
+                )
+                .green()
+            );
+        }
+        Command::Features { recompute } => {
+            let stats = similarity::compute_records(&db, PartCatalog::global(), recompute).await?;
+            event!(
+                Level::INFO,
+                "{}",
+                format!(
+                    "特征算完了: 算了 {} 条, 解析失败 {} 条, 跳过 {} 条",
+                    stats.computed, stats.unparsed, stats.skipped
+                )
+                .green()
+            );
+        }
+        Command::Salvage => {
+            let stats = salvage::salvage_records(&db).await?;
+            event!(
+                Level::INFO,
+                "{}",
+                format!(
+                    "检查完了: 不是 xml 的 {} 条, 能修好 {} 条 (一共修了 {} 处), 修不好 {} 条",
+                    stats.not_xml, stats.salvaged, stats.repairs, stats.failed
+                )
+                .green()
+            );
+        }
+        Command::Thumbnails => {
+            let stats =
+                thumbnail::render_records(&db, ThumbnailCache::global(), PartCatalog::global())
+                    .await?;
+            event!(
+                Level::INFO,
+                "{}",
+                format!(
+                    "缩略图画完了: 画了 {} 条, 画不出来 {} 条, 跳过 {} 条",
+                    stats.rendered, stats.failed, stats.skipped
+                )
+                .green()
+            );
+        }
+    }
+    db.close().await;
+    Ok(())
+}
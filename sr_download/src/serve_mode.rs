@@ -77,8 +77,11 @@ pub async fn main(mut stop_receiver: Receiver<()>) -> anyhow::Result<()> {
             )
             .await
             {
-                Ok(_) => {
+                Ok(saved) => {
                     db_max_id = work_id;
+                    if saved {
+                        web_part::thumbnail::enqueue(work_id);
+                    }
                     event!(
                         Level::INFO,
                         "{}",
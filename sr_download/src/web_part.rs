@@ -3,7 +3,10 @@ use std::{
     time::Duration,
 };
 
-use axum::{Router, routing::get};
+use axum::{
+    Router,
+    routing::{get, post},
+};
 use tracing::{Level, event};
 
 use crate::db_part;
@@ -13,12 +16,16 @@ pub mod cache;
 pub mod handlers;
 pub mod models;
 pub mod response;
+pub mod thumbnail;
 pub mod traits;
 
 use handlers::{
-    api_overview, api_record_detail, api_record_raw, api_service_status, dashboard_page,
-    empty_info, empty_resync, get_data_by_id, get_data_info_by_id, get_last_data, get_last_save,
-    get_last_ship, jump_to_dashboard, jump_to_dashboard_from_root, resync_request,
+    api_canonical_records, api_diff, api_json_to_xml, api_overview, api_record_canonical,
+    api_record_detail, api_record_parsed, api_record_plausibility, api_record_preview,
+    api_record_raw, api_record_staging, api_record_stats, api_record_thumbnail,
+    api_record_validation, api_service_status, api_similar_records, api_suspicious_records,
+    dashboard_page, empty_info, empty_resync, get_data_by_id, get_data_info_by_id, get_last_data,
+    get_last_save, get_last_ship, jump_to_dashboard, jump_to_dashboard_from_root, resync_request,
 };
 
 pub static WEB_REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
@@ -57,6 +64,7 @@ pub async fn web_main() -> anyhow::Result<()> {
         Duration::from_micros(conf.serve.refresh_interval as u64),
     )
     .await;
+    thumbnail::start_worker(db.clone());
     let app = Router::new()
         .route("/last/data", get(get_last_data).post(get_last_data))
         .route("/last/save", get(get_last_save).post(get_last_save))
@@ -73,6 +81,22 @@ pub async fn web_main() -> anyhow::Result<()> {
         .route("/api/service", get(api_service_status))
         .route("/api/records/{id}", get(api_record_detail))
         .route("/api/records/{id}/raw", get(api_record_raw))
+        .route("/api/records/{id}/stats", get(api_record_stats))
+        .route("/api/records/{id}/staging", get(api_record_staging))
+        .route("/api/records/{id}/validation", get(api_record_validation))
+        .route("/api/records/{id}/parsed", get(api_record_parsed))
+        .route("/api/xml", post(api_json_to_xml))
+        .route(
+            "/api/records/{id}/plausibility",
+            get(api_record_plausibility),
+        )
+        .route("/api/plausibility", get(api_suspicious_records))
+        .route("/api/records/{id}/preview.svg", get(api_record_preview))
+        .route("/api/records/{id}/thumb.png", get(api_record_thumbnail))
+        .route("/api/diff", get(api_diff))
+        .route("/api/records/{id}/canonical", get(api_record_canonical))
+        .route("/api/canonical/{hash}", get(api_canonical_records))
+        .route("/api/records/{id}/similar", get(api_similar_records))
         .route("/dashboard", get(dashboard_page).post(dashboard_page))
         .route("/dashboard.html", get(dashboard_page).post(dashboard_page))
         .route("/favicon.ico", get(assets::favicon).post(assets::favicon))
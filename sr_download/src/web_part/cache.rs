@@ -1,13 +1,14 @@
 use std::{sync::atomic::AtomicBool, time::Duration};
 
-use sqlx::PgPool;
 use tokio::sync::RwLock;
 
+use crate::db_part::DbPool;
+
 #[derive(Debug, Clone)]
 pub struct CacheData {}
 
 impl CacheData {
-    pub async fn new_from_db(_db: &PgPool) -> Self {
+    pub async fn new_from_db(_db: &DbPool) -> Self {
         Self {}
     }
 }
@@ -20,7 +21,7 @@ pub struct WebCache {
 }
 
 impl WebCache {
-    pub async fn new(db: &PgPool, refresh_interval: Duration) -> Self {
+    pub async fn new(db: &DbPool, refresh_interval: Duration) -> Self {
         let cache = CacheData::new_from_db(db).await;
         Self {
             updating: AtomicBool::new(false),
@@ -2,46 +2,59 @@ use std::{sync::LazyLock, time::Duration};
 
 use axum::{
     Json,
-    extract::{Path, State},
-    http::{HeaderMap, StatusCode},
-    response::{Html, IntoResponse},
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Response},
 };
-use sqlx::PgPool;
+use serde::Deserialize;
 
 use crate::{
     Downloader, SaveId,
-    db_part::{self, DbData, SaveType, utils::FromDb},
+    db_part::{self, DbData, DbPool, SaveType, canonical, plausibility, similarity, utils::FromDb},
+    xml_part::{
+        catalog::PartCatalog,
+        json::{ModelJson, json_to_xml},
+        model::{ShipData, XmlDocument},
+        plausibility::SUSPICIOUS_SCORE,
+        render::RenderOptions,
+        thumbnail::ThumbSize,
+        verify::ValidationReport,
+    },
 };
 
 use super::{
     INFO_PAGE, RESYNC_TOKEN, api_request_counter_pp,
     models::{
-        DashboardOverview, LastData, LastSave, LastShip, RawData, RecordDetail, ServiceStatus,
+        CanonicalList, DashboardOverview, LastData, LastSave, LastShip, ParseFailure, RawData,
+        RecordCanonical, RecordDetail, RecordDiff, RecordParsed, RecordPlausibility, RecordStaging,
+        RecordStats, RecordValidation, ServiceStatus, SimilarList, SuspiciousList,
+        SuspiciousRecord,
     },
     response::WebResponse,
+    thumbnail::{self, ThumbnailCache},
     web_request_counter_pp,
 };
 
 pub static RESYNC_DOWNLOADER: LazyLock<Downloader> =
     LazyLock::new(|| Downloader::new(Some(Duration::from_secs(10))));
 
-pub async fn get_last_data(State(db): State<PgPool>) -> Json<WebResponse<LastData>> {
+pub async fn get_last_data(State(db): State<DbPool>) -> Json<WebResponse<LastData>> {
     api_request_counter_pp();
     Json(WebResponse::new_with_data(LastData::from_db(&db).await))
 }
 
-pub async fn get_last_save(State(db): State<PgPool>) -> Json<WebResponse<LastSave>> {
+pub async fn get_last_save(State(db): State<DbPool>) -> Json<WebResponse<LastSave>> {
     api_request_counter_pp();
     Json(WebResponse::new_with_data(LastSave::from_db(&db).await))
 }
 
-pub async fn get_last_ship(State(db): State<PgPool>) -> Json<WebResponse<LastShip>> {
+pub async fn get_last_ship(State(db): State<DbPool>) -> Json<WebResponse<LastShip>> {
     api_request_counter_pp();
     Json(WebResponse::new_with_data(LastShip::from_db(&db).await))
 }
 
 pub async fn get_data_info_by_id(
-    State(db): State<PgPool>,
+    State(db): State<DbPool>,
     Path(raw_id): Path<String>,
 ) -> Json<WebResponse<LastData>> {
     api_request_counter_pp();
@@ -64,7 +77,7 @@ pub async fn empty_info() -> Json<WebResponse<()>> {
 }
 
 pub async fn get_data_by_id(
-    State(db): State<PgPool>,
+    State(db): State<DbPool>,
     Path(raw_id): Path<String>,
 ) -> Json<WebResponse<RawData>> {
     api_request_counter_pp();
@@ -80,7 +93,7 @@ pub async fn get_data_by_id(
     }
 }
 
-pub async fn api_overview(State(db): State<PgPool>) -> Json<WebResponse<DashboardOverview>> {
+pub async fn api_overview(State(db): State<DbPool>) -> Json<WebResponse<DashboardOverview>> {
     api_request_counter_pp();
     Json(WebResponse::new_normal(DashboardOverview {
         latest_data: LastData::from_db(&db).await,
@@ -96,7 +109,7 @@ pub async fn api_service_status() -> Json<WebResponse<ServiceStatus>> {
 }
 
 pub async fn api_record_detail(
-    State(db): State<PgPool>,
+    State(db): State<DbPool>,
     Path(raw_id): Path<String>,
 ) -> Json<WebResponse<RecordDetail>> {
     api_request_counter_pp();
@@ -123,7 +136,7 @@ pub async fn api_record_detail(
 }
 
 pub async fn api_record_raw(
-    State(db): State<PgPool>,
+    State(db): State<DbPool>,
     Path(raw_id): Path<String>,
 ) -> Json<WebResponse<RawData>> {
     api_request_counter_pp();
@@ -139,6 +152,489 @@ pub async fn api_record_raw(
     }
 }
 
+/// 按 id 读出记录
+async fn load_record<T>(db: &DbPool, raw_id: &str) -> Result<DbData, Json<WebResponse<T>>> {
+    let id = raw_id.parse::<SaveId>().map_err(|e| {
+        Json(WebResponse::new_error(
+            StatusCode::BAD_REQUEST,
+            format!("id parse error: {e:?}"),
+        ))
+    })?;
+    DbData::from_db(id, db)
+        .await
+        .ok_or_else(|| Json(WebResponse::new_missing("data not found")))
+}
+
+/// `blocking` 里出错的时候返回的状态码和原因
+type WorkError = (StatusCode, String);
+
+/// 解析和计算放到 blocking 线程上跑, 几 MB 的存档不能卡住 runtime 的线程
+async fn blocking<T, R>(
+    work: impl FnOnce() -> Result<R, WorkError> + Send + 'static,
+) -> Result<R, Json<WebResponse<T>>>
+where
+    R: Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(work).await.unwrap_or_else(|e| {
+        Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("worker failed: {e}"),
+        ))
+    });
+    result.map_err(|(status, msg)| Json(WebResponse::new_error(status, msg)))
+}
+
+/// 把记录解析成 xml, 在 [`blocking`] 里调
+fn parse_document(data: &DbData) -> Result<XmlDocument, WorkError> {
+    data.parse_xml().map_err(|e| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("xml parse error: {e}"),
+        )
+    })
+}
+
+/// 记录里的船, 存档的话是玩家当前那条, 在 [`blocking`] 里调
+fn parse_main_ship(data: &DbData) -> Result<ShipData, WorkError> {
+    match parse_document(data)?.main_ship() {
+        Some(ship) => Ok(ship.clone()),
+        None => Err((StatusCode::NOT_FOUND, "no ship in this save".to_string())),
+    }
+}
+
+pub async fn api_record_stats(
+    State(db): State<DbPool>,
+    Path(raw_id): Path<String>,
+) -> Json<WebResponse<RecordStats>> {
+    api_request_counter_pp();
+    let data = match load_record(&db, &raw_id).await {
+        Ok(data) => data,
+        Err(resp) => return resp,
+    };
+    blocking(move || {
+        let ship = parse_main_ship(&data)?;
+        Ok(Json(WebResponse::new_normal(RecordStats {
+            save_id: data.save_id,
+            save_type: data.save_type.to_string(),
+            stats: ship.stats(PartCatalog::global()),
+        })))
+    })
+    .await
+    .unwrap_or_else(|resp| resp)
+}
+
+pub async fn api_record_staging(
+    State(db): State<DbPool>,
+    Path(raw_id): Path<String>,
+) -> Json<WebResponse<RecordStaging>> {
+    api_request_counter_pp();
+    let data = match load_record(&db, &raw_id).await {
+        Ok(data) => data,
+        Err(resp) => return resp,
+    };
+    blocking(move || {
+        let ship = parse_main_ship(&data)?;
+        Ok(Json(WebResponse::new_normal(RecordStaging {
+            save_id: data.save_id,
+            save_type: data.save_type.to_string(),
+            staging: ship.simulate_staging(PartCatalog::global()),
+        })))
+    })
+    .await
+    .unwrap_or_else(|resp| resp)
+}
+
+pub async fn api_record_validation(
+    State(db): State<DbPool>,
+    Path(raw_id): Path<String>,
+) -> Json<WebResponse<RecordValidation>> {
+    api_request_counter_pp();
+    let data = match load_record(&db, &raw_id).await {
+        Ok(data) => data,
+        Err(resp) => return resp,
+    };
+    blocking(move || {
+        // 解析不了也算校验结果, 带上出错的位置
+        let (report, parse_error) = match data.parse_xml() {
+            Ok(doc) => (doc.validate(PartCatalog::global()), None),
+            Err(e) => (
+                ValidationReport::default(),
+                Some(ParseFailure {
+                    message: e.to_string(),
+                    position: e.position().cloned(),
+                }),
+            ),
+        };
+        Ok(Json(WebResponse::new_normal(RecordValidation {
+            save_id: data.save_id,
+            save_type: data.save_type.to_string(),
+            valid: parse_error.is_none() && report.is_valid(),
+            report,
+            parse_error,
+        })))
+    })
+    .await
+    .unwrap_or_else(|resp| resp)
+}
+
+pub async fn api_record_parsed(
+    State(db): State<DbPool>,
+    Path(raw_id): Path<String>,
+) -> Json<WebResponse<RecordParsed>> {
+    api_request_counter_pp();
+    let data = match load_record(&db, &raw_id).await {
+        Ok(data) => data,
+        Err(resp) => return resp,
+    };
+    blocking(move || {
+        let doc = parse_document(&data)?;
+        Ok(Json(WebResponse::new_normal(RecordParsed {
+            save_id: data.save_id,
+            save_type: data.save_type.to_string(),
+            model: ModelJson::new(doc),
+        })))
+    })
+    .await
+    .unwrap_or_else(|resp| resp)
+}
+
+/// 把 `/api/records/{id}/parsed` 那样的 JSON (有 `schema_version` 和 `document` 就行) 写回 xml
+pub async fn api_json_to_xml(body: String) -> Response {
+    api_request_counter_pp();
+    match json_to_xml(&body) {
+        Ok(xml) => ([(header::CONTENT_TYPE, "application/xml")], xml).into_response(),
+        Err(e) => Json(WebResponse::<()>::new_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("{e}"),
+        ))
+        .into_response(),
+    }
+}
+
+pub async fn api_record_plausibility(
+    State(db): State<DbPool>,
+    Path(raw_id): Path<String>,
+) -> Json<WebResponse<RecordPlausibility>> {
+    api_request_counter_pp();
+    let data = match load_record(&db, &raw_id).await {
+        Ok(data) => data,
+        Err(resp) => return resp,
+    };
+    let result = blocking(move || {
+        let report = parse_document(&data)?.plausibility(PartCatalog::global());
+        Ok((data, report))
+    })
+    .await;
+    let (data, report) = match result {
+        Ok(scored) => scored,
+        Err(resp) => return resp,
+    };
+    let stored = plausibility::load_score(&db, data.save_id).await;
+    Json(WebResponse::new_normal(RecordPlausibility {
+        save_id: data.save_id,
+        save_type: data.save_type.to_string(),
+        suspicious: report.is_suspicious(),
+        report,
+        stored,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct SuspiciousQuery {
+    /// 默认是 [`SUSPICIOUS_SCORE`]
+    min_score: Option<u32>,
+    /// 翻页用, 从这个 id 之后开始
+    #[serde(default)]
+    after: SaveId,
+    limit: Option<i64>,
+}
+
+pub async fn api_suspicious_records(
+    State(db): State<DbPool>,
+    Query(query): Query<SuspiciousQuery>,
+) -> Json<WebResponse<SuspiciousList>> {
+    api_request_counter_pp();
+    const MAX_LIMIT: i64 = 500;
+
+    let min_score = query.min_score.unwrap_or(SUSPICIOUS_SCORE);
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_LIMIT);
+    match plausibility::suspicious_records(&db, min_score, query.after, limit).await {
+        Ok(records) => Json(WebResponse::new_normal(SuspiciousList {
+            min_score,
+            records: records
+                .into_iter()
+                .map(|(save_id, score)| SuspiciousRecord { save_id, score })
+                .collect(),
+        })),
+        Err(e) => Json(WebResponse::new_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to load scores: {e}"),
+        )),
+    }
+}
+
+/// 一页最多这么多条
+const CANONICAL_MAX_LIMIT: i64 = 500;
+
+pub async fn api_record_canonical(
+    State(db): State<DbPool>,
+    Path(raw_id): Path<String>,
+) -> Json<WebResponse<RecordCanonical>> {
+    api_request_counter_pp();
+    let data = match load_record(&db, &raw_id).await {
+        Ok(data) => data,
+        Err(resp) => return resp,
+    };
+    let result = blocking(move || {
+        let canonical_hash = parse_main_ship(&data)?.canonical_hash().map_err(|e| {
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("canonical hash error: {e}"),
+            )
+        })?;
+        Ok((data, canonical_hash))
+    })
+    .await;
+    let (data, canonical_hash) = match result {
+        Ok(hashed) => hashed,
+        Err(resp) => return resp,
+    };
+    let same_design =
+        match canonical::records_with_hash(&db, &canonical_hash, 0, CANONICAL_MAX_LIMIT).await {
+            Ok(records) => records
+                .into_iter()
+                .filter(|id| *id != data.save_id)
+                .collect(),
+            Err(e) => {
+                return Json(WebResponse::new_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("failed to load canonical hashes: {e}"),
+                ));
+            }
+        };
+    Json(WebResponse::new_normal(RecordCanonical {
+        save_id: data.save_id,
+        save_type: data.save_type.to_string(),
+        canonical_hash: Some(canonical_hash),
+        stored: canonical::load_canonical(&db, data.save_id).await,
+        same_design,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct CanonicalQuery {
+    /// 翻页用, 从这个 id 之后开始
+    #[serde(default)]
+    after: SaveId,
+    limit: Option<i64>,
+}
+
+pub async fn api_canonical_records(
+    State(db): State<DbPool>,
+    Path(hash): Path<String>,
+    Query(query): Query<CanonicalQuery>,
+) -> Json<WebResponse<CanonicalList>> {
+    api_request_counter_pp();
+    let hash = hash.to_ascii_lowercase();
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Json(WebResponse::new_error(
+            StatusCode::BAD_REQUEST,
+            "canonical hash should be 64 hex digits",
+        ));
+    }
+    let limit = query.limit.unwrap_or(100).clamp(1, CANONICAL_MAX_LIMIT);
+    match canonical::records_with_hash(&db, &hash, query.after, limit).await {
+        Ok(records) => Json(WebResponse::new_normal(CanonicalList {
+            canonical_hash: hash,
+            records,
+        })),
+        Err(e) => Json(WebResponse::new_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to load canonical hashes: {e}"),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SimilarQuery {
+    limit: Option<usize>,
+}
+
+/// 库里存了特征就用存的, 没有的话现算
+pub async fn api_similar_records(
+    State(db): State<DbPool>,
+    Path(raw_id): Path<String>,
+    Query(query): Query<SimilarQuery>,
+) -> Json<WebResponse<SimilarList>> {
+    api_request_counter_pp();
+    const MAX_LIMIT: usize = 100;
+
+    let stored = match raw_id.parse::<SaveId>() {
+        Ok(id) => similarity::load_features(&db, id)
+            .await
+            .and_then(|stored| stored.features.map(|features| (id, features))),
+        Err(_) => None,
+    };
+    let (save_id, features) = match stored {
+        Some(stored) => stored,
+        None => {
+            let data = match load_record(&db, &raw_id).await {
+                Ok(data) => data,
+                Err(resp) => return resp,
+            };
+            let result = blocking(move || {
+                let ship = parse_main_ship(&data)?;
+                Ok((data.save_id, ship.features(PartCatalog::global())))
+            })
+            .await;
+            match result {
+                Ok(computed) => computed,
+                Err(resp) => return resp,
+            }
+        }
+    };
+    let limit = query.limit.unwrap_or(10).clamp(1, MAX_LIMIT);
+    match similarity::similar_records(&db, &features, save_id, limit).await {
+        Ok(records) => Json(WebResponse::new_normal(SimilarList {
+            save_id,
+            features,
+            records,
+        })),
+        Err(e) => Json(WebResponse::new_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to load features: {e}"),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    a: String,
+    b: String,
+}
+
+/// 比较两条记录的主船, 从 `a` 到 `b` 变了什么
+pub async fn api_diff(
+    State(db): State<DbPool>,
+    Query(query): Query<DiffQuery>,
+) -> Json<WebResponse<RecordDiff>> {
+    api_request_counter_pp();
+    let data_a = match load_record(&db, &query.a).await {
+        Ok(data) => data,
+        Err(resp) => return resp,
+    };
+    let data_b = match load_record(&db, &query.b).await {
+        Ok(data) => data,
+        Err(resp) => return resp,
+    };
+    blocking(move || {
+        let diff = parse_main_ship(&data_a)?.diff(&parse_main_ship(&data_b)?);
+        Ok(Json(WebResponse::new_normal(RecordDiff {
+            a: data_a.save_id,
+            b: data_b.save_id,
+            a_type: data_a.save_type.to_string(),
+            b_type: data_b.save_type.to_string(),
+            same_hash: data_a.blake_hash == data_b.blake_hash,
+            change_count: diff.change_count(),
+            diff,
+        })))
+    })
+    .await
+    .unwrap_or_else(|resp| resp)
+}
+
+#[derive(Deserialize)]
+pub struct PreviewQuery {
+    /// 把连接也画出来
+    #[serde(default)]
+    connections: bool,
+}
+
+/// 出错的时候还是返回 json
+pub async fn api_record_preview(
+    State(db): State<DbPool>,
+    Path(raw_id): Path<String>,
+    Query(query): Query<PreviewQuery>,
+) -> Response {
+    api_request_counter_pp();
+    let data = match load_record::<()>(&db, &raw_id).await {
+        Ok(data) => data,
+        Err(resp) => return resp.into_response(),
+    };
+    let options = RenderOptions {
+        connections: query.connections,
+        ..Default::default()
+    };
+    blocking::<(), _>(move || {
+        let svg = parse_document(&data)?.render_svg(PartCatalog::global(), &options);
+        Ok(([(header::CONTENT_TYPE, "image/svg+xml")], svg).into_response())
+    })
+    .await
+    .unwrap_or_else(IntoResponse::into_response)
+}
+
+#[derive(Deserialize)]
+pub struct ThumbnailQuery {
+    #[serde(default)]
+    size: ThumbSize,
+}
+
+/// 缩略图按 hash 缓存, 所以 ETag 就是 hash 加尺寸
+pub async fn api_record_thumbnail(
+    headers: HeaderMap,
+    State(db): State<DbPool>,
+    Path(raw_id): Path<String>,
+    Query(query): Query<ThumbnailQuery>,
+) -> Response {
+    api_request_counter_pp();
+    let id = match raw_id.parse::<SaveId>() {
+        Ok(id) => id,
+        Err(e) => {
+            return Json(WebResponse::<()>::new_error(
+                StatusCode::BAD_REQUEST,
+                format!("id parse error: {e:?}"),
+            ))
+            .into_response();
+        }
+    };
+    let Some(data) = DbData::from_db(id, &db).await else {
+        return Json(WebResponse::<()>::new_missing("data not found")).into_response();
+    };
+
+    let etag = format!("\"{}-{}\"", data.blake_hash, query.size);
+    let cache_headers = [
+        (header::CACHE_CONTROL, "public, max-age=86400".to_string()),
+        (header::ETAG, etag.clone()),
+    ];
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| value.as_bytes() == etag.as_bytes())
+    {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+
+    let size = query.size;
+    let png = tokio::task::spawn_blocking(move || {
+        ThumbnailCache::global().get_or_render(&data, PartCatalog::global(), size)
+    })
+    .await;
+    match png {
+        Ok(Some(png)) => {
+            (cache_headers, [(header::CONTENT_TYPE, "image/png")], png).into_response()
+        }
+        Ok(None) => Json(WebResponse::<()>::new_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "not a ship or save",
+        ))
+        .into_response(),
+        Err(e) => Json(WebResponse::<()>::new_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to render thumbnail: {e}"),
+        ))
+        .into_response(),
+    }
+}
+
 pub async fn jump_to_dashboard(Path(path): Path<String>) -> impl IntoResponse {
     (
         StatusCode::MOVED_PERMANENTLY,
@@ -165,7 +661,7 @@ pub async fn dashboard_page() -> Html<String> {
 
 pub async fn resync_request(
     headers: HeaderMap,
-    State(db): State<PgPool>,
+    State(db): State<DbPool>,
     Path(raw_id): Path<String>,
 ) -> Json<WebResponse<RawData>> {
     api_request_counter_pp();
@@ -193,6 +689,7 @@ pub async fn resync_request(
                     .await
                     {
                         Ok(true) => {
+                            thumbnail::enqueue(id);
                             let data = RawData::from_file(data, id);
                             Json(WebResponse::new_normal(data))
                         }
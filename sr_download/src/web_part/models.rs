@@ -1,13 +1,20 @@
 use std::io::Write;
 
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
 
 use crate::{
     SaveId,
-    db_part::{DbData, utils},
+    db_part::{
+        DbData, DbPool, canonical::StoredCanonical, plausibility::StoredScore,
+        similarity::SimilarRecord, utils,
+    },
     net::DownloadFile,
     web_part::{api_request_counter, service_uptime, web_request_counter},
+    xml_part::{
+        diff::ShipDiff, error::ErrorPosition, json::ModelJson, plausibility::PlausibilityReport,
+        similarity::ShipFeatures, staging::StagingReport, stats::ShipStats,
+        verify::ValidationReport,
+    },
 };
 
 #[derive(Serialize, Deserialize)]
@@ -20,7 +27,7 @@ pub struct LastData {
 }
 
 impl LastData {
-    pub async fn from_db_by_id(db: &PgPool, id: SaveId) -> Option<Self> {
+    pub async fn from_db_by_id(db: &DbPool, id: SaveId) -> Option<Self> {
         let data = DbData::from_db(id, db).await?;
         let xml_tested = data.verify_xml();
         Some(Self {
@@ -74,7 +81,7 @@ pub struct RawData {
 }
 
 impl RawData {
-    pub async fn from_db_by_id(db: &PgPool, id: SaveId) -> Option<Self> {
+    pub async fn from_db_by_id(db: &DbPool, id: SaveId) -> Option<Self> {
         let data = DbData::from_db(id, db).await?;
         let xml_tested = data.verify_xml();
         Some(Self {
@@ -136,3 +143,106 @@ pub struct RecordDetail {
     pub xml_status: String,
     pub raw_data: Option<String>,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordStats {
+    pub save_id: SaveId,
+    pub save_type: String,
+    pub stats: ShipStats,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordStaging {
+    pub save_id: SaveId,
+    pub save_type: String,
+    pub staging: StagingReport,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordValidation {
+    pub save_id: SaveId,
+    pub save_type: String,
+    /// 能解析, 而且没有 error 级别的问题
+    pub valid: bool,
+    pub report: ValidationReport,
+    /// 解析不了的话是原因, 这时候 `report` 是空的
+    pub parse_error: Option<ParseFailure>,
+}
+
+/// 解析好的 model, 格式见 [`crate::xml_part::json`]
+#[derive(Serialize, Deserialize)]
+pub struct RecordParsed {
+    pub save_id: SaveId,
+    pub save_type: String,
+    #[serde(flatten)]
+    pub model: ModelJson,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ParseFailure {
+    pub message: String,
+    /// 出错的位置, 见 [`ErrorPosition`]
+    pub position: Option<ErrorPosition>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordPlausibility {
+    pub save_id: SaveId,
+    pub save_type: String,
+    pub suspicious: bool,
+    /// 现算的
+    pub report: PlausibilityReport,
+    /// 库里存的, 还没打过分是 None
+    pub stored: Option<StoredScore>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuspiciousRecord {
+    pub save_id: SaveId,
+    #[serde(flatten)]
+    pub score: StoredScore,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuspiciousList {
+    pub min_score: u32,
+    pub records: Vec<SuspiciousRecord>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordCanonical {
+    pub save_id: SaveId,
+    pub save_type: String,
+    /// 现算的, 存档是玩家当前那条船
+    pub canonical_hash: Option<String>,
+    /// 库里存的, 还没算过是 None
+    pub stored: Option<StoredCanonical>,
+    /// 库里规范 hash 一样的其他记录, 最多 [`CanonicalList`] 一页那么多
+    pub same_design: Vec<SaveId>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CanonicalList {
+    pub canonical_hash: String,
+    pub records: Vec<SaveId>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SimilarList {
+    pub save_id: SaveId,
+    pub features: ShipFeatures,
+    /// 相似度从高到低
+    pub records: Vec<SimilarRecord>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordDiff {
+    pub a: SaveId,
+    pub b: SaveId,
+    pub a_type: String,
+    pub b_type: String,
+    /// 原始数据的 blake hash 一样
+    pub same_hash: bool,
+    pub change_count: usize,
+    pub diff: ShipDiff,
+}
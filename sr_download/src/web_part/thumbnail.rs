@@ -0,0 +1,286 @@
+//! 缩略图的磁盘缓存和后台生成
+//!
+//! 缓存按 `blake_hash` 存, `<thumbnail_dir>/<size>/<hash 前两位>/<hash>.png`
+//! 数据变了 hash 就变了, 所以不用管失效, 旧图片留着也没关系, 嫌占地方直接删目录就行
+//!
+//! serve 模式下载到新数据 / resync 之后会丢进队列, 后台一张张画好; 没画好之前请求的话就当场画
+//! `--fast`、`import`、`import-dir` 这些不经过 serve 模式进库的, 用 `srdownload thumbnails` 补画
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use tracing::{Level, event};
+
+use crate::{
+    SaveId,
+    db_part::{DbData, DbPool, SaveType, search},
+    xml_part::{
+        catalog::PartCatalog,
+        thumbnail::{ThumbSize, render_document_thumbnail},
+    },
+};
+
+static GLOBAL_CACHE: OnceLock<ThumbnailCache> = OnceLock::new();
+static QUEUE: OnceLock<UnboundedSender<SaveId>> = OnceLock::new();
+
+const BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThumbnailStats {
+    /// 画了的
+    pub rendered: usize,
+    /// 解析不了 / 画不出来的
+    pub failed: usize,
+    /// 每种尺寸都已经有了跳过的
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ThumbnailCache {
+    root: PathBuf,
+}
+
+impl ThumbnailCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// 按配置里的 `serve.thumbnail_dir`
+    pub fn global() -> &'static Self {
+        GLOBAL_CACHE.get_or_init(|| {
+            let conf = crate::config::ConfigFile::get_global();
+            Self::new(&conf.serve.thumbnail_dir)
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// hash 不是十六进制的话返回 None, 免得拼出奇怪的路径
+    pub fn path(&self, blake_hash: &str, size: ThumbSize) -> Option<PathBuf> {
+        if blake_hash.len() < 2 || !blake_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(
+            self.root
+                .join(size.as_str())
+                .join(&blake_hash[..2])
+                .join(format!("{blake_hash}.png")),
+        )
+    }
+
+    /// 每种尺寸都画好了
+    pub fn has_all(&self, blake_hash: &str) -> bool {
+        ThumbSize::ALL.into_iter().all(|size| {
+            self.path(blake_hash, size)
+                .is_some_and(|path| path.exists())
+        })
+    }
+
+    pub fn get(&self, blake_hash: &str, size: ThumbSize) -> Option<Vec<u8>> {
+        fs::read(self.path(blake_hash, size)?).ok()
+    }
+
+    pub fn put(&self, blake_hash: &str, size: ThumbSize, png: &[u8]) -> io::Result<()> {
+        let Some(path) = self.path(blake_hash, size) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("bad hash {blake_hash}"),
+            ));
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, png)?;
+        fs::rename(&tmp, &path)
+    }
+
+    /// 缓存里有就直接用, 没有就画一张存起来
+    ///
+    /// 不是船也不是存档 / 解析不了的返回 None, 缓存写不进去只打个日志
+    pub fn get_or_render(
+        &self,
+        data: &DbData,
+        catalog: &PartCatalog,
+        size: ThumbSize,
+    ) -> Option<Vec<u8>> {
+        if let Some(png) = self.get(&data.blake_hash, size) {
+            return Some(png);
+        }
+        if !matches!(data.save_type, SaveType::Ship | SaveType::Save) {
+            return None;
+        }
+        let doc = data.parse_xml().ok()?;
+        let png = match render_document_thumbnail(&doc, catalog, size) {
+            Ok(png) => png,
+            Err(e) => {
+                event!(Level::WARN, "{} 缩略图画不出来: {}", data.save_id, e);
+                return None;
+            }
+        };
+        if let Err(e) = self.put(&data.blake_hash, size, &png) {
+            event!(Level::WARN, "{} 缩略图缓存写入失败: {}", data.save_id, e);
+        }
+        Some(png)
+    }
+
+    /// 每种尺寸都画一张, 返回画了几张
+    pub fn render_all(&self, data: &DbData, catalog: &PartCatalog) -> usize {
+        ThumbSize::ALL
+            .into_iter()
+            .filter(|size| self.get_or_render(data, catalog, *size).is_some())
+            .count()
+    }
+}
+
+/// 给库里的船和存档补画缩略图, 已经画好的跳过
+pub async fn render_records(
+    db: &DbPool,
+    cache: &ThumbnailCache,
+    catalog: &PartCatalog,
+) -> anyhow::Result<ThumbnailStats> {
+    let mut stats = ThumbnailStats::default();
+    let mut after_id = 0;
+    loop {
+        let records = search::record_batch(db, after_id, SaveId::MAX, None, BATCH_SIZE).await?;
+        let Some(last) = records.last() else {
+            break;
+        };
+        after_id = last.data.save_id;
+
+        for record in records {
+            let data = record.data;
+            if !matches!(data.save_type, SaveType::Ship | SaveType::Save) {
+                continue;
+            }
+            if cache.has_all(&data.blake_hash) {
+                stats.skipped += 1;
+            } else if cache.render_all(&data, catalog) == ThumbSize::ALL.len() {
+                stats.rendered += 1;
+            } else {
+                stats.failed += 1;
+            }
+        }
+        event!(Level::INFO, "缩略图已经画到 {}", after_id);
+    }
+    Ok(stats)
+}
+
+/// 启动后台生成缩略图的任务, 只会启动一次
+pub fn start_worker(db: DbPool) {
+    let (sender, mut receiver) = unbounded_channel::<SaveId>();
+    if QUEUE.set(sender).is_err() {
+        return;
+    }
+    tokio::spawn(async move {
+        while let Some(save_id) = receiver.recv().await {
+            let Some(data) = DbData::from_db(save_id, &db).await else {
+                continue;
+            };
+            let result = tokio::task::spawn_blocking(move || {
+                ThumbnailCache::global().render_all(&data, PartCatalog::global())
+            })
+            .await;
+            match result {
+                Ok(count) => event!(Level::DEBUG, "{} 画好了 {} 张缩略图", save_id, count),
+                Err(e) => event!(Level::WARN, "{} 画缩略图的时候炸了: {}", save_id, e),
+            }
+        }
+    });
+}
+
+/// 把新存进来的记录丢给后台, 后台没启动的话什么都不做
+pub fn enqueue(save_id: SaveId) -> bool {
+    QUEUE
+        .get()
+        .is_some_and(|sender| sender.send(save_id).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ThumbnailCache, ThumbnailStats, render_records};
+    use crate::{
+        db_part::{DbData, SaveType, save_data_to_db, test_util::memory_db},
+        xml_part::{catalog::PartCatalog, thumbnail::ThumbSize},
+    };
+
+    #[test]
+    fn caches_by_hash() {
+        let dir = std::env::temp_dir().join(format!("sr_download_thumbs_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = ThumbnailCache::new(&dir);
+        let catalog = PartCatalog::embedded();
+
+        let ship = DbData::new(1, crate::net::EMPTY_SHIP.to_string(), SaveType::Ship);
+        assert!(cache.get(&ship.blake_hash, ThumbSize::Small).is_none());
+        assert_eq!(cache.render_all(&ship, catalog), 3);
+        let path = cache.path(&ship.blake_hash, ThumbSize::Small).unwrap();
+        assert!(path.starts_with(dir.join("small")));
+        assert_eq!(
+            cache.get(&ship.blake_hash, ThumbSize::Small),
+            Some(std::fs::read(&path).unwrap())
+        );
+
+        // 同样的内容换个 id 也是同一张图
+        let same = DbData::new(2, crate::net::EMPTY_SHIP.to_string(), SaveType::Ship);
+        assert!(cache.get(&same.blake_hash, ThumbSize::Large).is_some());
+
+        let broken = DbData::new(3, "<Ship".to_string(), SaveType::Ship);
+        assert!(
+            cache
+                .get_or_render(&broken, catalog, ThumbSize::Small)
+                .is_none()
+        );
+        let nothing = DbData::new(4, String::new(), SaveType::None);
+        assert_eq!(cache.render_all(&nothing, catalog), 0);
+
+        assert!(cache.path("../../etc", ThumbSize::Small).is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn backfills_records() {
+        let dir = std::env::temp_dir().join(format!(
+            "sr_download_thumbs_backfill_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = ThumbnailCache::new(&dir);
+        let catalog = PartCatalog::embedded();
+        let db = memory_db().await;
+        for (id, save_type, data) in [
+            (1, SaveType::Ship, crate::net::EMPTY_SHIP),
+            // 内容一样, 图是同一张
+            (2, SaveType::Ship, crate::net::EMPTY_SHIP),
+            (3, SaveType::Ship, "<Ship"),
+            (4, SaveType::None, ""),
+        ] {
+            save_data_to_db(id, save_type, data, None, &db)
+                .await
+                .unwrap();
+        }
+
+        let stats = render_records(&db, &cache, catalog).await.unwrap();
+        assert_eq!(
+            stats,
+            ThumbnailStats {
+                rendered: 1,
+                failed: 1,
+                skipped: 1,
+            }
+        );
+        let hash = DbData::from_db(1, &db).await.unwrap().blake_hash;
+        assert!(cache.has_all(&hash));
+
+        let again = render_records(&db, &cache, catalog).await.unwrap();
+        assert_eq!((again.rendered, again.skipped), (0, 2));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
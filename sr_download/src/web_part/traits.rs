@@ -1,11 +1,9 @@
-use sqlx::PgPool;
-
 use super::models::{LastData, LastSave, LastShip};
 use crate::db_part::utils::FromDb;
-use crate::db_part::{self, DbData};
+use crate::db_part::{self, DbData, DbPool};
 
 impl FromDb for LastData {
-    async fn from_db(db: &PgPool) -> Option<Self> {
+    async fn from_db(db: &DbPool) -> Option<Self> {
         let id = db_part::search::max_id(db).await;
         let data = DbData::from_db(id, db).await?;
         let xml_tested = data.verify_xml();
@@ -20,7 +18,7 @@ impl FromDb for LastData {
 }
 
 impl FromDb for LastSave {
-    async fn from_db(db: &PgPool) -> Option<Self> {
+    async fn from_db(db: &DbPool) -> Option<Self> {
         let data = db_part::search::max_save(db).await?;
         let xml_tested = data.verify_xml();
         Some(Self {
@@ -33,7 +31,7 @@ impl FromDb for LastSave {
 }
 
 impl FromDb for LastShip {
-    async fn from_db(db: &PgPool) -> Option<Self> {
+    async fn from_db(db: &DbPool) -> Option<Self> {
         let data = db_part::search::max_ship(db).await?;
         let xml_tested = data.verify_xml();
         Some(Self {
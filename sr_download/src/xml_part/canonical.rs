@@ -0,0 +1,470 @@
+//! 船的规范形式, 用来找换了 id / 挪了位置 / 浮点数格式不一样的重复上传
+//!
+//! - 原点挪到主指令舱 (有好几个的话取连着零件最多的那个, 再一样就取坐标最小的)
+//! - id 按连接图重新编号: 从原点开始广度优先, 同一层按相对坐标和零件类型排序, 从 1 开始
+//!   坐标和类型都一样 (叠在一起的重复零件) 的再按规范化之后的其他属性排, 还一样就按在原文里的顺序
+//! - 走不到的零件和 `DisconnectedParts` 接在后面, 也按坐标排
+//! - 浮点数都保留 [`CANONICAL_DECIMALS`] 位小数
+//!
+//! 规范形式写成 xml 之后的 blake3 就是规范 hash, 存档用的是玩家当前那条船
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use blake3::Hasher;
+
+use crate::xml_part::{
+    XmlResult,
+    graph::ShipGraph,
+    model::{Connection, DisconnectedGroup, Part, ShipData, ShipDocument, ShipFormat, XmlDocument},
+    write::write_ship_xml,
+};
+
+/// 浮点数保留几位小数
+pub const CANONICAL_DECIMALS: i32 = 3;
+
+/// 排序用的 key, 坐标先换成整数, 免得浮点误差影响顺序
+type PartKey<'a> = (i64, i64, &'a str);
+
+pub fn canonicalize(ship: &ShipData) -> ShipData {
+    let graph = ShipGraph::new(ship);
+    let start = main_pod(ship, &graph);
+    let origin = origin(ship, start);
+    let mut ids = Renumber::default();
+
+    for id in graph_order(&ship.parts, &graph, origin, start) {
+        ids.assign(id);
+    }
+
+    let mut groups: Vec<(&DisconnectedGroup, Vec<i64>)> = ship
+        .disconnected
+        .iter()
+        .map(|group| {
+            let graph = ShipGraph::from_group(group);
+            (group, graph_order(&group.parts, &graph, origin, None))
+        })
+        .collect();
+    groups.sort_by_key(|(group, _)| {
+        group
+            .parts
+            .iter()
+            .map(|part| part_key(part, origin))
+            .min()
+            .map(|(x, y, part_type)| (x, y, part_type.to_string()))
+    });
+    for (_, order) in &groups {
+        for id in order {
+            ids.assign(*id);
+        }
+    }
+
+    let disconnected = groups
+        .into_iter()
+        .map(|(group, _)| DisconnectedGroup {
+            parts: canonical_parts(&group.parts, origin, &mut ids),
+            connections: canonical_connections(&group.connections, &mut ids),
+        })
+        .collect();
+    ShipData {
+        version: ship.version,
+        lifted_off: ship.lifted_off,
+        touching_ground: ship.touching_ground,
+        parts: canonical_parts(&ship.parts, origin, &mut ids),
+        connections: canonical_connections(&ship.connections, &mut ids),
+        disconnected,
+    }
+}
+
+/// 规范形式的 blake3, 十六进制
+pub fn canonical_hash(ship: &ShipData) -> XmlResult<String> {
+    let xml = write_ship_xml(&ShipDocument {
+        ship: canonicalize(ship),
+        format: ShipFormat::Current,
+        extras: Default::default(),
+    })?;
+    let mut hasher = Hasher::new();
+    hasher.update(xml.as_bytes());
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// 主船的规范 hash, 一条船都没有的存档是 None
+pub fn canonical_document_hash(doc: &XmlDocument) -> Option<XmlResult<String>> {
+    doc.main_ship().map(canonical_hash)
+}
+
+/// 保留 [`CANONICAL_DECIMALS`] 位小数, `-0` 变成 `0`
+pub fn round(value: f64) -> f64 {
+    let scale = 10f64.powi(CANONICAL_DECIMALS);
+    let rounded = (value * scale).round() / scale;
+    if rounded == 0.0 { 0.0 } else { rounded }
+}
+
+fn units(value: f64) -> i64 {
+    (value * 10f64.powi(CANONICAL_DECIMALS)).round() as i64
+}
+
+fn part_key(part: &Part, origin: (f64, f64)) -> PartKey<'_> {
+    (
+        units(part.x - origin.0),
+        units(part.y - origin.1),
+        part.part_type_id.as_str(),
+    )
+}
+
+/// 连着零件最多的指令舱, 一样多的话取坐标最小的, 跟 id 没关系
+fn main_pod(ship: &ShipData, graph: &ShipGraph) -> Option<i64> {
+    let sizes: HashMap<i64, usize> = graph
+        .components()
+        .into_iter()
+        .flat_map(|component| {
+            let size = component.len();
+            component.into_iter().map(move |part| (part, size))
+        })
+        .collect();
+    ship.parts
+        .iter()
+        .filter(|part| part.attrs.pod.is_some())
+        .min_by_key(|part| {
+            let size = sizes.get(&part.id).copied().unwrap_or(0);
+            (std::cmp::Reverse(size), part_key(part, (0.0, 0.0)))
+        })
+        .map(|part| part.id)
+}
+
+/// 主指令舱的坐标, 没有指令舱的话取坐标最小的零件
+fn origin(ship: &ShipData, pod: Option<i64>) -> (f64, f64) {
+    let part = match pod {
+        Some(pod) => ship.parts.iter().find(|part| part.id == pod),
+        None => ship
+            .parts
+            .iter()
+            .chain(ship.disconnected.iter().flat_map(|group| &group.parts))
+            .min_by_key(|part| part_key(part, (0.0, 0.0))),
+    };
+    part.filter(|part| part.x.is_finite() && part.y.is_finite())
+        .map(|part| (part.x, part.y))
+        .unwrap_or((0.0, 0.0))
+}
+
+/// 先从 `start` 广度优先, 剩下的按坐标挨个当起点
+fn graph_order(
+    parts: &[Part],
+    graph: &ShipGraph,
+    origin: (f64, f64),
+    start: Option<i64>,
+) -> Vec<i64> {
+    // id 重复的只认第一个, 跟原文的顺序一起当最后的排序依据
+    let mut by_id: HashMap<i64, (usize, &Part)> = HashMap::new();
+    for part in parts {
+        let index = by_id.len();
+        by_id.entry(part.id).or_insert((index, part));
+    }
+    let keys: HashMap<i64, (PartKey, String, usize)> = by_id
+        .iter()
+        .map(|(&id, &(index, part))| {
+            (
+                id,
+                (part_key(part, origin), detail_key(part, origin), index),
+            )
+        })
+        .collect();
+    let mut sorted: Vec<i64> = by_id.keys().copied().collect();
+    sorted.sort_by(|a, b| keys[a].cmp(&keys[b]));
+
+    let mut visited = HashSet::new();
+    let mut order = Vec::with_capacity(sorted.len());
+    for root in start.into_iter().chain(sorted) {
+        if !by_id.contains_key(&root) || !visited.insert(root) {
+            continue;
+        }
+        let mut queue = VecDeque::from([root]);
+        while let Some(id) = queue.pop_front() {
+            order.push(id);
+            let mut next: Vec<i64> = graph
+                .neighbors(id)
+                .collect::<BTreeSet<i64>>()
+                .into_iter()
+                .filter(|id| by_id.contains_key(id) && !visited.contains(id))
+                .collect();
+            next.sort_by(|a, b| keys[a].cmp(&keys[b]));
+            for id in next {
+                visited.insert(id);
+                queue.push_back(id);
+            }
+        }
+    }
+    order
+}
+
+/// 坐标和类型一样的时候用来排序: 规范化之后除了 id 以外的所有属性
+///
+/// 分级里引用的 id 还没重新编号, 先当成 0
+fn detail_key(part: &Part, origin: (f64, f64)) -> String {
+    let mut part = rounded_part(part, origin);
+    part.id = 0;
+    for step in part.attrs.pod.iter_mut().flat_map(|pod| &mut pod.steps) {
+        for activate in &mut step.activates {
+            activate.id = 0;
+        }
+    }
+    format!("{part:?}")
+}
+
+/// 旧 id -> 新 id, 没见过的 id (比如连到不存在零件的连接) 按遇到的顺序往后排
+#[derive(Default)]
+struct Renumber {
+    map: HashMap<i64, i64>,
+}
+
+impl Renumber {
+    fn assign(&mut self, id: i64) -> i64 {
+        let next = self.map.len() as i64 + 1;
+        *self.map.entry(id).or_insert(next)
+    }
+}
+
+fn canonical_parts(parts: &[Part], origin: (f64, f64), ids: &mut Renumber) -> Vec<Part> {
+    let mut parts: Vec<Part> = parts
+        .iter()
+        .map(|part| {
+            let mut canonical = rounded_part(part, origin);
+            canonical.id = ids.assign(part.id);
+            for step in canonical
+                .attrs
+                .pod
+                .iter_mut()
+                .flat_map(|pod| &mut pod.steps)
+            {
+                for activate in &mut step.activates {
+                    activate.id = ids.assign(activate.id);
+                }
+            }
+            canonical
+        })
+        .collect();
+    parts.sort_by_key(|part| part.id);
+    parts
+}
+
+/// 坐标挪到 `origin` 并且所有浮点数保留 [`CANONICAL_DECIMALS`] 位小数, id 不动
+fn rounded_part(part: &Part, origin: (f64, f64)) -> Part {
+    let attrs = &part.attrs;
+    let mut canonical = part.clone();
+    canonical.x = round(part.x - origin.0);
+    canonical.y = round(part.y - origin.1);
+    canonical.angle = round(part.angle);
+    canonical.angle_v = round(part.angle_v);
+    canonical.attrs.tank_fuel = attrs.tank_fuel.map(round);
+    canonical.attrs.engine_fuel = attrs.engine_fuel.map(round);
+    canonical.attrs.chute_x = attrs.chute_x.map(round);
+    canonical.attrs.chute_y = attrs.chute_y.map(round);
+    canonical.attrs.chute_angle = attrs.chute_angle.map(round);
+    canonical.attrs.chute_height = attrs.chute_height.map(round);
+    canonical.attrs.extension = attrs.extension.map(round);
+    canonical.attrs.inflation = attrs.inflation.map(round);
+    if let Some(pod) = canonical.attrs.pod.as_mut() {
+        pod.throttle = round(pod.throttle);
+    }
+    canonical
+}
+
+fn canonical_connections(connections: &[Connection], ids: &mut Renumber) -> Vec<Connection> {
+    let mut connections: Vec<Connection> = connections
+        .iter()
+        .map(|connection| match *connection {
+            Connection::Normal {
+                parent_attach_point,
+                child_attach_point,
+                parent_part,
+                child_part,
+            } => Connection::Normal {
+                parent_attach_point,
+                child_attach_point,
+                parent_part: ids.assign(parent_part),
+                child_part: ids.assign(child_part),
+            },
+            Connection::Dock {
+                dock_part,
+                parent_part,
+                child_part,
+            } => Connection::Dock {
+                dock_part: ids.assign(dock_part),
+                parent_part: ids.assign(parent_part),
+                child_part: ids.assign(child_part),
+            },
+        })
+        .collect();
+    connections.sort_by_key(|connection| match *connection {
+        Connection::Normal {
+            parent_attach_point,
+            child_attach_point,
+            parent_part,
+            child_part,
+        } => (
+            0,
+            parent_part,
+            child_part,
+            parent_attach_point as i64,
+            child_attach_point as i64,
+        ),
+        Connection::Dock {
+            dock_part,
+            parent_part,
+            child_part,
+        } => (1, parent_part, child_part, dock_part, 0),
+    });
+    connections
+}
+
+impl ShipData {
+    pub fn canonical(&self) -> ShipData {
+        canonicalize(self)
+    }
+
+    pub fn canonical_hash(&self) -> XmlResult<String> {
+        canonical_hash(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_hash, canonicalize, round};
+    use crate::xml_part::{
+        model::{Connection, ShipData, XmlDocument},
+        parse::parse_any_xml,
+    };
+
+    const SAMPLE_SAVE: &str = include_str!("../save_1294489.xml");
+
+    fn sample_ship() -> ShipData {
+        parse_any_xml(SAMPLE_SAVE)
+            .unwrap()
+            .main_ship()
+            .unwrap()
+            .clone()
+    }
+
+    /// 换 id, 整体挪一下, 打乱顺序, 加点浮点误差
+    fn disguise(ship: &ShipData) -> ShipData {
+        let new_id = |id: i64| id * 7 + 1000;
+        let mut ship = ship.clone();
+        for part in &mut ship.parts {
+            part.id = new_id(part.id);
+            part.x += 123.25 + 1e-6;
+            part.y -= 42.5;
+            if let Some(pod) = &mut part.attrs.pod {
+                for step in &mut pod.steps {
+                    for activate in &mut step.activates {
+                        activate.id = new_id(activate.id);
+                    }
+                }
+            }
+        }
+        for connection in &mut ship.connections {
+            match connection {
+                Connection::Normal {
+                    parent_part,
+                    child_part,
+                    ..
+                } => {
+                    *parent_part = new_id(*parent_part);
+                    *child_part = new_id(*child_part);
+                }
+                Connection::Dock {
+                    dock_part,
+                    parent_part,
+                    child_part,
+                } => {
+                    *dock_part = new_id(*dock_part);
+                    *parent_part = new_id(*parent_part);
+                    *child_part = new_id(*child_part);
+                }
+            }
+        }
+        ship.parts.reverse();
+        ship.connections.reverse();
+        ship
+    }
+
+    #[test]
+    fn ignores_ids_offset_and_order() {
+        let ship = sample_ship();
+        let disguised = disguise(&ship);
+        assert_ne!(ship, disguised);
+        assert_eq!(canonicalize(&ship), canonicalize(&disguised));
+        assert_eq!(
+            canonical_hash(&ship).unwrap(),
+            canonical_hash(&disguised).unwrap()
+        );
+
+        // 规范形式再规范一遍不变
+        let canonical = canonicalize(&ship);
+        assert_eq!(canonicalize(&canonical), canonical);
+        let pod = canonical
+            .parts
+            .iter()
+            .find(|part| part.attrs.pod.is_some())
+            .unwrap();
+        assert_eq!(pod.id, 1);
+        assert_eq!((pod.x, pod.y), (0.0, 0.0));
+    }
+
+    /// 没有指令舱, 两个机身叠在同一个位置, 只有角度不一样, 引擎接在没转的那个上面
+    const COINCIDENT: &str = r#"<Ship version="1" liftedOff="0" touchingGround="1">
+  <Parts>
+    <Part partType="engine-1" id="1" x="0" y="3" angle="0" angleV="0" />
+    <Part partType="fuselage-1" id="2" x="0" y="0" angle="1.5" angleV="0" />
+    <Part partType="fuselage-1" id="3" x="0" y="0" angle="0" angleV="0" />
+  </Parts>
+  <Connections>
+    <Connection parentAttachPoint="1" childAttachPoint="2" parentPart="3" childPart="1" />
+  </Connections>
+</Ship>"#;
+
+    #[test]
+    fn coincident_parts_are_deterministic() {
+        let ship = match parse_any_xml(COINCIDENT).unwrap() {
+            XmlDocument::Ship(doc) => doc.ship,
+            XmlDocument::Save(_) => unreachable!(),
+        };
+        let canonical = canonicalize(&ship);
+        // 每次都是新的 HashMap, 两个机身谁先当起点不能跟着 HashMap 走
+        for _ in 0..32 {
+            assert_eq!(canonicalize(&ship), canonical);
+        }
+        assert_eq!(canonicalize(&disguise(&ship)), canonical);
+        assert_eq!(canonical.parts[0].angle, 0.0);
+        assert_eq!(canonical.parts[1].part_type_id, "engine-1");
+        assert_eq!(canonical.parts[2].angle, 1.5);
+        assert_eq!(
+            canonical.connections,
+            vec![Connection::Normal {
+                parent_attach_point: 1,
+                child_attach_point: 2,
+                parent_part: 1,
+                child_part: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn different_design_differs() {
+        let ship = sample_ship();
+        let mut changed = ship.clone();
+        changed.parts.last_mut().unwrap().x += 1.0;
+        assert_ne!(
+            canonical_hash(&ship).unwrap(),
+            canonical_hash(&changed).unwrap()
+        );
+
+        let empty = match parse_any_xml(crate::net::EMPTY_SHIP).unwrap() {
+            XmlDocument::Ship(doc) => doc.ship,
+            XmlDocument::Save(_) => unreachable!(),
+        };
+        assert_ne!(
+            canonical_hash(&ship).unwrap(),
+            canonical_hash(&empty).unwrap()
+        );
+        assert_eq!(round(-0.0001), 0.0);
+        assert_eq!(round(1.23456), 1.235);
+    }
+}
@@ -0,0 +1,480 @@
+//! 零件目录
+//!
+//! 从 `PartList.xml` 里读出每种零件的质量 / 尺寸 / 油箱 / 引擎 / 形状 / 连接点
+//! 默认用编译进来的 `sql/PartList.xml`, 配置里填了 `[catalog] part_list` 就用那个文件
+
+use std::{collections::HashMap, path::Path, sync::OnceLock};
+
+use quick_xml::de::from_str;
+use serde::{Deserialize, Serialize};
+use tracing::{Level, event};
+
+use crate::xml_part::{
+    error::{XmlError, XmlResult},
+    model::Part,
+};
+
+pub const EMBEDDED_PART_LIST: &str = include_str!("../../sql/PartList.xml");
+
+static EMBEDDED_CATALOG: OnceLock<PartCatalog> = OnceLock::new();
+static GLOBAL_CATALOG: OnceLock<PartCatalog> = OnceLock::new();
+
+fn just_true() -> bool {
+    true
+}
+
+/// 零件的大类, 对应 `type` 属性
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PartKind {
+    Pod,
+    Detacher,
+    Wheel,
+    Fuselage,
+    Strut,
+    Tank,
+    Engine,
+    Parachute,
+    Nosecone,
+    Rcs,
+    Solar,
+    #[serde(rename = "dockconnector")]
+    DockConnector,
+    #[serde(rename = "dockport")]
+    DockPort,
+    Lander,
+    #[serde(other)]
+    Unknown,
+}
+
+/// 燃料种类, 对应 `fuelType` 属性
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(from = "i32", into = "i32")]
+pub enum FuelType {
+    /// 0, 普通燃料
+    #[default]
+    Fuel,
+    /// 1, 给 RCS 用的单组元
+    Monopropellant,
+    /// 2, 电池
+    Battery,
+    /// 3, 固体燃料, 只有 SRB 在用
+    Solid,
+    Other(i32),
+}
+
+impl From<i32> for FuelType {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Self::Fuel,
+            1 => Self::Monopropellant,
+            2 => Self::Battery,
+            3 => Self::Solid,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl From<FuelType> for i32 {
+    fn from(value: FuelType) -> Self {
+        match value {
+            FuelType::Fuel => 0,
+            FuelType::Monopropellant => 1,
+            FuelType::Battery => 2,
+            FuelType::Solid => 3,
+            FuelType::Other(other) => other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TankSpec {
+    #[serde(rename = "@fuel")]
+    pub fuel: f64,
+    #[serde(rename = "@dryMass")]
+    pub dry_mass: f64,
+    #[serde(rename = "@fuelType", default)]
+    pub fuel_type: FuelType,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EngineSpec {
+    #[serde(rename = "@power")]
+    pub power: f64,
+    #[serde(rename = "@consumption")]
+    pub consumption: f64,
+    #[serde(rename = "@size")]
+    pub size: f64,
+    #[serde(rename = "@turn")]
+    pub turn: f64,
+    #[serde(rename = "@throttleExponential", default)]
+    pub throttle_exponential: bool,
+    #[serde(rename = "@fuelType", default)]
+    pub fuel_type: FuelType,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RcsSpec {
+    #[serde(rename = "@power")]
+    pub power: f64,
+    #[serde(rename = "@consumption")]
+    pub consumption: f64,
+    #[serde(rename = "@size")]
+    pub size: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SolarSpec {
+    #[serde(rename = "@chargeRate")]
+    pub charge_rate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanderSpec {
+    #[serde(rename = "@maxAngle")]
+    pub max_angle: f64,
+    #[serde(rename = "@minLength")]
+    pub min_length: f64,
+    #[serde(rename = "@maxLength")]
+    pub max_length: f64,
+    #[serde(rename = "@angleSpeed")]
+    pub angle_speed: f64,
+    #[serde(rename = "@lengthSpeed")]
+    pub length_speed: f64,
+    #[serde(rename = "@width")]
+    pub width: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DamageSpec {
+    #[serde(rename = "@disconnect")]
+    pub disconnect: f64,
+    #[serde(rename = "@explode")]
+    pub explode: f64,
+    #[serde(rename = "@explosionPower")]
+    pub explosion_power: f64,
+    #[serde(rename = "@explosionSize")]
+    pub explosion_size: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Vertex {
+    #[serde(rename = "@x")]
+    pub x: f64,
+    #[serde(rename = "@y")]
+    pub y: f64,
+}
+
+/// 碰撞形状, 顶点是逆时针的, 坐标以零件中心为原点
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Shape {
+    #[serde(rename = "@sensor", default)]
+    pub sensor: bool,
+    #[serde(rename = "Vertex", default)]
+    pub vertices: Vec<Vertex>,
+}
+
+/// 连接点的位置, 相对零件的外框
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AttachLocation {
+    Top,
+    Bottom,
+    TopCenter,
+    BottomCenter,
+    LeftCenter,
+    RightCenter,
+    LeftSide,
+    RightSide,
+}
+
+impl AttachLocation {
+    /// `Top` / `Bottom` / `LeftSide` / `RightSide` 是一整条边, 上面可以挂好几个零件
+    /// 带 `Center` 的只是一个点, 只能挂一个
+    pub fn is_edge(self) -> bool {
+        matches!(
+            self,
+            Self::Top | Self::Bottom | Self::LeftSide | Self::RightSide
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttachPoint {
+    /// 没有的时候用 `x` / `y`
+    #[serde(rename = "@location", default)]
+    pub location: Option<AttachLocation>,
+    #[serde(rename = "@x", default)]
+    pub x: Option<f64>,
+    #[serde(rename = "@y", default)]
+    pub y: Option<f64>,
+    #[serde(rename = "@fuelLine", default)]
+    pub fuel_line: bool,
+    #[serde(rename = "@breakAngle", default)]
+    pub break_angle: Option<f64>,
+    #[serde(rename = "@breakForce", default)]
+    pub break_force: Option<f64>,
+    #[serde(rename = "@order", default)]
+    pub order: Option<i32>,
+    #[serde(rename = "@group", default)]
+    pub group: Option<i32>,
+    #[serde(rename = "@flipX", default)]
+    pub flip_x: bool,
+}
+
+impl AttachPoint {
+    /// 只能挂一个零件的连接点
+    pub fn is_single_use(&self) -> bool {
+        !self.location.is_some_and(AttachLocation::is_edge)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+struct RawAttachPoints {
+    #[serde(rename = "AttachPoint", default)]
+    points: Vec<AttachPoint>,
+}
+
+/// 一种零件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartType {
+    #[serde(rename = "@id")]
+    pub id: String,
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@description", default)]
+    pub description: String,
+    #[serde(rename = "@sprite")]
+    pub sprite: String,
+    #[serde(rename = "@type")]
+    pub kind: PartKind,
+    /// 满载的质量, 游戏里显示的是这个数 * 500 kg
+    #[serde(rename = "@mass")]
+    pub mass: f64,
+    #[serde(rename = "@width")]
+    pub width: f64,
+    #[serde(rename = "@height")]
+    pub height: f64,
+    #[serde(rename = "@hidden", default)]
+    pub hidden: bool,
+    #[serde(rename = "@sandboxOnly", default)]
+    pub sandbox_only: bool,
+    #[serde(rename = "@category", default)]
+    pub category: Option<String>,
+    #[serde(rename = "@canExplode", default = "just_true")]
+    pub can_explode: bool,
+    #[serde(rename = "@buoyancy", default)]
+    pub buoyancy: f64,
+    #[serde(rename = "@drag", default)]
+    pub drag: Option<f64>,
+    #[serde(rename = "@friction", default)]
+    pub friction: Option<f64>,
+    #[serde(rename = "@coverHeight", default)]
+    pub cover_height: Option<f64>,
+    #[serde(rename = "@maxOccurrences", default)]
+    pub max_occurrences: Option<u32>,
+    #[serde(rename = "@ignoreEditorIntersections", default)]
+    pub ignore_editor_intersections: bool,
+    #[serde(rename = "@disableEditorRotation", default)]
+    pub disable_editor_rotation: bool,
+    #[serde(rename = "Tank", default)]
+    pub tank: Option<TankSpec>,
+    #[serde(rename = "Engine", default)]
+    pub engine: Option<EngineSpec>,
+    #[serde(rename = "Rcs", default)]
+    pub rcs: Option<RcsSpec>,
+    #[serde(rename = "Solar", default)]
+    pub solar: Option<SolarSpec>,
+    #[serde(rename = "Lander", default)]
+    pub lander: Option<LanderSpec>,
+    #[serde(rename = "Damage", default)]
+    pub damage: Option<DamageSpec>,
+    #[serde(rename = "Shape", default)]
+    pub shapes: Vec<Shape>,
+    #[serde(rename = "AttachPoints", default, with = "attach_points")]
+    pub attach_points: Vec<AttachPoint>,
+}
+
+/// `<AttachPoints><AttachPoint/>...</AttachPoints>` 摊平成一个 Vec
+mod attach_points {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{AttachPoint, RawAttachPoints};
+
+    pub fn serialize<S: Serializer>(points: &[AttachPoint], s: S) -> Result<S::Ok, S::Error> {
+        RawAttachPoints {
+            points: points.to_vec(),
+        }
+        .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<AttachPoint>, D::Error> {
+        Ok(RawAttachPoints::deserialize(d)?.points)
+    }
+}
+
+impl PartType {
+    /// 空油箱的质量, 不是油箱就是 `mass`
+    pub fn dry_mass(&self) -> f64 {
+        self.tank.as_ref().map_or(self.mass, |tank| tank.dry_mass)
+    }
+
+    pub fn attach_point(&self, index: usize) -> Option<&AttachPoint> {
+        self.attach_points.get(index)
+    }
+
+    /// `Connection` 里写的连接点, 下标是从 1 开始的
+    pub fn connection_attach_point(&self, index: i32) -> Option<&AttachPoint> {
+        usize::try_from(index)
+            .ok()?
+            .checked_sub(1)
+            .and_then(|index| self.attach_point(index))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename = "PartTypes")]
+struct RawPartList {
+    #[serde(rename = "PartType", default)]
+    part_types: Vec<PartType>,
+}
+
+/// 所有零件, 可以按 `partType` 查
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PartCatalog {
+    part_types: Vec<PartType>,
+    index: HashMap<String, usize>,
+}
+
+impl std::str::FromStr for PartCatalog {
+    type Err = XmlError;
+
+    fn from_str(data: &str) -> XmlResult<Self> {
+        let raw: RawPartList = from_str(data)?;
+        Ok(raw.part_types.into_iter().collect())
+    }
+}
+
+impl PartCatalog {
+    pub fn from_file(path: impl AsRef<Path>) -> XmlResult<Self> {
+        let data = std::fs::read_to_string(path)?;
+        data.parse()
+    }
+
+    /// 编译进来的 `sql/PartList.xml`
+    pub fn embedded() -> &'static Self {
+        EMBEDDED_CATALOG.get_or_init(|| {
+            EMBEDDED_PART_LIST
+                .parse()
+                .expect("内置的 PartList.xml 坏了")
+        })
+    }
+
+    /// 按配置加载的目录, 配置里没写或者读不出来就用内置的
+    pub fn global() -> &'static Self {
+        GLOBAL_CATALOG.get_or_init(|| {
+            let path = crate::config::GLOBAL_CFG
+                .get()
+                .and_then(|conf| conf.catalog.part_list.as_deref());
+            let Some(path) = path else {
+                return Self::embedded().clone();
+            };
+            match Self::from_file(path) {
+                Ok(catalog) => catalog,
+                Err(e) => {
+                    event!(Level::WARN, "读取零件目录 {} 失败, 用内置的: {}", path, e);
+                    Self::embedded().clone()
+                }
+            }
+        })
+    }
+
+    pub fn get(&self, part_type_id: &str) -> Option<&PartType> {
+        self.index
+            .get(part_type_id)
+            .map(|index| &self.part_types[*index])
+    }
+
+    /// 按 `Part::part_type_id` 查
+    pub fn part_type(&self, part: &Part) -> Option<&PartType> {
+        self.get(&part.part_type_id)
+    }
+
+    pub fn contains(&self, part_type_id: &str) -> bool {
+        self.index.contains_key(part_type_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PartType> {
+        self.part_types.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.part_types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.part_types.is_empty()
+    }
+}
+
+impl FromIterator<PartType> for PartCatalog {
+    fn from_iter<T: IntoIterator<Item = PartType>>(iter: T) -> Self {
+        let part_types: Vec<PartType> = iter.into_iter().collect();
+        let index = part_types
+            .iter()
+            .enumerate()
+            .map(|(index, part_type)| (part_type.id.clone(), index))
+            .collect();
+        Self { part_types, index }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AttachLocation, FuelType, PartCatalog, PartKind};
+    use crate::xml_part::parse::parse_ship_xml;
+
+    #[test]
+    fn loads_embedded_catalog() {
+        let catalog = PartCatalog::embedded();
+        assert_eq!(catalog.len(), 27);
+
+        let pod = catalog.get("pod-1").unwrap();
+        assert_eq!(pod.kind, PartKind::Pod);
+        assert_eq!(pod.mass, 1.0);
+        assert!(pod.hidden);
+        assert_eq!(pod.shapes[0].vertices.len(), 4);
+        assert_eq!(
+            pod.attach_points[0].location,
+            Some(AttachLocation::TopCenter)
+        );
+
+        let tank = catalog.get("fueltank-3").unwrap();
+        assert_eq!(tank.tank.as_ref().unwrap().fuel, 6000.0);
+        assert_eq!(tank.dry_mass(), 1.2);
+
+        let srb = catalog.get("engine-4").unwrap();
+        let engine = srb.engine.as_ref().unwrap();
+        assert_eq!(engine.fuel_type, FuelType::Solid);
+        assert!(srb.tank.is_some());
+        assert!(srb.sandbox_only);
+
+        let port = catalog.get("port-1").unwrap();
+        assert_eq!(port.shapes.len(), 4);
+        assert!(port.shapes[3].sensor);
+
+        assert!(!catalog.get("strut-1").unwrap().can_explode);
+        assert_eq!(
+            catalog.get("wheel-2").unwrap().attach_points[0].x,
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn looks_up_ship_parts() {
+        let doc = parse_ship_xml(crate::net::EMPTY_SHIP).unwrap();
+        let part_type = PartCatalog::embedded()
+            .part_type(&doc.ship.parts[0])
+            .unwrap();
+        assert_eq!(part_type.kind, PartKind::Pod);
+    }
+}
@@ -1,5 +1,8 @@
 use crate::xml_part::{model, raw};
 
+/// 老格式里指令舱零件的类型前缀, 老格式的分级挂在第一个这样的零件上
+pub const LEGACY_POD_PREFIX: &str = "pod";
+
 fn i8_to_bool(value: i8) -> bool {
     value != 0
 }
@@ -213,11 +216,33 @@ impl From<model::DisconnectedGroup> for raw::RawDisconnectedPart {
 
 impl From<raw::RawShipDocument> for model::ShipData {
     fn from(value: raw::RawShipDocument) -> Self {
+        let legacy_pod = value.is_legacy().then(|| model::PodData {
+            name: String::new(),
+            throttle: value.throttle.unwrap_or(0.0),
+            current_stage: value.current_stage.unwrap_or(0),
+            steps: value
+                .staging
+                .map(|staging| staging.steps.into_iter().map(Into::into).collect())
+                .unwrap_or_default(),
+        });
+        let mut parts: Vec<model::Part> = value.parts.parts.into_iter().map(Into::into).collect();
+        // 老格式的分级挂到指令舱零件上, 已经有 `<Pod>` 的话就不管了
+        if let Some(pod) = legacy_pod
+            && parts.iter().all(|part| part.attrs.pod.is_none())
+            && let Some(part) = parts
+                .iter_mut()
+                .find(|part| part.part_type_id.starts_with(LEGACY_POD_PREFIX))
+        {
+            part.attrs.pod = Some(pod);
+        }
         Self {
-            version: value.version,
+            version: value.version.unwrap_or(raw::DEFAULT_SHIP_VERSION),
             lifted_off: value.lifted_off != 0,
-            touching_ground: value.touching_ground != 0,
-            parts: value.parts.parts.into_iter().map(Into::into).collect(),
+            touching_ground: value
+                .touching_ground
+                .unwrap_or(raw::DEFAULT_TOUCHING_GROUND)
+                != 0,
+            parts,
             connections: value
                 .connections
                 .connections
@@ -226,6 +251,7 @@ impl From<raw::RawShipDocument> for model::ShipData {
                 .collect(),
             disconnected: value
                 .disconnected
+                .unwrap_or_default()
                 .parts
                 .into_iter()
                 .map(Into::into)
@@ -234,6 +260,7 @@ impl From<raw::RawShipDocument> for model::ShipData {
     }
 }
 
+/// 写成新格式
 impl From<model::ShipData> for raw::RawShipDocument {
     fn from(value: model::ShipData) -> Self {
         Self {
@@ -243,25 +270,82 @@ impl From<model::ShipData> for raw::RawShipDocument {
             connections: raw::RawConnections {
                 connections: value.connections.into_iter().map(Into::into).collect(),
             },
-            version: value.version,
+            version: Some(value.version),
             lifted_off: bool_to_i8(value.lifted_off),
-            touching_ground: bool_to_i8(value.touching_ground),
-            disconnected: raw::RawDisconnectedParts {
+            touching_ground: Some(bool_to_i8(value.touching_ground)),
+            disconnected: Some(raw::RawDisconnectedParts {
                 parts: value.disconnected.into_iter().map(Into::into).collect(),
-            },
+            }),
+            current_stage: None,
+            throttle: None,
+            staging: None,
+            extras: Default::default(),
         }
     }
 }
 
+/// 写成老格式
+///
+/// 第一个指令舱的分级搬到根节点上, 所有零件的 `<Pod>` 都去掉 (老格式只能有一套分级)
+/// 指令舱的名字、`version` 和 `touchingGround` 老格式里没有, 会丢掉
+pub fn to_legacy_raw(value: model::ShipData) -> raw::RawShipDocument {
+    let pod = value.parts.iter().find_map(|part| part.attrs.pod.clone());
+    let disconnected = (!value.disconnected.is_empty()).then(|| raw::RawDisconnectedParts {
+        parts: value.disconnected.into_iter().map(Into::into).collect(),
+    });
+    raw::RawShipDocument {
+        parts: raw::RawParts {
+            parts: value
+                .parts
+                .into_iter()
+                .map(|mut part| {
+                    part.attrs.pod = None;
+                    part.into()
+                })
+                .collect(),
+        },
+        connections: raw::RawConnections {
+            connections: value.connections.into_iter().map(Into::into).collect(),
+        },
+        version: None,
+        lifted_off: bool_to_i8(value.lifted_off),
+        touching_ground: None,
+        disconnected,
+        current_stage: Some(pod.as_ref().map_or(0, |pod| pod.current_stage)),
+        throttle: Some(pod.as_ref().map_or(0.0, |pod| pod.throttle)),
+        staging: Some(raw::RawLegacyStaging {
+            steps: pod
+                .map(|pod| pod.steps.into_iter().map(Into::into).collect())
+                .unwrap_or_default(),
+        }),
+        extras: Default::default(),
+    }
+}
+
 impl From<raw::RawShipDocument> for model::ShipDocument {
-    fn from(value: raw::RawShipDocument) -> Self {
-        Self { ship: value.into() }
+    fn from(mut value: raw::RawShipDocument) -> Self {
+        let format = if value.is_legacy() {
+            model::ShipFormat::Legacy
+        } else {
+            model::ShipFormat::Current
+        };
+        let extras = std::mem::take(&mut value.extras);
+        Self {
+            ship: value.into(),
+            format,
+            extras,
+        }
     }
 }
 
 impl From<model::ShipDocument> for raw::RawShipDocument {
     fn from(value: model::ShipDocument) -> Self {
-        value.ship.into()
+        let mut raw: raw::RawShipDocument = match value.format {
+            model::ShipFormat::Current => value.ship.into(),
+            model::ShipFormat::Legacy => to_legacy_raw(value.ship),
+        };
+        raw.extras = value.extras;
+        raw
     }
 }
 
@@ -340,6 +424,7 @@ impl From<raw::RawSaveDocument> for model::SaveDocument {
             ship_id: value.ship_id,
             pod_id: value.pod_id,
             nodes: value.nodes.nodes.into_iter().map(Into::into).collect(),
+            extras: value.extras,
         }
     }
 }
@@ -355,6 +440,7 @@ impl From<model::SaveDocument> for raw::RawSaveDocument {
             nodes: raw::RawNodes {
                 nodes: value.nodes.into_iter().map(Into::into).collect(),
             },
+            extras: value.extras,
         }
     }
 }
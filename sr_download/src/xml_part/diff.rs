@@ -0,0 +1,492 @@
+//! 两条船的结构差异
+//!
+//! 按零件 id 对齐, 报告加了 / 删了哪些零件, 哪些挪了位置 / 转了角度, 哪些属性变了,
+//! 还有分级和连接的变化
+//! 位置是原始的世界坐标, 存档里飞着的船整体挪了的话每个零件都会算成 moved
+//! `DisconnectedParts` 里的零件和连接也算在里面
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::xml_part::{
+    graph::{DockLink, NormalLink},
+    model::{Connection, Part, ShipData, StageStep, XmlDocument},
+};
+
+/// 位置差这么多以内不算挪了
+pub const POSITION_TOLERANCE: f64 = 1e-4;
+/// 角度差这么多以内不算转了, 弧度
+pub const ANGLE_TOLERANCE: f64 = 1e-4;
+/// 燃料之类的浮点属性差这么多以内不算变了
+pub const VALUE_TOLERANCE: f64 = 1e-6;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartRef {
+    pub part: i64,
+    pub part_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartMove {
+    pub part: i64,
+    pub from: (f64, f64),
+    pub to: (f64, f64),
+    pub distance: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartRotation {
+    pub part: i64,
+    /// 弧度
+    pub from: f64,
+    pub to: f64,
+    /// 转了多少, 已经折到 (-π, π]
+    pub delta: f64,
+}
+
+/// 同一个 id 换了零件类型
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeChange {
+    pub part: i64,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AttrValue {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+/// 一个属性变了, `from` / `to` 是 None 表示那边没有这个属性
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttrChange {
+    /// None 是船本身的属性 (`liftedOff` 之类)
+    pub part: Option<i64>,
+    /// 用 xml 里的名字, 子节点的属性写成 `Tank.fuel` / `Pod.name` 这样
+    pub attr: String,
+    pub from: Option<AttrValue>,
+    pub to: Option<AttrValue>,
+}
+
+/// 一个指令舱的分级变了, 每一级是这一级激活的零件 id
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StagingChange {
+    pub pod: i64,
+    pub from: Vec<Vec<i64>>,
+    pub to: Vec<Vec<i64>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ShipDiff {
+    pub added: Vec<PartRef>,
+    pub removed: Vec<PartRef>,
+    pub retyped: Vec<TypeChange>,
+    pub moved: Vec<PartMove>,
+    pub rotated: Vec<PartRotation>,
+    pub attrs: Vec<AttrChange>,
+    pub staging: Vec<StagingChange>,
+    pub links_added: Vec<NormalLink>,
+    pub links_removed: Vec<NormalLink>,
+    pub docks_added: Vec<DockLink>,
+    pub docks_removed: Vec<DockLink>,
+}
+
+impl ShipDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.retyped.is_empty()
+            && self.moved.is_empty()
+            && self.rotated.is_empty()
+            && self.attrs.is_empty()
+            && self.staging.is_empty()
+            && self.links_added.is_empty()
+            && self.links_removed.is_empty()
+            && self.docks_added.is_empty()
+            && self.docks_removed.is_empty()
+    }
+
+    /// 零件和连接一共变了几处
+    pub fn change_count(&self) -> usize {
+        self.added.len()
+            + self.removed.len()
+            + self.retyped.len()
+            + self.moved.len()
+            + self.rotated.len()
+            + self.attrs.len()
+            + self.staging.len()
+            + self.links_added.len()
+            + self.links_removed.len()
+            + self.docks_added.len()
+            + self.docks_removed.len()
+    }
+}
+
+/// 从 `a` 到 `b` 变了什么
+pub fn diff_ships(a: &ShipData, b: &ShipData) -> ShipDiff {
+    let mut diff = ShipDiff::default();
+
+    ship_attr(&mut diff, "version", a.version as f64, b.version as f64);
+    ship_attr(&mut diff, "liftedOff", a.lifted_off, b.lifted_off);
+    ship_attr(
+        &mut diff,
+        "touchingGround",
+        a.touching_ground,
+        b.touching_ground,
+    );
+
+    let parts_a = index_parts(a);
+    let parts_b = index_parts(b);
+    for (id, part) in &parts_a {
+        if !parts_b.contains_key(id) {
+            diff.removed.push(PartRef::new(part));
+        }
+    }
+    for (id, new) in &parts_b {
+        match parts_a.get(id) {
+            Some(old) => diff_part(&mut diff, old, new),
+            None => diff.added.push(PartRef::new(new)),
+        }
+    }
+
+    let (normal_a, dock_a) = index_connections(a);
+    let (normal_b, dock_b) = index_connections(b);
+    diff.links_added = normal_b.difference(&normal_a).copied().collect();
+    diff.links_removed = normal_a.difference(&normal_b).copied().collect();
+    diff.docks_added = dock_b.difference(&dock_a).copied().collect();
+    diff.docks_removed = dock_a.difference(&dock_b).copied().collect();
+    diff
+}
+
+/// 两边都有船的时候比较主船 (存档是玩家当前那条), 有一边没有就是 None
+pub fn diff_documents(a: &XmlDocument, b: &XmlDocument) -> Option<ShipDiff> {
+    Some(diff_ships(a.main_ship()?, b.main_ship()?))
+}
+
+impl PartRef {
+    fn new(part: &Part) -> Self {
+        Self {
+            part: part.id,
+            part_type: part.part_type_id.clone(),
+        }
+    }
+}
+
+/// id 重复的话只认第一个
+fn index_parts(ship: &ShipData) -> BTreeMap<i64, &Part> {
+    let mut parts = BTreeMap::new();
+    for part in ship
+        .parts
+        .iter()
+        .chain(ship.disconnected.iter().flat_map(|group| &group.parts))
+    {
+        parts.entry(part.id).or_insert(part);
+    }
+    parts
+}
+
+fn index_connections(ship: &ShipData) -> (BTreeSet<NormalLink>, BTreeSet<DockLink>) {
+    let mut normal = BTreeSet::new();
+    let mut docks = BTreeSet::new();
+    for connection in ship.connections.iter().chain(
+        ship.disconnected
+            .iter()
+            .flat_map(|group| &group.connections),
+    ) {
+        match *connection {
+            Connection::Normal {
+                parent_attach_point,
+                child_attach_point,
+                parent_part,
+                child_part,
+            } => {
+                normal.insert(NormalLink {
+                    parent: parent_part,
+                    child: child_part,
+                    parent_attach_point,
+                    child_attach_point,
+                });
+            }
+            Connection::Dock {
+                dock_part,
+                parent_part,
+                child_part,
+            } => {
+                docks.insert(DockLink {
+                    dock_part,
+                    parent: parent_part,
+                    child: child_part,
+                });
+            }
+        }
+    }
+    (normal, docks)
+}
+
+fn diff_part(diff: &mut ShipDiff, old: &Part, new: &Part) {
+    let id = old.id;
+    if old.part_type_id != new.part_type_id {
+        diff.retyped.push(TypeChange {
+            part: id,
+            from: old.part_type_id.clone(),
+            to: new.part_type_id.clone(),
+        });
+    }
+
+    let distance = (new.x - old.x).hypot(new.y - old.y);
+    if distance > POSITION_TOLERANCE || distance.is_nan() {
+        diff.moved.push(PartMove {
+            part: id,
+            from: (old.x, old.y),
+            to: (new.x, new.y),
+            distance,
+        });
+    }
+    let delta = wrap_angle(new.angle - old.angle);
+    if delta.abs() > ANGLE_TOLERANCE || delta.is_nan() {
+        diff.rotated.push(PartRotation {
+            part: id,
+            from: old.angle,
+            to: new.angle,
+            delta,
+        });
+    }
+
+    let mut changes = PartChanges { diff, part: id };
+    changes.number(
+        "editorAngle",
+        Some(old.editor_angle as f64),
+        Some(new.editor_angle as f64),
+    );
+    changes.number("angleV", Some(old.angle_v), Some(new.angle_v));
+    changes.flag("flippedX", Some(old.flipped_x), Some(new.flipped_x));
+    changes.flag("flippedY", Some(old.flipped_y), Some(new.flipped_y));
+    changes.flag("activated", Some(old.activated), Some(new.activated));
+    changes.flag("exploded", Some(old.exploded), Some(new.exploded));
+
+    let (a, b) = (&old.attrs, &new.attrs);
+    changes.number("Tank.fuel", a.tank_fuel, b.tank_fuel);
+    changes.number("Engine.fuel", a.engine_fuel, b.engine_fuel);
+    changes.number("chuteX", a.chute_x, b.chute_x);
+    changes.number("chuteY", a.chute_y, b.chute_y);
+    changes.number("chuteAngle", a.chute_angle, b.chute_angle);
+    changes.number("chuteHeight", a.chute_height, b.chute_height);
+    changes.number("extension", a.extension, b.extension);
+    changes.flag("inflate", a.inflate, b.inflate);
+    changes.number("inflation", a.inflation, b.inflation);
+    changes.flag("deployed", a.deployed, b.deployed);
+    changes.flag("rope", a.rope, b.rope);
+
+    let (pod_a, pod_b) = (a.pod.as_ref(), b.pod.as_ref());
+    changes.text(
+        "Pod.name",
+        pod_a.map(|pod| pod.name.as_str()),
+        pod_b.map(|pod| pod.name.as_str()),
+    );
+    changes.number(
+        "Pod.throttle",
+        pod_a.map(|pod| pod.throttle),
+        pod_b.map(|pod| pod.throttle),
+    );
+    changes.number(
+        "Pod.currentStage",
+        pod_a.map(|pod| pod.current_stage as f64),
+        pod_b.map(|pod| pod.current_stage as f64),
+    );
+
+    let steps_a = pod_a.map(|pod| stage_ids(&pod.steps)).unwrap_or_default();
+    let steps_b = pod_b.map(|pod| stage_ids(&pod.steps)).unwrap_or_default();
+    if steps_a != steps_b {
+        diff.staging.push(StagingChange {
+            pod: id,
+            from: steps_a,
+            to: steps_b,
+        });
+    }
+}
+
+fn stage_ids(steps: &[StageStep]) -> Vec<Vec<i64>> {
+    steps
+        .iter()
+        .map(|step| step.activates.iter().map(|activate| activate.id).collect())
+        .collect()
+}
+
+/// 折到 (-π, π]
+fn wrap_angle(angle: f64) -> f64 {
+    use std::f64::consts::{PI, TAU};
+    let wrapped = angle.rem_euclid(TAU);
+    if wrapped > PI { wrapped - TAU } else { wrapped }
+}
+
+fn ship_attr<T: Into<AttrValue> + PartialEq>(diff: &mut ShipDiff, attr: &str, a: T, b: T) {
+    if a != b {
+        diff.attrs.push(AttrChange {
+            part: None,
+            attr: attr.to_string(),
+            from: Some(a.into()),
+            to: Some(b.into()),
+        });
+    }
+}
+
+struct PartChanges<'a> {
+    diff: &'a mut ShipDiff,
+    part: i64,
+}
+
+impl PartChanges<'_> {
+    fn push(&mut self, attr: &str, from: Option<AttrValue>, to: Option<AttrValue>) {
+        self.diff.attrs.push(AttrChange {
+            part: Some(self.part),
+            attr: attr.to_string(),
+            from,
+            to,
+        });
+    }
+
+    fn number(&mut self, attr: &str, from: Option<f64>, to: Option<f64>) {
+        let same = match (from, to) {
+            (Some(a), Some(b)) => (a - b).abs() <= VALUE_TOLERANCE || (a.is_nan() && b.is_nan()),
+            (None, None) => true,
+            _ => false,
+        };
+        if !same {
+            self.push(attr, from.map(AttrValue::from), to.map(AttrValue::from));
+        }
+    }
+
+    fn flag(&mut self, attr: &str, from: Option<bool>, to: Option<bool>) {
+        if from != to {
+            self.push(attr, from.map(AttrValue::from), to.map(AttrValue::from));
+        }
+    }
+
+    fn text(&mut self, attr: &str, from: Option<&str>, to: Option<&str>) {
+        if from != to {
+            self.push(attr, from.map(AttrValue::from), to.map(AttrValue::from));
+        }
+    }
+}
+
+impl From<bool> for AttrValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<f64> for AttrValue {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<&str> for AttrValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl ShipData {
+    /// 从 `self` 到 `other` 变了什么
+    pub fn diff(&self, other: &ShipData) -> ShipDiff {
+        diff_ships(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AttrValue, PartRef, diff_documents, diff_ships};
+    use crate::xml_part::{
+        graph::NormalLink,
+        model::{Activation, Connection, StageStep},
+        parse::{parse_any_xml, parse_save_xml},
+    };
+
+    const SAMPLE_SAVE: &str = include_str!("../save_1294489.xml");
+
+    #[test]
+    fn same_ship_is_empty() {
+        let doc = parse_any_xml(SAMPLE_SAVE).unwrap();
+        let diff = diff_documents(&doc, &doc).unwrap();
+        assert!(diff.is_empty(), "{diff:?}");
+        assert_eq!(diff.change_count(), 0);
+    }
+
+    #[test]
+    fn reports_changes() {
+        let save = parse_save_xml(SAMPLE_SAVE).unwrap();
+        let old = crate::xml_part::model::XmlDocument::Save(save)
+            .main_ship()
+            .unwrap()
+            .clone();
+        let mut new = old.clone();
+
+        let removed = new.parts.pop().unwrap();
+        new.connections.retain(|connection| match connection {
+            Connection::Normal {
+                parent_part,
+                child_part,
+                ..
+            } => *parent_part != removed.id && *child_part != removed.id,
+            Connection::Dock { .. } => true,
+        });
+        let mut added = removed.clone();
+        added.id = 9999;
+        new.parts.push(added);
+
+        let pod = new
+            .parts
+            .iter_mut()
+            .find(|part| part.attrs.pod.is_some())
+            .unwrap();
+        let pod_id = pod.id;
+        pod.x += 1.0;
+        pod.angle += std::f64::consts::FRAC_PI_2;
+        let data = pod.attrs.pod.as_mut().unwrap();
+        data.name = "renamed".to_string();
+        data.steps.push(StageStep {
+            activates: vec![Activation {
+                id: 9999,
+                moved: false,
+            }],
+        });
+        new.lifted_off = !new.lifted_off;
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.removed, vec![PartRef::new(&removed)]);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].part, 9999);
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!(diff.moved[0].part, pod_id);
+        assert!((diff.moved[0].distance - 1.0).abs() < 1e-9);
+        assert_eq!(diff.rotated.len(), 1);
+        assert!((diff.rotated[0].delta - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert!(diff.attrs.iter().any(|change| change.part == Some(pod_id)
+            && change.attr == "Pod.name"
+            && change.to == Some(AttrValue::Text("renamed".to_string()))));
+        assert!(
+            diff.attrs
+                .iter()
+                .any(|change| change.part.is_none() && change.attr == "liftedOff")
+        );
+        assert_eq!(diff.staging.len(), 1);
+        assert_eq!(diff.staging[0].to.last(), Some(&vec![9999]));
+        assert!(diff.links_added.is_empty());
+        assert!(
+            diff.links_removed
+                .iter()
+                .all(|link: &NormalLink| { link.parent == removed.id || link.child == removed.id })
+        );
+
+        // 反过来比较, 加的和删的正好对调
+        let back = diff_ships(&new, &old);
+        assert_eq!(back.added, diff.removed);
+        assert_eq!(back.removed, diff.added);
+        assert_eq!(back.links_added, diff.links_removed);
+    }
+}
@@ -1,8 +1,26 @@
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug)]
 pub enum XmlError {
     Io(std::io::Error),
-    Deserialize(quick_xml::DeError),
+    /// serde 解析失败, `position` 是另外扫一遍原文找到的出错位置 (见 [`crate::xml_part::stream::locate_error`]), 找不到的话是 None
+    Deserialize {
+        error: quick_xml::DeError,
+        position: Option<ErrorPosition>,
+    },
+    /// 不是合法 xml
+    Syntax {
+        error: quick_xml::Error,
+        position: Option<ErrorPosition>,
+    },
     Serialize(String),
+    /// model 的 JSON 读写失败
+    Json(serde_json::Error),
+    /// JSON 的版本号对不上, 见 [`crate::xml_part::json`]
+    UnsupportedSchemaVersion {
+        expected: u32,
+        found: u32,
+    },
     UnsupportedRoot(String),
     UnexpectedDocumentType {
         expected: &'static str,
@@ -12,12 +30,86 @@ pub enum XmlError {
 
 pub type XmlResult<T> = Result<T, XmlError>;
 
+/// 出错的地方
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ErrorPosition {
+    /// 原文里的字节偏移
+    pub offset: usize,
+    /// 从 1 开始
+    pub line: usize,
+    /// 从 1 开始, 按字符数
+    pub column: usize,
+    /// 出错的节点, 写法跟 [`crate::xml_part::extra`] 一样, 属性的话后面再跟 `/@属性名`
+    /// (`Ship/Parts/Part[12]/@angle`); 下标跟 XPath 一样从 1 开始, `Part[12]` 是第 12 个零件;
+    /// 根节点外面的是空的
+    pub path: String,
+}
+
+impl ErrorPosition {
+    pub fn new(data: &str, offset: usize, path: String) -> Self {
+        let mut offset = offset.min(data.len());
+        while !data.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &data[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Self {
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            path,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)?;
+        if !self.path.is_empty() {
+            write!(f, " ({})", self.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl XmlError {
+    /// 读 `data` 的时候碰到不合法的 xml, `offset` 是 `Reader::error_position`
+    pub fn syntax(error: quick_xml::Error, data: &str, offset: u64) -> Self {
+        Self::Syntax {
+            error,
+            position: Some(ErrorPosition::new(data, offset as usize, String::new())),
+        }
+    }
+
+    /// 解析失败的位置
+    pub fn position(&self) -> Option<&ErrorPosition> {
+        match self {
+            Self::Deserialize { position, .. } | Self::Syntax { position, .. } => position.as_ref(),
+            _ => None,
+        }
+    }
+}
+
 impl std::fmt::Display for XmlError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Io(err) => write!(f, "io error: {err}"),
-            Self::Deserialize(err) => write!(f, "xml deserialize error: {err}"),
+            Self::Deserialize {
+                error,
+                position: Some(position),
+            } => write!(f, "xml deserialize error at {position}: {error}"),
+            Self::Deserialize { error, .. } => write!(f, "xml deserialize error: {error}"),
+            Self::Syntax {
+                error,
+                position: Some(position),
+            } => write!(f, "xml syntax error at {position}: {error}"),
+            Self::Syntax { error, .. } => write!(f, "xml syntax error: {error}"),
             Self::Serialize(err) => write!(f, "xml serialize error: {err}"),
+            Self::Json(err) => write!(f, "model json error: {err}"),
+            Self::UnsupportedSchemaVersion { expected, found } => write!(
+                f,
+                "unsupported model schema version: expected {expected}, found {found}"
+            ),
             Self::UnsupportedRoot(root) => write!(f, "unsupported xml root: {root}"),
             Self::UnexpectedDocumentType { expected, found } => {
                 write!(
@@ -39,7 +131,16 @@ impl From<std::io::Error> for XmlError {
 
 impl From<quick_xml::DeError> for XmlError {
     fn from(value: quick_xml::DeError) -> Self {
-        Self::Deserialize(value)
+        Self::Deserialize {
+            error: value,
+            position: None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for XmlError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
     }
 }
 
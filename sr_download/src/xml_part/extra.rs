@@ -0,0 +1,534 @@
+//! 不认识的属性和子节点
+//!
+//! [`crate::xml_part::raw`] 里的结构只认固定的那些属性, 文件里多出来的东西 serde 会直接扔掉
+//! 所以解析的时候另外扫一遍原文, 把 [`KNOWN`] 之外的属性和子节点按路径记下来, 写的时候再塞回去
+//!
+//! 路径长这样: `Ship/Parts/Part[12]`, 会重复的节点 (Part, Step, Connection ...) 带下标,
+//! 下标跟 XPath 一样从 1 开始 (`Part[12]` 是第 12 个), 同名的兄弟节点一起数
+//! 不认识的子节点整棵存下来, 写回去的时候接在已有的子节点后面
+//!
+//! 只管属性、元素和文本, 注释和 `<?xml ?>` 声明不留
+//! 不认识的节点里的文本原样留着 (包括空白), 跟子节点混在一起的也按原来的顺序写回去
+//! `<Connections>` / `<Nodes>` 下面有不认识的节点的话 serde 那边就解析失败了, 走不到这里
+//! 写之前结构变了 (换了格式、删了零件) 对不上的路径直接丢掉
+
+use std::collections::{BTreeMap, HashMap};
+
+use quick_xml::{
+    Reader, Writer,
+    escape::escape,
+    events::{BytesEnd, BytesStart, BytesText, Event},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::xml_part::error::{XmlError, XmlResult};
+
+/// 一个认识的节点
+pub struct NodeSchema {
+    pub name: &'static str,
+    /// 会有好几个兄弟, 路径里带下标
+    pub repeated: bool,
+    pub attrs: &'static [&'static str],
+    pub children: &'static [&'static str],
+    /// `children` 里必须有的, 少了 serde 那边就解析失败
+    pub required_children: &'static [&'static str],
+}
+
+const fn node(
+    name: &'static str,
+    repeated: bool,
+    attrs: &'static [&'static str],
+    children: &'static [&'static str],
+) -> NodeSchema {
+    NodeSchema {
+        name,
+        repeated,
+        attrs,
+        children,
+        required_children: &[],
+    }
+}
+
+impl NodeSchema {
+    const fn requires(self, required_children: &'static [&'static str]) -> Self {
+        Self {
+            required_children,
+            ..self
+        }
+    }
+}
+
+/// raw 层认识的节点, 跟 [`crate::xml_part::raw`] 里的 `rename` 对应
+///
+/// 老格式根节点下面的 `<Staging>` 跟 `<Pod>` 下面的共用一个
+/// [`crate::xml_part::stream`] 和 [`crate::xml_part::recover`] 也按这张表看哪些子节点必须有、能不能重复,
+/// 跟 serde 对不对得上由 `stream` 里的 `schema_matches_serde` 测试看着
+pub const KNOWN: &[NodeSchema] = &[
+    node(
+        "Ship",
+        false,
+        &[
+            "version",
+            "liftedOff",
+            "touchingGround",
+            "currentStage",
+            "throttle",
+        ],
+        &["Parts", "Connections", "DisconnectedParts", "Staging"],
+    )
+    .requires(&["Parts", "Connections"]),
+    node("Parts", false, &[], &["Part"]),
+    node(
+        "Part",
+        true,
+        &[
+            "partType",
+            "id",
+            "x",
+            "y",
+            "editorAngle",
+            "angle",
+            "angleV",
+            "flippedX",
+            "flippedY",
+            "activated",
+            "exploded",
+            "chuteX",
+            "chuteY",
+            "chuteAngle",
+            "chuteHeight",
+            "extension",
+            "inflate",
+            "inflation",
+            "deployed",
+            "rope",
+        ],
+        &["Tank", "Engine", "Pod"],
+    ),
+    node("Tank", false, &["fuel"], &[]),
+    node("Engine", false, &["fuel"], &[]),
+    node("Pod", false, &["name", "throttle"], &["Staging"]).requires(&["Staging"]),
+    node("Staging", false, &["currentStage"], &["Step"]),
+    node("Step", true, &[], &["Activate"]),
+    node("Activate", true, &["Id", "moved"], &[]),
+    node("Connections", false, &[], &["Connection", "DockConnection"]),
+    node(
+        "Connection",
+        true,
+        &[
+            "parentAttachPoint",
+            "childAttachPoint",
+            "parentPart",
+            "childPart",
+        ],
+        &[],
+    ),
+    node(
+        "DockConnection",
+        true,
+        &["dockPart", "parentPart", "childPart"],
+        &[],
+    ),
+    node("DisconnectedParts", false, &[], &["DisconnectedPart"]),
+    node("DisconnectedPart", true, &[], &["Parts", "Connections"])
+        .requires(&["Parts", "Connections"]),
+    node(
+        "Runtime",
+        false,
+        &[
+            "time",
+            "firstStageActivated",
+            "solarSystem",
+            "shipId",
+            "podId",
+        ],
+        &["Nodes"],
+    )
+    .requires(&["Nodes"]),
+    node("Nodes", false, &[], &["PlanetNode", "ShipNode"]),
+    node("PlanetNode", true, &["name", "trueAnomaly"], &[]),
+    node(
+        "ShipNode",
+        true,
+        &["id", "planet", "planetRadius", "x", "y", "vx", "vy"],
+        &["Ship"],
+    )
+    .requires(&["Ship"]),
+];
+
+pub fn known_node(name: &str) -> Option<&'static NodeSchema> {
+    KNOWN.iter().find(|schema| schema.name == name)
+}
+
+/// `name` 下面必须有的子节点
+pub fn required_children(name: &str) -> &'static [&'static str] {
+    known_node(name).map_or(&[], |schema| schema.required_children)
+}
+
+/// 只能有一个的子节点, 其他的 (`Part`, `Step` ...) 可以有好几个
+pub fn single_child(name: &str) -> bool {
+    known_node(name).is_some_and(|schema| !schema.repeated)
+}
+
+/// `parent` 下面叫 `name` 的子节点的路径, 会重复的节点带从 1 开始的下标 (`counts` 按名字数)
+///
+/// [`Extras`] 也是这么写的, 不过这里不管父节点认不认识它
+pub fn indexed_path(parent: &str, counts: &mut HashMap<String, usize>, name: &str) -> String {
+    if known_node(name).is_some_and(|schema| schema.repeated) {
+        let count = counts.entry(name.to_string()).or_default();
+        *count += 1;
+        format!("{parent}/{name}[{count}]")
+    } else {
+        format!("{parent}/{name}")
+    }
+}
+
+/// 随便一个 xml 元素
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct XmlElement {
+    pub name: String,
+    /// 反转义过的, 按原来的顺序
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<XmlElement>,
+    /// 转义过的, 第一个子节点前面的文本
+    pub text: String,
+    /// 转义过的, 这个节点结束之后到下一个兄弟节点之前的文本
+    #[serde(default)]
+    pub tail: String,
+}
+
+impl XmlElement {
+    /// 整个文档读成一棵树, 没有根节点的话是 None
+    pub fn parse(data: &str) -> XmlResult<Option<Self>> {
+        let mut reader = Reader::from_str(data);
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => return Ok(Some(read_element(&mut reader, &start, false)?)),
+                Event::Empty(start) => return Ok(Some(read_element(&mut reader, &start, true)?)),
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// 写成 xml, 没有缩进
+    pub fn to_xml(&self) -> XmlResult<String> {
+        let mut writer = Writer::new(Vec::new());
+        write_element(&mut writer, self)?;
+        String::from_utf8(writer.into_inner()).map_err(|err| XmlError::Serialize(err.to_string()))
+    }
+}
+
+/// 一个节点上多出来的东西
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct NodeExtra {
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<XmlElement>,
+}
+
+/// 整个文档多出来的东西, 路径 -> 多出来的属性和子节点
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Extras {
+    pub nodes: BTreeMap<String, NodeExtra>,
+}
+
+impl Extras {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, path: &str) -> Option<&NodeExtra> {
+        self.nodes.get(path)
+    }
+
+    fn entry(&mut self, path: &str) -> &mut NodeExtra {
+        self.nodes.entry(path.to_string()).or_default()
+    }
+}
+
+struct Frame {
+    path: String,
+    schema: Option<&'static NodeSchema>,
+    counts: HashMap<String, usize>,
+}
+
+/// 子节点的路径, 父节点不认识它的话是 None
+fn child_path(parent: Option<&mut Frame>, name: &str) -> Option<(String, &'static NodeSchema)> {
+    let schema = known_node(name)?;
+    let Some(parent) = parent else {
+        return Some((name.to_string(), schema));
+    };
+    if !parent
+        .schema
+        .is_some_and(|parent| parent.children.contains(&name))
+    {
+        return None;
+    }
+    let path = indexed_path(&parent.path, &mut parent.counts, name);
+    Some((path, schema))
+}
+
+fn element_name(start: &BytesStart) -> String {
+    String::from_utf8_lossy(start.name().as_ref()).to_string()
+}
+
+/// 反转义过的属性, 按原来的顺序
+pub fn read_attrs(start: &BytesStart) -> XmlResult<Vec<(String, String)>> {
+    let mut attrs = Vec::new();
+    for attr in start.attributes() {
+        let attr = attr.map_err(quick_xml::Error::from)?;
+        attrs.push((
+            String::from_utf8_lossy(attr.key.as_ref()).to_string(),
+            attr.unescape_value()?.into_owned(),
+        ));
+    }
+    Ok(attrs)
+}
+
+fn read_element(
+    reader: &mut Reader<&[u8]>,
+    start: &BytesStart,
+    empty: bool,
+) -> XmlResult<XmlElement> {
+    let mut element = XmlElement {
+        name: element_name(start),
+        attrs: read_attrs(start)?,
+        ..Default::default()
+    };
+    if empty {
+        return Ok(element);
+    }
+    loop {
+        let text = match reader.read_event()? {
+            Event::Start(start) => {
+                element.children.push(read_element(reader, &start, false)?);
+                continue;
+            }
+            Event::Empty(start) => {
+                element.children.push(read_element(reader, &start, true)?);
+                continue;
+            }
+            Event::Text(text) => text.decode().map_err(quick_xml::Error::from)?.into_owned(),
+            Event::GeneralRef(entity) => {
+                format!("&{};", entity.decode().map_err(quick_xml::Error::from)?)
+            }
+            Event::CData(data) => escape(data.decode().map_err(quick_xml::Error::from)?).into(),
+            Event::End(_) | Event::Eof => break,
+            _ => continue,
+        };
+        // 子节点后面的文本跟着那个子节点走, 写的时候顺序才对得上
+        match element.children.last_mut() {
+            Some(child) => child.tail.push_str(&text),
+            None => element.text.push_str(&text),
+        }
+    }
+    Ok(element)
+}
+
+/// 扫一遍原文, 找出 raw 层不认识的属性和子节点
+pub fn capture(data: &str) -> XmlResult<Extras> {
+    let mut extras = Extras::default();
+    let mut reader = Reader::from_str(data);
+    let mut stack: Vec<Frame> = Vec::new();
+    loop {
+        let (start, empty) = match reader.read_event()? {
+            Event::Start(start) => (start, false),
+            Event::Empty(start) => (start, true),
+            Event::End(_) => {
+                stack.pop();
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        let name = element_name(&start);
+        let Some((path, schema)) = child_path(stack.last_mut(), &name) else {
+            let element = read_element(&mut reader, &start, empty)?;
+            match stack.last() {
+                Some(parent) => extras.entry(&parent.path).children.push(element),
+                // 根节点都不认识, 不是船也不是存档
+                None => return Ok(Extras::default()),
+            }
+            continue;
+        };
+        for (key, value) in read_attrs(&start)? {
+            if !schema.attrs.contains(&key.as_str()) {
+                extras.entry(&path).attrs.push((key, value));
+            }
+        }
+        if !empty {
+            stack.push(Frame {
+                path,
+                schema: Some(schema),
+                counts: HashMap::new(),
+            });
+        }
+    }
+    Ok(extras)
+}
+
+fn write_element(writer: &mut Writer<Vec<u8>>, element: &XmlElement) -> XmlResult<()> {
+    let mut start = BytesStart::new(element.name.as_str());
+    for (key, value) in &element.attrs {
+        start.push_attribute((key.as_str(), value.as_str()));
+    }
+    if element.children.is_empty() && element.text.is_empty() {
+        writer.write_event(Event::Empty(start))?;
+        return Ok(());
+    }
+    writer.write_event(Event::Start(start))?;
+    if !element.text.is_empty() {
+        writer.write_event(Event::Text(BytesText::from_escaped(element.text.as_str())))?;
+    }
+    for child in &element.children {
+        write_element(writer, child)?;
+        if !child.tail.is_empty() {
+            writer.write_event(Event::Text(BytesText::from_escaped(child.tail.as_str())))?;
+        }
+    }
+    writer.write_event(Event::End(BytesEnd::new(element.name.as_str())))?;
+    Ok(())
+}
+
+fn with_attrs<'a>(start: BytesStart<'a>, extra: Option<&NodeExtra>) -> XmlResult<BytesStart<'a>> {
+    let mut start = start;
+    for (key, value) in extra.map_or(&[][..], |extra| &extra.attrs) {
+        // 已经有了的以写出来的为准
+        if start
+            .try_get_attribute(key.as_str())
+            .map_err(quick_xml::Error::from)?
+            .is_none()
+        {
+            start.push_attribute((key.as_str(), value.as_str()));
+        }
+    }
+    Ok(start)
+}
+
+/// 把 `extras` 塞回 serde 写出来的 `xml` 里, 没有多出来的东西就原样返回
+pub fn emit(xml: String, extras: &Extras) -> XmlResult<String> {
+    if extras.is_empty() {
+        return Ok(xml);
+    }
+    let mut reader = Reader::from_str(&xml);
+    let mut writer = Writer::new(Vec::with_capacity(xml.len()));
+    let mut stack: Vec<Frame> = Vec::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => {
+                let name = element_name(&start);
+                let (path, schema) = child_path(stack.last_mut(), &name)
+                    .map_or((String::new(), None), |(path, schema)| (path, Some(schema)));
+                let start = with_attrs(start, extras.get(&path))?;
+                writer.write_event(Event::Start(start))?;
+                stack.push(Frame {
+                    path,
+                    schema,
+                    counts: HashMap::new(),
+                });
+            }
+            Event::Empty(start) => {
+                let name = element_name(&start);
+                let path = child_path(stack.last_mut(), &name).map(|(path, _)| path);
+                let extra = path.as_deref().and_then(|path| extras.get(path));
+                let start = with_attrs(start, extra)?;
+                match extra.filter(|extra| !extra.children.is_empty()) {
+                    Some(extra) => {
+                        writer.write_event(Event::Start(start))?;
+                        for child in &extra.children {
+                            write_element(&mut writer, child)?;
+                        }
+                        writer.write_event(Event::End(BytesEnd::new(name)))?;
+                    }
+                    None => writer.write_event(Event::Empty(start))?,
+                }
+            }
+            Event::End(end) => {
+                if let Some(extra) = stack.pop().and_then(|frame| extras.get(&frame.path)) {
+                    for child in &extra.children {
+                        write_element(&mut writer, child)?;
+                    }
+                }
+                writer.write_event(Event::End(end))?;
+            }
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+    String::from_utf8(writer.into_inner()).map_err(|err| XmlError::Serialize(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{XmlElement, capture, emit};
+
+    const SHIP: &str = r#"<Ship version="1" liftedOff="0" touchingGround="1" mod="a &amp; b">
+    <Parts>
+        <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0"/>
+        <Part partType="fuselage-1" id="2" x="0" y="1" angle="0" angleV="0" paint="red">
+            <Tank fuel="10" />
+            <Decal side="left">Hello &lt;world&gt;<Layer n="1"/> again </Decal>
+        </Part>
+    </Parts>
+    <Connections/>
+    <Notes>keep me</Notes>
+</Ship>"#;
+
+    #[test]
+    fn captures_by_path() {
+        let extras = capture(SHIP).unwrap();
+        assert_eq!(extras.nodes.len(), 2);
+        let ship = extras.get("Ship").unwrap();
+        assert_eq!(ship.attrs, vec![("mod".to_string(), "a & b".to_string())]);
+        assert_eq!(ship.children[0].name, "Notes");
+        assert_eq!(ship.children[0].text, "keep me");
+
+        let part = extras.get("Ship/Parts/Part[2]").unwrap();
+        assert_eq!(part.attrs, vec![("paint".to_string(), "red".to_string())]);
+        let decal = &part.children[0];
+        assert_eq!(decal.attr("side"), Some("left"));
+        assert_eq!(decal.text, "Hello &lt;world&gt;");
+        assert_eq!(decal.children[0].name, "Layer");
+        assert_eq!(decal.children[0].tail, " again ");
+
+        assert!(capture(crate::net::EMPTY_SHIP).unwrap().is_empty());
+    }
+
+    #[test]
+    fn emits_back_in_place() {
+        let extras = capture(SHIP).unwrap();
+        let written = r#"<Ship version="1"><Parts><Part id="1"/><Part id="2"><Tank fuel="10"/></Part></Parts><Connections/></Ship>"#;
+        let xml = emit(written.to_string(), &extras).unwrap();
+        let tree = XmlElement::parse(&xml).unwrap().unwrap();
+        assert_eq!(tree.attr("mod"), Some("a & b"));
+        assert_eq!(tree.children.last().unwrap().name, "Notes");
+        let part = &tree.children[0].children[1];
+        assert_eq!(part.attr("paint"), Some("red"));
+        assert_eq!(
+            part.children
+                .iter()
+                .map(|child| child.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Tank", "Decal"]
+        );
+        assert_eq!(part.children[1].text, "Hello &lt;world&gt;");
+        assert!(
+            xml.contains(r#"<Decal side="left">Hello &lt;world&gt;<Layer n="1"/> again </Decal>"#)
+        );
+        // 再抓一遍还是一样的
+        assert_eq!(capture(&xml).unwrap(), extras);
+        // 没有多出来的东西就不动
+        assert_eq!(
+            emit(written.to_string(), &Default::default()).unwrap(),
+            written
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! 按游戏的习惯写 xml
+//!
+//! serde 写出来的东西游戏能读, 但是跟原文对不上: `0.000000` 变成 `0`, 属性顺序是结构体字段的顺序
+//! 这里手写一遍, 跟游戏存出来的一样:
+//! - 浮点数固定六位小数, 整数就是整数
+//! - 属性按游戏的顺序 (`partType id x y angle angleV editorAngle ...`)
+//! - 没有子节点的自闭合, 没有缩进, 没有 `<?xml ?>` 声明
+//! - 属性值转义 `& < > " '`, 撇号是 `&apos;` (比如 `Smalley&apos;s Comet`)
+//!
+//! 是按 raw 结构写的, raw 里 `Option` 的属性原来有就写, 没有就不写, 所以没改过的文档写回去一个字节都不差
+//! model 里有些属性 (`activated` 这种) 分不出原来有没有, 从 model 写的话都会带上
+//! 老格式里没有 `editorAngle` / `liftedOff` 的船, 写回去会补上默认值
+
+use std::fmt::{Display, Write};
+
+use crate::xml_part::{
+    error::XmlResult,
+    extra::emit,
+    raw::{
+        RawConnection, RawConnections, RawNode, RawPart, RawParts, RawSaveDocument,
+        RawShipDocument, RawStep,
+    },
+};
+
+struct Element {
+    name: &'static str,
+    attrs: String,
+}
+
+impl Element {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            attrs: String::new(),
+        }
+    }
+
+    fn attr(mut self, key: &str, value: impl Display) -> Self {
+        let value = value.to_string();
+        let _ = write!(self.attrs, " {key}=\"{}\"", escape_attr(&value));
+        self
+    }
+
+    fn float(self, key: &str, value: f64) -> Self {
+        self.attr(key, format_float(value))
+    }
+
+    fn opt(self, key: &str, value: Option<impl Display>) -> Self {
+        match value {
+            Some(value) => self.attr(key, value),
+            None => self,
+        }
+    }
+
+    fn opt_float(self, key: &str, value: Option<f64>) -> Self {
+        self.opt(key, value.map(format_float))
+    }
+}
+
+/// 游戏的浮点数: 六位小数
+pub fn format_float(value: f64) -> String {
+    format!("{value:.6}")
+}
+
+fn escape_attr(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Default)]
+struct GameWriter {
+    out: String,
+}
+
+impl GameWriter {
+    fn empty(&mut self, element: Element) {
+        let _ = write!(self.out, "<{}{}/>", element.name, element.attrs);
+    }
+
+    /// `children` 什么都没写的话改成自闭合
+    fn node(&mut self, element: Element, children: impl FnOnce(&mut Self)) {
+        let start = self.out.len();
+        let _ = write!(self.out, "<{}{}>", element.name, element.attrs);
+        let inner = self.out.len();
+        children(self);
+        if self.out.len() == inner {
+            self.out.truncate(start);
+            self.empty(element);
+        } else {
+            let _ = write!(self.out, "</{}>", element.name);
+        }
+    }
+
+    fn ship(&mut self, ship: &RawShipDocument) {
+        let element = Element::new("Ship")
+            .opt("version", ship.version)
+            .opt("currentStage", ship.current_stage)
+            .opt_float("throttle", ship.throttle)
+            .attr("liftedOff", ship.lifted_off)
+            .opt("touchingGround", ship.touching_ground);
+        self.node(element, |w| {
+            if let Some(disconnected) = &ship.disconnected {
+                w.node(Element::new("DisconnectedParts"), |w| {
+                    for group in &disconnected.parts {
+                        w.node(Element::new("DisconnectedPart"), |w| {
+                            w.parts(&group.parts);
+                            w.connections(&group.connections);
+                        });
+                    }
+                });
+            }
+            w.parts(&ship.parts);
+            w.connections(&ship.connections);
+            if let Some(staging) = &ship.staging {
+                w.node(Element::new("Staging"), |w| w.steps(&staging.steps));
+            }
+        });
+    }
+
+    fn parts(&mut self, parts: &RawParts) {
+        self.node(Element::new("Parts"), |w| {
+            for part in &parts.parts {
+                w.part(part);
+            }
+        });
+    }
+
+    fn part(&mut self, part: &RawPart) {
+        let element = Element::new("Part")
+            .attr("partType", &part.part_type_id)
+            .attr("id", part.id)
+            .float("x", part.x)
+            .float("y", part.y)
+            .float("angle", part.angle)
+            .float("angleV", part.angle_v)
+            .attr("editorAngle", part.editor_angle)
+            .opt("activated", part.activated)
+            .opt("exploded", part.exploded)
+            .opt("flippedX", part.flipped_x)
+            .opt("flippedY", part.flipped_y)
+            .opt_float("chuteX", part.chute_x)
+            .opt_float("chuteY", part.chute_y)
+            .opt_float("chuteAngle", part.chute_angle)
+            .opt_float("chuteHeight", part.chute_height)
+            .opt_float("extension", part.extension)
+            .opt_float("inflation", part.inflation)
+            .opt("inflate", part.inflate)
+            .opt("deployed", part.deployed)
+            .opt("rope", part.rope);
+        self.node(element, |w| {
+            if let Some(pod) = &part.pod {
+                let element = Element::new("Pod")
+                    .float("throttle", pod.throttle)
+                    .attr("name", &pod.name);
+                w.node(element, |w| {
+                    let staging =
+                        Element::new("Staging").attr("currentStage", pod.staging.current_stage);
+                    w.node(staging, |w| w.steps(&pod.staging.steps));
+                });
+            }
+            if let Some(tank) = &part.tank {
+                w.empty(Element::new("Tank").float("fuel", tank.fuel));
+            }
+            if let Some(engine) = &part.engine {
+                w.empty(Element::new("Engine").float("fuel", engine.fuel));
+            }
+        });
+    }
+
+    fn steps(&mut self, steps: &[RawStep]) {
+        for step in steps {
+            self.node(Element::new("Step"), |w| {
+                for activate in &step.activates {
+                    w.empty(
+                        Element::new("Activate")
+                            .attr("Id", activate.id)
+                            .attr("moved", activate.moved),
+                    );
+                }
+            });
+        }
+    }
+
+    fn connections(&mut self, connections: &RawConnections) {
+        self.node(Element::new("Connections"), |w| {
+            for connection in &connections.connections {
+                let element = match connection {
+                    RawConnection::Normal {
+                        parent_attach_point,
+                        child_attach_point,
+                        parent_part,
+                        child_part,
+                    } => Element::new("Connection")
+                        .attr("parentAttachPoint", parent_attach_point)
+                        .attr("childAttachPoint", child_attach_point)
+                        .attr("parentPart", parent_part)
+                        .attr("childPart", child_part),
+                    RawConnection::Dock {
+                        dock_part,
+                        parent_part,
+                        child_part,
+                    } => Element::new("DockConnection")
+                        .attr("dockPart", dock_part)
+                        .attr("parentPart", parent_part)
+                        .attr("childPart", child_part),
+                };
+                w.empty(element);
+            }
+        });
+    }
+
+    fn save(&mut self, save: &RawSaveDocument) {
+        let element = Element::new("Runtime")
+            .float("time", save.time)
+            .attr("firstStageActivated", save.first_stage_activated)
+            .attr("solarSystem", &save.solar_system)
+            .attr("shipId", save.ship_id)
+            .attr("podId", save.pod_id);
+        self.node(element, |w| {
+            w.node(Element::new("Nodes"), |w| {
+                for node in &save.nodes.nodes {
+                    match node {
+                        RawNode::Planet(planet) => w.empty(
+                            Element::new("PlanetNode")
+                                .attr("name", &planet.name)
+                                .opt_float("trueAnomaly", planet.true_anomaly),
+                        ),
+                        RawNode::Ship(node) => {
+                            let element = Element::new("ShipNode")
+                                .attr("id", node.id)
+                                .attr("planet", &node.planet)
+                                .float("planetRadius", node.planet_radius)
+                                .float("x", node.x)
+                                .float("y", node.y)
+                                .float("vx", node.vx)
+                                .float("vy", node.vy);
+                            w.node(element, |w| w.ship(&node.ship));
+                        }
+                    }
+                }
+            });
+        });
+    }
+}
+
+/// 按游戏的格式写船, [`RawShipDocument::extras`] 也会塞回去
+pub fn game_ship_xml(raw: &RawShipDocument) -> XmlResult<String> {
+    let mut writer = GameWriter::default();
+    writer.ship(raw);
+    emit(writer.out, &raw.extras)
+}
+
+/// 按游戏的格式写存档
+pub fn game_save_xml(raw: &RawSaveDocument) -> XmlResult<String> {
+    let mut writer = GameWriter::default();
+    writer.save(raw);
+    emit(writer.out, &raw.extras)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_float, game_save_xml, game_ship_xml};
+    use crate::xml_part::parse::{parse_raw_save_xml, parse_raw_ship_xml};
+
+    #[test]
+    fn byte_for_byte() {
+        let sample = include_str!("../save_1294489.xml");
+        let raw = parse_raw_save_xml(sample).unwrap();
+        let xml = game_save_xml(&raw).unwrap();
+        assert_eq!(xml, sample);
+        assert_eq!(
+            blake3::hash(xml.as_bytes()),
+            blake3::hash(sample.as_bytes())
+        );
+
+        let empty = crate::net::EMPTY_SHIP;
+        assert_eq!(
+            game_ship_xml(&parse_raw_ship_xml(empty).unwrap()).unwrap(),
+            empty
+        );
+
+        let named = empty.replace(r#"name="""#, r#"name="a &amp; &quot;b&quot; &lt;c&gt;""#);
+        assert_eq!(
+            game_ship_xml(&parse_raw_ship_xml(&named).unwrap()).unwrap(),
+            named
+        );
+    }
+
+    #[test]
+    fn game_conventions() {
+        assert_eq!(format_float(0.0), "0.000000");
+        assert_eq!(format_float(-2.5), "-2.500000");
+        assert_eq!(format_float(637100.0), "637100.000000");
+
+        let legacy = r#"<Ship currentStage="0" throttle="0.000000" liftedOff="0"><Parts><Part partType="pod-1" id="1" x="0.000000" y="0.000000" angle="0.000000" angleV="0.000000" editorAngle="0"/></Parts><Connections/><Staging/></Ship>"#;
+        assert_eq!(
+            game_ship_xml(&parse_raw_ship_xml(legacy).unwrap()).unwrap(),
+            legacy
+        );
+    }
+}
@@ -0,0 +1,516 @@
+//! 零件在世界坐标里的形状
+//!
+//! 零件目录里的 `width` / `height` / `Shape` 用的是编辑器的格子, 一格是世界坐标里的 [`PART_UNIT`]
+//! `Part.angle` 是弧度, 已经包含了 `editorAngle` 的那 90 度, 所以这里只看 `angle`
+//! `flippedX` / `flippedY` 是在零件自己的坐标系里镜像, 然后再旋转、平移
+//!
+//! 没写 `Shape` 的零件用 `width` x `height` 的矩形代替, `sensor` 的形状不参与碰撞, 这里也不算
+//! 零件目录里没有的零件没法摆, 记在 [`ShipGeometry::missing`] 里
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::xml_part::{
+    catalog::{PartCatalog, PartType, Shape},
+    model::{Part, ShipData},
+};
+
+/// 编辑器里一格在世界坐标里的长度
+pub const PART_UNIT: f64 = 0.5;
+/// 挤进去不到这么深的不算重叠, 贴在一起的零件算出来会有一点点误差
+pub const OVERLAP_TOLERANCE: f64 = 0.05;
+/// 和别的零件隔了这么远就不算同一个整体了
+pub const MAX_PART_GAP: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    fn dot(self, other: Self) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+}
+
+/// 轴对齐的外框
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bounds {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl Bounds {
+    /// 包住这些点的外框, 一个点都没有的话是 None
+    pub fn from_points(points: impl IntoIterator<Item = Point>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let mut bounds = Self {
+            min_x: first.x,
+            min_y: first.y,
+            max_x: first.x,
+            max_y: first.y,
+        };
+        for point in points {
+            bounds.min_x = bounds.min_x.min(point.x);
+            bounds.min_y = bounds.min_y.min(point.y);
+            bounds.max_x = bounds.max_x.max(point.x);
+            bounds.max_y = bounds.max_y.max(point.y);
+        }
+        Some(bounds)
+    }
+
+    pub fn width(&self) -> f64 {
+        self.max_x - self.min_x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.max_y - self.min_y
+    }
+
+    pub fn center(&self) -> Point {
+        Point::new(
+            (self.min_x + self.max_x) / 2.0,
+            (self.min_y + self.max_y) / 2.0,
+        )
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+
+    /// 两个外框之间的距离, 碰到或者重叠是 0
+    pub fn gap(&self, other: &Self) -> f64 {
+        let dx = (other.min_x - self.max_x)
+            .max(self.min_x - other.max_x)
+            .max(0.0);
+        let dy = (other.min_y - self.max_y)
+            .max(self.min_y - other.max_y)
+            .max(0.0);
+        dx.hypot(dy)
+    }
+}
+
+/// 摆到世界坐标里的一个零件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartShape {
+    pub part: i64,
+    pub part_type: String,
+    /// 零件中心
+    pub center: Point,
+    /// `width` x `height` 的外框, 逆时针, 从左下角开始 (没旋转的时候)
+    pub outline: [Point; 4],
+    /// 碰撞形状, 都是凸多边形, 逆时针
+    pub polygons: Vec<Vec<Point>>,
+    pub bounds: Bounds,
+    /// 零件类型写了 `ignoreEditorIntersections`, 编辑器里允许它和别的零件叠在一起
+    pub ignore_intersections: bool,
+}
+
+impl PartShape {
+    /// 把零件摆到世界坐标里, 坐标或者角度不是有限数的话返回 None
+    pub fn place(part: &Part, part_type: &PartType) -> Option<Self> {
+        if !(part.x.is_finite() && part.y.is_finite() && part.angle.is_finite()) {
+            return None;
+        }
+        let transform = Transform::new(part);
+        let half_w = part_type.width / 2.0;
+        let half_h = part_type.height / 2.0;
+        let rect = [
+            Point::new(-half_w, -half_h),
+            Point::new(half_w, -half_h),
+            Point::new(half_w, half_h),
+            Point::new(-half_w, half_h),
+        ];
+        let outline = rect.map(|point| transform.apply(point));
+
+        let solid: Vec<&Shape> = part_type
+            .shapes
+            .iter()
+            .filter(|shape| !shape.sensor && shape.vertices.len() >= 3)
+            .collect();
+        let polygons: Vec<Vec<Point>> = if solid.is_empty() {
+            vec![transform.polygon(rect.iter().copied())]
+        } else {
+            solid
+                .iter()
+                .map(|shape| {
+                    transform.polygon(
+                        shape
+                            .vertices
+                            .iter()
+                            .map(|vertex| Point::new(vertex.x, vertex.y)),
+                    )
+                })
+                .collect()
+        };
+        let bounds = Bounds::from_points(outline.iter().chain(polygons.iter().flatten()).copied())?;
+        Some(Self {
+            part: part.id,
+            part_type: part.part_type_id.clone(),
+            center: Point::new(part.x, part.y),
+            outline,
+            polygons,
+            bounds,
+            ignore_intersections: part_type.ignore_editor_intersections,
+        })
+    }
+
+    /// 和另一个零件挤进去了多深, 没碰到是 None
+    pub fn penetration(&self, other: &Self) -> Option<f64> {
+        if self.bounds.gap(&other.bounds) > 0.0 {
+            return None;
+        }
+        self.polygons
+            .iter()
+            .flat_map(|a| other.polygons.iter().filter_map(move |b| penetration(a, b)))
+            .reduce(f64::max)
+    }
+}
+
+/// 零件坐标系 -> 世界坐标
+struct Transform {
+    origin: Point,
+    sin: f64,
+    cos: f64,
+    flip_x: bool,
+    flip_y: bool,
+}
+
+impl Transform {
+    fn new(part: &Part) -> Self {
+        let (sin, cos) = part.angle.sin_cos();
+        Self {
+            origin: Point::new(part.x, part.y),
+            sin,
+            cos,
+            flip_x: part.flipped_x,
+            flip_y: part.flipped_y,
+        }
+    }
+
+    fn apply(&self, local: Point) -> Point {
+        let x = if self.flip_x { -local.x } else { local.x } * PART_UNIT;
+        let y = if self.flip_y { -local.y } else { local.y } * PART_UNIT;
+        Point::new(
+            self.origin.x + x * self.cos - y * self.sin,
+            self.origin.y + x * self.sin + y * self.cos,
+        )
+    }
+
+    /// 只镜像了一个方向的话顶点顺序会反过来, 这里再倒回逆时针
+    fn polygon(&self, local: impl Iterator<Item = Point>) -> Vec<Point> {
+        let mut points: Vec<Point> = local.map(|point| self.apply(point)).collect();
+        if self.flip_x != self.flip_y {
+            points.reverse();
+        }
+        points
+    }
+}
+
+/// 分离轴定理, 两个凸多边形在所有边的法线上都有重叠才算相交
+///
+/// 返回最浅的那个方向上的重叠长度, 就是至少要推开多远
+fn penetration(a: &[Point], b: &[Point]) -> Option<f64> {
+    let mut depth = f64::INFINITY;
+    for polygon in [a, b] {
+        for (index, start) in polygon.iter().enumerate() {
+            let end = polygon[(index + 1) % polygon.len()];
+            let axis = Point::new(start.y - end.y, end.x - start.x);
+            let length = axis.dot(axis).sqrt();
+            if length <= f64::EPSILON {
+                continue;
+            }
+            let axis = Point::new(axis.x / length, axis.y / length);
+            let (a_min, a_max) = project(a, axis);
+            let (b_min, b_max) = project(b, axis);
+            let overlap = a_max.min(b_max) - a_min.max(b_min);
+            if overlap <= 0.0 {
+                return None;
+            }
+            depth = depth.min(overlap);
+        }
+    }
+    depth.is_finite().then_some(depth)
+}
+
+fn project(polygon: &[Point], axis: Point) -> (f64, f64) {
+    polygon
+        .iter()
+        .map(|point| point.dot(axis))
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(value), max.max(value))
+        })
+}
+
+/// 两个零件叠在一起了
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Overlap {
+    pub a: i64,
+    pub b: i64,
+    pub depth: f64,
+}
+
+/// 离大部队太远的零件, `distance` 是到主体最近的零件的外框距离
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FarPart {
+    pub part: i64,
+    pub distance: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ShipGeometry {
+    /// 按零件出现的顺序
+    pub shapes: Vec<PartShape>,
+    /// 所有零件的外框, 一个零件都摆不出来的时候是 None
+    pub bounds: Option<Bounds>,
+    /// 零件目录里没有 / 坐标不是有限数, 摆不出来的零件
+    pub missing: Vec<i64>,
+}
+
+impl ShipGeometry {
+    /// 主船体和 `DisconnectedParts` 里的零件都算
+    pub fn new(ship: &ShipData, catalog: &PartCatalog) -> Self {
+        Self::from_parts(
+            ship.parts
+                .iter()
+                .chain(ship.disconnected.iter().flat_map(|group| &group.parts)),
+            catalog,
+        )
+    }
+
+    pub fn from_parts<'a>(
+        parts: impl IntoIterator<Item = &'a Part>,
+        catalog: &PartCatalog,
+    ) -> Self {
+        let mut geometry = Self::default();
+        for part in parts {
+            match catalog
+                .part_type(part)
+                .and_then(|part_type| PartShape::place(part, part_type))
+            {
+                Some(shape) => geometry.shapes.push(shape),
+                None => geometry.missing.push(part.id),
+            }
+        }
+        geometry.bounds = geometry
+            .shapes
+            .iter()
+            .map(|shape| shape.bounds)
+            .reduce(|a, b| a.union(&b));
+        geometry
+    }
+
+    pub fn get(&self, part: i64) -> Option<&PartShape> {
+        self.shapes.iter().find(|shape| shape.part == part)
+    }
+
+    /// 外框按 min_x 排好的下标, 扫描的时候 x 方向离得太远就可以提前停
+    fn sorted_by_min_x(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.shapes.len()).collect();
+        order.sort_by(|&a, &b| {
+            self.shapes[a]
+                .bounds
+                .min_x
+                .total_cmp(&self.shapes[b].bounds.min_x)
+        });
+        order
+    }
+
+    /// 挤进去超过 [`OVERLAP_TOLERANCE`] 的零件对, 带 `ignoreEditorIntersections` 的零件不算
+    ///
+    /// 每一对里 `a` 的 id 比 `b` 小, 整体按 (a, b) 排
+    pub fn overlaps(&self) -> Vec<Overlap> {
+        let order = self.sorted_by_min_x();
+        let mut overlaps = Vec::new();
+        for (index, &i) in order.iter().enumerate() {
+            let first = &self.shapes[i];
+            if first.ignore_intersections {
+                continue;
+            }
+            for &j in &order[index + 1..] {
+                let second = &self.shapes[j];
+                if second.bounds.min_x > first.bounds.max_x {
+                    break;
+                }
+                if second.ignore_intersections || first.part == second.part {
+                    continue;
+                }
+                if let Some(depth) = first.penetration(second)
+                    && depth > OVERLAP_TOLERANCE
+                {
+                    overlaps.push(Overlap {
+                        a: first.part.min(second.part),
+                        b: first.part.max(second.part),
+                        depth,
+                    });
+                }
+            }
+        }
+        overlaps.sort_by_key(|overlap| (overlap.a, overlap.b));
+        overlaps
+    }
+
+    /// 外框间距不超过 `max_gap` 的零件算一堆, 零件最多的那堆是主体 (一样多的话取先出现的)
+    ///
+    /// 返回不在主体里的零件, 按零件出现的顺序
+    pub fn far_parts(&self, max_gap: f64) -> Vec<FarPart> {
+        let count = self.shapes.len();
+        let mut roots: Vec<usize> = (0..count).collect();
+        fn find(roots: &mut [usize], mut index: usize) -> usize {
+            while roots[index] != index {
+                roots[index] = roots[roots[index]];
+                index = roots[index];
+            }
+            index
+        }
+
+        let order = self.sorted_by_min_x();
+        for (index, &i) in order.iter().enumerate() {
+            for &j in &order[index + 1..] {
+                if self.shapes[j].bounds.min_x - self.shapes[i].bounds.max_x > max_gap {
+                    break;
+                }
+                if self.shapes[i].bounds.gap(&self.shapes[j].bounds) <= max_gap {
+                    let (a, b) = (find(&mut roots, i), find(&mut roots, j));
+                    // 小的下标当根, 一样大的堆就是先出现的赢
+                    roots[a.max(b)] = a.min(b);
+                }
+            }
+        }
+
+        let mut sizes: BTreeMap<usize, usize> = BTreeMap::new();
+        let clusters: Vec<usize> = (0..count).map(|index| find(&mut roots, index)).collect();
+        for cluster in &clusters {
+            *sizes.entry(*cluster).or_default() += 1;
+        }
+        let Some(main) = sizes
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(cluster, _)| *cluster)
+        else {
+            return Vec::new();
+        };
+
+        let main_bounds: Vec<&Bounds> = (0..count)
+            .filter(|index| clusters[*index] == main)
+            .map(|index| &self.shapes[index].bounds)
+            .collect();
+        (0..count)
+            .filter(|index| clusters[*index] != main)
+            .map(|index| FarPart {
+                part: self.shapes[index].part,
+                distance: main_bounds
+                    .iter()
+                    .map(|bounds| self.shapes[index].bounds.gap(bounds))
+                    .fold(f64::INFINITY, f64::min),
+            })
+            .collect()
+    }
+}
+
+impl ShipData {
+    pub fn geometry(&self, catalog: &PartCatalog) -> ShipGeometry {
+        ShipGeometry::new(self, catalog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_PART_GAP, PART_UNIT, ShipGeometry};
+    use crate::xml_part::{
+        catalog::PartCatalog,
+        model::SaveNode,
+        parse::{parse_save_xml, parse_ship_xml},
+    };
+
+    #[test]
+    fn places_parts_in_world_space() {
+        const SHIP: &str = r#"<Ship version="1" liftedOff="0" touchingGround="0">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+      <Pod throttle="0" name=""><Staging currentStage="0" /></Pod>
+    </Part>
+    <Part partType="fuselage-1" id="2" x="10" y="5" angle="1.5707963267948966" angleV="0" flippedX="1" />
+  </Parts>
+  <Connections />
+</Ship>"#;
+        let ship = parse_ship_xml(SHIP).unwrap().ship;
+        let geometry = ShipGeometry::new(&ship, PartCatalog::embedded());
+        assert!(geometry.missing.is_empty());
+
+        // 指令舱的形状是梯形, 底边 4 格, 顶边 2.6 格, 高 3 格
+        let pod = geometry.get(1).unwrap();
+        assert_eq!(pod.polygons.len(), 1);
+        assert_eq!(pod.bounds.width(), 4.0 * PART_UNIT);
+        assert_eq!(pod.bounds.height(), 3.0 * PART_UNIT);
+
+        // 转了 90 度的机身, 没有 Shape, 用外框代替
+        let fuselage = geometry.get(2).unwrap();
+        assert!((fuselage.bounds.center().x - 10.0).abs() < 1e-9);
+        assert!((fuselage.bounds.width() - 4.0 * PART_UNIT).abs() < 1e-9);
+
+        let bounds = geometry.bounds.unwrap();
+        assert_eq!((bounds.min_x, bounds.min_y), (-1.0, -0.75));
+        assert!((bounds.max_x - 11.0).abs() < 1e-9);
+        assert!((bounds.max_y - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn finds_overlaps_and_far_parts() {
+        const SHIP: &str = r#"<Ship version="1" liftedOff="0" touchingGround="0">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+      <Pod throttle="0" name=""><Staging currentStage="0" /></Pod>
+    </Part>
+    <Part partType="fueltank-1" id="2" x="0" y="-1.75" angle="0" angleV="0" />
+    <Part partType="fueltank-1" id="3" x="0.5" y="-2" angle="0" angleV="0" />
+    <Part partType="wheel-1" id="4" x="0" y="-2" angle="0" angleV="0" />
+    <Part partType="fuselage-1" id="5" x="500" y="0" angle="0" angleV="0" />
+    <Part partType="no-such-part" id="6" x="0" y="0" angle="0" angleV="0" />
+  </Parts>
+  <Connections />
+</Ship>"#;
+        let ship = parse_ship_xml(SHIP).unwrap().ship;
+        let geometry = ship.geometry(PartCatalog::embedded());
+        assert_eq!(geometry.missing, vec![6]);
+
+        // 1 和 2 正好贴着, 2 和 3 挤在一起, 轮子不算
+        let overlaps = geometry.overlaps();
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!((overlaps[0].a, overlaps[0].b), (2, 3));
+        assert!((overlaps[0].depth - 1.5).abs() < 1e-9);
+
+        let far = geometry.far_parts(MAX_PART_GAP);
+        assert_eq!(far.len(), 1);
+        assert_eq!(far[0].part, 5);
+        assert!((far[0].distance - 497.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sample_save_has_no_overlaps() {
+        let save = parse_save_xml(include_str!("../save_1294489.xml")).unwrap();
+        for node in &save.nodes {
+            let SaveNode::Ship(node) = node else {
+                continue;
+            };
+            let geometry = node.ship.geometry(PartCatalog::embedded());
+            assert!(geometry.missing.is_empty());
+            assert!(geometry.overlaps().is_empty(), "{:?}", geometry.overlaps());
+            assert!(geometry.far_parts(MAX_PART_GAP).is_empty());
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! 船的连接图
+//!
+//! 零件是点, `Connection::Normal` 是从 parent 指向 child 的边, `Connection::Dock` 单独记成对接关系
+//! 算连通性的时候两种边都当成无向的
+//!
+//! 校验 / 渲染 / 统计都从这里拿图, 不要再自己拼邻接表了
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::xml_part::model::{Connection, DisconnectedGroup, Part, ShipData};
+
+/// 一条普通连接
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct NormalLink {
+    pub parent: i64,
+    pub child: i64,
+    pub parent_attach_point: i32,
+    pub child_attach_point: i32,
+}
+
+/// 一条对接连接, `dock_part` 是对接口
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct DockLink {
+    pub dock_part: i64,
+    pub parent: i64,
+    pub child: i64,
+}
+
+/// 从根往下的 parent -> child 树
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartTree {
+    pub part: i64,
+    pub children: Vec<PartTree>,
+}
+
+impl PartTree {
+    /// 树里所有的零件, 先序
+    pub fn parts(&self) -> Vec<i64> {
+        let mut parts = vec![self.part];
+        for child in &self.children {
+            parts.extend(child.parts());
+        }
+        parts
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ShipGraph {
+    parts: BTreeSet<i64>,
+    /// 带 `Pod` 的零件
+    pods: Vec<i64>,
+    normal: Vec<NormalLink>,
+    docks: Vec<DockLink>,
+    children: HashMap<i64, Vec<usize>>,
+    parents: HashMap<i64, Vec<usize>>,
+    adjacency: HashMap<i64, Vec<i64>>,
+}
+
+impl ShipGraph {
+    pub fn new(ship: &ShipData) -> Self {
+        Self::from_parts(&ship.parts, &ship.connections)
+    }
+
+    /// `DisconnectedParts` 里的每一组也是一张单独的图
+    pub fn from_group(group: &DisconnectedGroup) -> Self {
+        Self::from_parts(&group.parts, &group.connections)
+    }
+
+    pub fn from_parts(parts: &[Part], connections: &[Connection]) -> Self {
+        let mut graph = Self {
+            parts: parts.iter().map(|part| part.id).collect(),
+            pods: parts
+                .iter()
+                .filter(|part| part.attrs.pod.is_some())
+                .map(|part| part.id)
+                .collect(),
+            ..Default::default()
+        };
+        for connection in connections {
+            match *connection {
+                Connection::Normal {
+                    parent_attach_point,
+                    child_attach_point,
+                    parent_part,
+                    child_part,
+                } => {
+                    let index = graph.normal.len();
+                    graph.normal.push(NormalLink {
+                        parent: parent_part,
+                        child: child_part,
+                        parent_attach_point,
+                        child_attach_point,
+                    });
+                    graph.children.entry(parent_part).or_default().push(index);
+                    graph.parents.entry(child_part).or_default().push(index);
+                    graph.link(parent_part, child_part);
+                }
+                Connection::Dock {
+                    dock_part,
+                    parent_part,
+                    child_part,
+                } => {
+                    graph.docks.push(DockLink {
+                        dock_part,
+                        parent: parent_part,
+                        child: child_part,
+                    });
+                    graph.link(parent_part, child_part);
+                }
+            }
+        }
+        graph
+    }
+
+    fn link(&mut self, a: i64, b: i64) {
+        self.adjacency.entry(a).or_default().push(b);
+        self.adjacency.entry(b).or_default().push(a);
+    }
+
+    pub fn parts(&self) -> &BTreeSet<i64> {
+        &self.parts
+    }
+
+    pub fn contains(&self, part: i64) -> bool {
+        self.parts.contains(&part)
+    }
+
+    pub fn normal_links(&self) -> &[NormalLink] {
+        &self.normal
+    }
+
+    pub fn dock_links(&self) -> &[DockLink] {
+        &self.docks
+    }
+
+    /// 根, 也就是第一个带 `Pod` 的零件
+    pub fn root(&self) -> Option<i64> {
+        self.pods.first().copied()
+    }
+
+    /// 所有带 `Pod` 的零件
+    pub fn pods(&self) -> &[i64] {
+        &self.pods
+    }
+
+    /// `part` 作为 parent 的连接
+    pub fn children(&self, part: i64) -> impl Iterator<Item = &NormalLink> {
+        self.links(&self.children, part)
+    }
+
+    /// `part` 作为 child 的连接
+    pub fn parents(&self, part: i64) -> impl Iterator<Item = &NormalLink> {
+        self.links(&self.parents, part)
+    }
+
+    fn links<'a>(
+        &'a self,
+        index: &'a HashMap<i64, Vec<usize>>,
+        part: i64,
+    ) -> impl Iterator<Item = &'a NormalLink> {
+        index
+            .get(&part)
+            .into_iter()
+            .flatten()
+            .map(|index| &self.normal[*index])
+    }
+
+    /// 不分方向的相邻零件, 包括对接的
+    pub fn neighbors(&self, part: i64) -> impl Iterator<Item = i64> + '_ {
+        self.adjacency.get(&part).into_iter().flatten().copied()
+    }
+
+    /// 指向不存在的零件的连接
+    pub fn dangling_links(&self) -> Vec<Connection> {
+        let normal = self
+            .normal
+            .iter()
+            .filter(|link| !self.contains(link.parent) || !self.contains(link.child))
+            .map(|link| Connection::Normal {
+                parent_attach_point: link.parent_attach_point,
+                child_attach_point: link.child_attach_point,
+                parent_part: link.parent,
+                child_part: link.child,
+            });
+        let docks = self
+            .docks
+            .iter()
+            .filter(|link| {
+                !self.contains(link.dock_part)
+                    || !self.contains(link.parent)
+                    || !self.contains(link.child)
+            })
+            .map(|link| Connection::Dock {
+                dock_part: link.dock_part,
+                parent_part: link.parent,
+                child_part: link.child,
+            });
+        normal.chain(docks).collect()
+    }
+
+    /// 从 `start` 能走到的零件, `cut` 里的零件不往外连
+    pub fn reachable_from(&self, start: i64, cut: &HashSet<i64>) -> BTreeSet<i64> {
+        self.component(start, &self.parts, cut)
+    }
+
+    fn component(&self, start: i64, within: &BTreeSet<i64>, cut: &HashSet<i64>) -> BTreeSet<i64> {
+        let mut group = BTreeSet::from([start]);
+        let mut stack = vec![start];
+        while let Some(part) = stack.pop() {
+            if cut.contains(&part) {
+                continue;
+            }
+            for next in self.neighbors(part) {
+                if within.contains(&next) && !cut.contains(&next) && group.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        group
+    }
+
+    /// 所有连通块, 按最小的零件 id 排序
+    pub fn components(&self) -> Vec<BTreeSet<i64>> {
+        self.components_within(&self.parts, &HashSet::new())
+    }
+
+    /// 只看 `within` 里的零件, 并且 `cut` 里的零件不连任何东西 (分离器炸了之后就是这样)
+    pub fn components_within(
+        &self,
+        within: &BTreeSet<i64>,
+        cut: &HashSet<i64>,
+    ) -> Vec<BTreeSet<i64>> {
+        let mut seen = HashSet::new();
+        let mut groups = Vec::new();
+        for &start in within {
+            if seen.contains(&start) {
+                continue;
+            }
+            let group = self.component(start, within, cut);
+            seen.extend(group.iter().copied());
+            groups.push(group);
+        }
+        groups
+    }
+
+    /// 从根走不到的零件, 没有根的时候是空的
+    pub fn orphans(&self) -> Vec<i64> {
+        let Some(root) = self.root() else {
+            return Vec::new();
+        };
+        let reachable = self.reachable_from(root, &HashSet::new());
+        self.parts.difference(&reachable).copied().collect()
+    }
+
+    /// 从 `root` 顺着 parent -> child 往下的树, 走过的零件不会再走一遍
+    pub fn tree_from(&self, root: i64) -> PartTree {
+        let mut visited = HashSet::from([root]);
+        self.subtree(root, &mut visited)
+    }
+
+    /// 从根开始的树
+    pub fn tree(&self) -> Option<PartTree> {
+        self.root().map(|root| self.tree_from(root))
+    }
+
+    fn subtree(&self, part: i64, visited: &mut HashSet<i64>) -> PartTree {
+        let mut children = Vec::new();
+        let child_ids: Vec<i64> = self.children(part).map(|link| link.child).collect();
+        for child in child_ids {
+            if visited.insert(child) {
+                children.push(self.subtree(child, visited));
+            }
+        }
+        PartTree { part, children }
+    }
+
+    /// 普通连接里有没有 parent -> child 的环
+    pub fn has_cycle(&self) -> bool {
+        self.find_cycle().is_some()
+    }
+
+    /// 普通连接里的一个环, 见 [`find_directed_cycle`]
+    pub fn find_cycle(&self) -> Option<Vec<i64>> {
+        find_directed_cycle(self.normal.iter().map(|link| (link.parent, link.child)))
+    }
+}
+
+/// 有向图里有没有环, 边是 `(from, to)`
+pub fn has_directed_cycle(edges: impl IntoIterator<Item = (i64, i64)>) -> bool {
+    find_directed_cycle(edges).is_some()
+}
+
+/// 找一个环, 返回环上的点, 按边的方向排
+///
+/// 从最小的点开始找, 同一张图每次找到的都是同一个环
+pub fn find_directed_cycle(edges: impl IntoIterator<Item = (i64, i64)>) -> Option<Vec<i64>> {
+    let mut out: BTreeMap<i64, Vec<i64>> = BTreeMap::new();
+    for (from, to) in edges {
+        out.entry(from).or_default().push(to);
+        out.entry(to).or_default();
+    }
+
+    // 0 没走过, 1 正在走, 2 走完了
+    let mut state: HashMap<i64, u8> = HashMap::new();
+    for &start in out.keys() {
+        if state.get(&start).copied().unwrap_or(0) != 0 {
+            continue;
+        }
+        // 栈里就是当前这条路径
+        let mut stack = vec![(start, 0usize)];
+        state.insert(start, 1);
+        while let Some((node, next)) = stack.pop() {
+            let Some(&child) = out[&node].get(next) else {
+                state.insert(node, 2);
+                continue;
+            };
+            stack.push((node, next + 1));
+            match state.get(&child).copied().unwrap_or(0) {
+                0 => {
+                    state.insert(child, 1);
+                    stack.push((child, 0));
+                }
+                1 => {
+                    let from = stack.iter().position(|(node, _)| *node == child)?;
+                    return Some(stack[from..].iter().map(|(node, _)| *node).collect());
+                }
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+impl ShipData {
+    pub fn graph(&self) -> ShipGraph {
+        ShipGraph::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashSet};
+
+    use super::{ShipGraph, find_directed_cycle, has_directed_cycle};
+    use crate::xml_part::parse::parse_ship_xml;
+
+    /// pod(1) -> tank(2) -> engine(3), pod(1) -> port(4) 对接 plug(5), 6 谁也没连
+    const SHIP: &str = r#"<Ship version="1" liftedOff="0" touchingGround="1">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+      <Pod throttle="1" name=""><Staging currentStage="0" /></Pod>
+    </Part>
+    <Part partType="fueltank-1" id="2" x="0" y="-3.5" angle="0" angleV="0" />
+    <Part partType="engine-1" id="3" x="0" y="-7.5" angle="0" angleV="0" />
+    <Part partType="port-1" id="4" x="2.5" y="0" angle="0" angleV="0" />
+    <Part partType="dock-1" id="5" x="4" y="0" angle="0" angleV="0" />
+    <Part partType="fuselage-1" id="6" x="20" y="0" angle="0" angleV="0" />
+  </Parts>
+  <Connections>
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="2" childPart="3" />
+    <Connection parentAttachPoint="1" childAttachPoint="2" parentPart="1" childPart="4" />
+    <DockConnection dockPart="4" parentPart="4" childPart="5" />
+  </Connections>
+</Ship>"#;
+
+    #[test]
+    fn builds_graph() {
+        let ship = parse_ship_xml(SHIP).unwrap().ship;
+        let graph = ShipGraph::new(&ship);
+
+        assert_eq!(graph.root(), Some(1));
+        assert_eq!(graph.normal_links().len(), 3);
+        assert_eq!(graph.dock_links().len(), 1);
+        assert_eq!(
+            graph.children(1).map(|link| link.child).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        assert_eq!(
+            graph.parents(3).map(|link| link.parent).collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(
+            graph.components(),
+            vec![BTreeSet::from([1, 2, 3, 4, 5]), BTreeSet::from([6])]
+        );
+        assert_eq!(graph.orphans(), vec![6]);
+        assert!(graph.dangling_links().is_empty());
+        assert!(!graph.has_cycle());
+
+        // 对接不是 parent -> child, 不进树
+        let tree = graph.tree().unwrap();
+        assert_eq!(tree.parts(), vec![1, 2, 3, 4]);
+
+        let split = graph.components_within(graph.parts(), &HashSet::from([2]));
+        assert_eq!(split.len(), 4);
+    }
+
+    #[test]
+    fn detects_cycles() {
+        assert!(!has_directed_cycle([(1, 2), (2, 3), (1, 3)]));
+        assert!(has_directed_cycle([(1, 2), (2, 3), (3, 1)]));
+        assert!(has_directed_cycle([(4, 4)]));
+        assert_eq!(
+            find_directed_cycle([(1, 2), (2, 3), (3, 4), (4, 2)]),
+            Some(vec![2, 3, 4])
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! model 的 JSON 形式, 给看板和别的工具用
+//!
+//! 就是 [`crate::xml_part::model`] 直接 serde 出来, 外面包一层版本号:
+//!
+//! ```json
+//! { "schema_version": 1, "document": { "type": "ship", "ship": { ... }, "format": "current" } }
+//! ```
+//!
+//! - `document.type` 是 `ship` / `save`, 后面跟 [`ShipDocument`] / [`SaveDocument`] 的字段
+//! - 存档的 `nodes` 和船的 `connections` 用 `kind` 区分 (`planet` / `ship`, `normal` / `dock`)
+//! - 字段名就是 model 里的名字 (`part_type_id`, `angle_v` ...), 不是 xml 里的
+//! - `attrs` 里没有的属性是 null, 读的时候可以不写; `extras` 没东西的话不写
+//! - NaN / 无穷写成字符串 `"NaN"` / `"inf"` / `"-inf"`, 读回来还是原来的值
+//!
+//! 字段改名、删掉或者改了意思就把 [`MODEL_SCHEMA_VERSION`] 加一, 只加可以不写的字段不用改
+//! 读的时候版本号对不上直接报错, 不猜
+//!
+//! [`ShipDocument`]: crate::xml_part::model::ShipDocument
+//! [`SaveDocument`]: crate::xml_part::model::SaveDocument
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use crate::xml_part::{
+    error::{XmlError, XmlResult},
+    model::XmlDocument,
+    write::write_xml_document,
+};
+
+/// JSON 的版本号
+pub const MODEL_SCHEMA_VERSION: u32 = 1;
+
+/// 带版本号的 JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelJson {
+    pub schema_version: u32,
+    pub document: XmlDocument,
+}
+
+impl ModelJson {
+    pub fn new(document: XmlDocument) -> Self {
+        Self {
+            schema_version: MODEL_SCHEMA_VERSION,
+            document,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ModelJsonRef<'a> {
+    schema_version: u32,
+    document: &'a XmlDocument,
+}
+
+/// 先只读版本号, 版本不对的话不用管后面的字段对不对
+#[derive(Deserialize)]
+struct SchemaVersion {
+    schema_version: u32,
+}
+
+impl XmlDocument {
+    pub fn to_json(&self) -> XmlResult<String> {
+        let json = ModelJsonRef {
+            schema_version: MODEL_SCHEMA_VERSION,
+            document: self,
+        };
+        Ok(serde_json::to_string(&json)?)
+    }
+
+    pub fn from_json(json: &str) -> XmlResult<Self> {
+        let SchemaVersion { schema_version } = serde_json::from_str(json)?;
+        if schema_version != MODEL_SCHEMA_VERSION {
+            return Err(XmlError::UnsupportedSchemaVersion {
+                expected: MODEL_SCHEMA_VERSION,
+                found: schema_version,
+            });
+        }
+        let model: ModelJson = serde_json::from_str(json)?;
+        Ok(model.document)
+    }
+}
+
+/// JSON 里的浮点数, 不是有限数的话写成字符串, 数字和字符串读的时候都认
+struct Float(f64);
+
+impl Serialize for Float {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            value if value.is_finite() => serializer.serialize_f64(value),
+            value if value.is_nan() => serializer.serialize_str("NaN"),
+            value if value > 0.0 => serializer.serialize_str("inf"),
+            _ => serializer.serialize_str("-inf"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Float {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FloatVisitor;
+
+        impl de::Visitor<'_> for FloatVisitor {
+            type Value = Float;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str(r#"a number, "NaN", "inf" or "-inf""#)
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Float, E> {
+                Ok(Float(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Float, E> {
+                Ok(Float(value as f64))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Float, E> {
+                Ok(Float(value as f64))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Float, E> {
+                match value {
+                    "NaN" => Ok(Float(f64::NAN)),
+                    "inf" => Ok(Float(f64::INFINITY)),
+                    "-inf" => Ok(Float(f64::NEG_INFINITY)),
+                    _ => Err(E::invalid_value(de::Unexpected::Str(value), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(FloatVisitor)
+    }
+}
+
+/// model 里 `f64` 字段的 `#[serde(with)]`
+pub(crate) mod float {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Float;
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        Float(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Float::deserialize(deserializer).map(|value| value.0)
+    }
+}
+
+/// model 里 `Option<f64>` 字段的 `#[serde(with)]`, 要跟 `default` 一起用, 不然不能不写
+pub(crate) mod opt_float {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Float;
+
+    pub fn serialize<S: Serializer>(value: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.map(Float).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<f64>, D::Error> {
+        Option::<Float>::deserialize(deserializer).map(|value| value.map(|value| value.0))
+    }
+}
+
+/// JSON 读成 model 再写成 xml, 用工具改完船写回去用
+pub fn json_to_xml(json: &str) -> XmlResult<String> {
+    write_xml_document(&XmlDocument::from_json(json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{MODEL_SCHEMA_VERSION, json_to_xml};
+    use crate::xml_part::{XmlError, model::XmlDocument, parse::parse_any_xml};
+
+    const EMPTY_SHIP: &str = crate::net::EMPTY_SHIP;
+    const SAMPLE_SAVE: &str = include_str!("../save_1294489.xml");
+
+    #[test]
+    fn round_trips_through_json() {
+        let modded = EMPTY_SHIP.replacen("<Part ", r#"<Part paint="red" "#, 1);
+        for data in [EMPTY_SHIP, SAMPLE_SAVE, modded.as_str()] {
+            let doc = parse_any_xml(data).unwrap();
+            let json = doc.to_json().unwrap();
+            assert_eq!(XmlDocument::from_json(&json).unwrap(), doc);
+            assert_eq!(parse_any_xml(&json_to_xml(&json).unwrap()).unwrap(), doc);
+        }
+    }
+
+    #[test]
+    fn non_finite_floats_round_trip() {
+        let mut doc = parse_any_xml(EMPTY_SHIP).unwrap();
+        let XmlDocument::Ship(ship) = &mut doc else {
+            unreachable!()
+        };
+        let part = &mut ship.ship.parts[0];
+        part.x = f64::NAN;
+        part.y = f64::INFINITY;
+        part.angle = f64::NEG_INFINITY;
+        part.attrs.tank_fuel = Some(f64::NAN);
+
+        let json = doc.to_json().unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+        let written = &value["document"]["ship"]["parts"][0];
+        assert_eq!(written["x"], "NaN");
+        assert_eq!(written["y"], "inf");
+        assert_eq!(written["angle"], "-inf");
+        assert_eq!(written["attrs"]["tank_fuel"], "NaN");
+        assert_eq!(written["attrs"]["engine_fuel"], Value::Null);
+
+        let XmlDocument::Ship(back) = XmlDocument::from_json(&json).unwrap() else {
+            panic!("not a ship")
+        };
+        let part = &back.ship.parts[0];
+        assert!(part.x.is_nan());
+        assert_eq!(part.y, f64::INFINITY);
+        assert_eq!(part.angle, f64::NEG_INFINITY);
+        assert!(part.attrs.tank_fuel.unwrap().is_nan());
+        assert_eq!(part.attrs.engine_fuel, None);
+        let XmlDocument::Ship(xml) = parse_any_xml(&json_to_xml(&json).unwrap()).unwrap() else {
+            panic!("not a ship")
+        };
+        assert!(xml.ship.parts[0].x.is_nan());
+        assert_eq!(xml.ship.parts[0].angle, f64::NEG_INFINITY);
+
+        let mut value = value;
+        value["document"]["ship"]["parts"][0]["x"] = "infinity".into();
+        assert!(matches!(
+            XmlDocument::from_json(&value.to_string()),
+            Err(XmlError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn documented_shape() {
+        let json = parse_any_xml(SAMPLE_SAVE).unwrap().to_json().unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["schema_version"], MODEL_SCHEMA_VERSION);
+        let document = &value["document"];
+        assert_eq!(document["type"], "save");
+        assert_eq!(document["nodes"][0]["kind"], "planet");
+        let ship = &document["nodes"][5];
+        assert_eq!(ship["kind"], "ship");
+        assert_eq!(ship["ship"]["connections"][0]["kind"], "normal");
+        assert_eq!(ship["ship"]["parts"][0]["part_type_id"], "pod-1");
+        assert!(document.get("extras").is_none());
+    }
+
+    #[test]
+    fn edits_come_back_as_xml() {
+        let json = parse_any_xml(EMPTY_SHIP).unwrap().to_json().unwrap();
+        let mut value: Value = serde_json::from_str(&json).unwrap();
+        let part = &mut value["document"]["ship"]["parts"][0];
+        part["attrs"]["pod"]["name"] = "edited".into();
+        part["x"] = 2.5.into();
+        let xml = json_to_xml(&value.to_string()).unwrap();
+        assert!(xml.contains(r#"name="edited""#), "{xml}");
+        assert!(xml.contains(r#"x="2.5""#), "{xml}");
+
+        value["schema_version"] = (MODEL_SCHEMA_VERSION + 1).into();
+        assert!(matches!(
+            XmlDocument::from_json(&value.to_string()),
+            Err(XmlError::UnsupportedSchemaVersion { found, .. }) if found == MODEL_SCHEMA_VERSION + 1
+        ));
+        assert!(matches!(json_to_xml("{}"), Err(XmlError::Json(_))));
+    }
+}
@@ -1,8 +1,24 @@
+pub mod canonical;
+pub mod catalog;
 pub mod convert;
+pub mod diff;
 pub mod error;
+pub mod extra;
+pub mod game_xml;
+pub mod geometry;
+pub mod graph;
+pub mod json;
 pub mod model;
 pub mod parse;
+pub mod plausibility;
 pub mod raw;
+pub mod recover;
+pub mod render;
+pub mod similarity;
+pub mod staging;
+pub mod stats;
+pub mod stream;
+pub mod thumbnail;
 pub mod verify;
 pub mod write;
 
@@ -10,10 +26,29 @@ pub use error::{XmlError, XmlResult};
 
 #[cfg(test)]
 mod tests {
-    use super::{model::XmlDocument, parse, write};
+    use super::{
+        extra::XmlElement,
+        model::{ShipFormat, XmlDocument},
+        parse,
+        write::{self, WriteStyle},
+    };
 
     const EMPTY_SHIP: &str = crate::net::EMPTY_SHIP;
     const SAMPLE_SAVE: &str = include_str!("../save_1294489.xml");
+    /// 跟 144444 一样的老格式, 多了一级分级
+    const LEGACY_SHIP: &str = r#"<Ship currentStage="1" throttle="0.500000" liftedOff="0">
+    <Parts>
+        <Part partType="pod-1" id="1" x="-4.000000" y="3.250000" angle="0.000000" angleV="0.000000" activated="0" exploded="0"/>
+        <Part partType="detacher-1" id="2" x="-4.000000" y="2.250000" angle="0.000000" angleV="0.000000" activated="0" exploded="0"/>
+    </Parts>
+    <Connections>
+        <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2"/>
+    </Connections>
+    <Staging>
+        <Step><Activate Id="2" moved="0"/></Step>
+    </Staging>
+</Ship>
+"#;
 
     #[test]
     fn parses_ship_document() {
@@ -60,4 +95,166 @@ mod tests {
             XmlDocument::Save(_)
         ));
     }
+
+    #[test]
+    fn parses_legacy_ship() {
+        let doc = parse::parse_ship_xml(LEGACY_SHIP).unwrap();
+        assert_eq!(doc.format, ShipFormat::Legacy);
+        assert_eq!(doc.ship.version, 1);
+        let pod = doc.ship.parts[0].attrs.pod.as_ref().unwrap();
+        assert_eq!(pod.current_stage, 1);
+        assert_eq!(pod.throttle, 0.5);
+        assert_eq!(pod.steps.len(), 1);
+        assert_eq!(pod.steps[0].activates[0].id, 2);
+        assert!(doc.ship.parts[1].attrs.pod.is_none());
+
+        // 144444 那种空的 `<Staging/>` 也能认出来
+        let empty = LEGACY_SHIP.replace(
+            "<Staging>\n        <Step><Activate Id=\"2\" moved=\"0\"/></Step>\n    </Staging>",
+            "<Staging/>",
+        );
+        let doc = parse::parse_ship_xml(&empty).unwrap();
+        assert_eq!(doc.format, ShipFormat::Legacy);
+        assert!(doc.ship.parts[0].attrs.pod.as_ref().unwrap().steps.is_empty());
+
+        assert_eq!(
+            parse::parse_ship_xml(EMPTY_SHIP).unwrap().format,
+            ShipFormat::Current
+        );
+    }
+
+    #[test]
+    fn legacy_round_trip_both_formats() {
+        let doc = parse::parse_ship_xml(LEGACY_SHIP).unwrap();
+
+        let legacy = write::write_ship_xml(&doc).unwrap();
+        assert!(legacy.starts_with(r#"<Ship liftedOff="0" currentStage="1""#));
+        assert!(!legacy.contains("<Pod"));
+        assert_eq!(parse::parse_ship_xml(&legacy).unwrap(), doc);
+
+        let current = write::write_ship_xml_as(&doc, ShipFormat::Current).unwrap();
+        assert!(current.contains("<Pod"));
+        assert!(!current.contains("currentStage=\"1\" throttle"));
+        let reparsed = parse::parse_ship_xml(&current).unwrap();
+        assert_eq!(reparsed.format, ShipFormat::Current);
+        assert_eq!(reparsed.ship, doc.ship);
+
+        // 新格式也能写成老格式
+        let back = write::write_ship_xml_as(&reparsed, ShipFormat::Legacy).unwrap();
+        assert_eq!(parse::parse_ship_xml(&back).unwrap(), doc);
+    }
+
+    /// `written` 里有 `original` 的所有东西: 属性值一样 (数字按数值比), 同名子节点一一对应
+    ///
+    /// `written` 多出来的属性和子节点不管, 那些是写的时候补上的默认值, 只有空白的文本也不管 (缩进)
+    fn covers(original: &XmlElement, written: &XmlElement) -> bool {
+        let same_value = |a: &str, b: &str| {
+            a == b
+                || matches!(
+                    (a.parse::<f64>(), b.parse::<f64>()),
+                    (Ok(a), Ok(b)) if a == b
+                )
+        };
+        let same_text = |a: &str, b: &str| a == b || (a.trim().is_empty() && b.trim().is_empty());
+        original.name == written.name
+            && same_text(&original.text, &written.text)
+            && same_text(&original.tail, &written.tail)
+            && original.attrs.iter().all(|(key, value)| {
+                written
+                    .attr(key)
+                    .is_some_and(|other| same_value(value, other))
+            })
+            && original.children.iter().all(|child| {
+                let (ours, theirs) = (named(original, &child.name), named(written, &child.name));
+                ours.len() == theirs.len() && ours.iter().zip(&theirs).all(|(a, b)| covers(a, b))
+            })
+    }
+
+    fn named<'a>(element: &'a XmlElement, name: &str) -> Vec<&'a XmlElement> {
+        element
+            .children
+            .iter()
+            .filter(|child| child.name == name)
+            .collect()
+    }
+
+    /// 塞一些游戏不认识的东西进去
+    fn with_unknowns(data: &str) -> String {
+        let close = data.rfind("</").unwrap();
+        let mut data = format!(
+            "{}<Notes author=\"me &amp; you\">keep <b>this</b> and\n  <i>that</i> too </Notes>{}",
+            &data[..close],
+            &data[close..]
+        );
+        data = data.replacen("<Parts>", "<Parts modded=\"1\">", 1);
+        data.replacen("<Part ", "<Part paint=\"red\" ", 1)
+    }
+
+    #[test]
+    fn lossless_round_trip_corpus() {
+        for data in [EMPTY_SHIP, SAMPLE_SAVE, LEGACY_SHIP] {
+            for data in [data.to_string(), with_unknowns(data)] {
+                let doc = parse::parse_any_xml(&data).unwrap();
+                let original = XmlElement::parse(&data).unwrap().unwrap();
+                for style in [WriteStyle::Serde, WriteStyle::Game] {
+                    let xml = write::write_xml_document_with(&doc, style).unwrap();
+                    assert_eq!(parse::parse_any_xml(&xml).unwrap(), doc);
+
+                    let written = XmlElement::parse(&xml).unwrap().unwrap();
+                    assert!(covers(&original, &written), "lost data in:\n{xml}");
+                }
+            }
+        }
+
+        let doc = parse::parse_ship_xml(&with_unknowns(EMPTY_SHIP)).unwrap();
+        assert!(!doc.extras.is_empty());
+        let xml = write::write_ship_xml(&doc).unwrap();
+        assert!(xml.contains("paint=\"red\""));
+        assert!(xml.contains(
+            "<Notes author=\"me &amp; you\">keep <b>this</b> and\n  <i>that</i> too </Notes>"
+        ));
+    }
+
+    #[test]
+    fn errors_carry_position() {
+        let ship = [
+            r#"<Ship version="1" liftedOff="0" touchingGround="0">"#,
+            "<Parts>",
+            r#"  <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0"/>"#,
+            r#"  <Part partType="fueltank-1" id="2" x="0" y="1" angle="abc" angleV="0"/>"#,
+            "</Parts>",
+            "<Connections/>",
+            "</Ship>",
+        ]
+        .join("\n");
+        let position = |data: &str| {
+            let err = parse::parse_any_xml(data).unwrap_err();
+            let position = err.position().cloned().unwrap();
+            assert!(err.to_string().contains(&position.to_string()), "{err}");
+            (position.line, position.column, position.path)
+        };
+
+        let column = ship.lines().nth(3).unwrap().find("angle=\"abc").unwrap() + 1;
+        assert_eq!(
+            position(&ship),
+            (4, column, "Ship/Parts/Part[2]/@angle".to_string())
+        );
+        let good = ship.replace("abc", "0");
+        assert!(parse::parse_any_xml(&good).is_ok());
+        assert_eq!(
+            position(&good.replacen(r#" id="1""#, "", 1)),
+            (3, 3, "Ship/Parts/Part[1]/@id".to_string())
+        );
+        assert_eq!(
+            position(&good.replace("<Connections/>", "")),
+            (1, 1, "Ship".to_string())
+        );
+        assert_eq!(
+            position(&good.replace("</Parts>", "</Partz>")),
+            (5, 1, "Ship/Parts".to_string())
+        );
+
+        let err = parse::parse_any_xml("\n  <Ship").unwrap_err();
+        assert_eq!(err.position().map(|p| (p.line, p.column)), Some((2, 3)));
+    }
 }
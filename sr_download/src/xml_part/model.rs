@@ -1,4 +1,12 @@
-#[derive(Debug, Clone, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+use crate::xml_part::{
+    extra::Extras,
+    json::{float, opt_float},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum XmlDocument {
     Ship(ShipDocument),
     Save(SaveDocument),
@@ -11,48 +19,86 @@ impl XmlDocument {
             Self::Save(_) => "Save",
         }
     }
+
+    /// 船就是它自己, 存档里是玩家正在开的那条 (`shipId`)
+    pub fn main_ship(&self) -> Option<&ShipData> {
+        match self {
+            Self::Ship(doc) => Some(&doc.ship),
+            Self::Save(doc) => doc.nodes.iter().find_map(|node| match node {
+                SaveNode::Ship(node) if node.id == doc.ship_id => Some(&node.ship),
+                _ => None,
+            }),
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ShipDocument {
     pub ship: ShipData,
+    /// 解析出来的时候是什么格式, 写回去默认也用这个
+    pub format: ShipFormat,
+    /// 原文里不认识的属性和子节点, 写回去的时候会带上
+    #[serde(default, skip_serializing_if = "Extras::is_empty")]
+    pub extras: Extras,
+}
+
+/// `<Ship>` 的两种布局
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShipFormat {
+    /// 有 `version`, 分级在指令舱零件的 `<Pod>` 里
+    #[default]
+    Current,
+    /// 老格式, `currentStage` / `throttle` 在 `<Ship>` 上, 分级在根节点下面的 `<Staging>`
+    Legacy,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SaveDocument {
+    #[serde(with = "float")]
     pub time: f64,
     pub first_stage_activated: bool,
     pub solar_system: String,
     pub ship_id: i64,
     pub pod_id: i64,
     pub nodes: Vec<SaveNode>,
+    /// 原文里不认识的属性和子节点, 写回去的时候会带上
+    #[serde(default, skip_serializing_if = "Extras::is_empty")]
+    pub extras: Extras,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum SaveNode {
     Planet(PlanetNode),
     Ship(ShipNode),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PlanetNode {
     pub name: String,
+    #[serde(default, with = "opt_float")]
     pub true_anomaly: Option<f64>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ShipNode {
     pub id: i64,
     pub planet: String,
+    #[serde(with = "float")]
     pub planet_radius: f64,
+    #[serde(with = "float")]
     pub x: f64,
+    #[serde(with = "float")]
     pub y: f64,
+    #[serde(with = "float")]
     pub vx: f64,
+    #[serde(with = "float")]
     pub vy: f64,
     pub ship: ShipData,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ShipData {
     pub version: i32,
     pub lifted_off: bool,
@@ -62,64 +108,79 @@ pub struct ShipData {
     pub disconnected: Vec<DisconnectedGroup>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DisconnectedGroup {
     pub parts: Vec<Part>,
     pub connections: Vec<Connection>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Part {
     pub part_type_id: String,
     pub id: i64,
+    #[serde(with = "float")]
     pub x: f64,
+    #[serde(with = "float")]
     pub y: f64,
     pub editor_angle: i32,
+    #[serde(with = "float")]
     pub angle: f64,
+    #[serde(with = "float")]
     pub angle_v: f64,
     pub flipped_x: bool,
     pub flipped_y: bool,
     pub activated: bool,
     pub exploded: bool,
+    #[serde(default)]
     pub attrs: PartAttrs,
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct PartAttrs {
+    #[serde(default, with = "opt_float")]
     pub tank_fuel: Option<f64>,
+    #[serde(default, with = "opt_float")]
     pub engine_fuel: Option<f64>,
     pub pod: Option<PodData>,
+    #[serde(default, with = "opt_float")]
     pub chute_x: Option<f64>,
+    #[serde(default, with = "opt_float")]
     pub chute_y: Option<f64>,
+    #[serde(default, with = "opt_float")]
     pub chute_angle: Option<f64>,
+    #[serde(default, with = "opt_float")]
     pub chute_height: Option<f64>,
+    #[serde(default, with = "opt_float")]
     pub extension: Option<f64>,
     pub inflate: Option<bool>,
+    #[serde(default, with = "opt_float")]
     pub inflation: Option<f64>,
     pub deployed: Option<bool>,
     pub rope: Option<bool>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PodData {
     pub name: String,
+    #[serde(with = "float")]
     pub throttle: f64,
     pub current_stage: i32,
     pub steps: Vec<StageStep>,
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct StageStep {
     pub activates: Vec<Activation>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Activation {
     pub id: i64,
     pub moved: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum Connection {
     Normal {
         parent_attach_point: i32,
@@ -16,7 +16,7 @@ fn detect_root(data: &str) -> XmlResult<String> {
             }
             Ok(Event::Eof) => return Err(XmlError::UnsupportedRoot("<empty>".to_string())),
             Ok(_) => {}
-            Err(err) => return Err(err.into()),
+            Err(err) => return Err(XmlError::syntax(err, data, reader.error_position())),
         }
     }
 }
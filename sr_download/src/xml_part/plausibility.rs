@@ -0,0 +1,434 @@
+//! 合理性检查, 用来找改过文件 / 开了挂的船
+//!
+//! 跟 `verify` 不一样, 这里的问题游戏大多能读进去, 只是正常玩不可能做出来:
+//! 油箱里的油比容量还多、零件目录里没有的零件、坐标飞到天上去之类的
+//!
+//! 每种问题有个权重, 加起来 (最多 [`MAX_SCORE`]) 就是可疑分数, 存进库里方便筛
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::xml_part::{
+    catalog::{FuelType, PartCatalog},
+    model::{Connection, Part, SaveNode, ShipData, XmlDocument},
+};
+
+pub const MAX_SCORE: u32 = 100;
+/// 到这个分数就算可疑
+pub const SUSPICIOUS_SCORE: u32 = 30;
+/// 油量允许的误差, 游戏存档里的浮点数会有一点点偏差
+const FUEL_TOLERANCE: f64 = 1e-3;
+/// 零件离根零件超过这么远就不像是一条船了
+pub const MAX_SHIP_SPREAD: f64 = 2_000.0;
+/// 坐标的绝对值超过这个就不正常了, 存档里是相对星球的坐标, 所以给得很宽
+pub const MAX_COORDINATE: f64 = 1e10;
+/// 角度是弧度, 一直转也转不到这么大
+pub const MAX_ANGLE: f64 = 1e4;
+/// 角速度, 弧度每秒
+pub const MAX_ANGULAR_VELOCITY: f64 = 1e3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FlagKind {
+    /// 零件目录里没有这种零件
+    UnknownPartType { part: i64, part_type: String },
+    /// 油量超过了零件目录里的容量
+    OverfilledTank { part: i64, fuel: f64, capacity: f64 },
+    /// 油量是负的
+    NegativeFuel { part: i64, fuel: f64 },
+    /// 零件带了 `Tank` / `Engine` 数据, 但是这种零件根本没有油箱 / 不是引擎
+    UnexpectedFuel {
+        part: i64,
+        part_type: String,
+        element: String,
+    },
+    /// 引擎直接连着的油箱里没有一个是它能烧的
+    IncompatibleFuel {
+        engine: i64,
+        fuel_type: FuelType,
+        tanks: Vec<i64>,
+    },
+    /// 坐标 / 角度是 NaN 或者无穷
+    NonFinite { part: i64, field: String },
+    /// 坐标 / 角度 / 角速度大得离谱
+    AbsurdValue {
+        part: i64,
+        field: String,
+        value: f64,
+    },
+    /// 离根零件太远
+    FarFromShip { part: i64, distance: f64 },
+    /// `editorAngle` 只能是 0..=3
+    BadEditorAngle { part: i64, editor_angle: i32 },
+}
+
+impl FlagKind {
+    /// 这个问题值几分
+    pub fn weight(&self) -> u32 {
+        match self {
+            Self::UnknownPartType { .. } => 20,
+            Self::OverfilledTank { .. } => 30,
+            Self::NegativeFuel { .. } => 20,
+            Self::UnexpectedFuel { .. } => 20,
+            Self::IncompatibleFuel { .. } => 5,
+            Self::NonFinite { .. } => 40,
+            Self::AbsurdValue { .. } => 30,
+            Self::FarFromShip { .. } => 15,
+            Self::BadEditorAngle { .. } => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlausibilityFlag {
+    /// 存档里的 `ShipNode` id, 船文件是 None
+    pub node: Option<i64>,
+    #[serde(flatten)]
+    pub kind: FlagKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct PlausibilityReport {
+    pub flags: Vec<PlausibilityFlag>,
+    /// 0..=[`MAX_SCORE`], 越大越可疑
+    pub score: u32,
+}
+
+impl PlausibilityReport {
+    fn from_flags(flags: Vec<PlausibilityFlag>) -> Self {
+        let score = flags
+            .iter()
+            .map(|flag| flag.kind.weight())
+            .sum::<u32>()
+            .min(MAX_SCORE);
+        Self { flags, score }
+    }
+
+    pub fn is_suspicious(&self) -> bool {
+        self.score >= SUSPICIOUS_SCORE
+    }
+}
+
+/// 检查一条船, `DisconnectedParts` 里的零件也算
+pub fn check_ship(ship: &ShipData, catalog: &PartCatalog) -> PlausibilityReport {
+    PlausibilityReport::from_flags(ship_flags(ship, catalog, None))
+}
+
+/// 检查整个文件, 存档的话每条船都查
+pub fn check_document(doc: &XmlDocument, catalog: &PartCatalog) -> PlausibilityReport {
+    let flags = match doc {
+        XmlDocument::Ship(doc) => ship_flags(&doc.ship, catalog, None),
+        XmlDocument::Save(doc) => doc
+            .nodes
+            .iter()
+            .filter_map(|node| match node {
+                SaveNode::Ship(node) => Some(ship_flags(&node.ship, catalog, Some(node.id))),
+                SaveNode::Planet(_) => None,
+            })
+            .flatten()
+            .collect(),
+    };
+    PlausibilityReport::from_flags(flags)
+}
+
+fn ship_flags(ship: &ShipData, catalog: &PartCatalog, node: Option<i64>) -> Vec<PlausibilityFlag> {
+    let mut flags = Vec::new();
+    let parts: Vec<&Part> = ship
+        .parts
+        .iter()
+        .chain(
+            ship.disconnected
+                .iter()
+                .flat_map(|group| group.parts.iter()),
+        )
+        .collect();
+    let connections: Vec<&Connection> = ship
+        .connections
+        .iter()
+        .chain(
+            ship.disconnected
+                .iter()
+                .flat_map(|group| group.connections.iter()),
+        )
+        .collect();
+
+    let root = ship.parts.first().filter(|part| part_is_finite(part));
+    for part in &parts {
+        check_part_fuel(&mut flags, part, catalog);
+        check_part_position(&mut flags, part, root);
+    }
+    check_engine_fuel_types(&mut flags, &parts, &connections, catalog);
+
+    flags
+        .into_iter()
+        .map(|kind| PlausibilityFlag { node, kind })
+        .collect()
+}
+
+fn check_part_fuel(flags: &mut Vec<FlagKind>, part: &Part, catalog: &PartCatalog) {
+    let Some(part_type) = catalog.part_type(part) else {
+        flags.push(FlagKind::UnknownPartType {
+            part: part.id,
+            part_type: part.part_type_id.clone(),
+        });
+        return;
+    };
+
+    // SRB 的油是存在 `Tank` 里的, 所以容量也看 `Tank`
+    // 普通引擎没有油箱, `Engine fuel` 是它自己的一点缓存, 目录里没有容量可比
+    for (element, fuel, has_slot, capacity) in [
+        (
+            "Tank",
+            part.attrs.tank_fuel,
+            part_type.tank.is_some(),
+            part_type.tank.as_ref().map(|tank| tank.fuel),
+        ),
+        (
+            "Engine",
+            part.attrs.engine_fuel,
+            part_type.engine.is_some(),
+            None,
+        ),
+    ] {
+        let Some(fuel) = fuel else {
+            continue;
+        };
+        if !has_slot {
+            flags.push(FlagKind::UnexpectedFuel {
+                part: part.id,
+                part_type: part.part_type_id.clone(),
+                element: element.to_string(),
+            });
+            continue;
+        }
+        if !fuel.is_finite() {
+            flags.push(FlagKind::NonFinite {
+                part: part.id,
+                field: format!("{element}.fuel"),
+            });
+        } else if fuel < -FUEL_TOLERANCE {
+            flags.push(FlagKind::NegativeFuel {
+                part: part.id,
+                fuel,
+            });
+        } else if let Some(capacity) = capacity
+            && fuel > capacity + FUEL_TOLERANCE
+        {
+            flags.push(FlagKind::OverfilledTank {
+                part: part.id,
+                fuel,
+                capacity,
+            });
+        }
+    }
+}
+
+fn part_is_finite(part: &Part) -> bool {
+    part.x.is_finite() && part.y.is_finite()
+}
+
+fn check_part_position(flags: &mut Vec<FlagKind>, part: &Part, root: Option<&Part>) {
+    for (field, value, limit) in [
+        ("x", part.x, MAX_COORDINATE),
+        ("y", part.y, MAX_COORDINATE),
+        ("angle", part.angle, MAX_ANGLE),
+        ("angleV", part.angle_v, MAX_ANGULAR_VELOCITY),
+    ] {
+        if !value.is_finite() {
+            flags.push(FlagKind::NonFinite {
+                part: part.id,
+                field: field.to_string(),
+            });
+        } else if value.abs() > limit {
+            flags.push(FlagKind::AbsurdValue {
+                part: part.id,
+                field: field.to_string(),
+                value,
+            });
+        }
+    }
+    if !(0..=3).contains(&part.editor_angle) {
+        flags.push(FlagKind::BadEditorAngle {
+            part: part.id,
+            editor_angle: part.editor_angle,
+        });
+    }
+    if let Some(root) = root
+        && part_is_finite(part)
+    {
+        let distance = (part.x - root.x).hypot(part.y - root.y);
+        if distance > MAX_SHIP_SPREAD {
+            flags.push(FlagKind::FarFromShip {
+                part: part.id,
+                distance,
+            });
+        }
+    }
+}
+
+/// 引擎直接连着油箱, 但是没有一个油箱的燃料是它能用的
+///
+/// 自己带油的引擎 (SRB) 不用看; 油箱的燃料种类跟 `stats` 里一样, 挂在引擎上的油箱跟着引擎走
+fn check_engine_fuel_types(
+    flags: &mut Vec<FlagKind>,
+    parts: &[&Part],
+    connections: &[&Connection],
+    catalog: &PartCatalog,
+) {
+    let types: HashMap<i64, _> = parts
+        .iter()
+        .filter_map(|part| Some((part.id, catalog.part_type(part)?)))
+        .collect();
+    let tank_fuel = |id: &i64| {
+        let part_type = types.get(id)?;
+        let tank = part_type.tank.as_ref()?;
+        Some(
+            part_type
+                .engine
+                .as_ref()
+                .map_or(tank.fuel_type, |engine| engine.fuel_type),
+        )
+    };
+
+    for part in parts {
+        let Some(part_type) = types.get(&part.id) else {
+            continue;
+        };
+        let Some(engine) = &part_type.engine else {
+            continue;
+        };
+        if part_type.tank.is_some() {
+            continue;
+        }
+        let mut tanks: Vec<(i64, FuelType)> = connections
+            .iter()
+            .filter_map(|connection| match **connection {
+                Connection::Normal {
+                    parent_part,
+                    child_part,
+                    ..
+                } if parent_part == part.id => Some(child_part),
+                Connection::Normal {
+                    parent_part,
+                    child_part,
+                    ..
+                } if child_part == part.id => Some(parent_part),
+                _ => None,
+            })
+            .filter_map(|id| Some((id, tank_fuel(&id)?)))
+            .collect();
+        tanks.sort_by_key(|(id, _)| *id);
+        tanks.dedup_by_key(|(id, _)| *id);
+        if !tanks.is_empty()
+            && tanks
+                .iter()
+                .all(|(_, fuel_type)| *fuel_type != engine.fuel_type)
+        {
+            flags.push(FlagKind::IncompatibleFuel {
+                engine: part.id,
+                fuel_type: engine.fuel_type,
+                tanks: tanks.into_iter().map(|(id, _)| id).collect(),
+            });
+        }
+    }
+}
+
+impl ShipData {
+    pub fn plausibility(&self, catalog: &PartCatalog) -> PlausibilityReport {
+        check_ship(self, catalog)
+    }
+}
+
+impl XmlDocument {
+    pub fn plausibility(&self, catalog: &PartCatalog) -> PlausibilityReport {
+        check_document(self, catalog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlagKind, check_document, check_ship};
+    use crate::xml_part::{
+        catalog::{FuelType, PartCatalog},
+        parse::{parse_any_xml, parse_ship_xml},
+    };
+
+    const CHEATED: &str = r#"<Ship version="1" liftedOff="0" touchingGround="1">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+      <Pod throttle="1" name=""><Staging currentStage="0" /></Pod>
+    </Part>
+    <Part partType="fueltank-1" id="2" x="0" y="-3.5" angle="0" angleV="0"><Tank fuel="99999" /></Part>
+    <Part partType="ion-0" id="3" x="0" y="-7.5" angle="0" angleV="0" editorAngle="7"><Engine fuel="0" /></Part>
+    <Part partType="engine-4" id="4" x="5000" y="0" angle="0" angleV="1e9"><Tank fuel="-5" /></Part>
+    <Part partType="nosecone-1" id="5" x="0" y="1" angle="0" angleV="0"><Tank fuel="1" /></Part>
+    <Part partType="warp-drive" id="6" x="0" y="2" angle="0" angleV="0" />
+  </Parts>
+  <Connections>
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="2" childPart="3" />
+  </Connections>
+  <DisconnectedParts />
+</Ship>"#;
+
+    #[test]
+    fn flags_cheated_ship() {
+        let ship = parse_ship_xml(CHEATED).unwrap().ship;
+        let report = check_ship(&ship, PartCatalog::embedded());
+        let kinds: Vec<_> = report.flags.iter().map(|flag| flag.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                FlagKind::OverfilledTank {
+                    part: 2,
+                    fuel: 99999.0,
+                    capacity: 1500.0
+                },
+                FlagKind::BadEditorAngle {
+                    part: 3,
+                    editor_angle: 7
+                },
+                FlagKind::NegativeFuel {
+                    part: 4,
+                    fuel: -5.0
+                },
+                FlagKind::AbsurdValue {
+                    part: 4,
+                    field: "angleV".to_string(),
+                    value: 1e9
+                },
+                FlagKind::FarFromShip {
+                    part: 4,
+                    distance: 5000.0
+                },
+                FlagKind::UnexpectedFuel {
+                    part: 5,
+                    part_type: "nosecone-1".to_string(),
+                    element: "Tank".to_string()
+                },
+                FlagKind::UnknownPartType {
+                    part: 6,
+                    part_type: "warp-drive".to_string()
+                },
+                FlagKind::IncompatibleFuel {
+                    engine: 3,
+                    fuel_type: FuelType::Battery,
+                    tanks: vec![2]
+                },
+            ]
+        );
+        assert_eq!(report.score, 100);
+        assert!(report.is_suspicious());
+    }
+
+    #[test]
+    fn normal_documents_are_not_suspicious() {
+        let ship = parse_ship_xml(crate::net::EMPTY_SHIP).unwrap().ship;
+        assert_eq!(check_ship(&ship, PartCatalog::embedded()).score, 0);
+
+        let save = parse_any_xml(include_str!("../save_1294489.xml")).unwrap();
+        let report = check_document(&save, PartCatalog::embedded());
+        assert!(report.flags.is_empty(), "{:?}", report.flags);
+    }
+}
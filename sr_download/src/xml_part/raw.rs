@@ -1,21 +1,34 @@
 use quick_xml::de::from_str;
 use serde::{Deserialize, Serialize};
 
-use crate::xml_part::error::XmlResult;
+use crate::xml_part::{
+    error::{XmlError, XmlResult},
+    extra::{Extras, capture},
+    stream::locate_error,
+};
 
-fn default_ship_version() -> i32 {
-    1
+pub const DEFAULT_SHIP_VERSION: i32 = 1;
+pub const DEFAULT_TOUCHING_GROUND: i8 = 1;
+
+/// serde 失败了才再扫一遍原文找位置, 只有出错的时候才多花这点时间
+fn deserialize_error(error: quick_xml::DeError, data: &str) -> XmlError {
+    let position = locate_error(data, &error);
+    XmlError::Deserialize { error, position }
 }
+
 fn default_lifted_off() -> i8 {
     0
 }
-fn default_touching_ground() -> i8 {
-    1
-}
 fn default_editor_angle() -> i32 {
     0
 }
 
+/// `<Ship>`, 同时兼容老格式
+///
+/// 老格式 (比如 144444) 长这样:
+/// `<Ship currentStage="0" throttle="0.000000" liftedOff="0">`, 没有 `version` / `touchingGround`,
+/// 指令舱零件下面没有 `<Pod>`, 分级是根节点下面的 `<Staging>`
+/// 老格式特有的几个字段在新格式里都是 None
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename = "Ship")]
 pub struct RawShipDocument {
@@ -23,22 +36,52 @@ pub struct RawShipDocument {
     pub parts: RawParts,
     #[serde(rename = "Connections")]
     pub connections: RawConnections,
-    #[serde(rename = "@version", default = "default_ship_version")]
-    pub version: i32,
+    /// 没有的话是 [`DEFAULT_SHIP_VERSION`]
+    #[serde(rename = "@version", skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
     #[serde(rename = "@liftedOff", default = "default_lifted_off")]
     pub lifted_off: i8,
-    #[serde(rename = "@touchingGround", default = "default_touching_ground")]
-    pub touching_ground: i8,
-    #[serde(rename = "DisconnectedParts", default)]
-    pub disconnected: RawDisconnectedParts,
+    /// 没有的话是 [`DEFAULT_TOUCHING_GROUND`]
+    #[serde(rename = "@touchingGround", skip_serializing_if = "Option::is_none")]
+    pub touching_ground: Option<i8>,
+    #[serde(rename = "DisconnectedParts", skip_serializing_if = "Option::is_none")]
+    pub disconnected: Option<RawDisconnectedParts>,
+    /// 老格式, 当前分级
+    #[serde(rename = "@currentStage", skip_serializing_if = "Option::is_none")]
+    pub current_stage: Option<i32>,
+    /// 老格式, 油门
+    #[serde(rename = "@throttle", skip_serializing_if = "Option::is_none")]
+    pub throttle: Option<f64>,
+    /// 老格式, 根节点下面的分级
+    #[serde(rename = "Staging", skip_serializing_if = "Option::is_none")]
+    pub staging: Option<RawLegacyStaging>,
+    /// 上面没有的属性和子节点, 见 [`crate::xml_part::extra`]
+    ///
+    /// 只有根节点上的有东西, 存档里的船算在存档的里面
+    #[serde(skip)]
+    pub extras: Extras,
 }
 
 impl RawShipDocument {
     pub fn from_str(data: &str) -> XmlResult<Self> {
-        Ok(from_str(data)?)
+        let mut raw: Self = from_str(data).map_err(|err| deserialize_error(err, data))?;
+        raw.extras = capture(data)?;
+        Ok(raw)
+    }
+
+    /// 有老格式特有的东西就算老格式
+    pub fn is_legacy(&self) -> bool {
+        self.staging.is_some() || self.current_stage.is_some() || self.throttle.is_some()
     }
 }
 
+/// 老格式根节点下面的 `<Staging>`, 没有 `currentStage`, 那个在 `<Ship>` 上
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct RawLegacyStaging {
+    #[serde(rename = "Step", default)]
+    pub steps: Vec<RawStep>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct RawParts {
     #[serde(rename = "Part", default)]
@@ -199,11 +242,16 @@ pub struct RawSaveDocument {
     pub pod_id: i64,
     #[serde(rename = "Nodes")]
     pub nodes: RawNodes,
+    /// 上面没有的属性和子节点, 见 [`crate::xml_part::extra`]
+    #[serde(skip)]
+    pub extras: Extras,
 }
 
 impl RawSaveDocument {
     pub fn from_str(data: &str) -> XmlResult<Self> {
-        Ok(from_str(data)?)
+        let mut raw: Self = from_str(data).map_err(|err| deserialize_error(err, data))?;
+        raw.extras = capture(data)?;
+        Ok(raw)
     }
 }
 
@@ -0,0 +1,790 @@
+//! 把坏掉的 xml 修到能解析
+//!
+//! `not xml` 的记录大多是下载断在半中间, 或者被什么东西改坏了, quick_xml 碰到第一个错就停, 救不回来
+//! 这里自己宽松地扫一遍原文, 边扫边修:
+//! - 根节点前面、后面不是 xml 的东西扔掉
+//! - 断在半中间的标签扔掉, 到最后还没关的节点补上结束标签
+//! - 对不上的结束标签: 外面有同名的节点就把中间的都关掉, 没有就扔掉
+//! - 认不出来的 `&xxx;` 当成普通的 `&`, 没转义的 `<` 当成文本
+//! - 重复的属性留第一个, 写坏了的属性扔掉
+//!
+//! 然后按 raw 层的要求再修一遍结构: 解析不了的零件、连接、节点 (少了属性、数字写坏了) 整个扔掉,
+//! 少了 `Parts` / `Connections` 这种必须的子节点补一个空的
+//! 最后写成 xml 走一遍正常的 [`parse_any_xml`], 每一处修改都记在 [`Repair`] 里
+
+use std::collections::HashMap;
+
+use quick_xml::{Reader, de::from_str, escape::escape, events::Event};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::xml_part::{
+    error::{XmlError, XmlResult},
+    extra::{XmlElement, indexed_path, known_node, required_children, single_child},
+    model::XmlDocument,
+    parse::parse_any_xml,
+    raw::{
+        RawActivate, RawConnection, RawDisconnectedPart, RawFuelEngine, RawFuelTank, RawNode,
+        RawPart, RawPod,
+    },
+};
+
+/// 修了一处
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Repair {
+    /// 原文里出问题的字节偏移
+    pub offset: usize,
+    /// 出问题的节点, 写法跟 [`crate::xml_part::extra`] 一样 (`Ship/Parts/Part[12]`, 下标从 1 开始),
+    /// 根节点外面的是空的
+    pub path: String,
+    #[serde(flatten)]
+    pub kind: RepairKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RepairKind {
+    /// 根节点前面的垃圾, 扔掉了
+    LeadingJunk { len: usize },
+    /// 根节点结束之后的东西, 扔掉了
+    TrailingJunk { len: usize },
+    /// 文件断在标签中间, 这半个标签扔掉了
+    TruncatedTag,
+    /// 没有结束标签, 补上了
+    UnclosedElement { name: String },
+    /// 找不到开始标签的结束标签, 扔掉了
+    StrayEndTag { name: String },
+    /// 认不出来的实体, 当成普通的 `&`
+    BadEntity { entity: String },
+    /// 没转义的 `<`, 当成文本
+    StrayLessThan,
+    /// 没有 `=` 或者没有引号的属性, 扔掉了
+    BadAttribute { name: String },
+    /// 重复的属性, 留了第一个
+    DuplicateAttribute { name: String },
+    /// 少了必须有的子节点, 补了一个空的
+    MissingChild { name: String },
+    /// raw 层解析不了的节点, 整个扔掉了
+    DroppedElement { name: String, reason: String },
+}
+
+/// 修好的文档
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recovered {
+    pub document: XmlDocument,
+    /// 按在原文里的位置排, 本来就好好的话是空的
+    pub repairs: Vec<Repair>,
+    /// 修好之后的 xml, 本来就好好的话就是原文
+    pub xml: String,
+}
+
+/// 修完再解析, 修完还是解析不了 (比如根节点都没有) 的话返回解析的错误
+pub fn recover_xml(data: &str) -> XmlResult<Recovered> {
+    if well_formed(data)
+        && let Ok(document) = parse_any_xml(data)
+    {
+        return Ok(Recovered {
+            document,
+            repairs: Vec::new(),
+            xml: data.to_string(),
+        });
+    }
+    let (root, mut repairs) = Lexer::new(data).run();
+    let Some(mut root) = root else {
+        return Err(XmlError::UnsupportedRoot("<empty>".to_string()));
+    };
+    let path = root.name.clone();
+    repair_node(&mut root, &path, &mut repairs);
+    let xml = root.to_element().to_xml()?;
+    let document = parse_any_xml(&xml)?;
+    repairs.sort_by_key(|repair| repair.offset);
+    Ok(Recovered {
+        document,
+        repairs,
+        xml,
+    })
+}
+
+fn well_formed(data: &str) -> bool {
+    let mut reader = Reader::from_str(data);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Eof) => return true,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+}
+
+/// 扫出来的节点, 多记一个在原文里的位置
+struct Node {
+    name: String,
+    offset: usize,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+    /// 转义过的, 跟 [`XmlElement::text`] 一样
+    text: String,
+    /// 转义过的, 跟 [`XmlElement::tail`] 一样
+    tail: String,
+}
+
+impl Node {
+    fn new(name: &str, offset: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            offset,
+            attrs: Vec::new(),
+            children: Vec::new(),
+            text: String::new(),
+            tail: String::new(),
+        }
+    }
+
+    fn to_element(&self) -> XmlElement {
+        XmlElement {
+            name: self.name.clone(),
+            attrs: self.attrs.clone(),
+            children: self.children.iter().map(Node::to_element).collect(),
+            text: self.text.clone(),
+            tail: self.tail.clone(),
+        }
+    }
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || matches!(c, '_' | ':')
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | ':' | '-' | '.')
+}
+
+fn resolve_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let code = match entity.strip_prefix("#x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => entity.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+struct Frame {
+    node: Node,
+    path: String,
+    counts: HashMap<String, usize>,
+}
+
+/// 宽松地扫原文, 扫出一棵树
+struct Lexer<'a> {
+    data: &'a str,
+    pos: usize,
+    stack: Vec<Frame>,
+    /// 根节点结束了就有
+    root: Option<Node>,
+    repairs: Vec<Repair>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(data: &'a str) -> Self {
+        Self {
+            data,
+            pos: 0,
+            stack: Vec::new(),
+            root: None,
+            repairs: Vec::new(),
+        }
+    }
+
+    fn path(&self) -> String {
+        self.stack
+            .last()
+            .map(|frame| frame.path.clone())
+            .unwrap_or_default()
+    }
+
+    fn repair(&mut self, offset: usize, kind: RepairKind) {
+        let path = self.path();
+        self.repairs.push(Repair { offset, path, kind });
+    }
+
+    fn run(mut self) -> (Option<Node>, Vec<Repair>) {
+        while self.pos < self.data.len() {
+            if self.root.is_some() {
+                self.trailing();
+                break;
+            }
+            let rest = &self.data[self.pos..];
+            let Some(lt) = rest.find('<') else {
+                self.text(rest.len());
+                break;
+            };
+            if lt > 0 {
+                self.text(lt);
+            }
+            if !self.markup() {
+                break;
+            }
+        }
+        while let Some(frame) = self.stack.last() {
+            let (offset, name) = (frame.node.offset, frame.node.name.clone());
+            self.repair(offset, RepairKind::UnclosedElement { name });
+            self.pop();
+        }
+        (self.root, self.repairs)
+    }
+
+    /// `len` 长的文本, 根节点前面的是垃圾
+    fn text(&mut self, len: usize) {
+        let offset = self.pos;
+        let raw = &self.data[offset..offset + len];
+        self.pos += len;
+        if self.stack.is_empty() {
+            if !raw
+                .trim_matches(|c: char| c.is_whitespace() || c == '\u{feff}')
+                .is_empty()
+            {
+                self.repair(offset, RepairKind::LeadingJunk { len });
+            }
+            return;
+        }
+        let (text, repairs) = decode(raw, offset);
+        for (offset, kind) in repairs {
+            self.repair(offset, kind);
+        }
+        self.push_text(&text);
+    }
+
+    /// 有子节点的话接在最后一个子节点后面
+    fn push_text(&mut self, text: &str) {
+        let Some(frame) = self.stack.last_mut() else {
+            return;
+        };
+        let node = &mut frame.node;
+        match node.children.last_mut() {
+            Some(child) => child.tail.push_str(&escape(text)),
+            None => node.text.push_str(&escape(text)),
+        }
+    }
+
+    /// 根节点后面除了空白、注释和 `<? ?>` 都算垃圾
+    fn trailing(&mut self) {
+        let mut rest = &self.data[self.pos..];
+        loop {
+            rest = rest.trim_start();
+            let skipped = if rest.starts_with("<!--") {
+                rest.find("-->").map(|end| end + 3)
+            } else if rest.starts_with("<?") {
+                rest.find("?>").map(|end| end + 2)
+            } else {
+                None
+            };
+            match skipped {
+                Some(end) => rest = &rest[end..],
+                None => break,
+            }
+        }
+        if !rest.is_empty() {
+            let offset = self.data.len() - rest.len();
+            let len = rest.len();
+            self.repair(offset, RepairKind::TrailingJunk { len });
+        }
+        self.pos = self.data.len();
+    }
+
+    /// 扫一个 `<` 开头的东西, 断掉了的话返回 false
+    fn markup(&mut self) -> bool {
+        let rest = &self.data[self.pos..];
+        if rest.starts_with("<!--") {
+            return self.skip_past("-->");
+        }
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let Some(end) = cdata.find("]]>") else {
+                return self.truncated();
+            };
+            let text = &cdata[..end];
+            if self.stack.is_empty() {
+                self.repair(self.pos, RepairKind::LeadingJunk { len: end + 12 });
+            } else {
+                self.push_text(text);
+            }
+            self.pos += end + 12;
+            return true;
+        }
+        if rest.starts_with("<?") {
+            return self.skip_past("?>");
+        }
+        if rest.starts_with("<!") {
+            return self.skip_past(">");
+        }
+        if rest.starts_with("</") {
+            return self.end_tag();
+        }
+        match rest[1..].chars().next() {
+            Some(c) if is_name_start(c) => self.start_tag(),
+            Some(_) => {
+                self.repair(self.pos, RepairKind::StrayLessThan);
+                self.push_text("<");
+                self.pos += 1;
+                true
+            }
+            None => self.truncated(),
+        }
+    }
+
+    fn truncated(&mut self) -> bool {
+        self.repair(self.pos, RepairKind::TruncatedTag);
+        self.pos = self.data.len();
+        false
+    }
+
+    fn skip_past(&mut self, pattern: &str) -> bool {
+        match self.data[self.pos..].find(pattern) {
+            Some(end) => {
+                self.pos += end + pattern.len();
+                true
+            }
+            None => self.truncated(),
+        }
+    }
+
+    fn name_end(&self, from: usize) -> usize {
+        self.data[from..]
+            .char_indices()
+            .find(|(_, c)| !is_name_char(*c))
+            .map_or(self.data.len(), |(i, _)| from + i)
+    }
+
+    fn skip_space(&self, from: usize) -> usize {
+        self.data[from..]
+            .char_indices()
+            .find(|(_, c)| !c.is_whitespace())
+            .map_or(self.data.len(), |(i, _)| from + i)
+    }
+
+    fn end_tag(&mut self) -> bool {
+        let offset = self.pos;
+        let Some(end) = self.data[offset..].find('>') else {
+            return self.truncated();
+        };
+        let name = self.data[offset + 2..offset + end].trim().to_string();
+        self.pos = offset + end + 1;
+        let Some(depth) = self.stack.iter().rposition(|frame| frame.node.name == name) else {
+            self.repair(offset, RepairKind::StrayEndTag { name });
+            return true;
+        };
+        while self.stack.len() > depth + 1 {
+            let node = &self.stack[self.stack.len() - 1].node;
+            let (offset, name) = (node.offset, node.name.clone());
+            self.repair(offset, RepairKind::UnclosedElement { name });
+            self.pop();
+        }
+        self.pop();
+        true
+    }
+
+    fn start_tag(&mut self) -> bool {
+        let offset = self.pos;
+        let bytes = self.data.as_bytes();
+        let name_end = self.name_end(offset + 1);
+        let mut node = Node::new(&self.data[offset + 1..name_end], offset);
+        // 标签完整了才算数
+        let mut repairs = Vec::new();
+        let mut at = name_end;
+        let empty = loop {
+            at = self.skip_space(at);
+            match bytes.get(at) {
+                None => return self.truncated(),
+                Some(b'>') => {
+                    at += 1;
+                    break false;
+                }
+                Some(b'/') if bytes.get(at + 1) == Some(&b'>') => {
+                    at += 2;
+                    break true;
+                }
+                Some(_) => {}
+            }
+            let key_end = self.name_end(at);
+            if key_end == at {
+                // 连名字都不是, 跳到下一个空白或者标签结尾
+                let junk_end = self.data[at..]
+                    .char_indices()
+                    .skip(1)
+                    .find(|(_, c)| c.is_whitespace() || matches!(c, '>' | '/'))
+                    .map_or(self.data.len(), |(i, _)| at + i);
+                let name = self.data[at..junk_end].to_string();
+                repairs.push((at, RepairKind::BadAttribute { name }));
+                at = junk_end;
+                continue;
+            }
+            let key = self.data[at..key_end].to_string();
+            let key_offset = at;
+            at = self.skip_space(key_end);
+            if bytes.get(at) != Some(&b'=') {
+                if at >= bytes.len() {
+                    return self.truncated();
+                }
+                repairs.push((key_offset, RepairKind::BadAttribute { name: key }));
+                continue;
+            }
+            at = self.skip_space(at + 1);
+            let quote = match bytes.get(at) {
+                None => return self.truncated(),
+                Some(&quote @ (b'"' | b'\'')) => quote as char,
+                Some(_) => {
+                    // 没有引号, 扔到下一个空白或者标签结尾
+                    at = self.data[at..]
+                        .char_indices()
+                        .find(|(_, c)| c.is_whitespace() || *c == '>')
+                        .map_or(self.data.len(), |(i, _)| at + i);
+                    repairs.push((key_offset, RepairKind::BadAttribute { name: key }));
+                    continue;
+                }
+            };
+            let Some(len) = self.data[at + 1..].find(quote) else {
+                return self.truncated();
+            };
+            let (value, entity_repairs) = decode(&self.data[at + 1..at + 1 + len], at + 1);
+            repairs.extend(entity_repairs);
+            at += len + 2;
+            if node.attrs.iter().any(|(name, _)| *name == key) {
+                repairs.push((key_offset, RepairKind::DuplicateAttribute { name: key }));
+            } else {
+                node.attrs.push((key, value));
+            }
+        };
+        self.pos = at;
+
+        let path = match self.stack.last_mut() {
+            Some(parent) => indexed_path(&parent.path, &mut parent.counts, &node.name),
+            None => node.name.clone(),
+        };
+        self.repairs
+            .extend(repairs.into_iter().map(|(offset, kind)| Repair {
+                offset,
+                path: path.clone(),
+                kind,
+            }));
+        self.stack.push(Frame {
+            node,
+            path,
+            counts: HashMap::new(),
+        });
+        if empty {
+            self.pop();
+        }
+        true
+    }
+
+    fn pop(&mut self) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        match self.stack.last_mut() {
+            Some(parent) => parent.node.children.push(frame.node),
+            None => self.root = Some(frame.node),
+        }
+    }
+}
+
+/// 反转义, 认不出来的实体原样留着
+fn decode(raw: &str, offset: usize) -> (String, Vec<(usize, RepairKind)>) {
+    let mut text = String::with_capacity(raw.len());
+    let mut repairs = Vec::new();
+    let mut rest = raw;
+    while let Some(amp) = rest.find('&') {
+        text.push_str(&rest[..amp]);
+        let tail = &rest[amp..];
+        let entity = tail
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &tail[..=end]);
+        match entity.and_then(|entity| resolve_entity(&entity[1..entity.len() - 1])) {
+            Some(c) => {
+                text.push(c);
+                rest = &tail[entity.map_or(1, str::len)..];
+            }
+            None => {
+                text.push('&');
+                let entity = entity.unwrap_or("&").to_string();
+                repairs.push((
+                    offset + (raw.len() - tail.len()),
+                    RepairKind::BadEntity { entity },
+                ));
+                rest = &tail[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    (text, repairs)
+}
+
+/// 补上的必须有的子节点, `ShipNode` 少了船就没得补了, 返回 None
+fn default_child(parent: &str, name: &str) -> Option<Node> {
+    let mut node = Node::new(name, 0);
+    match (parent, name) {
+        ("ShipNode", "Ship") => return None,
+        ("Pod", "Staging") => node
+            .attrs
+            .push(("currentStage".to_string(), "0".to_string())),
+        _ => {}
+    }
+    Some(node)
+}
+
+fn check_as<T: DeserializeOwned>(xml: &str) -> Result<(), String> {
+    from_str::<T>(xml).map(drop).map_err(|err| err.to_string())
+}
+
+/// raw 层能不能解析这个节点, 只看能整个扔掉的那些 (会重复的和可有可无的)
+fn check(parent: &str, node: &Node) -> Result<(), String> {
+    if matches!(parent, "Connections" | "Nodes") && known_node(&node.name).is_none() {
+        // serde 那边 `$value` 碰到不认识的节点会直接失败
+        return Err("不认识的节点".to_string());
+    }
+    let xml = || node.to_element().to_xml().map_err(|err| err.to_string());
+    match node.name.as_str() {
+        "Part" => check_as::<RawPart>(&xml()?),
+        "Connection" | "DockConnection" => check_as::<RawConnection>(&xml()?),
+        "Activate" => check_as::<RawActivate>(&xml()?),
+        "PlanetNode" | "ShipNode" => check_as::<RawNode>(&xml()?),
+        "Tank" => check_as::<RawFuelTank>(&xml()?),
+        "Engine" => check_as::<RawFuelEngine>(&xml()?),
+        "Pod" => check_as::<RawPod>(&xml()?),
+        "DisconnectedPart" => check_as::<RawDisconnectedPart>(&xml()?),
+        _ => Ok(()),
+    }
+}
+
+/// 从下往上修: 先修子节点, 解析不了的子节点扔掉, 再补上少了的必须有的子节点
+fn repair_node(node: &mut Node, path: &str, repairs: &mut Vec<Repair>) {
+    let mut counts = HashMap::new();
+    for mut child in std::mem::take(&mut node.children) {
+        let child_path = indexed_path(path, &mut counts, &child.name);
+        repair_node(&mut child, &child_path, repairs);
+        let duplicate =
+            single_child(&child.name) && node.children.iter().any(|other| other.name == child.name);
+        let checked = if duplicate {
+            Err(format!("<{}> 下面已经有一个了", node.name))
+        } else {
+            check(&node.name, &child)
+        };
+        match checked {
+            Ok(()) => node.children.push(child),
+            Err(reason) => repairs.push(Repair {
+                offset: child.offset,
+                path: child_path,
+                kind: RepairKind::DroppedElement {
+                    name: child.name,
+                    reason,
+                },
+            }),
+        }
+    }
+    for name in required_children(&node.name) {
+        if node.children.iter().any(|child| child.name == *name) {
+            continue;
+        }
+        if let Some(mut child) = default_child(&node.name, name) {
+            child.offset = node.offset;
+            node.children.push(child);
+            repairs.push(Repair {
+                offset: node.offset,
+                path: path.to_string(),
+                kind: RepairKind::MissingChild {
+                    name: name.to_string(),
+                },
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Repair, RepairKind, recover_xml, well_formed};
+    use crate::xml_part::{model::XmlDocument, parse::parse_any_xml};
+
+    const EMPTY_SHIP: &str = crate::net::EMPTY_SHIP;
+    const SAMPLE_SAVE: &str = include_str!("../save_1294489.xml");
+
+    fn repaired(data: &str) -> Vec<(String, RepairKind)> {
+        recover_xml(data)
+            .unwrap()
+            .repairs
+            .into_iter()
+            .map(|Repair { path, kind, .. }| (path, kind))
+            .collect()
+    }
+
+    fn name(name: &str) -> String {
+        name.to_string()
+    }
+
+    #[test]
+    fn intact_documents_are_untouched() {
+        for data in [EMPTY_SHIP, SAMPLE_SAVE] {
+            let recovered = recover_xml(data).unwrap();
+            assert!(recovered.repairs.is_empty());
+            assert_eq!(recovered.xml, data);
+            assert_eq!(recovered.document, parse_any_xml(data).unwrap());
+        }
+    }
+
+    #[test]
+    fn salvages_truncated_save() {
+        let cut = SAMPLE_SAVE.rfind("<Part ").unwrap() + 20;
+        let recovered = recover_xml(&SAMPLE_SAVE[..cut]).unwrap();
+        let XmlDocument::Save(save) = &recovered.document else {
+            panic!("应该是存档");
+        };
+        let ship = recovered.document.main_ship().unwrap();
+        assert_eq!(save.nodes.len(), 6);
+        assert_eq!(ship.parts.len(), 6);
+        assert!(ship.connections.is_empty());
+        assert_eq!(parse_any_xml(&recovered.xml).unwrap(), recovered.document);
+
+        let ship_path = "Runtime/Nodes/ShipNode[1]/Ship";
+        assert_eq!(
+            repaired(&SAMPLE_SAVE[..cut]),
+            vec![
+                (
+                    name("Runtime"),
+                    RepairKind::UnclosedElement {
+                        name: name("Runtime")
+                    }
+                ),
+                (
+                    name("Runtime/Nodes"),
+                    RepairKind::UnclosedElement {
+                        name: name("Nodes")
+                    }
+                ),
+                (
+                    name("Runtime/Nodes/ShipNode[1]"),
+                    RepairKind::UnclosedElement {
+                        name: name("ShipNode")
+                    }
+                ),
+                (
+                    name(ship_path),
+                    RepairKind::UnclosedElement { name: name("Ship") }
+                ),
+                (
+                    name(ship_path),
+                    RepairKind::MissingChild {
+                        name: name("Connections")
+                    }
+                ),
+                (
+                    format!("{ship_path}/Parts"),
+                    RepairKind::UnclosedElement {
+                        name: name("Parts")
+                    }
+                ),
+                (format!("{ship_path}/Parts"), RepairKind::TruncatedTag),
+            ]
+        );
+    }
+
+    #[test]
+    fn fixes_common_corruption() {
+        let data = format!(
+            "HTTP/1.1 200 OK\n\n{}\0\0junk",
+            EMPTY_SHIP
+                .replace(r#"name="""#, r#"name="R&D" name="again""#)
+                .replace(r#"editorAngle="0">"#, r#"editorAngle="0" broken>"#)
+                .replace(
+                    "</Parts>",
+                    r#"<Part partType="fueltank-1" id="2" x="abc" y="0" angle="0" angleV="0"/></Foo></Parts>"#,
+                )
+        );
+        let recovered = recover_xml(&data).unwrap();
+        let ship = recovered.document.main_ship().unwrap();
+        assert_eq!(ship.parts.len(), 1);
+        assert_eq!(ship.parts[0].attrs.pod.as_ref().unwrap().name, "R&D");
+
+        let part = name("Ship/Parts/Part[1]");
+        let kinds: Vec<_> = recovered
+            .repairs
+            .into_iter()
+            .map(|repair| (repair.path, repair.kind))
+            .collect();
+        assert!(matches!(
+            &kinds[..],
+            [
+                (root, RepairKind::LeadingJunk { len: 17 }),
+                (p1, RepairKind::BadAttribute { name: broken }),
+                (pod, RepairKind::BadEntity { entity }),
+                (pod2, RepairKind::DuplicateAttribute { name: dup }),
+                (p2, RepairKind::DroppedElement { name: dropped, .. }),
+                (parts, RepairKind::StrayEndTag { name: foo }),
+                (root2, RepairKind::TrailingJunk { len: 6 }),
+            ] if root.is_empty()
+                && root2.is_empty()
+                && *p1 == part
+                && broken == "broken"
+                && *pod == format!("{part}/Pod")
+                && *pod2 == *pod
+                && entity == "&"
+                && dup == "name"
+                && p2 == "Ship/Parts/Part[2]"
+                && dropped == "Part"
+                && parts == "Ship/Parts"
+                && foo == "Foo"
+        ));
+    }
+
+    #[test]
+    fn closes_mismatched_tags() {
+        let data = EMPTY_SHIP.replace("</Pod></Part>", "</Part>");
+        let recovered = recover_xml(&data).unwrap();
+        assert!(
+            recovered.document.main_ship().unwrap().parts[0]
+                .attrs
+                .pod
+                .is_some()
+        );
+        assert_eq!(
+            repaired(&data),
+            vec![(
+                name("Ship/Parts/Part[1]/Pod"),
+                RepairKind::UnclosedElement { name: name("Pod") }
+            )]
+        );
+    }
+
+    #[test]
+    fn hopeless_documents_fail() {
+        for data in ["", "hello", "<Ship", "<!-- nothing -->", "<Foo/>"] {
+            assert!(recover_xml(data).is_err(), "{data:?}");
+        }
+    }
+
+    #[test]
+    fn every_truncation_is_handled() {
+        for (data, step) in [(EMPTY_SHIP, 1), (SAMPLE_SAVE, 7)] {
+            for cut in (0..data.len()).step_by(step) {
+                if let Ok(recovered) = recover_xml(&data[..cut]) {
+                    assert!(well_formed(&recovered.xml), "{cut}");
+                    assert_eq!(
+                        parse_any_xml(&recovered.xml).unwrap(),
+                        recovered.document,
+                        "{cut}"
+                    );
+                }
+            }
+        }
+        // 只要根节点的开始标签在, 船就能救回来
+        let start = EMPTY_SHIP.find('>').unwrap() + 1;
+        for cut in start..EMPTY_SHIP.len() {
+            assert!(recover_xml(&EMPTY_SHIP[..cut]).is_ok(), "{cut}");
+        }
+    }
+}
@@ -0,0 +1,449 @@
+//! 把船画成 SVG
+//!
+//! 零件的形状和位置都从 [`crate::xml_part::geometry`] 拿, 这里只管换成 SVG 的坐标 (y 轴朝下) 和上色
+//! 存档里的每条船各画一块, 从左往右排, 因为它们的坐标是相对各自星球的, 放在一起画没意义
+//!
+//! 零件目录里没有的零件画成一个红色的小圆点
+
+use std::{collections::HashMap, fmt::Write};
+
+use crate::xml_part::{
+    catalog::{PartCatalog, PartKind},
+    geometry::{Bounds, Point, ShipGeometry},
+    graph::ShipGraph,
+    model::{Part, SaveDocument, SaveNode, ShipData, XmlDocument},
+};
+
+/// 世界坐标里的 1 默认画成几个像素
+pub const DEFAULT_SCALE: f64 = 20.0;
+/// 图片最长的边默认不超过这么多像素, 超过了就整体缩小
+pub const DEFAULT_MAX_SIZE: f64 = 2048.0;
+/// 每块标题占的高度, 像素
+const TITLE_HEIGHT: f64 = 16.0;
+/// 找不到零件类型的零件画成这么大的点, 世界坐标
+const MISSING_RADIUS: f64 = 0.5;
+pub const BACKGROUND: &str = "#1b1f2a";
+pub const OUTLINE: &str = "#101010";
+/// 零件目录里没有的零件
+pub const MISSING_COLOR: &str = "#ff3030";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderOptions {
+    /// 世界坐标里的 1 画成几个像素
+    pub scale: f64,
+    /// 四周和每块之间的留白, 像素
+    pub padding: f64,
+    /// 最长的边不超过这么多像素
+    pub max_size: f64,
+    /// 把连接画成线, 对接是虚线
+    pub connections: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            scale: DEFAULT_SCALE,
+            padding: 10.0,
+            max_size: DEFAULT_MAX_SIZE,
+            connections: false,
+        }
+    }
+}
+
+/// 每种零件的颜色
+pub fn part_color(kind: &PartKind) -> &'static str {
+    match kind {
+        PartKind::Pod => "#e8e8e8",
+        PartKind::Detacher => "#f2c744",
+        PartKind::Wheel => "#4a4a4a",
+        PartKind::Fuselage => "#a8adb5",
+        PartKind::Strut => "#7d858f",
+        PartKind::Tank => "#e07b39",
+        PartKind::Engine => "#c0392b",
+        PartKind::Parachute => "#f5f0e1",
+        PartKind::Nosecone => "#d5d8dc",
+        PartKind::Rcs => "#8e44ad",
+        PartKind::Solar => "#2e5fa8",
+        PartKind::DockConnector | PartKind::DockPort => "#27ae60",
+        PartKind::Lander => "#95a5a6",
+        PartKind::Unknown => "#ff00ff",
+    }
+}
+
+/// 画一条船, `DisconnectedParts` 里的零件也画
+pub fn render_ship_svg(ship: &ShipData, catalog: &PartCatalog, options: &RenderOptions) -> String {
+    render_panels(&[Panel::new(ship, catalog, None)], catalog, options)
+}
+
+/// 存档里的每条船各画一块
+pub fn render_save_svg(
+    save: &SaveDocument,
+    catalog: &PartCatalog,
+    options: &RenderOptions,
+) -> String {
+    let panels: Vec<Panel> = save
+        .nodes
+        .iter()
+        .filter_map(|node| match node {
+            SaveNode::Ship(node) => Some(Panel::new(
+                &node.ship,
+                catalog,
+                Some(format!("{} @ {}", node.id, node.planet)),
+            )),
+            _ => None,
+        })
+        .collect();
+    render_panels(&panels, catalog, options)
+}
+
+pub fn render_document_svg(
+    doc: &XmlDocument,
+    catalog: &PartCatalog,
+    options: &RenderOptions,
+) -> String {
+    match doc {
+        XmlDocument::Ship(doc) => render_ship_svg(&doc.ship, catalog, options),
+        XmlDocument::Save(doc) => render_save_svg(doc, catalog, options),
+    }
+}
+
+/// 一条船画成的一块
+struct Panel<'a> {
+    title: Option<String>,
+    parts: Vec<&'a Part>,
+    geometry: ShipGeometry,
+    graphs: Vec<ShipGraph>,
+    bounds: Bounds,
+}
+
+impl<'a> Panel<'a> {
+    fn new(ship: &'a ShipData, catalog: &PartCatalog, title: Option<String>) -> Self {
+        let parts: Vec<&Part> = ship
+            .parts
+            .iter()
+            .chain(ship.disconnected.iter().flat_map(|group| &group.parts))
+            .collect();
+        let geometry = ShipGeometry::new(ship, catalog);
+        let graphs = std::iter::once(ShipGraph::new(ship))
+            .chain(ship.disconnected.iter().map(ShipGraph::from_group))
+            .collect();
+
+        let markers = parts
+            .iter()
+            .filter(|part| geometry.missing.contains(&part.id))
+            .filter(|part| part.x.is_finite() && part.y.is_finite())
+            .flat_map(|part| {
+                [
+                    Point::new(part.x - MISSING_RADIUS, part.y - MISSING_RADIUS),
+                    Point::new(part.x + MISSING_RADIUS, part.y + MISSING_RADIUS),
+                ]
+            });
+        let bounds = Bounds::from_points(
+            geometry
+                .bounds
+                .iter()
+                .flat_map(|bounds| {
+                    [
+                        Point::new(bounds.min_x, bounds.min_y),
+                        Point::new(bounds.max_x, bounds.max_y),
+                    ]
+                })
+                .chain(markers),
+        )
+        // 一个零件都画不出来也给一小块地方
+        .unwrap_or(Bounds {
+            min_x: 0.0,
+            min_y: 0.0,
+            max_x: 1.0,
+            max_y: 1.0,
+        });
+        Self {
+            title,
+            parts,
+            geometry,
+            graphs,
+            bounds,
+        }
+    }
+
+    fn title_height(&self) -> f64 {
+        if self.title.is_some() {
+            TITLE_HEIGHT
+        } else {
+            0.0
+        }
+    }
+}
+
+/// 世界坐标 -> 图片坐标
+struct Canvas {
+    scale: f64,
+    left: f64,
+    top: f64,
+    bounds: Bounds,
+}
+
+impl Canvas {
+    fn map(&self, point: Point) -> (f64, f64) {
+        (
+            self.left + (point.x - self.bounds.min_x) * self.scale,
+            self.top + (self.bounds.max_y - point.y) * self.scale,
+        )
+    }
+
+    fn points(&self, points: &[Point]) -> String {
+        points
+            .iter()
+            .map(|point| {
+                let (x, y) = self.map(*point);
+                format!("{x:.2},{y:.2}")
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn render_panels(panels: &[Panel], catalog: &PartCatalog, options: &RenderOptions) -> String {
+    let padding = options.padding.max(0.0);
+    let content_width: f64 = panels.iter().map(|panel| panel.bounds.width()).sum();
+    let content_height = panels
+        .iter()
+        .map(|panel| panel.bounds.height())
+        .fold(0.0, f64::max);
+    let title_height = panels.iter().map(Panel::title_height).fold(0.0, f64::max);
+    let fixed_width = padding * (panels.len() + 1) as f64;
+    let fixed_height = padding * 2.0 + title_height;
+
+    let mut scale = options.scale;
+    if content_width > 0.0 {
+        scale = scale.min((options.max_size - fixed_width).max(1.0) / content_width);
+    }
+    if content_height > 0.0 {
+        scale = scale.min((options.max_size - fixed_height).max(1.0) / content_height);
+    }
+    let width = (fixed_width + content_width * scale).ceil();
+    let height = (fixed_height + content_height * scale).ceil();
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{BACKGROUND}"/>"#
+    );
+
+    let mut left = padding;
+    for panel in panels {
+        let canvas = Canvas {
+            scale,
+            left,
+            top: padding + title_height,
+            bounds: panel.bounds,
+        };
+        render_panel(&mut svg, panel, &canvas, catalog, options);
+        left += panel.bounds.width() * scale + padding;
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn render_panel(
+    svg: &mut String,
+    panel: &Panel,
+    canvas: &Canvas,
+    catalog: &PartCatalog,
+    options: &RenderOptions,
+) {
+    svg.push_str("<g class=\"ship\">\n");
+    if let Some(title) = &panel.title {
+        let _ = writeln!(
+            svg,
+            r##"<text x="{:.2}" y="{:.2}" fill="#cccccc" font-family="monospace" font-size="12">{}</text>"##,
+            canvas.left,
+            canvas.top - 4.0,
+            escape(title)
+        );
+    }
+
+    let shapes: HashMap<i64, usize> = panel
+        .geometry
+        .shapes
+        .iter()
+        .enumerate()
+        .map(|(index, shape)| (shape.part, index))
+        .collect();
+    for part in &panel.parts {
+        let label = format!("{} {}", part.id, part.part_type_id);
+        let Some(shape) = shapes
+            .get(&part.id)
+            .map(|index| &panel.geometry.shapes[*index])
+        else {
+            if part.x.is_finite() && part.y.is_finite() {
+                let (x, y) = canvas.map(Point::new(part.x, part.y));
+                let _ = writeln!(
+                    svg,
+                    r#"<circle cx="{x:.2}" cy="{y:.2}" r="{:.2}" fill="{MISSING_COLOR}"><title>{}</title></circle>"#,
+                    (MISSING_RADIUS * canvas.scale).max(1.0),
+                    escape(&label)
+                );
+            }
+            continue;
+        };
+        let color = catalog
+            .part_type(part)
+            .map(|part_type| part_color(&part_type.kind))
+            .unwrap_or(part_color(&PartKind::Unknown));
+        let opacity = if part.exploded { 0.4 } else { 1.0 };
+        let _ = writeln!(
+            svg,
+            r#"<g class="part" data-id="{}" data-type="{}" opacity="{opacity}"><title>{}</title>"#,
+            part.id,
+            escape(&part.part_type_id),
+            escape(&label)
+        );
+        for polygon in &shape.polygons {
+            let _ = writeln!(
+                svg,
+                r#"<polygon points="{}" fill="{color}" stroke="{OUTLINE}" stroke-width="1"/>"#,
+                canvas.points(polygon)
+            );
+        }
+        svg.push_str("</g>\n");
+    }
+
+    if options.connections {
+        render_connections(svg, panel, canvas);
+    }
+    svg.push_str("</g>\n");
+}
+
+fn render_connections(svg: &mut String, panel: &Panel, canvas: &Canvas) {
+    let centers: HashMap<i64, Point> = panel
+        .geometry
+        .shapes
+        .iter()
+        .map(|shape| (shape.part, shape.center))
+        .collect();
+    svg.push_str("<g class=\"connections\" stroke-linecap=\"round\">\n");
+    for graph in &panel.graphs {
+        let normal = graph
+            .normal_links()
+            .iter()
+            .map(|link| (link.parent, link.child, false));
+        let docks = graph
+            .dock_links()
+            .iter()
+            .map(|link| (link.parent, link.child, true));
+        for (parent, child, dock) in normal.chain(docks) {
+            let (Some(from), Some(to)) = (centers.get(&parent), centers.get(&child)) else {
+                continue;
+            };
+            let (x1, y1) = canvas.map(*from);
+            let (x2, y2) = canvas.map(*to);
+            let style = if dock {
+                r##"stroke="#2ecc71" stroke-dasharray="4 3""##
+            } else {
+                r##"stroke="#3fa9f5""##
+            };
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{x1:.2}" y1="{y1:.2}" x2="{x2:.2}" y2="{y2:.2}" {style} stroke-width="2"/>"#
+            );
+        }
+    }
+    svg.push_str("</g>\n");
+}
+
+/// 属性和文本里都能用
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl ShipData {
+    pub fn render_svg(&self, catalog: &PartCatalog, options: &RenderOptions) -> String {
+        render_ship_svg(self, catalog, options)
+    }
+}
+
+impl XmlDocument {
+    pub fn render_svg(&self, catalog: &PartCatalog, options: &RenderOptions) -> String {
+        render_document_svg(self, catalog, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RenderOptions, render_save_svg, render_ship_svg};
+    use crate::xml_part::{
+        catalog::PartCatalog,
+        parse::{parse_save_xml, parse_ship_xml},
+    };
+
+    #[test]
+    fn renders_ship() {
+        const SHIP: &str = r#"<Ship version="1" liftedOff="0" touchingGround="0">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+      <Pod throttle="0" name=""><Staging currentStage="0" /></Pod>
+    </Part>
+    <Part partType="fueltank-1" id="2" x="0" y="-1.75" angle="0" angleV="0" />
+    <Part partType="&lt;odd&gt;" id="3" x="3" y="0" angle="0" angleV="0" />
+  </Parts>
+  <Connections>
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
+  </Connections>
+</Ship>"#;
+        let ship = parse_ship_xml(SHIP).unwrap().ship;
+        let catalog = PartCatalog::embedded();
+
+        let svg = render_ship_svg(&ship, catalog, &RenderOptions::default());
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        // 外框 x: -1 .. 3.5, y: -2.75 .. 0.75, 每边留 10 像素
+        assert!(svg.contains(r#"width="110" height="90""#));
+        assert_eq!(svg.matches("<polygon").count(), 2);
+        assert_eq!(svg.matches("<circle").count(), 1);
+        assert!(svg.contains("3 &lt;odd&gt;"));
+        assert!(!svg.contains("<line"));
+
+        let options = RenderOptions {
+            connections: true,
+            ..Default::default()
+        };
+        let svg = render_ship_svg(&ship, catalog, &options);
+        assert_eq!(svg.matches("<line").count(), 1);
+
+        let small = RenderOptions {
+            max_size: 55.0,
+            ..Default::default()
+        };
+        let svg = render_ship_svg(&ship, catalog, &small);
+        assert!(svg.contains(r#"width="55""#));
+    }
+
+    #[test]
+    fn renders_every_ship_in_save() {
+        let save = parse_save_xml(include_str!("../save_1294489.xml")).unwrap();
+        let ships = save
+            .nodes
+            .iter()
+            .filter(|node| matches!(node, crate::xml_part::model::SaveNode::Ship(_)))
+            .count();
+        let svg = render_save_svg(&save, PartCatalog::embedded(), &RenderOptions::default());
+        assert_eq!(svg.matches("<g class=\"ship\">").count(), ships);
+        assert!(!svg.contains("NaN"));
+    }
+}
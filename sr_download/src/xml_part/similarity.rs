@@ -0,0 +1,147 @@
+//! 按零件组成找相似的船
+//!
+//! 每条船提取一个特征: 各种零件类型的数量 (直方图)、零件数、质量档位、分级数
+//! 相似度是直方图的加权 Jaccard (两边每种零件数量取小的加起来 / 取大的加起来), 在 0 到 1 之间
+//!
+//! 加权 Jaccard 不会超过 `零件少的那边 / 零件多的那边`, 所以按零件数先筛一遍不会漏掉够相似的船,
+//! 库里就是靠 `part_count` 上的索引做这个的, 见 [`crate::db_part::similarity`]
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::xml_part::{
+    catalog::PartCatalog,
+    model::{ShipData, XmlDocument},
+    staging::find_pod,
+    stats::ship_stats,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShipFeatures {
+    /// 主船体的零件数, 不算 `DisconnectedParts`
+    pub part_count: u32,
+    /// `floor(log2(湿重 kg))`, 差一档就是重了一倍
+    pub mass_class: i32,
+    /// 第一个指令舱的分级数
+    pub stage_count: u32,
+    /// 零件类型 -> 数量
+    pub histogram: BTreeMap<String, u32>,
+}
+
+pub fn ship_features(ship: &ShipData, catalog: &PartCatalog) -> ShipFeatures {
+    let mut histogram = BTreeMap::new();
+    for part in &ship.parts {
+        *histogram.entry(part.part_type_id.clone()).or_insert(0) += 1;
+    }
+    let wet_mass = ship_stats(ship, catalog).wet_mass;
+    ShipFeatures {
+        part_count: ship.parts.len() as u32,
+        mass_class: mass_class(wet_mass),
+        stage_count: find_pod(ship).map_or(0, |(_, pod)| pod.steps.len() as u32),
+        histogram,
+    }
+}
+
+/// 主船的特征, 一条船都没有的存档是 None
+pub fn document_features(doc: &XmlDocument, catalog: &PartCatalog) -> Option<ShipFeatures> {
+    doc.main_ship().map(|ship| ship_features(ship, catalog))
+}
+
+/// 1 kg 以下 (包括 NaN) 都算第 0 档
+pub fn mass_class(wet_mass: f64) -> i32 {
+    wet_mass.max(1.0).log2().floor() as i32
+}
+
+/// 直方图的加权 Jaccard, 两边都是空的算完全一样
+pub fn weighted_jaccard(a: &BTreeMap<String, u32>, b: &BTreeMap<String, u32>) -> f64 {
+    let mut shared = 0u64;
+    let mut total = 0u64;
+    for (part_type, count) in a {
+        let other = b.get(part_type).copied().unwrap_or(0);
+        shared += (*count).min(other) as u64;
+        total += (*count).max(other) as u64;
+    }
+    for (part_type, count) in b {
+        if !a.contains_key(part_type) {
+            total += *count as u64;
+        }
+    }
+    if total == 0 {
+        1.0
+    } else {
+        shared as f64 / total as f64
+    }
+}
+
+/// 相似度至少是 `min_similarity` 的船的零件数范围 (闭区间)
+pub fn part_count_range(part_count: u32, min_similarity: f64) -> (u32, u32) {
+    let min_similarity = min_similarity.clamp(f64::EPSILON, 1.0);
+    let low = (part_count as f64 * min_similarity).ceil() as u32;
+    let high = (part_count as f64 / min_similarity).floor();
+    (low, high.min(u32::MAX as f64) as u32)
+}
+
+impl ShipFeatures {
+    pub fn similarity(&self, other: &ShipFeatures) -> f64 {
+        weighted_jaccard(&self.histogram, &other.histogram)
+    }
+
+    /// 相似度一样的时候按这个排, 质量档位和分级数差得越少越靠前
+    pub fn distance(&self, other: &ShipFeatures) -> u32 {
+        self.mass_class.abs_diff(other.mass_class) + self.stage_count.abs_diff(other.stage_count)
+    }
+}
+
+impl ShipData {
+    pub fn features(&self, catalog: &PartCatalog) -> ShipFeatures {
+        ship_features(self, catalog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{mass_class, part_count_range, ship_features, weighted_jaccard};
+    use crate::xml_part::{catalog::PartCatalog, parse::parse_any_xml};
+
+    fn histogram(items: &[(&str, u32)]) -> BTreeMap<String, u32> {
+        items
+            .iter()
+            .map(|(part_type, count)| (part_type.to_string(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn extracts_features() {
+        let doc = parse_any_xml(include_str!("../save_1294489.xml")).unwrap();
+        let ship = doc.main_ship().unwrap();
+        let features = ship_features(ship, PartCatalog::embedded());
+        assert_eq!(features.part_count as usize, ship.parts.len());
+        assert_eq!(
+            features.histogram.values().sum::<u32>(),
+            features.part_count
+        );
+        assert_eq!(features.histogram.get("pod-1"), Some(&1));
+        assert!(features.mass_class > 0);
+        assert_eq!(features.similarity(&features), 1.0);
+        assert_eq!(features.distance(&features), 0);
+    }
+
+    #[test]
+    fn jaccard_and_bounds() {
+        let a = histogram(&[("pod-1", 1), ("fuselage-1", 3)]);
+        let b = histogram(&[("pod-1", 1), ("fuselage-1", 1), ("engine-1", 2)]);
+        // min: 1 + 1 + 0, max: 1 + 3 + 2
+        assert!((weighted_jaccard(&a, &b) - 2.0 / 6.0).abs() < 1e-12);
+        assert_eq!(weighted_jaccard(&a, &b), weighted_jaccard(&b, &a));
+        assert_eq!(weighted_jaccard(&BTreeMap::new(), &BTreeMap::new()), 1.0);
+        assert_eq!(weighted_jaccard(&a, &BTreeMap::new()), 0.0);
+
+        assert_eq!(part_count_range(10, 0.5), (5, 20));
+        assert_eq!(part_count_range(3, 1.0), (3, 3));
+        assert_eq!(mass_class(f64::NAN), 0);
+        assert_eq!(mass_class(1024.0), 10);
+    }
+}
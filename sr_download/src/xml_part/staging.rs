@@ -0,0 +1,250 @@
+//! 分级模拟
+//!
+//! 按 `PodData::steps` 的顺序一级一级激活零件:
+//! - 引擎点火, 降落伞打开
+//! - 分离器 (`PartKind::Detacher`, 也就是 `detacher-1` / `detacher-2`) 断开它身上所有的连接
+//!
+//! 每一级分离之后, 船会裂成几块, 带着指令舱的那块继续往下走, 其他的就掉了
+//! 分离器自己不跟任何一边走, 算单独的一块
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::xml_part::{
+    catalog::{PartCatalog, PartKind},
+    graph::ShipGraph,
+    model::{PodData, ShipData},
+};
+
+/// 连在一起的一堆零件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Assembly {
+    /// 排好序的零件 id
+    pub parts: Vec<i64>,
+    pub has_pod: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct StageOutcome {
+    /// 从 0 开始, 对应 `PodData::steps` 的下标
+    pub stage: usize,
+    pub engines: Vec<i64>,
+    pub parachutes: Vec<i64>,
+    pub detachers: Vec<i64>,
+    /// 其他被激活的零件 (着陆腿 / 太阳能板之类的)
+    pub other: Vec<i64>,
+    /// 要激活但是已经不在船上 (或者根本不存在) 的 id
+    pub missing: Vec<i64>,
+    /// 这一级之后船裂成的几块, 第一块是带指令舱的
+    pub assemblies: Vec<Assembly>,
+}
+
+impl StageOutcome {
+    /// 带着指令舱继续飞的那块
+    pub fn vessel(&self) -> Option<&Assembly> {
+        self.assemblies.iter().find(|assembly| assembly.has_pod)
+    }
+
+    /// 这一级掉下去的
+    pub fn separated(&self) -> impl Iterator<Item = &Assembly> {
+        self.assemblies.iter().filter(|assembly| !assembly.has_pod)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct StagingReport {
+    /// 带 `Pod` 的零件, 没有的时候所有零件都算船
+    pub pod_part: Option<i64>,
+    /// 发射之前的船, 也就是跟指令舱连着的零件
+    pub initial: Vec<i64>,
+    /// 一开始就没跟指令舱连着的零件
+    pub loose: Vec<Assembly>,
+    pub stages: Vec<StageOutcome>,
+}
+
+impl StagingReport {
+    /// 第 `stage` 级激活之后的船, `None` 就是发射之前
+    pub fn vessel_after(&self, stage: Option<usize>) -> &[i64] {
+        match stage {
+            None => &self.initial,
+            Some(stage) => self.stages[..=stage]
+                .iter()
+                .rev()
+                .find_map(|outcome| outcome.vessel())
+                .map_or(&self.initial, |assembly| &assembly.parts),
+        }
+    }
+}
+
+fn to_assemblies(groups: Vec<BTreeSet<i64>>, pod_part: Option<i64>) -> Vec<Assembly> {
+    let mut assemblies: Vec<Assembly> = groups
+        .into_iter()
+        .map(|group| Assembly {
+            has_pod: pod_part.is_some_and(|pod| group.contains(&pod)),
+            parts: group.into_iter().collect(),
+        })
+        .collect();
+    assemblies.sort_by_key(|assembly| !assembly.has_pod);
+    assemblies
+}
+
+/// 船上第一个带 `Pod` 的零件, 和它的分级
+pub fn find_pod(ship: &ShipData) -> Option<(i64, &PodData)> {
+    ship.parts
+        .iter()
+        .find_map(|part| part.attrs.pod.as_ref().map(|pod| (part.id, pod)))
+}
+
+/// 模拟一遍分级
+pub fn simulate_staging(ship: &ShipData, catalog: &PartCatalog) -> StagingReport {
+    let graph = ShipGraph::new(ship);
+    let kinds: HashMap<i64, Option<&PartKind>> = ship
+        .parts
+        .iter()
+        .map(|part| (part.id, catalog.part_type(part).map(|ty| &ty.kind)))
+        .collect();
+    let all_parts = graph.parts().clone();
+    let pod = find_pod(ship);
+    let pod_part = pod.map(|(id, _)| id);
+
+    let mut fired = HashSet::new();
+    let mut vessel = all_parts.clone();
+    let mut loose = Vec::new();
+    if let Some(pod_part) = pod_part {
+        for assembly in to_assemblies(graph.components_within(&all_parts, &fired), Some(pod_part)) {
+            if assembly.has_pod {
+                vessel = assembly.parts.into_iter().collect();
+            } else {
+                loose.push(assembly);
+            }
+        }
+    }
+    let initial = vessel.iter().copied().collect();
+
+    let mut stages = Vec::new();
+    for (stage, step) in pod
+        .map(|(_, pod)| pod.steps.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+    {
+        let mut outcome = StageOutcome {
+            stage,
+            ..Default::default()
+        };
+        for activate in &step.activates {
+            if !vessel.contains(&activate.id) {
+                outcome.missing.push(activate.id);
+                continue;
+            }
+            match kinds.get(&activate.id).copied().flatten() {
+                Some(PartKind::Engine) => outcome.engines.push(activate.id),
+                Some(PartKind::Parachute) => outcome.parachutes.push(activate.id),
+                Some(PartKind::Detacher) => {
+                    fired.insert(activate.id);
+                    outcome.detachers.push(activate.id);
+                }
+                _ => outcome.other.push(activate.id),
+            }
+        }
+
+        outcome.assemblies = to_assemblies(graph.components_within(&vessel, &fired), pod_part);
+        if let Some(next) = outcome.vessel() {
+            vessel = next.parts.iter().copied().collect();
+        }
+        stages.push(outcome);
+    }
+
+    StagingReport {
+        pod_part,
+        initial,
+        loose,
+        stages,
+    }
+}
+
+impl ShipData {
+    pub fn simulate_staging(&self, catalog: &PartCatalog) -> StagingReport {
+        simulate_staging(self, catalog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::simulate_staging;
+    use crate::xml_part::{catalog::PartCatalog, parse::parse_ship_xml};
+
+    /// pod(1) - tank(2) - detacher(3) - tank(4) - engine(5)
+    ///                 \- side detacher(6) - srb(7)
+    const STACK: &str = r#"<Ship version="1" liftedOff="0" touchingGround="1">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+      <Pod throttle="1" name="">
+        <Staging currentStage="0">
+          <Step><Activate Id="5" moved="1" /><Activate Id="7" moved="1" /></Step>
+          <Step><Activate Id="6" moved="1" /></Step>
+          <Step><Activate Id="3" moved="1" /><Activate Id="5" moved="1" /></Step>
+        </Staging>
+      </Pod>
+    </Part>
+    <Part partType="fueltank-1" id="2" x="0" y="-3.5" angle="0" angleV="0" />
+    <Part partType="detacher-1" id="3" x="0" y="-6" angle="0" angleV="0" />
+    <Part partType="fueltank-1" id="4" x="0" y="-8.5" angle="0" angleV="0" />
+    <Part partType="engine-1" id="5" x="0" y="-12.5" angle="0" angleV="0" />
+    <Part partType="detacher-2" id="6" x="2.5" y="-3.5" angle="0" angleV="0" />
+    <Part partType="engine-4" id="7" x="5" y="-3.5" angle="0" angleV="0" />
+    <Part partType="fuselage-1" id="8" x="20" y="20" angle="0" angleV="0" />
+  </Parts>
+  <Connections>
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="2" childPart="3" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="3" childPart="4" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="4" childPart="5" />
+    <Connection parentAttachPoint="4" childAttachPoint="1" parentPart="2" childPart="6" />
+    <Connection parentAttachPoint="2" childAttachPoint="3" parentPart="6" childPart="7" />
+  </Connections>
+</Ship>"#;
+
+    #[test]
+    fn detachers_split_the_ship() {
+        let ship = parse_ship_xml(STACK).unwrap().ship;
+        let report = simulate_staging(&ship, PartCatalog::embedded());
+
+        assert_eq!(report.pod_part, Some(1));
+        assert_eq!(report.initial, vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(report.loose.len(), 1);
+        assert_eq!(report.loose[0].parts, vec![8]);
+
+        let first = &report.stages[0];
+        assert_eq!(first.engines, vec![5, 7]);
+        assert_eq!(first.assemblies.len(), 1);
+
+        let second = &report.stages[1];
+        assert_eq!(second.detachers, vec![6]);
+        assert_eq!(second.vessel().unwrap().parts, vec![1, 2, 3, 4, 5]);
+        let separated: Vec<_> = second.separated().map(|a| a.parts.clone()).collect();
+        assert_eq!(separated, vec![vec![6], vec![7]]);
+
+        let third = &report.stages[2];
+        assert_eq!(third.detachers, vec![3]);
+        assert_eq!(third.engines, vec![5]);
+        assert_eq!(third.vessel().unwrap().parts, vec![1, 2]);
+        assert_eq!(report.vessel_after(Some(2)), &[1, 2]);
+        assert_eq!(report.vessel_after(None).len(), 7);
+    }
+
+    #[test]
+    fn parts_left_behind_cannot_be_activated() {
+        let mut ship = parse_ship_xml(STACK).unwrap().ship;
+        let pod = ship.parts[0].attrs.pod.as_mut().unwrap();
+        pod.steps.swap(0, 2);
+        let report = simulate_staging(&ship, PartCatalog::embedded());
+
+        // 先激活再分离, 所以 5 还能点着, 只是跟着下面一起掉了
+        assert_eq!(report.stages[0].engines, vec![5]);
+        assert_eq!(report.stages[0].vessel().unwrap().parts, vec![1, 2, 6, 7]);
+        assert_eq!(report.stages[2].engines, Vec::<i64>::new());
+        assert_eq!(report.stages[2].missing, vec![5, 7]);
+    }
+}
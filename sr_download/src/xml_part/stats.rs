@@ -0,0 +1,530 @@
+//! 船的物理数据: 质量 / 燃料 / 推力 / 推重比 / 每一级的 delta-v
+//!
+//! 质量用 kg, 推力用 kN, 换算系数跟游戏里显示的一致:
+//! - 零件目录里的 `mass` * 500 = kg
+//! - 引擎的 `power` * 85 = kN (Tiny 21 的 power 是 0.25)
+//!
+//! 油箱的质量按剩余燃料在 `dryMass` 和 `mass` 之间插值
+//! 每一级掉下去的零件由 `staging::simulate_staging` 决定
+//! 燃料只在分离器隔开的一块里共用, SRB 自带的只给自己用
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::xml_part::{
+    catalog::{FuelType, PartCatalog, PartKind, PartType},
+    graph::ShipGraph,
+    model::{Part, ShipData},
+    staging::{StagingReport, simulate_staging},
+};
+
+/// 零件目录里 1 个质量单位对应的 kg
+pub const MASS_SCALE: f64 = 500.0;
+/// 引擎 1 点 power 对应的 kN
+pub const POWER_TO_KN: f64 = 85.0;
+/// 算推重比用的重力加速度
+pub const STANDARD_GRAVITY: f64 = 9.81;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FuelTotal {
+    pub fuel_type: FuelType,
+    /// 游戏里的燃料单位
+    pub amount: f64,
+    /// kg
+    pub mass: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StageStats {
+    /// 从 0 开始, 对应 `PodData::steps` 的下标
+    pub stage: usize,
+    /// 这一级在工作的引擎数 (包括之前点着的)
+    pub engines: usize,
+    /// kN
+    pub thrust: f64,
+    /// kg
+    pub start_mass: f64,
+    /// kg
+    pub end_mass: f64,
+    /// 这一级烧掉的燃料, kg
+    pub burned_mass: f64,
+    /// 秒, 没有燃料可烧的时候是 0
+    pub isp: f64,
+    /// m/s
+    pub delta_v: f64,
+    pub twr: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShipStats {
+    pub part_count: usize,
+    /// 目录里找不到的零件类型, 这些零件不参与计算
+    pub unknown_parts: Vec<String>,
+    /// kg
+    pub wet_mass: f64,
+    /// kg
+    pub dry_mass: f64,
+    pub fuel: Vec<FuelTotal>,
+    pub engine_count: usize,
+    /// 所有引擎同时点火的推力, kN
+    pub total_thrust: f64,
+    /// 满载, 所有引擎同时点火
+    pub twr: f64,
+    pub stages: Vec<StageStats>,
+    /// m/s
+    pub total_delta_v: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PartState {
+    /// kg
+    dry_mass: f64,
+    tank: Option<TankState>,
+    engine: Option<EngineState>,
+}
+
+impl PartState {
+    fn mass(&self) -> f64 {
+        self.dry_mass + self.tank.map_or(0.0, |tank| tank.fuel * tank.unit_mass)
+    }
+}
+
+/// 一个油箱 (包括 SRB 自带的)
+#[derive(Debug, Clone, Copy)]
+struct TankState {
+    fuel_type: FuelType,
+    /// 剩余燃料
+    fuel: f64,
+    /// 每单位燃料的质量, kg
+    unit_mass: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EngineState {
+    fuel_type: FuelType,
+    /// kN
+    thrust: f64,
+    /// 燃料单位每秒
+    consumption: f64,
+}
+
+/// 引擎自带的油箱 (SRB) 只给它自己用, 燃料类型跟着引擎走
+fn tank_state(part: &Part, part_type: &PartType) -> Option<TankState> {
+    let tank = part_type.tank.as_ref()?;
+    let fuel_type = part_type
+        .engine
+        .as_ref()
+        .map_or(tank.fuel_type, |engine| engine.fuel_type);
+    let fuel = part.attrs.tank_fuel.unwrap_or(tank.fuel).max(0.0);
+    let unit_mass = if tank.fuel > 0.0 {
+        (part_type.mass - tank.dry_mass) / tank.fuel * MASS_SCALE
+    } else {
+        0.0
+    };
+    Some(TankState {
+        fuel_type,
+        fuel,
+        unit_mass,
+    })
+}
+
+fn engine_state(part_type: &PartType) -> Option<EngineState> {
+    let engine = part_type.engine.as_ref()?;
+    Some(EngineState {
+        fuel_type: engine.fuel_type,
+        thrust: engine.power * POWER_TO_KN,
+        consumption: engine.consumption,
+    })
+}
+
+/// 引擎能从哪里拿燃料
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum FuelPool {
+    /// 引擎自带的油箱, 只给这个零件用
+    Own(i64),
+    /// 分离器隔开的第几块里某种燃料的油箱
+    Shared(usize, i32),
+}
+
+/// 算一条船的物理数据
+pub fn ship_stats(ship: &ShipData, catalog: &PartCatalog) -> ShipStats {
+    let mut unknown_parts = Vec::new();
+    let mut parts = HashMap::new();
+    let mut detachers = HashSet::new();
+
+    for part in &ship.parts {
+        let Some(part_type) = catalog.part_type(part) else {
+            unknown_parts.push(part.part_type_id.clone());
+            continue;
+        };
+        if part_type.kind == PartKind::Detacher {
+            detachers.insert(part.id);
+        }
+        parts.insert(
+            part.id,
+            PartState {
+                dry_mass: part_type.dry_mass() * MASS_SCALE,
+                tank: tank_state(part, part_type),
+                engine: engine_state(part_type),
+            },
+        );
+    }
+    unknown_parts.sort();
+    unknown_parts.dedup();
+
+    let dry_mass = parts.values().map(|part| part.dry_mass).sum();
+    let mut fuel: BTreeMap<i32, FuelTotal> = BTreeMap::new();
+    for tank in parts.values().filter_map(|part| part.tank.as_ref()) {
+        let total = fuel.entry(tank.fuel_type.into()).or_insert(FuelTotal {
+            fuel_type: tank.fuel_type,
+            amount: 0.0,
+            mass: 0.0,
+        });
+        total.amount += tank.fuel;
+        total.mass += tank.fuel * tank.unit_mass;
+    }
+    let fuel: Vec<FuelTotal> = fuel.into_values().collect();
+    let wet_mass = dry_mass + fuel.iter().map(|total| total.mass).sum::<f64>();
+    let engine_count = parts.values().filter(|part| part.engine.is_some()).count();
+    let total_thrust = parts
+        .values()
+        .filter_map(|part| part.engine.as_ref())
+        .map(|engine| engine.thrust)
+        .sum();
+
+    let graph = ShipGraph::new(ship);
+    let stages = stage_stats(&simulate_staging(ship, catalog), &graph, &detachers, parts);
+    let total_delta_v = stages.iter().map(|stage| stage.delta_v).sum();
+
+    ShipStats {
+        part_count: ship.parts.len(),
+        unknown_parts,
+        wet_mass,
+        dry_mass,
+        fuel,
+        engine_count,
+        total_thrust,
+        twr: twr(total_thrust, wet_mass),
+        stages,
+        total_delta_v,
+    }
+}
+
+fn twr(thrust: f64, mass: f64) -> f64 {
+    if mass > 0.0 {
+        thrust * 1000.0 / (mass * STANDARD_GRAVITY)
+    } else {
+        0.0
+    }
+}
+
+/// 按分级模拟的结果一级一级算, 每一级先激活 / 分离, 再把船上点着的引擎能用的燃料全烧完
+///
+/// 还没炸的分离器两边的燃料不通, 同一块里的同种燃料是共用的, 不管燃料管怎么接
+fn stage_stats(
+    report: &StagingReport,
+    graph: &ShipGraph,
+    detachers: &HashSet<i64>,
+    mut parts: HashMap<i64, PartState>,
+) -> Vec<StageStats> {
+    // 没有分级的时候当成一级, 船上所有引擎一起点
+    let steps: Vec<(Vec<i64>, &[i64])> = if report.stages.is_empty() {
+        vec![(report.initial.clone(), report.initial.as_slice())]
+    } else {
+        report
+            .stages
+            .iter()
+            .map(|outcome| {
+                let vessel = outcome
+                    .vessel()
+                    .map_or(&[][..], |assembly| assembly.parts.as_slice());
+                (outcome.engines.clone(), vessel)
+            })
+            .collect()
+    };
+
+    let mut active: HashSet<i64> = HashSet::new();
+    let mut stages = Vec::with_capacity(steps.len());
+    for (stage, (engines, vessel)) in steps.into_iter().enumerate() {
+        active.extend(engines);
+        let start_mass: f64 = vessel
+            .iter()
+            .filter_map(|id| parts.get(id))
+            .map(PartState::mass)
+            .sum();
+
+        let within: BTreeSet<i64> = vessel.iter().copied().collect();
+        let mut groups = HashMap::new();
+        for (index, group) in graph
+            .components_within(&within, detachers)
+            .into_iter()
+            .enumerate()
+        {
+            groups.extend(group.into_iter().map(|id| (id, index)));
+        }
+        let pool = |id: i64, part: &PartState, fuel_type: FuelType| {
+            if part.engine.is_some() && part.tank.is_some() {
+                FuelPool::Own(id)
+            } else {
+                FuelPool::Shared(groups[&id], fuel_type.into())
+            }
+        };
+
+        // 每个燃料池还剩多少 (单位, kg)
+        let mut remaining: HashMap<FuelPool, (f64, f64)> = HashMap::new();
+        for (&id, part) in vessel.iter().filter_map(|id| Some(id).zip(parts.get(id))) {
+            if let Some(tank) = &part.tank {
+                let entry = remaining.entry(pool(id, part, tank.fuel_type)).or_default();
+                entry.0 += tank.fuel;
+                entry.1 += tank.fuel * tank.unit_mass;
+            }
+        }
+
+        let mut engine_count = 0;
+        let mut thrust = 0.0;
+        let mut burning_thrust = 0.0;
+        let mut mass_flow = 0.0;
+        let mut burned_pools = HashSet::new();
+        for (&id, part) in vessel
+            .iter()
+            .filter(|id| active.contains(id))
+            .filter_map(|id| Some(id).zip(parts.get(id)))
+        {
+            let Some(engine) = &part.engine else {
+                continue;
+            };
+            engine_count += 1;
+            thrust += engine.thrust;
+            let engine_pool = pool(id, part, engine.fuel_type);
+            let Some((amount, pool_mass)) = remaining.get(&engine_pool).copied() else {
+                continue;
+            };
+            if amount <= 0.0 {
+                continue;
+            }
+            burning_thrust += engine.thrust;
+            mass_flow += engine.consumption * pool_mass / amount;
+            burned_pools.insert(engine_pool);
+        }
+
+        let burned_mass: f64 = burned_pools
+            .iter()
+            .filter_map(|engine_pool| remaining.get(engine_pool))
+            .map(|(_, mass)| mass)
+            .sum();
+        for &id in vessel {
+            let Some(part) = parts.get_mut(&id) else {
+                continue;
+            };
+            let empty = part
+                .tank
+                .is_some_and(|tank| burned_pools.contains(&pool(id, part, tank.fuel_type)));
+            if let Some(tank) = part.tank.as_mut().filter(|_| empty) {
+                tank.fuel = 0.0;
+            }
+        }
+
+        let end_mass = start_mass - burned_mass;
+        let isp = if mass_flow > 0.0 {
+            burning_thrust * 1000.0 / (mass_flow * STANDARD_GRAVITY)
+        } else {
+            0.0
+        };
+        let delta_v = if end_mass > 0.0 && burned_mass > 0.0 {
+            isp * STANDARD_GRAVITY * (start_mass / end_mass).ln()
+        } else {
+            0.0
+        };
+        stages.push(StageStats {
+            stage,
+            engines: engine_count,
+            thrust,
+            start_mass,
+            end_mass,
+            burned_mass,
+            isp,
+            delta_v,
+            twr: twr(thrust, start_mass),
+        });
+    }
+    stages
+}
+
+impl ShipData {
+    pub fn stats(&self, catalog: &PartCatalog) -> ShipStats {
+        ship_stats(self, catalog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MASS_SCALE, ship_stats};
+    use crate::xml_part::{catalog::PartCatalog, parse::parse_ship_xml};
+
+    const TWO_STAGE_SHIP: &str = r#"<Ship version="1" liftedOff="0" touchingGround="1">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+      <Pod throttle="1" name="">
+        <Staging currentStage="0">
+          <Step><Activate Id="5" moved="1" /></Step>
+          <Step><Activate Id="4" moved="1" /><Activate Id="3" moved="1" /></Step>
+        </Staging>
+      </Pod>
+    </Part>
+    <Part partType="fueltank-1" id="2" x="0" y="-3.5" angle="0" angleV="0">
+      <Tank fuel="1500" />
+    </Part>
+    <Part partType="engine-2" id="3" x="0" y="-8.5" angle="0" angleV="0">
+      <Engine fuel="0" />
+    </Part>
+    <Part partType="detacher-2" id="4" x="2.5" y="-3.5" angle="0" angleV="0" />
+    <Part partType="engine-4" id="5" x="4" y="-8.5" angle="0" angleV="0">
+      <Tank fuel="4500" />
+      <Engine fuel="0" />
+    </Part>
+    <Part partType="mystery-1" id="6" x="0" y="2" angle="0" angleV="0" />
+  </Parts>
+  <Connections>
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="2" childPart="3" />
+    <Connection parentAttachPoint="4" childAttachPoint="1" parentPart="2" childPart="4" />
+    <Connection parentAttachPoint="2" childAttachPoint="3" parentPart="4" childPart="5" />
+  </Connections>
+</Ship>"#;
+
+    #[test]
+    fn mass_and_thrust() {
+        let ship = parse_ship_xml(TWO_STAGE_SHIP).unwrap().ship;
+        let stats = ship_stats(&ship, PartCatalog::embedded());
+
+        assert_eq!(stats.part_count, 6);
+        assert_eq!(stats.unknown_parts, vec!["mystery-1".to_string()]);
+        // pod 1.0 + tank 0.5 + engine 1.25 + detacher 0.25 + srb 3.15
+        let dry = (1.0 + 0.5 + 1.25 + 0.25 + 3.15) * MASS_SCALE;
+        assert!((stats.dry_mass - dry).abs() < 1e-6);
+        // 油箱满的, srb 剩一半
+        let wet = dry + 3.0 * MASS_SCALE + (22.0 - 3.15) / 2.0 * MASS_SCALE;
+        assert!((stats.wet_mass - wet).abs() < 1e-6);
+        assert_eq!(stats.fuel.len(), 2);
+        assert_eq!(stats.engine_count, 2);
+        assert!((stats.total_thrust - 8.0 * 85.0).abs() < 1e-6);
+        assert!(stats.twr > 0.0);
+    }
+
+    #[test]
+    fn stages_follow_pod_steps() {
+        let ship = parse_ship_xml(TWO_STAGE_SHIP).unwrap().ship;
+        let stats = ship_stats(&ship, PartCatalog::embedded());
+
+        assert_eq!(stats.stages.len(), 2);
+        // 先点 srb, 没连上的 mystery-1 不算
+        let first = &stats.stages[0];
+        assert_eq!(first.engines, 1);
+        assert!((first.start_mass - stats.wet_mass).abs() < 1e-6);
+        assert!((first.burned_mass - (22.0 - 3.15) / 2.0 * MASS_SCALE).abs() < 1e-6);
+        assert!(first.delta_v > 0.0);
+
+        // 再把 srb 连着分离器一起扔掉, 点主引擎
+        let second = &stats.stages[1];
+        assert_eq!(second.engines, 1);
+        let dropped = (3.15 + 0.25) * MASS_SCALE;
+        assert!((second.start_mass - (first.end_mass - dropped)).abs() < 1e-6);
+        assert!((second.burned_mass - 3.0 * MASS_SCALE).abs() < 1e-6);
+        assert!(second.delta_v > 0.0);
+        assert!((stats.total_delta_v - first.delta_v - second.delta_v).abs() < 1e-6);
+    }
+
+    /// pod(1) - tank(2), 两边各挂一个分离器 (3, 5) 和 SRB (4, 6), 两个 SRB 分两级点
+    const TWO_SRBS: &str = r#"<Ship version="1" liftedOff="0" touchingGround="1">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+      <Pod throttle="1" name="">
+        <Staging currentStage="0">
+          <Step><Activate Id="4" moved="1" /></Step>
+          <Step><Activate Id="3" moved="1" /><Activate Id="6" moved="1" /></Step>
+        </Staging>
+      </Pod>
+    </Part>
+    <Part partType="fueltank-1" id="2" x="0" y="-3.5" angle="0" angleV="0" />
+    <Part partType="detacher-2" id="3" x="2.5" y="-3.5" angle="0" angleV="0" />
+    <Part partType="engine-4" id="4" x="4" y="-8.5" angle="0" angleV="0" />
+    <Part partType="detacher-2" id="5" x="-2.5" y="-3.5" angle="0" angleV="0" />
+    <Part partType="engine-4" id="6" x="-4" y="-8.5" angle="0" angleV="0" />
+  </Parts>
+  <Connections>
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
+    <Connection parentAttachPoint="4" childAttachPoint="1" parentPart="2" childPart="3" />
+    <Connection parentAttachPoint="2" childAttachPoint="3" parentPart="3" childPart="4" />
+    <Connection parentAttachPoint="3" childAttachPoint="2" parentPart="2" childPart="5" />
+    <Connection parentAttachPoint="1" childAttachPoint="4" parentPart="5" childPart="6" />
+  </Connections>
+</Ship>"#;
+
+    #[test]
+    fn srb_fuel_stays_with_its_srb() {
+        let ship = parse_ship_xml(TWO_SRBS).unwrap().ship;
+        let stats = ship_stats(&ship, PartCatalog::embedded());
+
+        assert_eq!(stats.stages.len(), 2);
+        let srb_fuel = (22.0 - 3.15) * MASS_SCALE;
+        // 第一个 SRB 只烧自己的, 第二个 SRB 到第二级还是满的
+        let first = &stats.stages[0];
+        assert!((first.burned_mass - srb_fuel).abs() < 1e-6);
+        let second = &stats.stages[1];
+        let dropped = (0.25 + 3.15) * MASS_SCALE;
+        assert!((second.start_mass - (first.end_mass - dropped)).abs() < 1e-6);
+        assert!((second.burned_mass - srb_fuel).abs() < 1e-6);
+        assert!(second.delta_v > 0.0);
+    }
+
+    /// pod(1) - tank(2) - engine(3) - detacher(4) - tank(5) - engine(6), 先点下面的 6
+    const LIQUID_STACK: &str = r#"<Ship version="1" liftedOff="0" touchingGround="1">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+      <Pod throttle="1" name="">
+        <Staging currentStage="0">
+          <Step><Activate Id="6" moved="1" /></Step>
+          <Step><Activate Id="4" moved="1" /><Activate Id="3" moved="1" /></Step>
+        </Staging>
+      </Pod>
+    </Part>
+    <Part partType="fueltank-1" id="2" x="0" y="-3.5" angle="0" angleV="0" />
+    <Part partType="engine-1" id="3" x="0" y="-7" angle="0" angleV="0" />
+    <Part partType="detacher-1" id="4" x="0" y="-9" angle="0" angleV="0" />
+    <Part partType="fueltank-1" id="5" x="0" y="-11.5" angle="0" angleV="0" />
+    <Part partType="engine-2" id="6" x="0" y="-16.5" angle="0" angleV="0" />
+  </Parts>
+  <Connections>
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="2" childPart="3" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="3" childPart="4" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="4" childPart="5" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="5" childPart="6" />
+  </Connections>
+</Ship>"#;
+
+    #[test]
+    fn detachers_split_fuel() {
+        let ship = parse_ship_xml(LIQUID_STACK).unwrap().ship;
+        let stats = ship_stats(&ship, PartCatalog::embedded());
+
+        assert_eq!(stats.stages.len(), 2);
+        // 下面一级烧不到分离器上面的油箱
+        let first = &stats.stages[0];
+        assert_eq!(first.engines, 1);
+        assert!((first.burned_mass - 3.0 * MASS_SCALE).abs() < 1e-6);
+        let second = &stats.stages[1];
+        assert_eq!(second.engines, 1);
+        assert!((second.burned_mass - 3.0 * MASS_SCALE).abs() < 1e-6);
+        assert!(second.delta_v > 0.0);
+    }
+
+    #[test]
+    fn empty_ship_has_no_delta_v() {
+        let ship = parse_ship_xml(crate::net::EMPTY_SHIP).unwrap().ship;
+        let stats = ship_stats(&ship, PartCatalog::embedded());
+        assert_eq!(stats.wet_mass, MASS_SCALE);
+        assert_eq!(stats.total_delta_v, 0.0);
+    }
+}
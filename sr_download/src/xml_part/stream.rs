@@ -0,0 +1,1027 @@
+//! 一遍扫完的流式解析 / 校验
+//!
+//! `parse_any_xml` 要把整个文档反序列化成 model, `verify_ship` 以前还要先扫两遍 (是不是 xml、形状对不对)
+//! 几 MB 的存档这样很慢, 这里直接在 `quick_xml::Reader` 的事件上走一遍, 同时:
+//! - 检查是不是合法 xml
+//! - 检查 serde 那边会不会解析失败 (少了必须的属性 / 子节点, 数字写错了, `<Connections>` 里有不认识的节点)
+//! - 记下节点列表、每条船的零件数之类的摘要
+//! - 做 [`crate::xml_part::verify`] 里除了几何以外的所有检查
+//!
+//! 每条船只留零件的 id、类型、坐标、连接和分级, 这条船扫完校验完就扔掉, 不会建完整的 model
+//! 几何检查 (零件重叠、离得太远) 要零件的形状, 这里不做, 反正都只是 `Warning`
+
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use quick_xml::{
+    Reader,
+    events::{BytesStart, Event},
+};
+use serde::Serialize;
+
+use crate::xml_part::{
+    catalog::PartCatalog,
+    convert::LEGACY_POD_PREFIX,
+    error::ErrorPosition,
+    extra::{indexed_path, read_attrs, required_children, single_child},
+    graph::find_directed_cycle,
+    model::Connection,
+    verify::{
+        IssueKind, IssueLocation, ValidationReport, check_attach_points, check_connections,
+        check_finite,
+    },
+};
+
+/// 扫一遍得到的东西
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StreamSummary {
+    /// 根节点的名字, 空文档是 None
+    pub root: Option<String>,
+    /// 根节点是自闭合的 (`<Ship/>`)
+    pub root_empty: bool,
+    /// 不是合法 xml 的话是出错的原因
+    pub xml_error: Option<String>,
+    /// serde 解析会失败的话是第一个原因
+    pub schema_error: Option<String>,
+    /// `xml_error` 出在哪
+    pub xml_error_at: Option<ErrorPosition>,
+    /// `schema_error` 出在哪, 属性的问题路径后面带 `/@属性名`
+    pub schema_error_at: Option<ErrorPosition>,
+    pub shape: RootShape,
+    /// 存档的节点, 按原来的顺序
+    pub nodes: Vec<NodeSummary>,
+    /// 船文件是一条, 存档是每个 `ShipNode` 一条
+    pub ships: Vec<ShipSummary>,
+    /// 除了几何以外的校验结果
+    pub report: ValidationReport,
+}
+
+/// 根节点下面直接有哪些子节点
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RootShape {
+    pub parts: bool,
+    pub connections: bool,
+    pub disconnected: bool,
+    pub nodes: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NodeSummary {
+    Planet {
+        name: String,
+    },
+    Ship {
+        id: i64,
+        planet: String,
+        /// 主船体的零件数
+        part_count: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ShipSummary {
+    /// 存档里的 `ShipNode` id, 船文件是 None
+    pub node: Option<i64>,
+    /// 主船体的零件数, 不算 `DisconnectedParts`
+    pub part_count: usize,
+    /// 主船体的连接数, 包括对接
+    pub connection_count: usize,
+    pub disconnected_groups: usize,
+    pub disconnected_parts: usize,
+    /// 带 `Pod` 的零件数 (老格式的分级也算一个)
+    pub pod_count: usize,
+    /// 第一个指令舱的分级数
+    pub stage_count: usize,
+}
+
+impl StreamSummary {
+    pub fn is_xml(&self) -> bool {
+        self.xml_error.is_none()
+    }
+
+    /// 根节点是 `<Ship>`, 下面有 `Parts` 和 `Connections`
+    pub fn is_ship_shape(&self) -> bool {
+        self.is_xml()
+            && !self.root_empty
+            && self.root.as_deref() == Some("Ship")
+            && self.shape.parts
+            && self.shape.connections
+    }
+
+    /// 根节点是 `<Runtime>`, 下面有 `Nodes`
+    pub fn is_save_shape(&self) -> bool {
+        self.is_xml()
+            && !self.root_empty
+            && self.root.as_deref() == Some("Runtime")
+            && self.shape.nodes
+    }
+
+    /// serde 能解析出来
+    pub fn is_parsable(&self) -> bool {
+        self.is_xml() && self.schema_error.is_none()
+    }
+
+    /// 能解析, 而且没有 `Severity::Error` 的问题
+    pub fn is_valid(&self) -> bool {
+        self.is_parsable() && self.report.is_valid()
+    }
+}
+
+struct Frame {
+    name: String,
+    children: Vec<String>,
+    /// 出错的时候报位置用
+    path: String,
+    offset: usize,
+    counts: HashMap<String, usize>,
+}
+
+struct PartScan {
+    id: i64,
+    part_type: String,
+    x: f64,
+    y: f64,
+    angle: f64,
+    angle_v: f64,
+}
+
+#[derive(Default)]
+struct GroupScan {
+    parts: Vec<PartScan>,
+    /// 带 `Pod` 的零件和它的分级
+    pods: Vec<(i64, Vec<Vec<i64>>)>,
+    connections: Vec<Connection>,
+}
+
+#[derive(Default)]
+struct ShipScan {
+    node: Option<i64>,
+    /// 第 0 组是主船体, 后面是 `DisconnectedParts`
+    groups: Vec<GroupScan>,
+    group: usize,
+    part: i64,
+    /// 有老格式特有的东西
+    legacy: bool,
+    legacy_steps: Vec<Vec<i64>>,
+    /// 现在的 `<Staging>` 是不是在 `<Pod>` 下面
+    pod_staging: bool,
+}
+
+impl ShipScan {
+    fn group(&mut self) -> &mut GroupScan {
+        &mut self.groups[self.group]
+    }
+
+    fn steps(&mut self) -> Option<&mut Vec<Vec<i64>>> {
+        if self.pod_staging {
+            self.groups[self.group]
+                .pods
+                .last_mut()
+                .map(|(_, steps)| steps)
+        } else {
+            Some(&mut self.legacy_steps)
+        }
+    }
+}
+
+struct ShipNodeScan {
+    id: i64,
+    planet: String,
+    pods: HashSet<i64>,
+}
+
+#[derive(Default)]
+struct SaveScan {
+    solar_system: String,
+    ship_id: i64,
+    pod_id: i64,
+    planets: HashSet<String>,
+    node_ids: HashSet<i64>,
+    ships: Vec<ShipNodeScan>,
+}
+
+struct Scanner<'a> {
+    data: &'a str,
+    /// None 是只找解析错误的位置, 不做校验, 见 [`locate_error`]
+    catalog: Option<&'a PartCatalog>,
+    summary: StreamSummary,
+    stack: Vec<Frame>,
+    /// 正在跳过的不认识的节点有几层
+    skip: usize,
+    ship: Option<ShipScan>,
+    save: Option<SaveScan>,
+    /// 现在的 `ShipNode`
+    node: Option<i64>,
+    /// 正在扫的标签的路径和 `<` 的偏移
+    tag_path: String,
+    tag_offset: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(data: &'a str, catalog: Option<&'a PartCatalog>) -> Self {
+        Self {
+            data,
+            catalog,
+            summary: StreamSummary::default(),
+            stack: Vec::new(),
+            skip: 0,
+            ship: None,
+            save: None,
+            node: None,
+            tag_path: String::new(),
+            tag_offset: 0,
+        }
+    }
+
+    /// 记在正在扫的标签上
+    fn schema_error(&mut self, reason: String) {
+        self.schema_error_at(reason, self.tag_offset, self.tag_path.clone());
+    }
+
+    fn schema_error_at(&mut self, reason: String, offset: usize, path: String) {
+        if self.summary.schema_error.is_none() {
+            self.summary.schema_error = Some(reason);
+            self.summary.schema_error_at = Some(ErrorPosition::new(self.data, offset, path));
+        }
+    }
+
+    /// 正在扫的标签里属性 `key` 的偏移, 找不到就是标签的
+    fn attr_offset(&self, key: &str) -> usize {
+        let tag = &self.data[self.tag_offset..];
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        tag.match_indices(key)
+            .find(|(at, _)| {
+                tag[..*at].ends_with(char::is_whitespace)
+                    && tag[at + key.len()..].trim_start().starts_with('=')
+            })
+            .map_or(self.tag_offset, |(at, _)| self.tag_offset + at)
+    }
+
+    /// 没有或者格式不对的话记一个 `schema_error`, 返回默认值接着扫
+    fn required<T: FromStr + Default>(
+        &mut self,
+        attrs: &[(String, String)],
+        element: &str,
+        key: &str,
+    ) -> T {
+        match attrs.iter().find(|(name, _)| name == key) {
+            Some((_, value)) => self.parse(element, key, value).unwrap_or_default(),
+            None => {
+                let path = format!("{}/@{key}", self.tag_path);
+                self.schema_error_at(format!("<{element}> 没有 {key}"), self.tag_offset, path);
+                T::default()
+            }
+        }
+    }
+
+    fn optional<T: FromStr>(
+        &mut self,
+        attrs: &[(String, String)],
+        element: &str,
+        key: &str,
+    ) -> Option<T> {
+        let (_, value) = attrs.iter().find(|(name, _)| name == key)?;
+        self.parse(element, key, value)
+    }
+
+    fn parse<T: FromStr>(&mut self, element: &str, key: &str, value: &str) -> Option<T> {
+        let parsed = value.parse().ok();
+        if parsed.is_none() {
+            let reason = format!("<{element}> 的 {key}=\"{value}\" 格式不对");
+            let path = format!("{}/@{key}", self.tag_path);
+            self.schema_error_at(reason, self.attr_offset(key), path);
+        }
+        parsed
+    }
+
+    /// `offset` 是这个标签的 `<` 在原文里的偏移
+    fn open(&mut self, start: &BytesStart, empty: bool, offset: usize) {
+        if self.skip > 0 {
+            if !empty {
+                self.skip += 1;
+            }
+            return;
+        }
+        let name = String::from_utf8_lossy(start.name().as_ref()).to_string();
+        self.tag_offset = offset;
+        self.tag_path = match self.stack.last_mut() {
+            Some(frame) => indexed_path(&frame.path, &mut frame.counts, &name),
+            None => name.clone(),
+        };
+        let attrs = match read_attrs(start) {
+            Ok(attrs) => attrs,
+            Err(err) => {
+                self.schema_error(format!("<{name}> 的属性读不出来: {err}"));
+                Vec::new()
+            }
+        };
+        let parent = self.stack.last().map(|frame| frame.name.clone());
+        if parent.is_none() {
+            if self.summary.root.is_some() {
+                // 第二个根节点, 不管
+                self.skip = usize::from(!empty);
+                return;
+            }
+            self.summary.root = Some(name.clone());
+            self.summary.root_empty = empty;
+        }
+        if !self.element(parent.as_deref(), &name, &attrs) {
+            self.skip = usize::from(!empty);
+            return;
+        }
+        if let Some(frame) = self.stack.last_mut() {
+            let duplicate = single_child(&name) && frame.children.contains(&name);
+            frame.children.push(name.clone());
+            if duplicate {
+                let reason = format!("<{}> 下面有好几个 <{name}>", frame.name);
+                self.schema_error(reason);
+            }
+        }
+        if self.stack.len() == 1 {
+            let shape = &mut self.summary.shape;
+            match name.as_str() {
+                "Parts" => shape.parts = true,
+                "Connections" => shape.connections = true,
+                "DisconnectedParts" => shape.disconnected = true,
+                "Nodes" => shape.nodes = true,
+                _ => {}
+            }
+        }
+        self.stack.push(Frame {
+            name,
+            children: Vec::new(),
+            path: self.tag_path.clone(),
+            offset,
+            counts: HashMap::new(),
+        });
+        if empty {
+            self.close();
+        }
+    }
+
+    fn close(&mut self) {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return;
+        }
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        for child in required_children(&frame.name) {
+            if !frame.children.iter().any(|name| name == child) {
+                let reason = format!("<{}> 下面没有 <{child}>", frame.name);
+                self.schema_error_at(reason, frame.offset, frame.path.clone());
+            }
+        }
+        match frame.name.as_str() {
+            "Ship" => {
+                if let Some(ship) = self.ship.take() {
+                    self.finish_ship(ship);
+                }
+            }
+            "DisconnectedPart" => {
+                if let Some(ship) = &mut self.ship {
+                    ship.group = 0;
+                }
+            }
+            "ShipNode" => self.node = None,
+            "Runtime" => {
+                if let Some(save) = self.save.take() {
+                    self.finish_save(save);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 认识的节点返回 true, 不认识的整个跳过
+    fn element(&mut self, parent: Option<&str>, name: &str, attrs: &[(String, String)]) -> bool {
+        match (parent, name) {
+            (None | Some("ShipNode"), "Ship") => self.open_ship(attrs),
+            (None, "Runtime") => self.open_save(attrs),
+            (Some("Ship"), "Parts" | "Connections" | "DisconnectedParts") => {}
+            (Some("Ship"), "Staging") => {
+                if let Some(ship) = &mut self.ship {
+                    ship.legacy = true;
+                    ship.pod_staging = false;
+                }
+            }
+            (Some("DisconnectedParts"), "DisconnectedPart") => {
+                if let Some(ship) = &mut self.ship {
+                    ship.groups.push(GroupScan::default());
+                    ship.group = ship.groups.len() - 1;
+                }
+            }
+            (Some("DisconnectedPart"), "Parts" | "Connections") => {}
+            (Some("Parts"), "Part") => self.open_part(attrs),
+            (Some("Part"), "Tank" | "Engine") => {
+                self.required::<f64>(attrs, name, "fuel");
+            }
+            (Some("Part"), "Pod") => {
+                self.required::<String>(attrs, name, "name");
+                self.required::<f64>(attrs, name, "throttle");
+                if let Some(ship) = &mut self.ship {
+                    let part = ship.part;
+                    ship.group().pods.push((part, Vec::new()));
+                }
+            }
+            (Some("Pod"), "Staging") => {
+                self.required::<i32>(attrs, name, "currentStage");
+                if let Some(ship) = &mut self.ship {
+                    ship.pod_staging = true;
+                }
+            }
+            (Some("Staging"), "Step") => {
+                if let Some(steps) = self.ship.as_mut().and_then(ShipScan::steps) {
+                    steps.push(Vec::new());
+                }
+            }
+            (Some("Step"), "Activate") => {
+                let id = self.required::<i64>(attrs, name, "Id");
+                self.required::<i8>(attrs, name, "moved");
+                if let Some(step) = self
+                    .ship
+                    .as_mut()
+                    .and_then(ShipScan::steps)
+                    .and_then(|steps| steps.last_mut())
+                {
+                    step.push(id);
+                }
+            }
+            (Some("Connections"), "Connection") => {
+                let connection = Connection::Normal {
+                    parent_attach_point: self.required(attrs, name, "parentAttachPoint"),
+                    child_attach_point: self.required(attrs, name, "childAttachPoint"),
+                    parent_part: self.required(attrs, name, "parentPart"),
+                    child_part: self.required(attrs, name, "childPart"),
+                };
+                if let Some(ship) = &mut self.ship {
+                    ship.group().connections.push(connection);
+                }
+            }
+            (Some("Connections"), "DockConnection") => {
+                let connection = Connection::Dock {
+                    dock_part: self.required(attrs, name, "dockPart"),
+                    parent_part: self.required(attrs, name, "parentPart"),
+                    child_part: self.required(attrs, name, "childPart"),
+                };
+                if let Some(ship) = &mut self.ship {
+                    ship.group().connections.push(connection);
+                }
+            }
+            (Some("Runtime"), "Nodes") => {}
+            (Some("Nodes"), "PlanetNode") => self.open_planet(attrs),
+            (Some("Nodes"), "ShipNode") => self.open_ship_node(attrs),
+            (Some(parent @ ("Connections" | "Nodes")), _) => {
+                self.schema_error(format!("<{parent}> 下面有不认识的 <{name}>"));
+                return false;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn open_ship(&mut self, attrs: &[(String, String)]) {
+        self.optional::<i32>(attrs, "Ship", "version");
+        self.optional::<i8>(attrs, "Ship", "liftedOff");
+        self.optional::<i8>(attrs, "Ship", "touchingGround");
+        let current_stage = self.optional::<i32>(attrs, "Ship", "currentStage");
+        let throttle = self.optional::<f64>(attrs, "Ship", "throttle");
+        self.ship = Some(ShipScan {
+            node: self.node,
+            groups: vec![GroupScan::default()],
+            legacy: current_stage.is_some() || throttle.is_some(),
+            ..Default::default()
+        });
+    }
+
+    fn open_part(&mut self, attrs: &[(String, String)]) {
+        let part = PartScan {
+            part_type: self.required(attrs, "Part", "partType"),
+            id: self.required(attrs, "Part", "id"),
+            x: self.required(attrs, "Part", "x"),
+            y: self.required(attrs, "Part", "y"),
+            angle: self.required(attrs, "Part", "angle"),
+            angle_v: self.required(attrs, "Part", "angleV"),
+        };
+        self.optional::<i32>(attrs, "Part", "editorAngle");
+        for key in [
+            "flippedX",
+            "flippedY",
+            "activated",
+            "exploded",
+            "inflate",
+            "deployed",
+            "rope",
+        ] {
+            self.optional::<i8>(attrs, "Part", key);
+        }
+        for key in [
+            "chuteX",
+            "chuteY",
+            "chuteAngle",
+            "chuteHeight",
+            "extension",
+            "inflation",
+        ] {
+            self.optional::<f64>(attrs, "Part", key);
+        }
+        if let Some(ship) = &mut self.ship {
+            ship.part = part.id;
+            ship.group().parts.push(part);
+        }
+    }
+
+    fn open_save(&mut self, attrs: &[(String, String)]) {
+        let time: f64 = self.required(attrs, "Runtime", "time");
+        self.required::<i8>(attrs, "Runtime", "firstStageActivated");
+        let save = SaveScan {
+            solar_system: self.required(attrs, "Runtime", "solarSystem"),
+            ship_id: self.required(attrs, "Runtime", "shipId"),
+            pod_id: self.required(attrs, "Runtime", "podId"),
+            ..Default::default()
+        };
+        check_finite(
+            &mut self.summary.report,
+            IssueLocation::default(),
+            None,
+            &[("time", time)],
+        );
+        self.save = Some(save);
+    }
+
+    fn open_planet(&mut self, attrs: &[(String, String)]) {
+        let name: String = self.required(attrs, "PlanetNode", "name");
+        let true_anomaly = self.optional::<f64>(attrs, "PlanetNode", "trueAnomaly");
+        let top = IssueLocation::default();
+        if let Some(save) = &mut self.save
+            && !save.planets.insert(name.clone())
+        {
+            self.summary
+                .report
+                .push(top, IssueKind::DuplicatePlanet { name: name.clone() });
+        }
+        if let Some(true_anomaly) = true_anomaly {
+            check_finite(
+                &mut self.summary.report,
+                top,
+                None,
+                &[("trueAnomaly", true_anomaly)],
+            );
+        }
+        self.summary.nodes.push(NodeSummary::Planet { name });
+    }
+
+    fn open_ship_node(&mut self, attrs: &[(String, String)]) {
+        let id: i64 = self.required(attrs, "ShipNode", "id");
+        let planet: String = self.required(attrs, "ShipNode", "planet");
+        let mut values = Vec::new();
+        for key in ["planetRadius", "x", "y", "vx", "vy"] {
+            values.push((key, self.required::<f64>(attrs, "ShipNode", key)));
+        }
+        if let Some(save) = &mut self.save {
+            if !save.node_ids.insert(id) {
+                self.summary.report.push(
+                    IssueLocation::default(),
+                    IssueKind::DuplicateNodeId { node: id },
+                );
+            }
+            save.ships.push(ShipNodeScan {
+                id,
+                planet: planet.clone(),
+                pods: HashSet::new(),
+            });
+        }
+        let location = IssueLocation {
+            node: Some(id),
+            group: None,
+        };
+        check_finite(&mut self.summary.report, location, None, &values);
+        self.summary.nodes.push(NodeSummary::Ship {
+            id,
+            planet,
+            part_count: 0,
+        });
+        self.node = Some(id);
+    }
+
+    /// 一条船扫完了, 跟 [`crate::xml_part::verify::validate_ship`] 一样查一遍 (不查几何)
+    fn finish_ship(&mut self, mut ship: ShipScan) {
+        let Some(catalog) = self.catalog else {
+            return;
+        };
+        // 老格式的分级挂到指令舱零件上, 见 `convert`
+        let main = &mut ship.groups[0];
+        if ship.legacy
+            && main.pods.is_empty()
+            && let Some(part) = main
+                .parts
+                .iter()
+                .find(|part| part.part_type.starts_with(LEGACY_POD_PREFIX))
+        {
+            main.pods
+                .push((part.id, std::mem::take(&mut ship.legacy_steps)));
+        }
+
+        let report = &mut self.summary.report;
+        let node = ship.node;
+        let location = |index: usize| IssueLocation {
+            node,
+            group: index.checked_sub(1),
+        };
+        let mut part_ids = HashSet::new();
+        for (index, group) in ship.groups.iter().enumerate() {
+            for part in &group.parts {
+                if !part_ids.insert(part.id) {
+                    report.push(
+                        location(index),
+                        IssueKind::DuplicatePartId { part: part.id },
+                    );
+                }
+                check_finite(
+                    report,
+                    location(index),
+                    Some(part.id),
+                    &[
+                        ("x", part.x),
+                        ("y", part.y),
+                        ("angle", part.angle),
+                        ("angleV", part.angle_v),
+                    ],
+                );
+                if !catalog.contains(&part.part_type) {
+                    report.push(
+                        location(index),
+                        IssueKind::UnknownPartType {
+                            part: part.id,
+                            part_type: part.part_type.clone(),
+                        },
+                    );
+                }
+            }
+        }
+
+        let part_types: HashMap<i64, &str> = ship
+            .groups
+            .iter()
+            .flat_map(|group| &group.parts)
+            .map(|part| (part.id, part.part_type.as_str()))
+            .collect();
+        for (index, group) in ship.groups.iter().enumerate() {
+            check_connections(report, location(index), &group.connections, &part_ids);
+            check_attach_points(
+                report,
+                location(index),
+                &group.connections,
+                &part_types,
+                catalog,
+            );
+            let edges = group
+                .connections
+                .iter()
+                .filter_map(|connection| match *connection {
+                    Connection::Normal {
+                        parent_part,
+                        child_part,
+                        ..
+                    } => Some((parent_part, child_part)),
+                    Connection::Dock { .. } => None,
+                });
+            if let Some(parts) = find_directed_cycle(edges) {
+                report.push(location(index), IssueKind::Cycle { parts });
+            }
+            for (pod, steps) in &group.pods {
+                for (stage, step) in steps.iter().enumerate() {
+                    for id in step {
+                        if !part_ids.contains(id) {
+                            report.push(
+                                location(index),
+                                IssueKind::UnknownActivation {
+                                    pod: *pod,
+                                    stage,
+                                    part: *id,
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        let main = &ship.groups[0];
+        let disconnected = &ship.groups[1..];
+        self.summary.ships.push(ShipSummary {
+            node,
+            part_count: main.parts.len(),
+            connection_count: main.connections.len(),
+            disconnected_groups: disconnected.len(),
+            disconnected_parts: disconnected.iter().map(|group| group.parts.len()).sum(),
+            pod_count: main.pods.len(),
+            stage_count: main.pods.first().map_or(0, |(_, steps)| steps.len()),
+        });
+        if let Some(node) = node {
+            if let Some(NodeSummary::Ship { id, part_count, .. }) = self.summary.nodes.last_mut()
+                && *id == node
+            {
+                *part_count = main.parts.len();
+            }
+            if let Some(save) = &mut self.save
+                && let Some(scan) = save.ships.last_mut()
+            {
+                scan.pods = main.pods.iter().map(|(pod, _)| *pod).collect();
+            }
+        }
+    }
+
+    /// 存档扫完了, 跟 [`crate::xml_part::verify::validate_save`] 一样查存档本身的引用
+    fn finish_save(&mut self, save: SaveScan) {
+        if self.catalog.is_none() {
+            return;
+        }
+        let report = &mut self.summary.report;
+        let top = IssueLocation::default();
+        if save.planets.is_empty() || save.solar_system.is_empty() {
+            report.push(top, IssueKind::EmptySolarSystem);
+        }
+        match save.ships.iter().find(|node| node.id == save.ship_id) {
+            None => report.push(
+                top,
+                IssueKind::MissingShipNode {
+                    ship_id: save.ship_id,
+                },
+            ),
+            Some(node) if !node.pods.contains(&save.pod_id) => report.push(
+                top,
+                IssueKind::MissingPodPart {
+                    ship_id: save.ship_id,
+                    pod_id: save.pod_id,
+                },
+            ),
+            Some(_) => {}
+        }
+        for node in &save.ships {
+            if !save.planets.is_empty() && !save.planets.contains(&node.planet) {
+                report.push(
+                    IssueLocation {
+                        node: Some(node.id),
+                        group: None,
+                    },
+                    IssueKind::UnknownPlanet {
+                        name: node.planet.clone(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// 扫一遍 `data`, 不管是船还是存档还是别的什么东西都不会失败
+pub fn scan(data: &str, catalog: &PartCatalog) -> StreamSummary {
+    let mut scanner = Scanner::new(data, Some(catalog));
+    run(&mut scanner, |_| false);
+    scanner.summary
+}
+
+/// serde 解析 `data` 失败了, 找 `error` 出在哪
+///
+/// 只看节点和属性能不能解析, 不查零件表也不做校验, 找到就停
+pub fn locate_error(data: &str, error: &quick_xml::DeError) -> Option<ErrorPosition> {
+    let mut scanner = Scanner::new(data, None);
+    // xml 写错了的话前面的节点可能已经对不上了, 要一直找到 xml 出错的地方
+    let invalid_xml = matches!(error, quick_xml::DeError::InvalidXml(_));
+    run(&mut scanner, |summary| {
+        !invalid_xml && summary.schema_error.is_some()
+    });
+    let summary = scanner.summary;
+    if invalid_xml {
+        summary.xml_error_at.or(summary.schema_error_at)
+    } else {
+        summary.schema_error_at.or(summary.xml_error_at)
+    }
+}
+
+/// 在 `Reader` 的事件上跑 `scanner`, 碰到不合法的 xml 或者 `stop` 返回 true 就停
+fn run(scanner: &mut Scanner, stop: impl Fn(&StreamSummary) -> bool) {
+    let data = scanner.data;
+    let mut reader = Reader::from_str(data);
+    reader.config_mut().trim_text(true);
+    while !stop(&scanner.summary) {
+        // 前面的空白被 trim 掉了, 往后找 `<`
+        let before = reader.buffer_position() as usize;
+        let tag_offset = || before + data[before..].find('<').unwrap_or(0);
+        match reader.read_event() {
+            Ok(Event::Start(start)) => scanner.open(&start, false, tag_offset()),
+            Ok(Event::Empty(start)) => scanner.open(&start, true, tag_offset()),
+            Ok(Event::End(_)) => scanner.close(),
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(err) => {
+                let path = scanner
+                    .stack
+                    .last()
+                    .map(|frame| frame.path.clone())
+                    .unwrap_or_default();
+                let offset = reader.error_position() as usize;
+                scanner.summary.xml_error = Some(err.to_string());
+                scanner.summary.xml_error_at = Some(ErrorPosition::new(data, offset, path));
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NodeSummary, scan};
+    use crate::xml_part::{
+        catalog::PartCatalog, extra::XmlElement, parse::parse_any_xml, verify::validate_document,
+    };
+
+    const SAMPLE_SAVE: &str = include_str!("../save_1294489.xml");
+
+    /// 跟先建 model 再校验的结果对一下
+    fn same_as_model(data: &str) {
+        let catalog = PartCatalog::embedded();
+        let summary = scan(data, catalog);
+        match parse_any_xml(data) {
+            Ok(doc) => {
+                assert!(summary.is_parsable(), "{data}: {:?}", summary.schema_error);
+                let model = validate_document(&doc, catalog);
+                let errors = |report: &crate::xml_part::verify::ValidationReport| {
+                    let mut errors: Vec<String> =
+                        report.errors().map(|issue| format!("{issue:?}")).collect();
+                    errors.sort();
+                    errors
+                };
+                assert_eq!(errors(&summary.report), errors(&model), "{data}");
+            }
+            Err(_) => assert!(!summary.is_parsable(), "{data}"),
+        }
+    }
+
+    #[test]
+    fn summarizes_save() {
+        let summary = scan(SAMPLE_SAVE, PartCatalog::embedded());
+        assert!(summary.is_save_shape() && summary.is_valid());
+        assert!(!summary.is_ship_shape());
+        assert_eq!(summary.nodes.len(), 17);
+        let doc = parse_any_xml(SAMPLE_SAVE).unwrap();
+        let ship = doc.main_ship().unwrap();
+        assert!(summary.nodes.contains(&NodeSummary::Ship {
+            id: 1,
+            planet: "Smearth".to_string(),
+            part_count: ship.parts.len(),
+        }));
+        assert_eq!(summary.ships.len(), 1);
+        assert_eq!(summary.ships[0].part_count, ship.parts.len());
+        assert_eq!(summary.ships[0].stage_count, 5);
+
+        let empty = scan(crate::net::EMPTY_SHIP, PartCatalog::embedded());
+        assert!(empty.is_ship_shape() && empty.shape.disconnected && empty.is_valid());
+        assert_eq!(empty.ships[0].pod_count, 1);
+
+        assert!(!scan("<Ship", PartCatalog::embedded()).is_xml());
+        assert_eq!(scan("", PartCatalog::embedded()).root, None);
+    }
+
+    #[test]
+    fn matches_model_validation() {
+        let empty = crate::net::EMPTY_SHIP;
+        let cases = [
+            SAMPLE_SAVE.to_string(),
+            empty.to_string(),
+            // 少了必须的属性 / 数字不对 / 不认识的连接
+            empty.replace(r#" x="0.000000""#, ""),
+            empty.replace(r#"id="1""#, r#"id="one""#),
+            empty.replace("<Connections/>", "<Connections><Link/></Connections>"),
+            empty.replace("<Staging currentStage=\"0\"/>", ""),
+            // 不认识的东西不影响
+            empty.replace("<Parts>", "<Parts><Note/>"),
+            empty.replace(
+                "<Connections/>",
+                "<Connections/><Extra><Parts><Part/></Parts></Extra>",
+            ),
+            // 校验出来的问题
+            empty.replace(
+                "<Connections/>",
+                r#"<Connections><Connection parentAttachPoint="9" childAttachPoint="1" parentPart="1" childPart="1"/><Connection parentAttachPoint="9" childAttachPoint="1" parentPart="1" childPart="1"/></Connections>"#,
+            ),
+            empty.replace(
+                "<Staging currentStage=\"0\"/>",
+                r#"<Staging currentStage="0"><Step><Activate Id="7" moved="0"/></Step></Staging>"#,
+            ),
+            empty.replace(r#"x="0.000000""#, r#"x="NaN""#),
+            SAMPLE_SAVE.replace(r#"shipId="1""#, r#"shipId="2""#),
+            SAMPLE_SAVE.replace(r#"podId="1""#, r#"podId="1199368""#),
+            SAMPLE_SAVE.replace(r#"planet="Smearth""#, r#"planet="Nowhere""#),
+            SAMPLE_SAVE.replace(r#"<PlanetNode name="Sun"/>"#, r#"<PlanetNode name="Smars"/>"#),
+            SAMPLE_SAVE.replace(r#"childPart="1199386""#, r#"childPart="1""#),
+            // 老格式
+            r#"<Ship currentStage="0" throttle="0.000000" liftedOff="0"><Parts><Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0"/></Parts><Connections/><Staging><Step><Activate Id="5" moved="0"/></Step></Staging></Ship>"#.to_string(),
+        ];
+        for case in &cases {
+            same_as_model(case);
+        }
+        let summaries: Vec<_> = cases
+            .iter()
+            .map(|case| scan(case, PartCatalog::embedded()))
+            .collect();
+        let unparsable = summaries.iter().filter(|s| !s.is_parsable()).count();
+        let invalid = summaries
+            .iter()
+            .filter(|s| s.is_parsable() && !s.is_valid())
+            .count();
+        assert_eq!((unparsable, invalid), (4, 9));
+    }
+
+    /// 用得到的节点都有一遍: 对接、散落的零件、老格式的分级
+    const EVERY_NODE_SHIP: &str = r#"<Ship version="1" liftedOff="0" touchingGround="1">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0" flippedX="0" chuteX="0">
+      <Pod throttle="1" name="a"><Staging currentStage="0"><Step><Activate Id="2" moved="1"/></Step></Staging></Pod>
+    </Part>
+    <Part partType="engine-4" id="2" x="0" y="-3" angle="0" angleV="0"><Tank fuel="1"/><Engine fuel="0"/></Part>
+    <Part partType="port-1" id="3" x="2" y="0" angle="0" angleV="0"/>
+  </Parts>
+  <Connections>
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2"/>
+    <DockConnection dockPart="3" parentPart="1" childPart="2"/>
+  </Connections>
+  <DisconnectedParts>
+    <DisconnectedPart>
+      <Parts><Part partType="fuselage-1" id="4" x="9" y="9" angle="0" angleV="0"/></Parts>
+      <Connections/>
+    </DisconnectedPart>
+  </DisconnectedParts>
+</Ship>"#;
+
+    const LEGACY_SHIP: &str = r#"<Ship currentStage="0" throttle="0" liftedOff="0">
+  <Parts><Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0"/></Parts>
+  <Connections/>
+  <Staging><Step><Activate Id="1" moved="1"/></Step></Staging>
+</Ship>"#;
+
+    /// 所有节点的下标路径
+    fn element_paths(element: &XmlElement, path: &mut Vec<usize>, paths: &mut Vec<Vec<usize>>) {
+        paths.push(path.clone());
+        for (index, child) in element.children.iter().enumerate() {
+            path.push(index);
+            element_paths(child, path, paths);
+            path.pop();
+        }
+    }
+
+    fn element_at<'a>(root: &'a mut XmlElement, path: &[usize]) -> &'a mut XmlElement {
+        path.iter()
+            .fold(root, |element, index| &mut element.children[*index])
+    }
+
+    /// 必须有的属性 (`Scanner::element`) 和子节点 ([`crate::xml_part::extra::KNOWN`]) 是照着 raw 层另外写的,
+    /// 这里把每个属性、每个子节点挨个删掉, 每个子节点挨个多复制一份, 会不会解析失败要跟 serde 一样
+    #[test]
+    fn schema_matches_serde() {
+        let catalog = PartCatalog::embedded();
+        for data in [
+            SAMPLE_SAVE,
+            crate::net::EMPTY_SHIP,
+            EVERY_NODE_SHIP,
+            LEGACY_SHIP,
+        ] {
+            let root = XmlElement::parse(data).unwrap().unwrap();
+            let mut paths = Vec::new();
+            element_paths(&root, &mut Vec::new(), &mut paths);
+            for path in paths {
+                let element = element_at(&mut root.clone(), &path).clone();
+                let mut mutated = Vec::new();
+                let mut mutate = |name: String, change: &dyn Fn(&mut XmlElement)| {
+                    let mut tree = root.clone();
+                    change(element_at(&mut tree, &path));
+                    mutated.push((name, tree));
+                };
+                for (index, (key, _)) in element.attrs.iter().enumerate() {
+                    let name = format!("删掉 <{}> 的 {key}", element.name);
+                    mutate(name, &|e| drop(e.attrs.remove(index)));
+                }
+                for (index, child) in element.children.iter().enumerate() {
+                    let name = format!("删掉 <{}> 下面的 <{}>", element.name, child.name);
+                    mutate(name, &|e| drop(e.children.remove(index)));
+                    let name = format!("复制 <{}> 下面的 <{}>", element.name, child.name);
+                    mutate(name, &|e| {
+                        e.children.insert(index, e.children[index].clone())
+                    });
+                }
+                for (name, tree) in mutated {
+                    let xml = tree.to_xml().unwrap();
+                    assert_eq!(
+                        scan(&xml, catalog).is_parsable(),
+                        parse_any_xml(&xml).is_ok(),
+                        "{name}:\n{xml}"
+                    );
+                }
+            }
+        }
+    }
+}
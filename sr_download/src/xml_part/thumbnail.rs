@@ -0,0 +1,296 @@
+//! 船的 PNG 缩略图
+//!
+//! 用 tiny-skia 在 CPU 上画, 形状和颜色都和 [`crate::xml_part::render`] 的 SVG 一样
+//! 缩略图都是正方形, 整条船缩放到正好放进去, 居中
+//! 存档画的是玩家当前那条船
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Stroke, Transform};
+
+use crate::xml_part::{
+    XmlError, XmlResult,
+    catalog::{PartCatalog, PartKind},
+    geometry::{Bounds, Point, ShipGeometry},
+    model::{Part, ShipData, XmlDocument},
+    render::{BACKGROUND, MISSING_COLOR, OUTLINE, part_color},
+};
+
+/// 四周留白占边长的比例
+const MARGIN: f64 = 0.05;
+/// 找不到零件类型的零件画成的点的半径, 像素
+const MISSING_RADIUS: f32 = 2.0;
+
+/// 缩略图只有这几种尺寸
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbSize {
+    /// 128 x 128
+    Small,
+    /// 256 x 256
+    #[default]
+    Medium,
+    /// 512 x 512
+    Large,
+}
+
+impl ThumbSize {
+    pub const ALL: [Self; 3] = [Self::Small, Self::Medium, Self::Large];
+
+    pub fn pixels(self) -> u32 {
+        match self {
+            Self::Small => 128,
+            Self::Medium => 256,
+            Self::Large => 512,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Small => "small",
+            Self::Medium => "medium",
+            Self::Large => "large",
+        }
+    }
+}
+
+impl std::fmt::Display for ThumbSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 画一条船, 返回 PNG 数据
+pub fn render_thumbnail(
+    ship: &ShipData,
+    catalog: &PartCatalog,
+    size: ThumbSize,
+) -> XmlResult<Vec<u8>> {
+    let mut pixmap = blank(size)?;
+    let pixels = size.pixels();
+    let geometry = ShipGeometry::new(ship, catalog);
+    let parts: Vec<&Part> = ship
+        .parts
+        .iter()
+        .chain(ship.disconnected.iter().flat_map(|group| &group.parts))
+        .collect();
+    let exploded: HashSet<i64> = parts
+        .iter()
+        .filter(|part| part.exploded)
+        .map(|part| part.id)
+        .collect();
+    let markers: Vec<Point> = parts
+        .iter()
+        .filter(|part| geometry.missing.contains(&part.id))
+        .filter(|part| part.x.is_finite() && part.y.is_finite())
+        .map(|part| Point::new(part.x, part.y))
+        .collect();
+    let bounds = Bounds::from_points(
+        geometry
+            .bounds
+            .iter()
+            .flat_map(|bounds| {
+                [
+                    Point::new(bounds.min_x, bounds.min_y),
+                    Point::new(bounds.max_x, bounds.max_y),
+                ]
+            })
+            .chain(markers.iter().copied()),
+    );
+    if let Some(bounds) = bounds {
+        let frame = Frame::fit(bounds, pixels as f64);
+        let outline = Stroke {
+            width: (frame.scale * 0.05).clamp(0.25, 1.0) as f32,
+            ..Default::default()
+        };
+        let mut outline_paint = Paint::default();
+        outline_paint.set_color(parse_color(OUTLINE));
+        outline_paint.anti_alias = true;
+
+        for shape in &geometry.shapes {
+            let kind = catalog
+                .get(&shape.part_type)
+                .map(|part_type| &part_type.kind)
+                .unwrap_or(&PartKind::Unknown);
+            let mut color = parse_color(part_color(kind));
+            if exploded.contains(&shape.part) {
+                color.set_alpha(0.4);
+            }
+            let mut paint = Paint::default();
+            paint.set_color(color);
+            paint.anti_alias = true;
+            for polygon in &shape.polygons {
+                let mut builder = PathBuilder::new();
+                for (index, point) in polygon.iter().enumerate() {
+                    let (x, y) = frame.map(*point);
+                    if index == 0 {
+                        builder.move_to(x, y);
+                    } else {
+                        builder.line_to(x, y);
+                    }
+                }
+                builder.close();
+                let Some(path) = builder.finish() else {
+                    continue;
+                };
+                pixmap.fill_path(
+                    &path,
+                    &paint,
+                    FillRule::Winding,
+                    Transform::identity(),
+                    None,
+                );
+                pixmap.stroke_path(&path, &outline_paint, &outline, Transform::identity(), None);
+            }
+        }
+
+        let mut paint = Paint::default();
+        paint.set_color(parse_color(MISSING_COLOR));
+        paint.anti_alias = true;
+        for marker in markers {
+            let (x, y) = frame.map(marker);
+            if let Some(path) = PathBuilder::from_circle(x, y, MISSING_RADIUS) {
+                pixmap.fill_path(
+                    &path,
+                    &paint,
+                    FillRule::Winding,
+                    Transform::identity(),
+                    None,
+                );
+            }
+        }
+    }
+
+    encode(&pixmap)
+}
+
+/// 船是它自己, 存档是玩家当前那条船, 一条船都没有的话是一张空图
+pub fn render_document_thumbnail(
+    doc: &XmlDocument,
+    catalog: &PartCatalog,
+    size: ThumbSize,
+) -> XmlResult<Vec<u8>> {
+    match doc.main_ship() {
+        Some(ship) => render_thumbnail(ship, catalog, size),
+        None => encode(&blank(size)?),
+    }
+}
+
+/// 只有背景的图
+fn blank(size: ThumbSize) -> XmlResult<Pixmap> {
+    let pixels = size.pixels();
+    let mut pixmap = Pixmap::new(pixels, pixels)
+        .ok_or_else(|| XmlError::Serialize(format!("bad thumbnail size {pixels}")))?;
+    pixmap.fill(parse_color(BACKGROUND));
+    Ok(pixmap)
+}
+
+fn encode(pixmap: &Pixmap) -> XmlResult<Vec<u8>> {
+    pixmap
+        .encode_png()
+        .map_err(|err| XmlError::Serialize(err.to_string()))
+}
+
+/// 世界坐标 -> 图片坐标, 居中
+struct Frame {
+    scale: f64,
+    center: Point,
+    half: f64,
+}
+
+impl Frame {
+    fn fit(bounds: Bounds, pixels: f64) -> Self {
+        let usable = pixels * (1.0 - MARGIN * 2.0);
+        let longest = bounds.width().max(bounds.height());
+        // 只有一个点的时候随便给个比例
+        let scale = if longest > f64::EPSILON {
+            usable / longest
+        } else {
+            1.0
+        };
+        Self {
+            scale,
+            center: bounds.center(),
+            half: pixels / 2.0,
+        }
+    }
+
+    fn map(&self, point: Point) -> (f32, f32) {
+        (
+            (self.half + (point.x - self.center.x) * self.scale) as f32,
+            (self.half - (point.y - self.center.y) * self.scale) as f32,
+        )
+    }
+}
+
+/// `#rrggbb`
+fn parse_color(hex: &str) -> Color {
+    let hex = hex.trim_start_matches('#');
+    let channel = |index: usize| {
+        hex.get(index..index + 2)
+            .and_then(|text| u8::from_str_radix(text, 16).ok())
+            .unwrap_or(0)
+    };
+    Color::from_rgba8(channel(0), channel(2), channel(4), 255)
+}
+
+impl ShipData {
+    pub fn thumbnail(&self, catalog: &PartCatalog, size: ThumbSize) -> XmlResult<Vec<u8>> {
+        render_thumbnail(self, catalog, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tiny_skia::Pixmap;
+
+    use super::{ThumbSize, parse_color, render_document_thumbnail, render_thumbnail};
+    use crate::xml_part::{
+        catalog::{PartCatalog, PartKind},
+        parse::{parse_any_xml, parse_ship_xml},
+        render::{BACKGROUND, part_color},
+    };
+
+    fn pixel(pixmap: &Pixmap, x: u32, y: u32) -> (u8, u8, u8) {
+        let color = pixmap.pixel(x, y).unwrap().demultiply();
+        (color.red(), color.green(), color.blue())
+    }
+
+    fn rgb(hex: &str) -> (u8, u8, u8) {
+        let color = parse_color(hex).to_color_u8();
+        (color.red(), color.green(), color.blue())
+    }
+
+    #[test]
+    fn renders_fitted_png() {
+        let ship = parse_ship_xml(crate::net::EMPTY_SHIP).unwrap().ship;
+        for size in ThumbSize::ALL {
+            let png = render_thumbnail(&ship, PartCatalog::embedded(), size).unwrap();
+            assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+            let pixmap = Pixmap::decode_png(&png).unwrap();
+            assert_eq!(pixmap.width(), size.pixels());
+            assert_eq!(pixmap.height(), size.pixels());
+
+            // 指令舱放大到占满宽度, 中间是指令舱的颜色, 角上是背景
+            let half = size.pixels() / 2;
+            assert_eq!(pixel(&pixmap, half, half), rgb(part_color(&PartKind::Pod)));
+            assert_eq!(pixel(&pixmap, 0, 0), rgb(BACKGROUND));
+            assert_eq!(pixel(&pixmap, half, 2), rgb(BACKGROUND));
+        }
+    }
+
+    #[test]
+    fn renders_save_main_ship() {
+        let doc = parse_any_xml(include_str!("../save_1294489.xml")).unwrap();
+        let png =
+            render_document_thumbnail(&doc, PartCatalog::embedded(), ThumbSize::Small).unwrap();
+        let pixmap = Pixmap::decode_png(&png).unwrap();
+        let background = rgb(BACKGROUND);
+        let drawn = (0..pixmap.width())
+            .flat_map(|x| (0..pixmap.height()).map(move |y| (x, y)))
+            .filter(|(x, y)| pixel(&pixmap, *x, *y) != background)
+            .count();
+        assert!(drawn > 100, "{drawn}");
+    }
+}
@@ -1 +1,823 @@
-pub use crate::db_part::utils::{ShipVerifyState, verify_ship, verify_xml};
+//! 船 / 存档的结构校验
+//!
+//! 以前 `verify_ship` 只给一个 `ShipVerifyState`, 船坏了也不知道坏在哪
+//! 这里在解析好的 `model` 上跑一遍, 把每个问题连同涉及的 id 一条条列出来
+//!
+//! `Severity::Error` 的问题就是以前会被判成 `BrokenShip` 的那些, 存档有的话就是 `BrokenSave`
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+pub use crate::db_part::utils::{
+    SaveVerifyState, ShipVerifyState, verify_save, verify_ship, verify_xml,
+};
+use crate::xml_part::{
+    catalog::PartCatalog,
+    geometry::{self, ShipGeometry},
+    graph::ShipGraph,
+    model::{
+        Connection, DisconnectedGroup, Part, SaveDocument, SaveNode, ShipData, ShipNode,
+        XmlDocument,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// 游戏里读不出来 / 会出错的
+    Error,
+    /// 能读, 但是不太对劲
+    Warning,
+}
+
+/// 问题出在哪
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct IssueLocation {
+    /// 存档里的 `ShipNode` id, 船文件是 None
+    pub node: Option<i64>,
+    /// `DisconnectedParts` 里的第几组, 主船体是 None
+    pub group: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IssueKind {
+    /// 同一个 id 出现了不止一次
+    DuplicatePartId { part: i64 },
+    /// 连接引用了不存在的零件, `missing` 是找不到的那些 id
+    DanglingConnection {
+        parent: i64,
+        child: i64,
+        dock: Option<i64>,
+        missing: Vec<i64>,
+    },
+    /// 自己连自己 (对接的话是对接口 / parent / child 里有重复的)
+    SelfConnection {
+        parent: i64,
+        child: i64,
+        dock: Option<i64>,
+    },
+    /// 完全一样的连接写了两遍
+    DuplicateConnection {
+        parent: i64,
+        child: i64,
+        dock: Option<i64>,
+    },
+    /// parent -> child 绕了一圈, `parts` 按连接方向排
+    Cycle { parts: Vec<i64> },
+    /// 分级里激活了不存在的零件
+    UnknownActivation { pod: i64, stage: usize, part: i64 },
+    /// 零件目录里没有这种零件
+    UnknownPartType { part: i64, part_type: String },
+    /// 坐标 / 速度 / 角度之类的是 NaN 或者无穷, `part` 是 None 的时候说的是节点或者存档本身
+    NonFiniteValue { part: Option<i64>, field: String },
+    /// 存档的 `shipId` 找不到对应的 `ShipNode`
+    MissingShipNode { ship_id: i64 },
+    /// 存档的 `podId` 不是玩家那条船上带 `Pod` 的零件
+    MissingPodPart { ship_id: i64, pod_id: i64 },
+    /// 两个 `ShipNode` 用了同一个 id
+    DuplicateNodeId { node: i64 },
+    /// 同一个星球写了两遍
+    DuplicatePlanet { name: String },
+    /// `ShipNode.planet` 不在存档的 `PlanetNode` 里
+    UnknownPlanet { name: String },
+    /// 存档里一个星球都没有, 或者没写 `solarSystem`
+    EmptySolarSystem,
+    /// 连接点下标超出了零件类型声明的范围 (下标从 1 开始, `count` 是一共有几个)
+    AttachPointOutOfRange {
+        part: i64,
+        part_type: String,
+        index: i32,
+        count: usize,
+    },
+    /// 零件类型一个连接点都没有, 却出现在了连接里
+    NoAttachPoints { part: i64, part_type: String },
+    /// 只能挂一个零件的连接点上挂了好几个
+    AttachPointOccupied {
+        part: i64,
+        index: i32,
+        children: Vec<i64>,
+    },
+    /// 两个零件的碰撞形状挤在一起了, `part` 的 id 比 `other` 小
+    PartsOverlap { part: i64, other: i64 },
+    /// 零件离船的主体太远, 见 [`geometry::MAX_PART_GAP`]
+    FarFromAssembly { part: i64 },
+}
+
+impl IssueKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::UnknownPartType { .. }
+            | Self::PartsOverlap { .. }
+            | Self::FarFromAssembly { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    #[serde(flatten)]
+    pub location: IssueLocation,
+    #[serde(flatten)]
+    pub kind: IssueKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn push(&mut self, location: IssueLocation, kind: IssueKind) {
+        self.issues.push(ValidationIssue {
+            severity: kind.severity(),
+            location,
+            kind,
+        });
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Warning)
+    }
+
+    /// 没有 `Severity::Error` 的问题
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn extend(&mut self, other: ValidationReport) {
+        self.issues.extend(other.issues);
+    }
+}
+
+/// 校验一条船, 主船体和 `DisconnectedParts` 里的零件共用一套 id
+pub fn validate_ship(ship: &ShipData, catalog: &PartCatalog) -> ValidationReport {
+    validate_ship_at(ship, catalog, None)
+}
+
+fn validate_ship_at(ship: &ShipData, catalog: &PartCatalog, node: Option<i64>) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let main = IssueLocation { node, group: None };
+    let groups: Vec<(IssueLocation, &[Part], &[Connection])> =
+        std::iter::once((main, ship.parts.as_slice(), ship.connections.as_slice()))
+            .chain(ship.disconnected.iter().enumerate().map(
+                |(index, DisconnectedGroup { parts, connections })| {
+                    (
+                        IssueLocation {
+                            node,
+                            group: Some(index),
+                        },
+                        parts.as_slice(),
+                        connections.as_slice(),
+                    )
+                },
+            ))
+            .collect();
+
+    let mut part_ids = HashSet::new();
+    for (location, parts, _) in &groups {
+        for part in *parts {
+            if !part_ids.insert(part.id) {
+                report.push(*location, IssueKind::DuplicatePartId { part: part.id });
+            }
+            check_part_finite(&mut report, *location, part);
+            if !catalog.contains(&part.part_type_id) {
+                report.push(
+                    *location,
+                    IssueKind::UnknownPartType {
+                        part: part.id,
+                        part_type: part.part_type_id.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    let all_parts: HashMap<i64, &str> = groups
+        .iter()
+        .flat_map(|(_, parts, _)| parts.iter())
+        .map(|part| (part.id, part.part_type_id.as_str()))
+        .collect();
+    for (location, parts, connections) in &groups {
+        check_connections(&mut report, *location, connections, &part_ids);
+        check_attach_points(&mut report, *location, connections, &all_parts, catalog);
+        if let Some(parts) = ShipGraph::from_parts(parts, connections).find_cycle() {
+            report.push(*location, IssueKind::Cycle { parts });
+        }
+        check_activations(&mut report, *location, parts, &part_ids);
+    }
+    // `DisconnectedParts` 本来就是散落的零件, 只看主船体
+    check_geometry(&mut report, main, &ship.parts, catalog);
+    report
+}
+
+fn check_geometry(
+    report: &mut ValidationReport,
+    location: IssueLocation,
+    parts: &[Part],
+    catalog: &PartCatalog,
+) {
+    let geometry = ShipGeometry::from_parts(parts, catalog);
+    for overlap in geometry.overlaps() {
+        report.push(
+            location,
+            IssueKind::PartsOverlap {
+                part: overlap.a,
+                other: overlap.b,
+            },
+        );
+    }
+    for far in geometry.far_parts(geometry::MAX_PART_GAP) {
+        report.push(location, IssueKind::FarFromAssembly { part: far.part });
+    }
+}
+
+pub(super) fn check_connections(
+    report: &mut ValidationReport,
+    location: IssueLocation,
+    connections: &[Connection],
+    part_ids: &HashSet<i64>,
+) {
+    let mut seen = HashSet::new();
+    for connection in connections {
+        let (parent, child, dock) = match *connection {
+            Connection::Normal {
+                parent_part,
+                child_part,
+                ..
+            } => (parent_part, child_part, None),
+            Connection::Dock {
+                dock_part,
+                parent_part,
+                child_part,
+            } => (parent_part, child_part, Some(dock_part)),
+        };
+
+        let mut missing: Vec<i64> = dock
+            .into_iter()
+            .chain([parent, child])
+            .filter(|id| !part_ids.contains(id))
+            .collect();
+        missing.dedup();
+        if !missing.is_empty() {
+            report.push(
+                location,
+                IssueKind::DanglingConnection {
+                    parent,
+                    child,
+                    dock,
+                    missing,
+                },
+            );
+        }
+        if parent == child || dock.is_some_and(|dock| dock == parent || dock == child) {
+            report.push(
+                location,
+                IssueKind::SelfConnection {
+                    parent,
+                    child,
+                    dock,
+                },
+            );
+        }
+        let key = match *connection {
+            Connection::Normal {
+                parent_attach_point,
+                child_attach_point,
+                ..
+            } => (parent, child, dock, parent_attach_point, child_attach_point),
+            Connection::Dock { .. } => (parent, child, dock, 0, 0),
+        };
+        if !seen.insert(key) {
+            report.push(
+                location,
+                IssueKind::DuplicateConnection {
+                    parent,
+                    child,
+                    dock,
+                },
+            );
+        }
+    }
+}
+
+/// 按零件目录检查普通连接的连接点, 目录里没有的零件类型跳过 (已经报过 `UnknownPartType` 了)
+///
+/// `parts` 是零件 id -> 零件类型
+pub(super) fn check_attach_points(
+    report: &mut ValidationReport,
+    location: IssueLocation,
+    connections: &[Connection],
+    parts: &HashMap<i64, &str>,
+    catalog: &PartCatalog,
+) {
+    let mut occupied: BTreeMap<(i64, i32), Vec<i64>> = BTreeMap::new();
+    let mut no_points = Vec::new();
+    for connection in connections {
+        let Connection::Normal {
+            parent_attach_point,
+            child_attach_point,
+            parent_part,
+            child_part,
+        } = *connection
+        else {
+            continue;
+        };
+        for (id, index) in [
+            (parent_part, parent_attach_point),
+            (child_part, child_attach_point),
+        ] {
+            let Some(&type_id) = parts.get(&id) else {
+                continue;
+            };
+            let Some(part_type) = catalog.get(type_id) else {
+                continue;
+            };
+            if part_type.attach_points.is_empty() {
+                if !no_points.contains(&id) {
+                    no_points.push(id);
+                    report.push(
+                        location,
+                        IssueKind::NoAttachPoints {
+                            part: id,
+                            part_type: type_id.to_string(),
+                        },
+                    );
+                }
+                continue;
+            }
+            let Some(point) = part_type.connection_attach_point(index) else {
+                report.push(
+                    location,
+                    IssueKind::AttachPointOutOfRange {
+                        part: id,
+                        part_type: type_id.to_string(),
+                        index,
+                        count: part_type.attach_points.len(),
+                    },
+                );
+                continue;
+            };
+            if id == parent_part && point.is_single_use() {
+                // 重复的连接已经报过 `DuplicateConnection` 了, 这里只算一次
+                let children = occupied
+                    .entry((parent_part, parent_attach_point))
+                    .or_default();
+                if !children.contains(&child_part) {
+                    children.push(child_part);
+                }
+            }
+        }
+    }
+    for ((part, index), children) in occupied {
+        if children.len() > 1 {
+            report.push(
+                location,
+                IssueKind::AttachPointOccupied {
+                    part,
+                    index,
+                    children,
+                },
+            );
+        }
+    }
+}
+
+pub(super) fn check_finite(
+    report: &mut ValidationReport,
+    location: IssueLocation,
+    part: Option<i64>,
+    fields: &[(&str, f64)],
+) {
+    for (field, value) in fields {
+        if !value.is_finite() {
+            report.push(
+                location,
+                IssueKind::NonFiniteValue {
+                    part,
+                    field: field.to_string(),
+                },
+            );
+        }
+    }
+}
+
+fn check_part_finite(report: &mut ValidationReport, location: IssueLocation, part: &Part) {
+    check_finite(
+        report,
+        location,
+        Some(part.id),
+        &[
+            ("x", part.x),
+            ("y", part.y),
+            ("angle", part.angle),
+            ("angleV", part.angle_v),
+        ],
+    );
+}
+
+fn check_activations(
+    report: &mut ValidationReport,
+    location: IssueLocation,
+    parts: &[Part],
+    part_ids: &HashSet<i64>,
+) {
+    for part in parts {
+        let Some(pod) = &part.attrs.pod else {
+            continue;
+        };
+        for (stage, step) in pod.steps.iter().enumerate() {
+            for activate in &step.activates {
+                if !part_ids.contains(&activate.id) {
+                    report.push(
+                        location,
+                        IssueKind::UnknownActivation {
+                            pod: part.id,
+                            stage,
+                            part: activate.id,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// 校验存档: 存档本身的引用和数值, 再加上每条船各自的校验
+pub fn validate_save(doc: &SaveDocument, catalog: &PartCatalog) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let top = IssueLocation::default();
+    check_finite(&mut report, top, None, &[("time", doc.time)]);
+
+    let mut planets = HashSet::new();
+    let mut node_ids = HashSet::new();
+    for node in &doc.nodes {
+        match node {
+            SaveNode::Planet(planet) => {
+                if !planets.insert(planet.name.as_str()) {
+                    report.push(
+                        top,
+                        IssueKind::DuplicatePlanet {
+                            name: planet.name.clone(),
+                        },
+                    );
+                }
+                if let Some(true_anomaly) = planet.true_anomaly {
+                    check_finite(&mut report, top, None, &[("trueAnomaly", true_anomaly)]);
+                }
+            }
+            SaveNode::Ship(ship) => {
+                if !node_ids.insert(ship.id) {
+                    report.push(top, IssueKind::DuplicateNodeId { node: ship.id });
+                }
+            }
+        }
+    }
+    if planets.is_empty() || doc.solar_system.is_empty() {
+        report.push(top, IssueKind::EmptySolarSystem);
+    }
+
+    let ships: Vec<&ShipNode> = doc
+        .nodes
+        .iter()
+        .filter_map(|node| match node {
+            SaveNode::Ship(ship) => Some(ship),
+            SaveNode::Planet(_) => None,
+        })
+        .collect();
+    match ships.iter().find(|node| node.id == doc.ship_id) {
+        None => report.push(
+            top,
+            IssueKind::MissingShipNode {
+                ship_id: doc.ship_id,
+            },
+        ),
+        Some(node) => {
+            let has_pod = node
+                .ship
+                .parts
+                .iter()
+                .any(|part| part.id == doc.pod_id && part.attrs.pod.is_some());
+            if !has_pod {
+                report.push(
+                    top,
+                    IssueKind::MissingPodPart {
+                        ship_id: doc.ship_id,
+                        pod_id: doc.pod_id,
+                    },
+                );
+            }
+        }
+    }
+
+    for node in ships {
+        let location = IssueLocation {
+            node: Some(node.id),
+            group: None,
+        };
+        if !planets.is_empty() && !planets.contains(node.planet.as_str()) {
+            report.push(
+                location,
+                IssueKind::UnknownPlanet {
+                    name: node.planet.clone(),
+                },
+            );
+        }
+        check_finite(
+            &mut report,
+            location,
+            None,
+            &[
+                ("planetRadius", node.planet_radius),
+                ("x", node.x),
+                ("y", node.y),
+                ("vx", node.vx),
+                ("vy", node.vy),
+            ],
+        );
+        report.extend(validate_ship_at(&node.ship, catalog, Some(node.id)));
+    }
+    report
+}
+
+/// 校验整个文件
+pub fn validate_document(doc: &XmlDocument, catalog: &PartCatalog) -> ValidationReport {
+    match doc {
+        XmlDocument::Ship(doc) => validate_ship(&doc.ship, catalog),
+        XmlDocument::Save(doc) => validate_save(doc, catalog),
+    }
+}
+
+impl ShipData {
+    pub fn validate(&self, catalog: &PartCatalog) -> ValidationReport {
+        validate_ship(self, catalog)
+    }
+}
+
+impl SaveDocument {
+    pub fn validate(&self, catalog: &PartCatalog) -> ValidationReport {
+        validate_save(self, catalog)
+    }
+}
+
+impl XmlDocument {
+    pub fn validate(&self, catalog: &PartCatalog) -> ValidationReport {
+        validate_document(self, catalog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IssueKind, IssueLocation, Severity, validate_save, validate_ship};
+    use crate::xml_part::{
+        catalog::PartCatalog,
+        parse::{parse_save_xml, parse_ship_xml},
+    };
+
+    const BROKEN: &str = r#"<Ship version="1" liftedOff="0" touchingGround="1">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+      <Pod throttle="1" name="">
+        <Staging currentStage="0"><Step><Activate Id="9" moved="1" /></Step></Staging>
+      </Pod>
+    </Part>
+    <Part partType="fueltank-1" id="2" x="0" y="-3.5" angle="0" angleV="0" />
+    <Part partType="engine-1" id="3" x="0" y="-7.5" angle="0" angleV="0" />
+    <Part partType="warp-drive" id="4" x="9" y="0" angle="0" angleV="0" />
+  </Parts>
+  <Connections>
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="2" childPart="3" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="3" childPart="1" />
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
+    <Connection parentAttachPoint="1" childAttachPoint="1" parentPart="4" childPart="4" />
+    <DockConnection dockPart="5" parentPart="1" childPart="6" />
+  </Connections>
+  <DisconnectedParts>
+    <DisconnectedPart>
+      <Parts><Part partType="fueltank-1" id="2" x="20" y="0" angle="0" angleV="0" /></Parts>
+      <Connections />
+    </DisconnectedPart>
+  </DisconnectedParts>
+</Ship>"#;
+
+    #[test]
+    fn reports_every_issue() {
+        let ship = parse_ship_xml(BROKEN).unwrap().ship;
+        let report = validate_ship(&ship, PartCatalog::embedded());
+        let main = IssueLocation::default();
+        let group = IssueLocation {
+            node: None,
+            group: Some(0),
+        };
+        let found: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.location, issue.kind.clone()))
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                (
+                    main,
+                    IssueKind::UnknownPartType {
+                        part: 4,
+                        part_type: "warp-drive".to_string()
+                    }
+                ),
+                (group, IssueKind::DuplicatePartId { part: 2 }),
+                (
+                    main,
+                    IssueKind::DuplicateConnection {
+                        parent: 1,
+                        child: 2,
+                        dock: None
+                    }
+                ),
+                (
+                    main,
+                    IssueKind::SelfConnection {
+                        parent: 4,
+                        child: 4,
+                        dock: None
+                    }
+                ),
+                (
+                    main,
+                    IssueKind::DanglingConnection {
+                        parent: 1,
+                        child: 6,
+                        dock: Some(5),
+                        missing: vec![5, 6]
+                    }
+                ),
+                (
+                    main,
+                    IssueKind::Cycle {
+                        parts: vec![1, 2, 3]
+                    }
+                ),
+                (
+                    main,
+                    IssueKind::UnknownActivation {
+                        pod: 1,
+                        stage: 0,
+                        part: 9
+                    }
+                ),
+            ]
+        );
+        assert!(!report.is_valid());
+        assert_eq!(report.warnings().count(), 1);
+        assert_eq!(report.issues[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn clean_ship_has_no_issues() {
+        let ship = parse_ship_xml(crate::net::EMPTY_SHIP).unwrap().ship;
+        let report = validate_ship(&ship, PartCatalog::embedded());
+        assert!(report.is_empty());
+        assert!(report.is_valid());
+    }
+
+    #[test]
+    fn reports_save_issues() {
+        const SAVE: &str = r#"<Runtime time="1" firstStageActivated="0" solarSystem="SmolarSystem.xml" shipId="2" podId="1">
+  <Nodes>
+    <PlanetNode name="Sun" />
+    <PlanetNode name="Smearth" trueAnomaly="0" />
+    <PlanetNode name="Smearth" trueAnomaly="1" />
+    <ShipNode id="1" planet="Smars" planetRadius="1" x="0" y="NaN" vx="0" vy="inf">
+      <Ship version="1" liftedOff="1" touchingGround="0">
+        <Parts>
+          <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+            <Pod throttle="0" name=""><Staging currentStage="0" /></Pod>
+          </Part>
+        </Parts>
+        <Connections />
+      </Ship>
+    </ShipNode>
+  </Nodes>
+</Runtime>"#;
+        let save = parse_save_xml(SAVE).unwrap();
+        let report = validate_save(&save, PartCatalog::embedded());
+        let node = IssueLocation {
+            node: Some(1),
+            group: None,
+        };
+        let found: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.location, issue.kind.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    IssueLocation::default(),
+                    IssueKind::DuplicatePlanet {
+                        name: "Smearth".to_string()
+                    }
+                ),
+                (
+                    IssueLocation::default(),
+                    IssueKind::MissingShipNode { ship_id: 2 }
+                ),
+                (
+                    node,
+                    IssueKind::UnknownPlanet {
+                        name: "Smars".to_string()
+                    }
+                ),
+                (
+                    node,
+                    IssueKind::NonFiniteValue {
+                        part: None,
+                        field: "y".to_string()
+                    }
+                ),
+                (
+                    node,
+                    IssueKind::NonFiniteValue {
+                        part: None,
+                        field: "vy".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn sample_save_is_valid() {
+        let save = parse_save_xml(include_str!("../save_1294489.xml")).unwrap();
+        let report = validate_save(&save, PartCatalog::embedded());
+        assert!(report.is_empty(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn checks_attach_points() {
+        // 两个引擎挂在油箱侧面 (边, 可以挂多个), 两个降落伞挂在指令舱顶上 (点, 只能挂一个)
+        const SHIP: &str = r#"<Ship version="1" liftedOff="0" touchingGround="1">
+  <Parts>
+    <Part partType="pod-1" id="1" x="0" y="0" angle="0" angleV="0">
+      <Pod throttle="1" name=""><Staging currentStage="0" /></Pod>
+    </Part>
+    <Part partType="fueltank-1" id="2" x="0" y="-3.5" angle="0" angleV="0" />
+    <Part partType="engine-1" id="3" x="2" y="-3.5" angle="0" angleV="0" />
+    <Part partType="engine-1" id="4" x="2" y="-4.5" angle="0" angleV="0" />
+    <Part partType="parachute-1" id="5" x="0" y="1" angle="0" angleV="0" />
+    <Part partType="parachute-1" id="6" x="0" y="1" angle="0" angleV="0" />
+  </Parts>
+  <Connections>
+    <Connection parentAttachPoint="2" childAttachPoint="1" parentPart="1" childPart="2" />
+    <Connection parentAttachPoint="4" childAttachPoint="1" parentPart="2" childPart="3" />
+    <Connection parentAttachPoint="4" childAttachPoint="1" parentPart="2" childPart="4" />
+    <Connection parentAttachPoint="1" childAttachPoint="1" parentPart="1" childPart="5" />
+    <Connection parentAttachPoint="1" childAttachPoint="2" parentPart="1" childPart="6" />
+  </Connections>
+  <DisconnectedParts />
+</Ship>"#;
+        let ship = parse_ship_xml(SHIP).unwrap().ship;
+        let report = validate_ship(&ship, PartCatalog::embedded());
+        let kinds: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| issue.kind.clone())
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                IssueKind::AttachPointOutOfRange {
+                    part: 6,
+                    part_type: "parachute-1".to_string(),
+                    index: 2,
+                    count: 1
+                },
+                IssueKind::AttachPointOccupied {
+                    part: 1,
+                    index: 1,
+                    children: vec![5, 6]
+                },
+                // 摆的位置也是叠在一起的
+                IssueKind::PartsOverlap { part: 3, other: 4 },
+                IssueKind::PartsOverlap { part: 5, other: 6 },
+            ]
+        );
+    }
+}
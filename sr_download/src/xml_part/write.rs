@@ -2,22 +2,49 @@ use quick_xml::se::to_string;
 
 use crate::xml_part::{
     error::{XmlError, XmlResult},
-    model::{SaveDocument, ShipDocument, XmlDocument},
+    extra::emit,
+    game_xml::{game_save_xml, game_ship_xml},
+    model::{SaveDocument, ShipDocument, ShipFormat, XmlDocument},
     raw::{RawSaveDocument, RawShipDocument},
 };
 
+/// 写成什么样子
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteStyle {
+    /// quick-xml serde 默认的, 浮点数是最短的写法, 属性按 raw 结构体字段的顺序
+    ///
+    /// 规范 hash 是按这个算的, 不要改
+    #[default]
+    Serde,
+    /// 跟游戏存出来的一样, 见 [`crate::xml_part::game_xml`]
+    Game,
+}
+
+/// 解析的时候记下来的 [`RawShipDocument::extras`] 会原样塞回去
 pub fn write_raw_ship_xml(raw: &RawShipDocument) -> XmlResult<String> {
-    to_string(raw).map_err(|err| XmlError::Serialize(err.to_string()))
+    let xml = to_string(raw).map_err(|err| XmlError::Serialize(err.to_string()))?;
+    emit(xml, &raw.extras)
 }
 
 pub fn write_raw_save_xml(raw: &RawSaveDocument) -> XmlResult<String> {
-    to_string(raw).map_err(|err| XmlError::Serialize(err.to_string()))
+    let xml = to_string(raw).map_err(|err| XmlError::Serialize(err.to_string()))?;
+    emit(xml, &raw.extras)
 }
 
+/// 按 `document.format` 写
 pub fn write_ship_xml(document: &ShipDocument) -> XmlResult<String> {
     write_raw_ship_xml(&RawShipDocument::from(document.clone()))
 }
 
+/// 不管解析出来是什么格式, 按 `format` 写
+pub fn write_ship_xml_as(document: &ShipDocument, format: ShipFormat) -> XmlResult<String> {
+    write_ship_xml(&ShipDocument {
+        ship: document.ship.clone(),
+        format,
+        extras: document.extras.clone(),
+    })
+}
+
 pub fn write_save_xml(document: &SaveDocument) -> XmlResult<String> {
     write_raw_save_xml(&RawSaveDocument::from(document.clone()))
 }
@@ -28,3 +55,32 @@ pub fn write_xml_document(document: &XmlDocument) -> XmlResult<String> {
         XmlDocument::Save(doc) => write_save_xml(doc),
     }
 }
+
+pub fn write_raw_ship_xml_with(raw: &RawShipDocument, style: WriteStyle) -> XmlResult<String> {
+    match style {
+        WriteStyle::Serde => write_raw_ship_xml(raw),
+        WriteStyle::Game => game_ship_xml(raw),
+    }
+}
+
+pub fn write_raw_save_xml_with(raw: &RawSaveDocument, style: WriteStyle) -> XmlResult<String> {
+    match style {
+        WriteStyle::Serde => write_raw_save_xml(raw),
+        WriteStyle::Game => game_save_xml(raw),
+    }
+}
+
+pub fn write_ship_xml_with(document: &ShipDocument, style: WriteStyle) -> XmlResult<String> {
+    write_raw_ship_xml_with(&RawShipDocument::from(document.clone()), style)
+}
+
+pub fn write_save_xml_with(document: &SaveDocument, style: WriteStyle) -> XmlResult<String> {
+    write_raw_save_xml_with(&RawSaveDocument::from(document.clone()), style)
+}
+
+pub fn write_xml_document_with(document: &XmlDocument, style: WriteStyle) -> XmlResult<String> {
+    match document {
+        XmlDocument::Ship(doc) => write_ship_xml_with(doc, style),
+        XmlDocument::Save(doc) => write_save_xml_with(doc, style),
+    }
+}